@@ -12,26 +12,6 @@ use serde::{Serialize, Deserialize};
 pub struct TurtleCmdVelTopic {}
 
 impl TurtleCmdVelTopic {
-  const QOS: QosPolicies = QosPolicyBuilder::new()
-    .durability(Durability::Volatile)
-    .deadline(Deadline(DDSDuration::DURATION_INFINITE))
-    .latency_budget(LatencyBudget {
-      duration: DDSDuration::DURATION_ZERO,
-    })
-    .ownership(Ownership::Shared)
-    .liveliness(Liveliness::Automatic {
-      lease_duration: DDSDuration::DURATION_INFINITE,
-    })
-    .reliability(Reliability::Reliable {
-      max_blocking_time: DDSDuration::DURATION_ZERO,
-    })
-    .destination_order(DestinationOrder::ByReceptionTimestamp)
-    .history(History::KeepLast { depth: 10 })
-    .lifespan(Lifespan {
-      duration: DDSDuration::DURATION_INFINITE,
-    })
-    .build();
-
   pub fn topic_name() -> String {
     String::from("/turtle1/cmd_vel")
   }
@@ -45,7 +25,25 @@ impl TurtleCmdVelTopic {
   }
 
   pub fn get_qos() -> QosPolicies {
-    TurtleCmdVelTopic::QOS
+    QosPolicyBuilder::new()
+      .durability(Durability::Volatile)
+      .deadline(Deadline(DDSDuration::DURATION_INFINITE))
+      .latency_budget(LatencyBudget {
+        duration: DDSDuration::DURATION_ZERO,
+      })
+      .ownership(Ownership::Shared)
+      .liveliness(Liveliness::Automatic {
+        lease_duration: DDSDuration::DURATION_INFINITE,
+      })
+      .reliability(Reliability::Reliable {
+        max_blocking_time: DDSDuration::DURATION_ZERO,
+      })
+      .destination_order(DestinationOrder::ByReceptionTimestamp)
+      .history(History::KeepLast { depth: 10 })
+      .lifespan(Lifespan {
+        duration: DDSDuration::DURATION_INFINITE,
+      })
+      .build()
   }
 }
 
@@ -0,0 +1,522 @@
+// Parser and evaluator for the DDS-SQL filter expression language
+// (DDS spec v1.4 Annex B "Syntax for Queries and Filters").
+//
+// Grammar:
+//
+//   Condition  ::= Condition OR Condition | Condition AND Condition | NOT Condition
+//                | '(' Condition ')' | Predicate
+//   Predicate  ::= Operand RelOp Operand
+//                | Operand [NOT] BETWEEN Operand AND Operand
+//   RelOp      ::= '=' | '>' | '>=' | '<' | '<=' | '<>' | LIKE
+//   Operand    ::= FIELDNAME | INTEGERVALUE | FLOATVALUE | STRING | TRUE | FALSE | PARAMETER
+//
+// AND binds tighter than OR. Keywords are case-insensitive. FIELDNAME navigates
+// nested structures with '.' and sequences with '[n]'. PARAMETER is %0 .. %99.
+// As extensions, '==' and '!=' are accepted for equality and inequality.
+
+use std::cmp::Ordering;
+
+use super::field_value::FieldValue;
+
+pub(crate) type ParseResult<T> = std::result::Result<T, String>;
+
+// Maximum number of expression parameters, from DDS spec.
+pub(crate) const MAX_PARAMETERS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+  Bool(bool),
+  Integer(i128),
+  Float(f64),
+  String(String),
+}
+
+impl Value {
+  // None, if the values are not comparable.
+  fn compare(&self, other: &Value) -> Option<Ordering> {
+    match (self, other) {
+      (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+      (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+      (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+      (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+      (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+      (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathElement {
+  Member(String),
+  Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RelOp {
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+  Like,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operand {
+  Field(Vec<PathElement>),
+  Value(Value),
+}
+
+impl Operand {
+  fn evaluate<'a>(&'a self, sample: &'a FieldValue) -> Option<&'a Value> {
+    match self {
+      Operand::Field(path) => sample.lookup(path),
+      Operand::Value(value) => Some(value),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Condition {
+  And(Box<Condition>, Box<Condition>),
+  Or(Box<Condition>, Box<Condition>),
+  Not(Box<Condition>),
+  Compare { left: Operand, op: RelOp, right: Operand },
+  Between { operand: Operand, low: Operand, high: Operand },
+}
+
+impl Condition {
+  // A predicate referring to a field that the sample does not have, or comparing
+  // incompatible types, is false.
+  pub fn evaluate(&self, sample: &FieldValue) -> bool {
+    match self {
+      Condition::And(a, b) => a.evaluate(sample) && b.evaluate(sample),
+      Condition::Or(a, b) => a.evaluate(sample) || b.evaluate(sample),
+      Condition::Not(c) => ! c.evaluate(sample),
+      Condition::Compare { left, op, right } =>
+        match (left.evaluate(sample), right.evaluate(sample)) {
+          (Some(Value::String(text)), Some(Value::String(pattern))) if *op == RelOp::Like =>
+            like(text, pattern),
+          (Some(l), Some(r)) => match l.compare(r) {
+            Some(ordering) => match op {
+              RelOp::Equal => ordering == Ordering::Equal,
+              RelOp::NotEqual => ordering != Ordering::Equal,
+              RelOp::Less => ordering == Ordering::Less,
+              RelOp::LessOrEqual => ordering != Ordering::Greater,
+              RelOp::Greater => ordering == Ordering::Greater,
+              RelOp::GreaterOrEqual => ordering != Ordering::Less,
+              RelOp::Like => false,
+            }
+            None => false,
+          }
+          _ => false,
+        }
+      Condition::Between { operand, low, high } =>
+        match (operand.evaluate(sample), low.evaluate(sample), high.evaluate(sample)) {
+          (Some(v), Some(l), Some(h)) =>
+            matches!(v.compare(l), Some(Ordering::Greater) | Some(Ordering::Equal))
+            && matches!(v.compare(h), Some(Ordering::Less) | Some(Ordering::Equal)),
+          _ => false,
+        }
+    }
+  }
+}
+
+// SQL LIKE: '%' matches any sequence of characters, '_' matches any single character.
+fn like(text: &str, pattern: &str) -> bool {
+  let text: Vec<char> = text.chars().collect();
+  let pattern: Vec<char> = pattern.chars().collect();
+  let (mut t, mut p) = (0, 0);
+  // Position of latest '%' in pattern, and the text position it was matched at.
+  let mut backtrack: Option<(usize, usize)> = None;
+  while t < text.len() {
+    if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+      t += 1;
+      p += 1;
+    } else if p < pattern.len() && pattern[p] == '%' {
+      backtrack = Some((p, t));
+      p += 1;
+    } else if let Some((percent_p, percent_t)) = backtrack {
+      // Let the '%' match one more character.
+      p = percent_p + 1;
+      t = percent_t + 1;
+      backtrack = Some((percent_p, percent_t + 1));
+    } else {
+      return false
+    }
+  }
+  pattern[p..].iter().all( |c| *c == '%' )
+}
+
+// Expression parameters are given as strings. They are interpreted like literals
+// in the expression. Anything else is taken as a string, so that the quotes can be
+// left out.
+pub(crate) fn parse_parameter(parameter: &str) -> Value {
+  match Lexer::new(parameter).tokens().as_deref() {
+    Ok([Token::Literal(value)]) => value.clone(),
+    _ => Value::String(parameter.to_string()),
+  }
+}
+
+pub(crate) fn parse(expression: &str, parameters: &[Value]) -> ParseResult<Condition> {
+  let tokens = Lexer::new(expression).tokens()?;
+  let mut parser = Parser { tokens, position: 0, depth: 0, parameters };
+  let condition = parser.condition()?;
+  match parser.peek() {
+    None => Ok(condition),
+    Some(token) => Err(format!("Unexpected {:?} at end of filter expression", token)),
+  }
+}
+
+// ------------------------------------------------------------------------------
+// Lexer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Identifier(String),
+  Literal(Value),
+  Parameter(usize),
+  Operator(RelOp),
+  LeftParen,
+  RightParen,
+  LeftBracket,
+  RightBracket,
+  Dot,
+}
+
+struct Lexer {
+  chars: Vec<char>,
+  position: usize,
+}
+
+impl Lexer {
+  fn new(input: &str) -> Lexer {
+    Lexer { chars: input.chars().collect(), position: 0 }
+  }
+
+  fn peek_at(&self, offset: usize) -> Option<char> {
+    self.chars.get(self.position + offset).copied()
+  }
+
+  fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+    let start = self.position;
+    while self.peek_at(0).map(&predicate).unwrap_or(false) {
+      self.position += 1;
+    }
+    self.chars[start..self.position].iter().collect()
+  }
+
+  fn tokens(mut self) -> ParseResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    while let Some(c) = self.peek_at(0) {
+      let next_is_digit = self.peek_at(1).map( |n| n.is_ascii_digit() ).unwrap_or(false);
+      let token = match c {
+        _ if c.is_whitespace() => { self.position += 1; continue }
+        '(' => { self.position += 1; Token::LeftParen }
+        ')' => { self.position += 1; Token::RightParen }
+        '[' => { self.position += 1; Token::LeftBracket }
+        ']' => { self.position += 1; Token::RightBracket }
+        '.' => { self.position += 1; Token::Dot }
+        '=' | '<' | '>' | '!' => self.operator()?,
+        '%' => {
+          self.position += 1;
+          let digits = self.take_while( |c| c.is_ascii_digit() );
+          match digits.parse::<usize>() {
+            Ok(n) if n < MAX_PARAMETERS => Token::Parameter(n),
+            _ => return Err(format!("Bad parameter reference %{}", digits)),
+          }
+        }
+        '\'' => {
+          self.position += 1;
+          let s = self.take_while( |c| c != '\'' && c != '\n' );
+          if self.peek_at(0) != Some('\'') {
+            return Err(format!("Unterminated string '{}", s))
+          }
+          self.position += 1;
+          Token::Literal(Value::String(s))
+        }
+        _ if c.is_ascii_digit() || ((c == '-' || c == '+') && next_is_digit) => self.number()?,
+        _ if c.is_alphabetic() || c == '_' => {
+          let word = self.take_while( |c| c.is_alphanumeric() || c == '_' );
+          if word.eq_ignore_ascii_case("TRUE") {
+            Token::Literal(Value::Bool(true))
+          } else if word.eq_ignore_ascii_case("FALSE") {
+            Token::Literal(Value::Bool(false))
+          } else {
+            Token::Identifier(word)
+          }
+        }
+        _ => return Err(format!("Unexpected character {:?} in filter expression", c)),
+      };
+      tokens.push(token);
+    }
+    Ok(tokens)
+  }
+
+  fn operator(&mut self) -> ParseResult<Token> {
+    let op = self.take_while( |c| c == '=' || c == '<' || c == '>' || c == '!' );
+    let op = match op.as_str() {
+      "=" | "==" => RelOp::Equal,
+      "<>" | "!=" => RelOp::NotEqual,
+      "<" => RelOp::Less,
+      "<=" => RelOp::LessOrEqual,
+      ">" => RelOp::Greater,
+      ">=" => RelOp::GreaterOrEqual,
+      other => return Err(format!("Unknown operator {}", other)),
+    };
+    Ok(Token::Operator(op))
+  }
+
+  fn number(&mut self) -> ParseResult<Token> {
+    let negative = self.peek_at(0) == Some('-');
+    if matches!(self.peek_at(0), Some('-') | Some('+')) {
+      self.position += 1;
+    }
+    let hex = self.peek_at(0) == Some('0') && matches!(self.peek_at(1), Some('x') | Some('X'));
+    let (text, value) = if hex {
+      self.position += 2;
+      let digits = self.take_while( |c| c.is_ascii_hexdigit() );
+      let value = i128::from_str_radix(&digits, 16).map(Value::Integer);
+      (digits, value.map_err( |e| e.to_string() ))
+    } else {
+      let mut text = self.take_while( |c| c.is_ascii_digit() );
+      let mut is_float = false;
+      if self.peek_at(0) == Some('.') && self.peek_at(1).map( |c| c.is_ascii_digit() ).unwrap_or(false) {
+        self.position += 1;
+        text.push('.');
+        text.push_str(&self.take_while( |c| c.is_ascii_digit() ));
+        is_float = true;
+      }
+      if matches!(self.peek_at(0), Some('e') | Some('E')) {
+        let sign = matches!(self.peek_at(1), Some('-') | Some('+'));
+        let digit_at = if sign { 2 } else { 1 };
+        if self.peek_at(digit_at).map( |c| c.is_ascii_digit() ).unwrap_or(false) {
+          text.push('e');
+          if sign {
+            text.push(self.chars[self.position + 1]);
+          }
+          self.position += digit_at;
+          text.push_str(&self.take_while( |c| c.is_ascii_digit() ));
+          is_float = true;
+        }
+      }
+      let value = if is_float {
+        text.parse::<f64>().map(Value::Float).map_err( |e| e.to_string() )
+      } else {
+        text.parse::<i128>().map(Value::Integer).map_err( |e| e.to_string() )
+      };
+      (text, value)
+    };
+    match value {
+      Ok(Value::Integer(i)) if negative => Ok(Token::Literal(Value::Integer(-i))),
+      Ok(Value::Float(f)) if negative => Ok(Token::Literal(Value::Float(-f))),
+      Ok(value) => Ok(Token::Literal(value)),
+      Err(e) => Err(format!("Bad number {}: {}", text, e)),
+    }
+  }
+}
+
+// ------------------------------------------------------------------------------
+// Parser
+
+// Parsing recurses on NOT and parentheses, and filter expressions may come from remote
+// Readers, so nesting must be limited.
+const MAX_NESTING_DEPTH: usize = 64;
+
+struct Parser<'a> {
+  tokens: Vec<Token>,
+  position: usize,
+  depth: usize, // current nesting of NOT and parentheses
+  parameters: &'a [Value],
+}
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn next(&mut self) -> ParseResult<Token> {
+    let token = self.tokens.get(self.position).cloned()
+      .ok_or_else( || "Unexpected end of filter expression".to_string() )?;
+    self.position += 1;
+    Ok(token)
+  }
+
+  // Consumes the keyword, if it is next.
+  fn keyword(&mut self, keyword: &str) -> bool {
+    match self.peek() {
+      Some(Token::Identifier(word)) if word.eq_ignore_ascii_case(keyword) => {
+        self.position += 1;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+    if self.depth >= MAX_NESTING_DEPTH {
+      return Err(format!("Filter expression is nested deeper than {} levels", MAX_NESTING_DEPTH))
+    }
+    self.depth += 1;
+    let result = parse(self);
+    self.depth -= 1;
+    result
+  }
+
+  fn condition(&mut self) -> ParseResult<Condition> {
+    let mut condition = self.and_condition()?;
+    while self.keyword("OR") {
+      condition = Condition::Or(Box::new(condition), Box::new(self.and_condition()?));
+    }
+    Ok(condition)
+  }
+
+  fn and_condition(&mut self) -> ParseResult<Condition> {
+    let mut condition = self.not_condition()?;
+    while self.keyword("AND") {
+      condition = Condition::And(Box::new(condition), Box::new(self.not_condition()?));
+    }
+    Ok(condition)
+  }
+
+  fn not_condition(&mut self) -> ParseResult<Condition> {
+    if self.keyword("NOT") {
+      Ok(Condition::Not(Box::new(self.nested( |p| p.not_condition() )?)))
+    } else if self.peek() == Some(&Token::LeftParen) {
+      self.position += 1;
+      let condition = self.nested( |p| p.condition() )?;
+      match self.next()? {
+        Token::RightParen => Ok(condition),
+        other => Err(format!("Expected ')', found {:?}", other)),
+      }
+    } else {
+      self.predicate()
+    }
+  }
+
+  fn predicate(&mut self) -> ParseResult<Condition> {
+    let left = self.operand()?;
+    let negated = self.keyword("NOT");
+    if self.keyword("BETWEEN") {
+      let low = self.operand()?;
+      if ! self.keyword("AND") {
+        return Err("Expected AND in BETWEEN predicate".to_string())
+      }
+      let high = self.operand()?;
+      let between = Condition::Between { operand: left, low, high };
+      return Ok( if negated { Condition::Not(Box::new(between)) } else { between } )
+    } else if negated {
+      return Err("Expected BETWEEN after NOT".to_string())
+    }
+    let op = if self.keyword("LIKE") {
+      RelOp::Like
+    } else {
+      match self.next()? {
+        Token::Operator(op) => op,
+        other => return Err(format!("Expected comparison operator, found {:?}", other)),
+      }
+    };
+    let right = self.operand()?;
+    Ok(Condition::Compare { left, op, right })
+  }
+
+  fn operand(&mut self) -> ParseResult<Operand> {
+    match self.next()? {
+      Token::Literal(value) => Ok(Operand::Value(value)),
+      Token::Parameter(n) => match self.parameters.get(n) {
+        Some(value) => Ok(Operand::Value(value.clone())),
+        None => Err(format!("Parameter %{} given, but only {} parameters", n, self.parameters.len())),
+      }
+      Token::Identifier(name) => {
+        let mut path = vec![PathElement::Member(name)];
+        loop {
+          match self.peek() {
+            Some(Token::Dot) => {
+              self.position += 1;
+              match self.next()? {
+                Token::Identifier(name) => path.push(PathElement::Member(name)),
+                other => return Err(format!("Expected field name after '.', found {:?}", other)),
+              }
+            }
+            Some(Token::LeftBracket) => {
+              self.position += 1;
+              match (self.next()?, self.next()?) {
+                (Token::Literal(Value::Integer(i)), Token::RightBracket) if i >= 0 =>
+                  path.push(PathElement::Index(i as usize)),
+                _ => return Err("Expected index [n] in field name".to_string()),
+              }
+            }
+            _ => break,
+          }
+        }
+        Ok(Operand::Field(path))
+      }
+      other => Err(format!("Expected field name or value, found {:?}", other)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn like_patterns() {
+    assert!(like("hello", "hello"));
+    assert!(like("hello", "h%"));
+    assert!(like("hello", "%llo"));
+    assert!(like("hello", "h_l%o"));
+    assert!(like("hello", "%"));
+    assert!(like("", "%"));
+    assert!(like("abcbc", "%bc"));
+    assert!(!like("hello", "h_"));
+    assert!(!like("hello", "%x%"));
+    assert!(!like("", "_"));
+  }
+
+  #[test]
+  fn parse_parameters() {
+    assert_eq!(parse_parameter("5"), Value::Integer(5));
+    assert_eq!(parse_parameter("-0x10"), Value::Integer(-16));
+    assert_eq!(parse_parameter("2.5e3"), Value::Float(2500.0));
+    assert_eq!(parse_parameter("'quoted'"), Value::String("quoted".to_string()));
+    assert_eq!(parse_parameter("bare words"), Value::String("bare words".to_string()));
+    assert_eq!(parse_parameter("TRUE"), Value::Bool(true));
+  }
+
+  #[test]
+  fn parse_errors() {
+    let params = [Value::Integer(1)];
+    assert!(parse("a = %0", &params).is_ok());
+    assert!(parse("a = %1", &params).is_err());
+    assert!(parse("a =", &params).is_err());
+    assert!(parse("a = 'x", &params).is_err());
+    assert!(parse("(a = 1", &params).is_err());
+    assert!(parse("a NOT = 1", &params).is_err());
+    assert!(parse("a = 1 b", &params).is_err());
+    assert!(parse("a # 1", &params).is_err());
+  }
+
+  #[test]
+  fn nesting_limit() {
+    let nested = |depth: usize| 
+      format!("{}a = 1{}", "NOT (".repeat(depth), ")".repeat(depth));
+    assert!(parse(&nested(MAX_NESTING_DEPTH / 2), &[]).is_ok());
+    assert!(parse(&nested(MAX_NESTING_DEPTH), &[]).is_err());
+    assert!(parse(&"(".repeat(100_000), &[]).is_err());
+  }
+
+  #[test]
+  fn precedence() {
+    let c = parse("a = 1 OR b = 2 AND NOT c = 3", &[]).unwrap();
+    match c {
+      Condition::Or(_, right) => match *right {
+        Condition::And(_, not) => assert!(matches!(*not, Condition::Not(_))),
+        other => panic!("{:?}", other),
+      }
+      other => panic!("{:?}", other),
+    }
+  }
+}
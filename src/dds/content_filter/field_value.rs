@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use serde::{ser, Serialize};
+
+use crate::serialization::error::{Error, Result};
+
+use super::expression::{Value, PathElement};
+
+// Samples are serialized into this tree of field values, so that filter
+// expressions can refer to fields by name.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum FieldValue {
+  Primitive(Value),
+  Struct(BTreeMap<String, FieldValue>),
+  Sequence(Vec<FieldValue>),
+  // None, unit and unit structs
+  Absent,
+}
+
+impl FieldValue {
+  pub fn from_sample<D: Serialize>(sample: &D) -> Result<FieldValue> {
+    sample.serialize(FieldValueSerializer)
+  }
+
+  // Resolves a field name, e.g. "a.b[2].c". Only primitive values can be used
+  // in expressions.
+  pub fn lookup(&self, path: &[PathElement]) -> Option<&Value> {
+    let mut current = self;
+    for element in path {
+      current = match (element, current) {
+        (PathElement::Member(name), FieldValue::Struct(members)) => members.get(name)?,
+        (PathElement::Index(i), FieldValue::Sequence(items)) => items.get(*i)?,
+        _ => return None,
+      };
+    }
+    match current {
+      FieldValue::Primitive(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+struct FieldValueSerializer;
+
+impl ser::Serializer for FieldValueSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  type SerializeSeq = SequenceSerializer;
+  type SerializeTuple = SequenceSerializer;
+  type SerializeTupleStruct = SequenceSerializer;
+  type SerializeTupleVariant = SequenceSerializer;
+  type SerializeMap = MapSerializer;
+  type SerializeStruct = MapSerializer;
+  type SerializeStructVariant = MapSerializer;
+
+  fn serialize_bool(self, v: bool) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::Bool(v)))
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<FieldValue> {
+    self.serialize_i64(i64::from(v))
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<FieldValue> {
+    self.serialize_i64(i64::from(v))
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<FieldValue> {
+    self.serialize_i64(i64::from(v))
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::Integer(i128::from(v))))
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<FieldValue> {
+    self.serialize_u64(u64::from(v))
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<FieldValue> {
+    self.serialize_u64(u64::from(v))
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<FieldValue> {
+    self.serialize_u64(u64::from(v))
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::Integer(i128::from(v))))
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<FieldValue> {
+    self.serialize_f64(f64::from(v))
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::Float(v)))
+  }
+
+  fn serialize_char(self, v: char) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::String(v.to_string())))
+  }
+
+  fn serialize_str(self, v: &str) -> Result<FieldValue> {
+    Ok(FieldValue::Primitive(Value::String(v.to_string())))
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<FieldValue> {
+    Ok(FieldValue::Sequence(
+      v.iter().map( |b| FieldValue::Primitive(Value::Integer(i128::from(*b))) ).collect() ))
+  }
+
+  fn serialize_none(self) -> Result<FieldValue> {
+    Ok(FieldValue::Absent)
+  }
+
+  fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<FieldValue> {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<FieldValue> {
+    Ok(FieldValue::Absent)
+  }
+
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<FieldValue> {
+    Ok(FieldValue::Absent)
+  }
+
+  // Enumerations are compared by the name of the enumerator.
+  fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32,
+      variant: &'static str) -> Result<FieldValue> {
+    self.serialize_str(variant)
+  }
+
+  fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T)
+      -> Result<FieldValue> {
+    value.serialize(self)
+  }
+
+  // Variants with contents are represented by the contents only.
+  fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str,
+      _variant_index: u32, _variant: &'static str, value: &T) -> Result<FieldValue> {
+    value.serialize(self)
+  }
+
+  fn serialize_seq(self, len: Option<usize>) -> Result<SequenceSerializer> {
+    Ok(SequenceSerializer { items: Vec::with_capacity(len.unwrap_or(0)) })
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<SequenceSerializer> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SequenceSerializer> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32,
+      _variant: &'static str, len: usize) -> Result<SequenceSerializer> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer> {
+    Ok(MapSerializer { members: BTreeMap::new(), next_key: None })
+  }
+
+  fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer> {
+    self.serialize_map(Some(len))
+  }
+
+  fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32,
+      _variant: &'static str, len: usize) -> Result<MapSerializer> {
+    self.serialize_map(Some(len))
+  }
+}
+
+struct SequenceSerializer {
+  items: Vec<FieldValue>,
+}
+
+impl SequenceSerializer {
+  fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    self.items.push(value.serialize(FieldValueSerializer)?);
+    Ok(())
+  }
+}
+
+impl ser::SerializeSeq for SequenceSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Sequence(self.items))
+  }
+}
+
+impl ser::SerializeTuple for SequenceSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Sequence(self.items))
+  }
+}
+
+impl ser::SerializeTupleStruct for SequenceSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Sequence(self.items))
+  }
+}
+
+impl ser::SerializeTupleVariant for SequenceSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Sequence(self.items))
+  }
+}
+
+struct MapSerializer {
+  members: BTreeMap<String, FieldValue>,
+  next_key: Option<String>,
+}
+
+impl MapSerializer {
+  fn insert<T: ?Sized + Serialize>(&mut self, key: String, value: &T) -> Result<()> {
+    self.members.insert(key, value.serialize(FieldValueSerializer)?);
+    Ok(())
+  }
+}
+
+impl ser::SerializeMap for MapSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  // Map entries are accessed like struct members, so keys must be strings or integers.
+  fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+    let key = match key.serialize(FieldValueSerializer)? {
+      FieldValue::Primitive(Value::String(s)) => s,
+      FieldValue::Primitive(Value::Integer(i)) => i.to_string(),
+      other => return Err(Error::Message(format!("Unsupported map key {:?}", other))),
+    };
+    self.next_key = Some(key);
+    Ok(())
+  }
+
+  fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+    match self.next_key.take() {
+      Some(key) => self.insert(key, value),
+      None => Err(Error::Message("Map value without a key".to_string())),
+    }
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Struct(self.members))
+  }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+    self.insert(key.to_string(), value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Struct(self.members))
+  }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+  type Ok = FieldValue;
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+    self.insert(key.to_string(), value)
+  }
+
+  fn end(self) -> Result<FieldValue> {
+    Ok(FieldValue::Struct(self.members))
+  }
+}
@@ -0,0 +1,192 @@
+//! Content filtering of samples with DDS-SQL filter expressions, as used by
+//! ContentFilteredTopic.
+
+mod expression;
+mod field_value;
+
+#[allow(unused_imports)]
+use log::{debug, error, warn, info, trace};
+
+use serde::Serialize;
+
+use crate::{
+  discovery::content_filter_property::ContentFilterProperty,
+  dds::values::result::{Error, Result},
+};
+
+use expression::{Condition, MAX_PARAMETERS};
+use field_value::FieldValue;
+
+/// Filter class name of the DDS-SQL filter language. This is the only filter
+/// class supported.
+pub(crate) const DDSSQL_FILTER_CLASS: &str = "DDSSQL";
+
+/// Filter applied to samples on the Reader side, e.g. to skip samples that do not
+/// pass a ContentFilteredTopic. Typed by the sample, so that it can be stored in
+/// DataReaders that do not otherwise require `Serialize`.
+pub(crate) type SampleFilter<D> = Box<dyn Fn(&D) -> bool + Send + Sync>;
+
+/// Compiled DDS-SQL filter expression with its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ContentFilter {
+  condition: Condition,
+}
+
+impl ContentFilter {
+  pub fn new(filter_expression: &str, expression_parameters: &[String]) -> Result<ContentFilter> {
+    if expression_parameters.len() > MAX_PARAMETERS {
+      return Error::bad_parameter("Too many filter expression parameters, maximum is 100")
+    }
+    let parameters: Vec<_> = expression_parameters.iter()
+      .map( |p| expression::parse_parameter(p) )
+      .collect();
+    match expression::parse(filter_expression, &parameters) {
+      Ok(condition) => Ok(ContentFilter { condition }),
+      Err(reason) => Err(Error::BadParameter {
+        reason: format!("Bad filter expression {:?}: {}", filter_expression, reason)
+      }),
+    }
+  }
+
+  /// Filter from a remote Reader. Returns None if the filter class is not supported,
+  /// or the filter is not valid. In that case, the Writer does not filter.
+  pub fn from_property(property: &ContentFilterProperty) -> Option<ContentFilter> {
+    if property.filterClassName != DDSSQL_FILTER_CLASS {
+      info!("Unsupported content filter class {:?} on topic {:?}",
+        property.filterClassName, property.relatedTopicName);
+      return None
+    }
+    ContentFilter::new(&property.filterExpression, &property.expressionParameters)
+      .map_err( |e| warn!("Cannot use content filter of topic {:?}: {:?}",
+                  property.contentFilteredTopicName, e) )
+      .ok()
+  }
+
+  /// Does the sample pass the filter? None, if the sample cannot be examined field by field.
+  pub fn evaluate<D: Serialize>(&self, sample: &D) -> Option<bool> {
+    match FieldValue::from_sample(sample) {
+      Ok(fields) => Some(self.condition.evaluate(&fields)),
+      Err(e) => {
+        warn!("Content filter cannot examine sample: {:?}", e);
+        None
+      }
+    }
+  }
+
+  /// Samples that cannot be examined field by field do not pass.
+  pub fn matches<D: Serialize>(&self, sample: &D) -> bool {
+    self.evaluate(sample).unwrap_or(false)
+  }
+
+  pub fn into_sample_filter<D: Serialize>(self) -> SampleFilter<D> {
+    Box::new( move |sample| self.matches(sample) )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use serde::Serialize;
+
+  use super::*;
+
+  #[derive(Serialize)]
+  enum Color { Red, Blue }
+
+  #[derive(Serialize)]
+  struct Position { x: f32, y: f32 }
+
+  #[derive(Serialize)]
+  struct Shape {
+    color: Color,
+    name: String,
+    size: u32,
+    position: Position,
+    history: Vec<Position>,
+    visible: bool,
+    label: Option<char>,
+    extra: BTreeMap<String, i64>,
+  }
+
+  fn shape() -> Shape {
+    let mut extra = BTreeMap::new();
+    extra.insert("depth".to_string(), -3);
+    Shape {
+      color: Color::Red,
+      name: "square".to_string(),
+      size: 30,
+      position: Position { x: 10.5, y: 200.0 },
+      history: vec![ Position { x: 1.0, y: 2.0 }, Position { x: 3.0, y: 4.0 } ],
+      visible: true,
+      label: Some('S'),
+      extra,
+    }
+  }
+
+  fn matches(expression: &str, parameters: &[&str]) -> bool {
+    let parameters: Vec<String> = parameters.iter().map( |p| p.to_string() ).collect();
+    ContentFilter::new(expression, &parameters).unwrap().matches(&shape())
+  }
+
+  #[test]
+  fn filter_comparisons() {
+    assert!(matches("size = 30", &[]));
+    assert!(matches("size <> 31 AND size >= 30 AND size <= 30", &[]));
+    assert!(!matches("size > 30", &[]));
+    assert!(matches("size < 30.5", &[]));
+    assert!(matches("position.x > 10 AND position.y = 2e2", &[]));
+    assert!(matches("history[1].y = 4", &[]));
+    assert!(!matches("history[2].y = 4", &[]));
+    assert!(matches("color = 'Red'", &[]));
+    assert!(matches("visible = TRUE and label = 'S'", &[]));
+    assert!(matches("extra.depth = -3", &[]));
+    assert!(matches("name LIKE 's%re'", &[]));
+    assert!(matches("size between 10 and 30", &[]));
+    assert!(matches("size NOT BETWEEN 31 AND 40", &[]));
+    assert!(!matches("size = 'thirty'", &[]));
+    assert!(!matches("no_such_field = 1", &[]));
+    assert!(matches("NOT no_such_field = 1", &[]));
+  }
+
+  #[test]
+  fn filter_logic_and_parameters() {
+    assert!(matches("(size > %0 OR color = %1) AND name = %2", &["100", "Red", "'square'"]));
+    assert!(!matches("size > %0 OR color = %1", &["100", "Blue"]));
+    assert!(matches("position.x BETWEEN %0 AND %1", &["10", "11.0"]));
+    assert!(matches("size = %0 OR size = %0", &["30"]));
+  }
+
+  #[test]
+  fn filter_bad_expressions() {
+    assert!(ContentFilter::new("size = %0", &[]).is_err());
+    assert!(ContentFilter::new("size >", &[]).is_err());
+    assert!(ContentFilter::new("", &[]).is_err());
+    let too_many = vec!["1".to_string(); 101];
+    assert!(ContentFilter::new("size = %0", &too_many).is_err());
+  }
+
+  #[test]
+  fn filter_unexaminable_sample() {
+    // Fields cannot be named by map keys that are not strings or integers.
+    let mut sample = BTreeMap::new();
+    sample.insert(vec![1u8], 1);
+    let filter = ContentFilter::new("size > 1", &[]).unwrap();
+    assert_eq!(filter.evaluate(&sample), None);
+    assert!(! filter.matches(&sample));
+  }
+
+  #[test]
+  fn filter_from_property() {
+    let mut property = ContentFilterProperty {
+      contentFilteredTopicName: "cft".to_string(),
+      relatedTopicName: "shapes".to_string(),
+      filterClassName: DDSSQL_FILTER_CLASS.to_string(),
+      filterExpression: "size < %0".to_string(),
+      expressionParameters: vec!["50".to_string()],
+    };
+    assert!(ContentFilter::from_property(&property).unwrap().matches(&shape()));
+    property.filterClassName = "REGEXP".to_string();
+    assert_eq!(ContentFilter::from_property(&property), None);
+  }
+}
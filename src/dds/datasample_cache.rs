@@ -1,6 +1,6 @@
 use log::debug;
 
-use crate::structure::{time::Timestamp, guid::GUID};
+use crate::structure::{time::Timestamp, duration::Duration, guid::GUID, sequence_number::SequenceNumber};
 
 use crate::{
   dds::traits::key::{Key, Keyed, KeyHash},
@@ -8,10 +8,13 @@ use crate::{
 
 use crate::dds::with_key::datasample::DataSample;
 use crate::dds::sampleinfo::*;
+use crate::dds::statusevents::SampleRejectedStatusKind;
 use crate::dds::qos::QosPolicies;
 use crate::dds::qos::policy;
-use crate::dds::readcondition::ReadCondition;
+use crate::dds::querycondition::SampleCondition;
+use crate::dds::reader::{OwnershipCandidates, LostWriters};
 
+use std::cmp::{max, min};
 use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::ops::Bound::*;
 
@@ -33,6 +36,20 @@ pub(crate) fn result_ok_as_ref_err_clone<T, E: Clone>(
   }
 }
 
+// How many samples of an instance are kept when new samples arrive. The oldest samples
+// beyond this are dropped. None means that samples are never dropped (KEEP_ALL).
+pub(crate) fn instance_keep_count(qos: &QosPolicies) -> Option<usize> {
+  let depth = match qos.history() {
+    Some(policy::History::KeepAll) => return None,
+    Some(policy::History::KeepLast { depth }) => max(depth, 1) as usize,
+    None => 1, // default history policy
+  };
+  match qos.resource_limits().and_then( |rl| rl.samples_per_instance_limit() ) {
+    Some(max_samples_per_instance) => Some(min(depth, max_samples_per_instance)),
+    None => Some(depth),
+  }
+}
+
 // Data samples are here ordered and indexed by Timestamp, which must be a unique key.
 // RTPS Timestamp has sub-nanosecond resolution, so it could be unique, provided that the source
 // clock ticks frequently enough.
@@ -41,6 +58,39 @@ pub struct DataSampleCache<D: Keyed> {
   datasamples: BTreeMap<Timestamp, SampleWithMetaData<D>>, // ordered storage for deserialized samples
   pub(crate) instance_map: BTreeMap<D::K, InstanceMetaData>, // ordered storage for instances
   hash_to_key_map: BTreeMap<KeyHash, D::K>,
+  // Samples of coherent sets that are not yet complete, per Writer. These are
+  // not visible to the application until the whole set has been received.
+  pending_coherent_sets: BTreeMap<GUID, PendingCoherentSet<D>>,
+  // With EXCLUSIVE Ownership: Writers that may own instances, and their strengths.
+  ownership_candidates: OwnershipCandidates,
+  // Samples not accepted because of ResourceLimits, since the last take_rejected_samples()
+  rejected_samples: Vec<SampleRejection>,
+  // With ResourceLimits: instances whose sample count may have changed, since the last
+  // take_instance_usage_changes(), and samples per instance held in pending coherent sets.
+  changed_instances: BTreeSet<D::K>,
+  pending_instance_samples: BTreeMap<D::K, usize>,
+}
+
+struct PendingCoherentSet<D: Keyed> {
+  coherent_set: SequenceNumber, // first SequenceNumber of the set identifies the set
+  samples: Vec<PendingSample<D>>,
+}
+
+struct PendingSample<D: Keyed> {
+  sample: Result<D, D::K>,
+  sequence_number: SequenceNumber,
+  receive_timestamp: Timestamp,
+  source_timestamp: Option<Timestamp>,
+}
+
+// A sample that would have exceeded ResourceLimits. The Reader normally rejects these
+// already before acknowledging them. The DataReader reports the rest to the Reader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleRejection {
+  pub writer_guid: GUID,
+  pub sequence_number: SequenceNumber,
+  pub reason: SampleRejectedStatusKind,
+  pub instance_key: KeyHash,
 }
 
 pub(crate) struct InstanceMetaData {
@@ -48,6 +98,11 @@ pub(crate) struct InstanceMetaData {
   instance_state: InstanceState,         // latest known alive/not_alive state for this instance
   latest_generation_available: NotAliveGenerationCounts, // in this instance
   last_generation_accessed: NotAliveGenerationCounts, // in this instance
+  owner: Option<GUID>, // Writer owning this instance, if Ownership is EXCLUSIVE
+  last_accepted: Option<Timestamp>, // receive time of latest sample passing TimeBasedFilter
+  // newest source timestamp accepted in this instance, and the Writer of that sample
+  latest_source_timestamp: Option<(Timestamp, GUID)>,
+  writers: BTreeSet<GUID>, // Writers that have written this instance, and may still be alive
 }
 
 struct SampleWithMetaData<D: Keyed> {
@@ -88,6 +143,363 @@ where
       datasamples: BTreeMap::new(),
       instance_map: BTreeMap::new(),
       hash_to_key_map: BTreeMap::new(),
+      pending_coherent_sets: BTreeMap::new(),
+      ownership_candidates: OwnershipCandidates::new(),
+      rejected_samples: Vec::new(),
+      changed_instances: BTreeSet::new(),
+      pending_instance_samples: BTreeMap::new(),
+    }
+  }
+
+  pub fn take_rejected_samples(&mut self) -> Vec<SampleRejection> {
+    std::mem::take(&mut self.rejected_samples)
+  }
+
+  fn mark_changed(&mut self, instance_key: &D::K) {
+    if self.qos.resource_limits().is_some() {
+      self.changed_instances.insert(instance_key.clone());
+    }
+  }
+
+  // Samples stored per instance, including pending coherent sets, for the instances changed
+  // since the last call, so that the Reader can check ResourceLimits. None means that the
+  // instance no longer takes up room, i.e. it would be reclaimed to make room.
+  pub fn take_instance_usage_changes(&mut self) -> Vec<(KeyHash, Option<usize>)> {
+    let changed_instances = std::mem::take(&mut self.changed_instances);
+    changed_instances.into_iter()
+      .map( |key| {
+        let pending = self.pending_instance_samples.get(&key).copied().unwrap_or(0);
+        let count = match self.instance_map.get(&key) {
+          Some(imd) if imd.instance_state == InstanceState::Alive 
+            || ! imd.instance_samples.is_empty() || pending > 0 =>
+            Some(imd.instance_samples.len() + pending),
+          Some(_) => None,
+          None if pending > 0 => Some(pending),
+          None => None,
+        };
+        (key.into_hash_key(), count)
+      })
+      .collect()
+  }
+
+  // Writers have lost liveliness or have been unmatched. An Alive instance becomes 
+  // NotAlive_NoWriters, when all the Writers that have written it are lost.
+  pub fn writers_lost(&mut self, lost_writers: &LostWriters) {
+    let mut changed = Vec::new();
+    for (key, imd) in self.instance_map.iter_mut() {
+      let writer_count = imd.writers.len();
+      imd.writers.retain( |w| ! lost_writers.contains(w) );
+      if imd.writers.is_empty() && writer_count > 0 
+        && imd.instance_state == InstanceState::Alive {
+        imd.instance_state = InstanceState::NotAlive_NoWriters;
+        changed.push(key.clone());
+      }
+    }
+    for key in changed {
+      self.mark_changed(&key);
+    }
+  }
+
+  // Lifespan: Samples expire when their lifespan has elapsed since they were written, 
+  // whether the application has read them or not. Expired samples are no longer available.
+  pub fn remove_expired_samples(&mut self, now: Timestamp) {
+    let lifespan = match self.qos.lifespan() {
+      Some(policy::Lifespan { duration }) => duration,
+      None => return,
+    };
+    let expired: Vec<Timestamp> = self.datasamples.iter()
+      .filter( |(ts, dsm)| now - Self::order_timestamp(**ts, dsm) > lifespan )
+      .map( |(ts, _)| *ts )
+      .collect();
+    for ts in expired {
+      if let Some(dsm) = self.datasamples.remove(&ts) {
+        debug!("Lifespan expired sample from {:?} received at {:?}", dsm.writer_guid, ts);
+        let instance_key = match dsm.sample {
+          Ok(d) => d.get_key(),
+          Err(k) => k,
+        };
+        if let Some(imd) = self.instance_map.get_mut(&instance_key) {
+          imd.instance_samples.remove(&ts);
+        }
+        self.mark_changed(&instance_key);
+      }
+    }
+  }
+
+  fn exclusive_ownership(&self) -> bool {
+    matches!(self.qos.ownership(), Some(policy::Ownership::Exclusive{..}))
+  }
+
+  // Updates the set of Writers that may own instances. Instances whose owner is no longer 
+  // a candidate are free to be taken over by the next Writer writing them.
+  pub fn set_ownership_candidates(&mut self, candidates: OwnershipCandidates) {
+    for imd in self.instance_map.values_mut() {
+      if let Some(owner) = imd.owner {
+        if ! candidates.contains_key(&owner) {
+          debug!("Writer {:?} lost ownership of instance", owner);
+          imd.owner = None;
+        }
+      }
+    }
+    self.ownership_candidates = candidates;
+  }
+
+  pub fn get_instance_owner(&self, key: &D::K) -> Option<GUID> {
+    self.instance_map.get(key).and_then( |imd| imd.owner )
+  }
+
+  // Ownership arbitration: Should a sample from writer_guid be accepted to instance_key?
+  // The sample is accepted, if the Writer already owns the instance, or the instance has no owner,
+  // or the Writer is stronger than the current owner. Then the Writer becomes the owner.
+  // Equal strengths are resolved in favour of the smaller GUID, so that all DataReaders choose
+  // the same owner.
+  fn accept_by_ownership(&mut self, instance_key: &D::K, writer_guid: GUID) -> bool {
+    if ! self.exclusive_ownership() {
+      return true
+    }
+    let strength = match self.ownership_candidates.get(&writer_guid) {
+      Some(s) => *s,
+      None => {
+        debug!("Rejecting sample from {:?}, which is not an ownership candidate.", writer_guid);
+        return false
+      }
+    };
+    let current_owner = self.instance_map.get(instance_key).and_then( |imd| imd.owner );
+    let accept = match current_owner {
+      None => true,
+      Some(owner) if owner == writer_guid => true,
+      Some(owner) => {
+        let owner_strength = self.ownership_candidates.get(&owner).copied().unwrap_or(i32::MIN);
+        strength > owner_strength || (strength == owner_strength && writer_guid < owner)
+      }
+    };
+    if accept && current_owner != Some(writer_guid) {
+      if let Some(imd) = self.instance_map.get_mut(instance_key) {
+        imd.owner = Some(writer_guid);
+      } // else: new instance, owner is set on creation
+    }
+    accept
+  }
+
+  // TimeBasedFilter: Data samples arriving less than minimum_separation after the previous
+  // accepted sample of the same instance are dropped. Disposes always pass, as they change
+  // the instance state. The RTPS Reader has already acknowledged the dropped samples.
+  fn accept_by_time_filter(&self, instance_key: &D::K, receive_timestamp: Timestamp, is_data: bool) -> bool {
+    let minimum_separation = match self.qos.time_based_filter() {
+      Some(policy::TimeBasedFilter { minimum_separation }) => minimum_separation,
+      None => return true,
+    };
+    if ! is_data || minimum_separation <= Duration::DURATION_ZERO {
+      return true
+    }
+    match self.instance_map.get(instance_key).and_then( |imd| imd.last_accepted ) {
+      Some(last) if receive_timestamp - last < minimum_separation => {
+        debug!("TimeBasedFilter dropped sample received at {:?}", receive_timestamp);
+        false
+      }
+      _ => true,
+    }
+  }
+
+  // DestinationOrder BY_SOURCE_TIMESTAMP: Samples stamped before the newest sample already
+  // accepted for the instance are dropped. Then the instance ends up in the same state, no
+  // matter in which order the samples of redundant Writers arrive. Samples without a source
+  // timestamp are stamped with their receive time.
+  // Samples of different Writers stamped at the same time are ordered by Writer GUID, so that
+  // the greater GUID wins.
+  fn accept_by_destination_order(&self, instance_key: &D::K, source_timestamp: Timestamp, 
+      writer_guid: GUID) -> bool {
+    if self.qos.destination_order() != Some(policy::DestinationOrder::BySourceTimeStamp) {
+      return true
+    }
+    match self.instance_map.get(instance_key).and_then( |imd| imd.latest_source_timestamp ) {
+      Some(latest) if (source_timestamp, writer_guid) < latest => {
+        debug!("DestinationOrder dropped sample stamped at {:?} by {:?}, as one stamped at {:?} was accepted", 
+               source_timestamp, writer_guid, latest);
+        false
+      }
+      _ => true,
+    }
+  }
+
+  // A sample accepted by DestinationOrder BY_SOURCE_TIMESTAMP replaces the samples of
+  // Writers with lesser GUIDs, which were stamped at the same time. Then it does not
+  // matter which one of the tied samples arrived first.
+  fn drop_destination_order_losers(&mut self, instance_key: &D::K, source_timestamp: Timestamp,
+      writer_guid: GUID) {
+    if self.qos.destination_order() != Some(policy::DestinationOrder::BySourceTimeStamp) {
+      return
+    }
+    let imd = match self.instance_map.get_mut(instance_key) {
+      Some(imd) => imd,
+      None => return,
+    };
+    let datasamples = &self.datasamples;
+    let losers: Vec<Timestamp> = imd.instance_samples.iter()
+      .filter( |ts| datasamples.get(ts).map( |dsm| 
+        dsm.source_timestamp.unwrap_or(**ts) == source_timestamp && dsm.writer_guid < writer_guid )
+        .unwrap_or(false) )
+      .cloned()
+      .collect();
+    for ts in losers {
+      debug!("DestinationOrder dropped sample received at {:?}, as a tied sample from {:?} won",
+             ts, writer_guid);
+      imd.instance_samples.remove(&ts);
+      self.datasamples.remove(&ts);
+    }
+  }
+
+  // ResourceLimits: Can a sample of the instance be added? With KEEP_LAST History, a sample 
+  // of an instance that is at its depth replaces the oldest one, so it does not need more room.
+  fn accept_by_resource_limits(&mut self, instance_key: &D::K) 
+    -> std::result::Result<(), SampleRejectedStatusKind> 
+  {
+    let limits = match self.qos.resource_limits() {
+      Some(limits) => limits,
+      None => return Ok(()),
+    };
+    let instance_samples = match self.instance_map.get(instance_key) {
+      Some(imd) => imd.instance_samples.len(),
+      None => {
+        if let Some(max_instances) = limits.instances_limit() {
+          if self.instance_map.len() >= max_instances {
+            self.reclaim_instances();
+          }
+          if self.instance_map.len() >= max_instances {
+            return Err(SampleRejectedStatusKind::ByInstancesLimit)
+          }
+        }
+        0
+      }
+    };
+    match instance_keep_count(&self.qos) {
+      Some(keep_count) if instance_samples >= keep_count => return Ok(()),
+      Some(_) => (),
+      None => if let Some(max_samples_per_instance) = limits.samples_per_instance_limit() {
+        if instance_samples >= max_samples_per_instance {
+          return Err(SampleRejectedStatusKind::BySamplesPerInstanceLimit)
+        }
+      }
+    }
+    match limits.samples_limit() {
+      Some(max_samples) if self.datasamples.len() >= max_samples =>
+        Err(SampleRejectedStatusKind::BySamplesLimit),
+      _ => Ok(()),
+    }
+  }
+
+  // Instances that are no longer alive, and have no samples left, are forgotten to make
+  // room for new instances.
+  fn reclaim_instances(&mut self) {
+    let hash_to_key_map = &mut self.hash_to_key_map;
+    let pending_instance_samples = &self.pending_instance_samples;
+    let mut reclaimed = Vec::new();
+    self.instance_map.retain( |key, imd| {
+      let keep = imd.instance_state == InstanceState::Alive || ! imd.instance_samples.is_empty()
+        || pending_instance_samples.contains_key(key);
+      if ! keep {
+        hash_to_key_map.remove(&key.into_hash_key());
+        reclaimed.push(key.clone());
+      }
+      keep
+    });
+    for key in reclaimed {
+      self.mark_changed(&key);
+    }
+  }
+
+  // With ordered access, samples are accessed in the order they were written (source timestamp),
+  // not in the order they were received.
+  fn ordered_access(&self) -> bool {
+    self.qos.presentation()
+      .map( |p| p.ordered_access )
+      .unwrap_or(false)
+  }
+
+  fn order_timestamp(receive_timestamp: Timestamp, dsm: &SampleWithMetaData<D>) -> Timestamp {
+    dsm.source_timestamp.unwrap_or(receive_timestamp)
+  }
+
+  fn sort_for_ordered_access(&self, keys: &mut [(Timestamp, D::K)]) {
+    if self.ordered_access() {
+      keys.sort_by_key( |(ts, _)| 
+        (self.datasamples.get(ts).map( |dsm| Self::order_timestamp(*ts, dsm) ), *ts) );
+    }
+  }
+
+  // Receive and order timestamps of samples that have not been read yet.
+  pub fn unread_sample_order(&self) -> Vec<(Timestamp, Timestamp)> {
+    self.datasamples.iter()
+      .filter( |(_, dsm)| ! dsm.sample_has_been_read )
+      .map( |(ts, dsm)| (*ts, Self::order_timestamp(*ts, dsm)) )
+      .collect()
+  }
+
+  fn coherent_access(&self) -> bool {
+    self.qos.presentation()
+      .map( |p| p.coherent_access )
+      .unwrap_or(false)
+  }
+
+  // Adds a sample that the Writer marked as belonging to a coherent set. With coherent access,
+  // the sample is held back until the set is complete.
+  pub fn add_coherent_sample(
+    &mut self,
+    coherent_set: SequenceNumber,
+    new_sample: Result<D, D::K>,
+    writer_guid: GUID,
+    sequence_number: SequenceNumber,
+    receive_timestamp: Timestamp,
+    source_timestamp: Option<Timestamp>,
+  ) {
+    if ! self.coherent_access() {
+      return self.add_sample(new_sample, writer_guid, sequence_number, receive_timestamp, 
+        source_timestamp)
+    }
+    // A sample belonging to a different set also ends the previous set.
+    if let Some(pending) = self.pending_coherent_sets.get(&writer_guid) {
+      if pending.coherent_set != coherent_set {
+        self.end_coherent_set(writer_guid, pending.coherent_set);
+      }
+    }
+    let instance_key = match &new_sample {
+      Ok(d) => d.get_key(),
+      Err(k) => k.clone(),
+    };
+    if self.qos.resource_limits().is_some() {
+      *self.pending_instance_samples.entry(instance_key.clone()).or_insert(0) += 1;
+      self.mark_changed(&instance_key);
+    }
+    self.pending_coherent_sets
+      .entry(writer_guid)
+      .or_insert_with( || PendingCoherentSet { coherent_set, samples: Vec::new() } )
+      .samples
+      .push( PendingSample { sample: new_sample, sequence_number, receive_timestamp, source_timestamp } );
+  }
+
+  // Makes the samples of a completed coherent set available to the application.
+  pub fn end_coherent_set(&mut self, writer_guid: GUID, coherent_set: SequenceNumber) {
+    match self.pending_coherent_sets.get(&writer_guid) {
+      Some(pending) if pending.coherent_set == coherent_set => {
+        if let Some(pending) = self.pending_coherent_sets.remove(&writer_guid) {
+          for ps in pending.samples {
+            let instance_key = match &ps.sample {
+              Ok(d) => d.get_key(),
+              Err(k) => k.clone(),
+            };
+            if let Some(count) = self.pending_instance_samples.get_mut(&instance_key) {
+              *count -= 1;
+              if *count == 0 {
+                self.pending_instance_samples.remove(&instance_key);
+              }
+            }
+            self.insert_sample(ps.sample, writer_guid, ps.sequence_number, ps.receive_timestamp, 
+              ps.source_timestamp)
+          }
+        }
+      }
+      _ => debug!("end_coherent_set: No pending coherent set {:?} from {:?}", 
+                coherent_set, writer_guid),
     }
   }
 
@@ -95,6 +507,22 @@ where
     &mut self,
     new_sample: Result<D, D::K>,
     writer_guid: GUID,
+    sequence_number: SequenceNumber,
+    receive_timestamp: Timestamp,
+    source_timestamp: Option<Timestamp>,
+  ) {
+    // A sample outside of any coherent set ends the set the Writer had going on.
+    if let Some(coherent_set) = self.pending_coherent_sets.get(&writer_guid).map( |p| p.coherent_set ) {
+      self.end_coherent_set(writer_guid, coherent_set)
+    }
+    self.insert_sample(new_sample, writer_guid, sequence_number, receive_timestamp, source_timestamp)
+  }
+
+  fn insert_sample(
+    &mut self,
+    new_sample: Result<D, D::K>,
+    writer_guid: GUID,
+    sequence_number: SequenceNumber,
     receive_timestamp: Timestamp,
     source_timestamp: Option<Timestamp>,
   ) {
@@ -102,6 +530,28 @@ where
       Ok(d) => d.get_key(),
       Err(k) => k.clone(),
     };
+    self.mark_changed(&instance_key);
+
+    if ! self.accept_by_time_filter(&instance_key, receive_timestamp, new_sample.is_ok()) {
+      return
+    }
+    let source_timestamp_or_now = source_timestamp.unwrap_or(receive_timestamp);
+    if ! self.accept_by_destination_order(&instance_key, source_timestamp_or_now, writer_guid) {
+      return
+    }
+    if let Err(reason) = self.accept_by_resource_limits(&instance_key) {
+      debug!("Rejected sample {:?} from {:?}: {:?}", sequence_number, writer_guid, reason);
+      self.rejected_samples.push( SampleRejection {
+        writer_guid, sequence_number, reason, instance_key: instance_key.into_hash_key(),
+      });
+      return
+    }
+    if ! self.accept_by_ownership(&instance_key, writer_guid) {
+      return
+    }
+    self.drop_destination_order_losers(&instance_key, source_timestamp_or_now, writer_guid);
+    let instance_keep_count = instance_keep_count(&self.qos);
+    let owner = if self.exclusive_ownership() { Some(writer_guid) } else { None };
 
     let new_instance_state = match new_sample {
       Ok(_) => InstanceState::Alive,
@@ -119,6 +569,10 @@ where
           instance_state: new_instance_state,
           latest_generation_available: NotAliveGenerationCounts::zero(), // this is new instance, so start from zero
           last_generation_accessed: NotAliveGenerationCounts::sub_zero(), // never accessed
+          owner,
+          last_accepted: None,
+          latest_source_timestamp: None,
+          writers: BTreeSet::new(),
         };
         self.instance_map.insert(instance_key.clone(), imd);
         self
@@ -132,6 +586,12 @@ where
     instance_metadata
       .instance_samples
       .insert(receive_timestamp);
+    if new_sample.is_ok() {
+      instance_metadata.last_accepted = Some(receive_timestamp);
+      instance_metadata.writers.insert(writer_guid);
+    }
+    instance_metadata.latest_source_timestamp = 
+      max(instance_metadata.latest_source_timestamp, Some((source_timestamp_or_now, writer_guid)));
 
     match (instance_metadata.instance_state, new_instance_state) {
       (InstanceState::Alive, _) => (), // was Alive, does not change counts
@@ -182,30 +642,14 @@ where
         },
       );
 
-    // garbage collect
-    let sample_keep_history_limit: Option<i32> = match self.qos.history() {
-      Some(policy::History::KeepAll) => None, // no limit
-      Some(policy::History::KeepLast { depth }) => Some(depth),
-      None => Some(1), // default history policy
-    };
-    let sample_keep_resource_limit = if let Some(policy::ResourceLimits {
-      max_samples: _,
-      max_instances: _,
-      max_samples_per_instance,
-    }) = self.qos.resource_limits
-    {
-      Some(max_samples_per_instance)
-    } else {
-      None
-    };
-
-    if let Some(instance_keep_count) = sample_keep_history_limit.or(sample_keep_resource_limit) {
-      let remove_count = instance_metadata.instance_samples.len() as i32 - instance_keep_count;
+    // garbage collect: KEEP_LAST History drops the oldest samples of the instance
+    if let Some(instance_keep_count) = instance_keep_count {
+      let remove_count = instance_metadata.instance_samples.len().saturating_sub(instance_keep_count);
       if remove_count > 0 {
         let keys_to_remove: Vec<_> = instance_metadata
           .instance_samples
           .iter()
-          .take(remove_count as usize)
+          .take(remove_count)
           .cloned()
           .collect();
         for k in keys_to_remove {
@@ -214,15 +658,15 @@ where
         }
       }
     }
-
-    // TODO: Implement other resource_limit settings than max_instances_per sample, i.e.
   }
 
   // Calling select_(instance)_keys_for access does not constitute access, i.e.
   // it does not change any state of the cache.
   // Samples are marked read or viewed only when "read" or "take" methods (below) are called.
-  pub fn select_keys_for_access(&self, rc: ReadCondition) -> Vec<(Timestamp, D::K)> {
-    self
+  pub fn select_keys_for_access<C>(&self, rc: C) -> Vec<(Timestamp, D::K)>
+    where C: SampleCondition<D>
+  {
+    let mut keys : Vec<(Timestamp, D::K)> = self
       .datasamples
       .iter()
       .filter_map(|(ts, dsm)| {
@@ -233,15 +677,19 @@ where
           None
         }
       })
-      .collect()
+      .collect();
+    self.sort_for_ordered_access(&mut keys);
+    keys
   }
 
-  pub fn select_instance_keys_for_access(
+  pub fn select_instance_keys_for_access<C>(
     &self,
     instance: D::K,
-    rc: ReadCondition,
-  ) -> Vec<(Timestamp, D::K)> {
-    match self.instance_map.get(&instance) {
+    rc: C,
+  ) -> Vec<(Timestamp, D::K)>
+    where C: SampleCondition<D>
+  {
+    let mut keys : Vec<(Timestamp, D::K)> = match self.instance_map.get(&instance) {
       None => Vec::new(),
       Some(imd) => imd
         .instance_samples
@@ -258,16 +706,19 @@ where
           }
         })
         .collect(),
-    }
+    };
+    self.sort_for_ordered_access(&mut keys);
+    keys
   }
 
   // select helper
-  fn sample_selector(
+  fn sample_selector<C: SampleCondition<D>>(
     &self,
-    rc: &ReadCondition,
+    condition: &C,
     imd: &InstanceMetaData,
     d: &SampleWithMetaData<D>,
   ) -> bool {
+    let rc = condition.read_condition();
     // check sample state
     (*rc.sample_state_mask() == SampleState::any()
       || rc.sample_state_mask()
@@ -289,15 +740,48 @@ where
       || rc.instance_state_mask()
           .contains( imd.instance_state )
     )
+    &&
+    // check query, if the sample has data
+    match d.sample {
+      Ok(ref sample) => condition.sample_matches(sample),
+      Err(_) => true,
+    }
   }
 
+  // The ranks of SampleInfo are relative to the collection returned by read or take.
+  // For each sample in the collection, this gives the number of samples of the same instance
+  // following it in the collection, and the generations of the Most Recent Sample of the 
+  // same Instance in the Collection (MRSIC).
+  fn collection_ranks(&self, keys: &[(Timestamp, D::K)]) -> Vec<(usize, i32)> {
+    let generations = |ts: &Timestamp| 
+      self.datasamples.get(ts).map( |dswm| dswm.generation_counts.total() ).unwrap_or(0);
+    // Most recently received sample of each instance in the collection
+    let mut mrsic: HashMap<&D::K, Timestamp> = HashMap::new();
+    for (ts, key) in keys {
+      let latest = mrsic.entry(key).or_insert(*ts);
+      *latest = max(*latest, *ts);
+    }
+    let mut following: HashMap<&D::K, usize> = HashMap::new();
+    let mut ranks: Vec<(usize, i32)> = keys.iter().rev()
+      .map( |(_, key)| {
+        let count = following.entry(key).or_insert(0);
+        let sample_rank = *count;
+        *count += 1;
+        (sample_rank, generations(&mrsic[key]))
+      })
+      .collect();
+    ranks.reverse();
+    ranks
+  }
+
+  // The Most Recent Sample (MRS) of the instance is the latest received, so its 
+  // generations are the latest available.
   fn make_sample_info(
     dswm: &SampleWithMetaData<D>,
     imd: &InstanceMetaData,
-    sample_rank: usize,
-    mrs_generations: i32,
-    mrsic_generations: i32,
+    (sample_rank, mrsic_generations): (usize, i32),
   ) -> SampleInfo {
+    let mrs_generations = imd.latest_generation_available.total();
     SampleInfo {
       sample_state: if dswm.sample_has_been_read {
         SampleState::Read
@@ -311,7 +795,7 @@ where
       },
       instance_state: imd.instance_state,
       generation_counts: dswm.generation_counts,
-      sample_rank: sample_rank as i32, // how many samples follow this one
+      sample_rank: sample_rank as i32, // how many samples of the instance follow this one
       generation_rank: mrsic_generations - dswm.generation_counts.total(),
       absolute_generation_rank: mrs_generations - dswm.generation_counts.total(),
       source_timestamp: dswm.source_timestamp,
@@ -361,27 +845,14 @@ where
     }
 
     let mut instance_generations: HashMap<D::K, NotAliveGenerationCounts> = HashMap::new();
-    let mrsic_total = self
-      .instance_map
-      .get(&keys.last().unwrap().1)
-      .unwrap()
-      .latest_generation_available
-      .total();
-    let mrs_total = self
-      .datasamples
-      .iter()
-      .next_back()
-      .unwrap()
-      .1
-      .generation_counts
-      .total();
+    let ranks = self.collection_ranks(keys);
     let mut sample_infos = VecDeque::with_capacity(len);
     // construct SampleInfos and record read/viewed
-    for (index, (ts, key)) in keys.iter().enumerate() {
+    for ((ts, key), ranks) in keys.iter().zip(ranks) {
       let dswm = self.datasamples.get_mut(ts).unwrap();
       let imd = self.instance_map.get(key).unwrap();
 
-      let sample_info = Self::make_sample_info(dswm, imd, len - index - 1, mrs_total, mrsic_total);
+      let sample_info = Self::make_sample_info(dswm, imd, ranks);
       dswm.sample_has_been_read = true; // mark as read
       Self::record_instance_generation_viewed(
         &mut instance_generations,
@@ -422,25 +893,17 @@ where
     }
 
     let mut instance_generations: HashMap<D::K, NotAliveGenerationCounts> = HashMap::new();
-    let mrsic_total = self
-      .instance_map
-      .get(&keys.last().unwrap().1)
-      .unwrap()
-      .latest_generation_available
-      .total();
-    let mrs_total = self
-      .datasamples
-      .iter()
-      .next_back()
-      .unwrap()
-      .1
-      .generation_counts
-      .total();
+    // ranks must be computed before the samples are removed
+    let ranks = self.collection_ranks(keys);
     // collect result
-    for (index, (ts, key)) in keys.iter().enumerate() {
+    for ((ts, key), ranks) in keys.iter().zip(ranks) {
       let dswm = self.datasamples.remove(ts).unwrap();
-      let imd = self.instance_map.get(key).unwrap();
-      let sample_info = Self::make_sample_info(&dswm, imd, len - index - 1, mrs_total, mrsic_total);
+      let imd = self.instance_map.get_mut(key).unwrap();
+      imd.instance_samples.remove(ts);
+      if self.qos.resource_limits().is_some() {
+        self.changed_instances.insert(key.clone());
+      }
+      let sample_info = Self::make_sample_info(&dswm, imd, ranks);
       //dwsm.sample_has_been_read = true; // no need to mark read, as the dswm is about to be destroyed
       Self::record_instance_generation_viewed(
         &mut instance_generations,
@@ -507,6 +970,10 @@ where
 
     for (ts, key) in keys.iter() {
       let dswm = self.datasamples.remove(ts).unwrap();
+      if let Some(imd) = self.instance_map.get_mut(key) {
+        imd.instance_samples.remove(ts);
+      }
+      self.mark_changed(key);
       //dwsm.sample_has_been_read = true; // no need to mark read, as the dswm is about to be destroyed
       Self::record_instance_generation_viewed(
         &mut instance_generations,
@@ -552,7 +1019,338 @@ where
 
 #[cfg(test)]
 mod tests {
-  // use super::*;
+  use super::*;
+  use crate::dds::qos::QosPolicyBuilder;
+  use crate::dds::readcondition::ReadCondition;
+  use crate::structure::{guid::{EntityKind, GuidPrefix}, duration::Duration};
+  use crate::test::random_data::*;
+
+  #[test]
+  fn dsc_coherent_set() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .presentation(policy::Presentation {
+        access_scope: policy::PresentationAccessScope::Topic,
+        coherent_access: true,
+        ordered_access: false,
+      })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let data = |a| RandomData { a, b: "coherent".to_string() };
+    let coherent_set = SequenceNumber::from(1);
+    let now = Timestamp::now();
+
+    datasample_cache.add_coherent_sample(coherent_set, Ok(data(1)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(1), None);
+    datasample_cache.add_coherent_sample(coherent_set, Ok(data(2)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(2), None);
+    // Held back until the set is complete
+    assert!(datasample_cache.select_keys_for_access(ReadCondition::any()).is_empty());
+
+    // End of some other set changes nothing
+    datasample_cache.end_coherent_set(writer_guid, SequenceNumber::from(7));
+    assert!(datasample_cache.select_keys_for_access(ReadCondition::any()).is_empty());
+
+    datasample_cache.end_coherent_set(writer_guid, coherent_set);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 2);
+
+    // A sample outside of coherent sets completes the pending set implicitly.
+    datasample_cache.add_coherent_sample(SequenceNumber::from(4), Ok(data(3)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(3), None);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 2);
+    datasample_cache.add_sample(Ok(data(4)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(4), None);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 4);
+  }
+
+  #[test]
+  fn dsc_time_based_filter() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .time_based_filter(policy::TimeBasedFilter { minimum_separation: Duration::from_millis(100) })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let data = |a| RandomData { a, b: "filtered".to_string() };
+    let now = Timestamp::now();
+
+    datasample_cache.add_sample(Ok(data(1)), writer_guid, SequenceNumber::default(), now, None);
+    datasample_cache.add_sample(Ok(data(1)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(50), None);
+    // Other instances are filtered separately
+    datasample_cache.add_sample(Ok(data(2)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(60), None);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 2);
+
+    // Separation is counted from the previous accepted sample, not the dropped one
+    datasample_cache.add_sample(Ok(data(1)), writer_guid, SequenceNumber::default(), now + Duration::from_millis(120), None);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 3);
+
+    // Disposes are never filtered
+    datasample_cache.add_sample(Err(data(1).get_key()), writer_guid, SequenceNumber::default(), now + Duration::from_millis(130), None);
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 4);
+  }
+
+  #[test]
+  fn dsc_writers_lost() {
+    let qos = QosPolicyBuilder::new().history(policy::History::KeepAll).build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer = |n| GUID {
+      guidPrefix: GuidPrefix::new(&[n; 12]),
+      ..GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED)
+    };
+    let data = RandomData { a: 1, b: "lost".to_string() };
+    let key = data.get_key();
+    let state = |dsc: &DataSampleCache<RandomData>| dsc.instance_map[&key].instance_state;
+    let now = Timestamp::now();
+
+    datasample_cache.add_sample(Ok(data.clone()), writer(1), SequenceNumber::default(), now, None);
+    datasample_cache.add_sample(Ok(data.clone()), writer(2), SequenceNumber::default(), now + Duration::from_millis(1), None);
+
+    // Writer 2 still alive
+    datasample_cache.writers_lost(&[writer(1)].iter().copied().collect());
+    assert_eq!(state(&datasample_cache), InstanceState::Alive);
+
+    datasample_cache.writers_lost(&[writer(2), writer(3)].iter().copied().collect());
+    assert_eq!(state(&datasample_cache), InstanceState::NotAlive_NoWriters);
+
+    // Writer comes back
+    datasample_cache.add_sample(Ok(data), writer(1), SequenceNumber::default(), now + Duration::from_millis(2), None);
+    assert_eq!(state(&datasample_cache), InstanceState::Alive);
+    assert_eq!(datasample_cache.instance_map[&key].latest_generation_available.no_writers_generation_count, 1);
+  }
+
+  #[test]
+  fn dsc_sample_ranks() {
+    let qos = QosPolicyBuilder::new().history(policy::History::KeepAll).build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let data = |a, b: &str| RandomData { a, b: b.to_string() };
+    let now = Timestamp::now();
+    let mut received = 0;
+    let mut add = |dsc: &mut DataSampleCache<RandomData>, sample| {
+      received += 1;
+      dsc.add_sample(sample, writer_guid, SequenceNumber::from(received),
+        now + Duration::from_millis(received), None);
+    };
+
+    // Instance 1 is disposed and reborn, then loses its writer and is reborn again.
+    add(&mut datasample_cache, Ok(data(1, "first")));
+    add(&mut datasample_cache, Ok(data(2, "other")));
+    add(&mut datasample_cache, Err(1));
+    add(&mut datasample_cache, Ok(data(1, "second")));
+    datasample_cache.writers_lost(&[writer_guid].iter().copied().collect());
+    add(&mut datasample_cache, Ok(data(1, "third")));
+
+    let ranks = |infos: Vec<SampleInfo>| -> Vec<(i32, i32, i32, i32, i32)> {
+      infos.iter()
+        .map( |i| (i.disposed_generation_count(), i.no_writers_generation_count(), 
+                   i.sample_rank(), i.generation_rank(), i.absolute_generation_rank()) )
+        .collect()
+    };
+
+    // Ranks are relative to the returned collection, absolute_generation_rank to all received.
+    let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+    assert_eq!(keys.len(), 5);
+    let partial: Vec<SampleInfo> = datasample_cache.read_by_keys(&keys[..3]).into_iter()
+      .map( |s| s.sample_info().clone() )
+      .collect();
+    assert_eq!(ranks(partial), vec![
+      (0, 0, 1, 0, 2), // first
+      (0, 0, 0, 0, 0), // other
+      (0, 0, 0, 0, 2), // dispose
+    ]);
+
+    let all: Vec<SampleInfo> = datasample_cache.take_by_keys(&keys).into_iter()
+      .map( |s| s.sample_info().clone() )
+      .collect();
+    assert_eq!(ranks(all), vec![
+      (0, 0, 3, 2, 2), // first
+      (0, 0, 0, 0, 0), // other
+      (0, 0, 2, 2, 2), // dispose
+      (1, 0, 1, 1, 1), // second
+      (1, 1, 0, 0, 0), // third
+    ]);
+  }
+
+  #[test]
+  fn dsc_exclusive_ownership() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .ownership(policy::Ownership::Exclusive { strength: 0 })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer = |n| GUID {
+      guidPrefix: GuidPrefix::new(&[n; 12]),
+      ..GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED)
+    };
+    let (weak, strong, unknown) = (writer(1), writer(2), writer(3));
+    let data = |b: &str| RandomData { a: 1, b: b.to_string() };
+    let key = data("").get_key();
+    let now = Timestamp::now();
+
+    let mut candidates = OwnershipCandidates::new();
+    candidates.insert(weak, 5);
+    candidates.insert(strong, 10);
+    datasample_cache.set_ownership_candidates(candidates.clone());
+
+    // Writers that are not candidates are never accepted
+    datasample_cache.add_sample(Ok(data("unknown")), unknown, SequenceNumber::default(), now, None);
+    assert_eq!(datasample_cache.get_instance_owner(&key), None);
+
+    datasample_cache.add_sample(Ok(data("weak")), weak, SequenceNumber::default(), now + Duration::from_millis(1), None);
+    assert_eq!(datasample_cache.get_instance_owner(&key), Some(weak));
+
+    // Stronger writer takes over, after which the weaker one is ignored
+    datasample_cache.add_sample(Ok(data("strong")), strong, SequenceNumber::default(), now + Duration::from_millis(2), None);
+    datasample_cache.add_sample(Ok(data("weak")), weak, SequenceNumber::default(), now + Duration::from_millis(3), None);
+    assert_eq!(datasample_cache.get_instance_owner(&key), Some(strong));
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 2);
+
+    // Owner is gone: the weaker writer fails over
+    candidates.remove(&strong);
+    datasample_cache.set_ownership_candidates(candidates);
+    assert_eq!(datasample_cache.get_instance_owner(&key), None);
+    datasample_cache.add_sample(Ok(data("weak")), weak, SequenceNumber::default(), now + Duration::from_millis(4), None);
+    assert_eq!(datasample_cache.get_instance_owner(&key), Some(weak));
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 3);
+  }
+
+  #[test]
+  fn dsc_destination_order() {
+    let by_source = QosPolicyBuilder::new()
+      .destination_order(policy::DestinationOrder::BySourceTimeStamp)
+      .history(policy::History::KeepAll)
+      .build();
+    let by_reception = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .build();
+    let writer_1 = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let writer_2 = GUID::new(GuidPrefix::new(b"OtherWriter"), writer_1.entityId);
+    let (low, high) = (min(writer_1, writer_2), max(writer_1, writer_2));
+    let now = Timestamp::now();
+    let data = |a, b: &str| RandomData { a, b: b.to_string() };
+
+    let received = |qos: QosPolicies| -> Vec<String> {
+      let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+      // The samples of the redundant Writer arrive late.
+      let samples = vec![
+        (data(1, "newer"), high, 3),
+        (data(2, "other instance"), high, 1),
+        (data(1, "older"), low, 2),
+        (data(1, "same time, lower GUID"), low, 3),
+        (data(1, "same time, same Writer"), high, 3),
+        (data(3, "same time, lower GUID first"), low, 5),
+        (data(3, "same time, greater GUID"), high, 5),
+      ];
+      for (i, (d, writer_guid, source_ms)) in samples.into_iter().enumerate() {
+        datasample_cache.add_sample(Ok(d), writer_guid, SequenceNumber::from(i as i64),
+          now + Duration::from_millis(10 + i as i64), Some(now + Duration::from_millis(source_ms)));
+      }
+      let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+      datasample_cache.take_by_keys(&keys).into_iter()
+        .map( |s| s.into_value().unwrap().b )
+        .collect()
+    };
+    assert_eq!(received(by_source), vec!["newer", "other instance", "same time, same Writer",
+      "same time, greater GUID"]);
+    assert_eq!(received(by_reception).len(), 7);
+  }
+
+  #[test]
+  fn dsc_lifespan() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .lifespan(policy::Lifespan { duration: Duration::from_millis(100) })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let now = Timestamp::now();
+    let data = |a, b: &str| RandomData { a, b: b.to_string() };
+    datasample_cache.add_sample(Ok(data(1, "old")), writer_guid, SequenceNumber::from(1),
+      now, Some(now - Duration::from_secs(1)));
+    datasample_cache.add_sample(Ok(data(1, "fresh")), writer_guid, SequenceNumber::from(2),
+      now + Duration::from_millis(1), Some(now));
+    datasample_cache.add_sample(Ok(data(2, "old")), writer_guid, SequenceNumber::from(3),
+      now + Duration::from_millis(2), Some(now - Duration::from_secs(1)));
+
+    // Already read samples expire also.
+    let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+    assert_eq!(datasample_cache.read_by_keys(&keys).len(), 3);
+    datasample_cache.remove_expired_samples(now + Duration::from_millis(10));
+
+    let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+    let remaining: Vec<String> = datasample_cache.take_by_keys(&keys).into_iter()
+      .map( |s| s.into_value().unwrap().b )
+      .collect();
+    assert_eq!(remaining, vec!["fresh"]);
+  }
+
+  #[test]
+  fn dsc_resource_limits() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .resource_limits(policy::ResourceLimits {
+        max_samples: 3,
+        max_instances: 2,
+        max_samples_per_instance: 2,
+      })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let data = |a| RandomData { a, b: "x".to_string() };
+    let now = Timestamp::now();
+    let mut add = |a: i64, sn: i64| datasample_cache.add_sample(Ok(data(a)), writer_guid,
+      SequenceNumber::from(sn), now + Duration::from_millis(sn), None);
+    add(1, 1);
+    add(1, 2);
+    add(1, 3); // instance is full
+    add(2, 4);
+    add(2, 5); // cache is full
+    add(3, 6); // no more instances
+    let reasons: Vec<_> = datasample_cache.take_rejected_samples().iter()
+      .map( |r| (r.sequence_number, r.reason, r.instance_key) )
+      .collect();
+    assert_eq!(reasons, vec![
+      (SequenceNumber::from(3), SampleRejectedStatusKind::BySamplesPerInstanceLimit, 1i64.into_hash_key()),
+      (SequenceNumber::from(5), SampleRejectedStatusKind::BySamplesLimit, 2i64.into_hash_key()),
+      (SequenceNumber::from(6), SampleRejectedStatusKind::ByInstancesLimit, 3i64.into_hash_key()),
+    ]);
+
+    // Taking frees samples, and disposed instances without samples are reclaimed.
+    let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+    assert_eq!(datasample_cache.take_by_keys(&keys).len(), 3);
+    datasample_cache.add_sample(Err(2), writer_guid, SequenceNumber::from(7),
+      now + Duration::from_millis(7), None);
+    let keys = datasample_cache.select_keys_for_access(ReadCondition::any());
+    datasample_cache.take_by_keys(&keys);
+    datasample_cache.add_sample(Ok(data(3)), writer_guid, SequenceNumber::from(8),
+      now + Duration::from_millis(8), None);
+    assert!(datasample_cache.take_rejected_samples().is_empty());
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 1);
+  }
+
+  #[test]
+  fn dsc_resource_limits_keep_last() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepLast { depth: 1 })
+      .resource_limits(policy::ResourceLimits {
+        max_samples: 2,
+        max_instances: policy::ResourceLimits::LENGTH_UNLIMITED,
+        max_samples_per_instance: policy::ResourceLimits::LENGTH_UNLIMITED,
+      })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let data = |a| RandomData { a, b: "x".to_string() };
+    let now = Timestamp::now();
+    for (sn, a) in [1, 1, 2, 2, 3].iter().enumerate() {
+      datasample_cache.add_sample(Ok(data(*a)), writer_guid, SequenceNumber::from(sn as i64),
+        now + Duration::from_millis(sn as i64), None);
+    }
+    // New samples replace the old ones of the same instance, but a third instance does not fit.
+    let rejections = datasample_cache.take_rejected_samples();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].reason, SampleRejectedStatusKind::BySamplesLimit);
+    assert_eq!(rejections[0].instance_key, 3i64.into_hash_key());
+    assert_eq!(datasample_cache.select_keys_for_access(ReadCondition::any()).len(), 2);
+  }
+
   // use crate::{
   //   structure::{time::Timestamp},
   // };
@@ -575,7 +1373,7 @@ mod tests {
     let org_ddsdata = DDSData::from(&data, Some(timestamp));
 
     let key = data.get_key().clone();
-    datasample_cache.add_sample(Ok(data.clone()), GUID::GUID_UNKNOWN, timestamp, None);
+    datasample_cache.add_sample(Ok(data.clone()), GUID::GUID_UNKNOWN, SequenceNumber::default(), timestamp, None);
     //datasample_cache.add_datasample(datasample).unwrap();
 
     let samples = datasample_cache.read_by_keys(&[(timestamp, key)]);
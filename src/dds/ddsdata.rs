@@ -13,6 +13,7 @@ use crate::structure::cache_change::ChangeKind;
 
 // Contents of a DATA submessage or several DATAFRAG submessages. This is either a
 // new sample, or key, or a key hash. The latter two are used to indicate dispose or unregister.
+// A DATA with no contents at all marks the end of a coherent set.
 pub enum DDSData {
   Data { serialized_payload: SerializedPayload } ,
   // DataFrags { 
@@ -25,6 +26,7 @@ pub enum DDSData {
   // },
   DisposeByKey { change_kind: ChangeKind, key: SerializedPayload, },
   DisposeByKeyHash { change_kind: ChangeKind, key_hash: KeyHash, }, 
+  CoherentSetEnd,
 }
 
 impl DDSData {
@@ -44,16 +46,24 @@ impl DDSData {
       DDSData::Data {..} /*| DDSData::DataFrags {..}*/ => ChangeKind::Alive,
       DDSData::DisposeByKey { change_kind, ..} => *change_kind,
       DDSData::DisposeByKeyHash { change_kind, .. } => *change_kind,
+      DDSData::CoherentSetEnd => ChangeKind::Alive,
     }
   }
 
-  // pub fn serialized_payload(&self) -> Option<&SerializedPayload> {
-  //   match &self {
-  //     DDSData::Data { serialized_payload } => Some( serialized_payload ),
-  //     DDSData::DisposeByKey { key , ..} => Some( key ),
-  //     DDSData::DisposeByKeyHash {..} => None,
-  //   }
-  // }
+  pub fn serialized_payload(&self) -> Option<&SerializedPayload> {
+    match &self {
+      DDSData::Data { serialized_payload } => Some( serialized_payload ),
+      DDSData::DisposeByKey { key , ..} => Some( key ),
+      DDSData::DisposeByKeyHash {..} | DDSData::CoherentSetEnd => None,
+    }
+  }
+
+  // Size of the payload on the wire, including SerializedPayload header.
+  pub fn payload_size(&self) -> usize {
+    self.serialized_payload()
+      .map( |sp| 4 + sp.value.len() ) // 4 = representation identifier + options
+      .unwrap_or(0)
+  }
 
   #[cfg(test)]
   pub fn data(&self) -> Option<Bytes> {
@@ -62,7 +72,7 @@ impl DDSData {
       // DDSData::DataFrags { _representation_identifier, bytes_frags } => 
       //   Some(   ) ,
       DDSData::DisposeByKey { key , ..} => Some( key.value.clone() ),
-      DDSData::DisposeByKeyHash {..} => None,
+      DDSData::DisposeByKeyHash {..} | DDSData::CoherentSetEnd => None,
     }
   }
   
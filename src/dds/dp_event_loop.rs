@@ -14,13 +14,14 @@ use crate::{
   dds::{message_receiver::MessageReceiver}, 
   dds::reader::{Reader, ReaderIngredients,}, 
   dds::writer::{Writer, WriterIngredients,},
-  network::util::get_local_multicast_locators,
+  network::util::{get_local_multicast_locators, IpMode},
   structure::builtin_endpoint::{BuiltinEndpointSet, },
   dds::qos::policy,
 };
 
 use crate::network::udp_listener::UDPListener;
 use crate::network::udp_sender::UDPSender;
+use crate::network::shm_transport::{ShmTransport, ShmListener};
 use crate::network::constant::*;
 use crate::structure::guid::{GuidPrefix, GUID, EntityId, TokenDecode};
 use crate::structure::entity::RTPSEntity;
@@ -44,6 +45,7 @@ pub struct DomainInfo {
   pub domain_participant_guid: GUID,
   pub domain_id: u16,
   pub participant_id: u16,
+  pub ip_mode: IpMode,
 }
 
 pub const PREEMPTIVE_ACKNACK_PERIOD: Duration = Duration::from_secs(5);
@@ -58,6 +60,7 @@ pub struct DPEventLoop {
   ddscache: Arc<RwLock<DDSCache>>,
   discovery_db: Arc<RwLock<DiscoveryDB>>,
   udp_listeners: HashMap<Token, UDPListener>,
+  shm_listener: Option<ShmListener>,
   message_receiver: MessageReceiver, // This contains our Readers
 
   // Adding readers
@@ -85,6 +88,7 @@ impl DPEventLoop {
   pub(crate) fn new(
     domain_info: DomainInfo,
     udp_listeners: HashMap<Token, UDPListener>,
+    shm_transport: Option<ShmTransport>,
     ddscache: Arc<RwLock<DDSCache>>,
     discovery_db: Arc<RwLock<DiscoveryDB>>,
     participant_guid_prefix: GuidPrefix,
@@ -173,15 +177,24 @@ impl DPEventLoop {
       .expect("Failed to register reader update notification.");
 
     // port number 0 menas OS chooses an available port number.
-    let udp_sender = UDPSender::new(0)
+    let mut udp_sender = UDPSender::new(0)
       .expect("UDPSender construction fail"); // TODO
 
+    let shm_listener = shm_transport.map( |ShmTransport { listener, sender, .. }| {
+      poll
+        .register(&listener, SHM_LISTENER_TOKEN, Ready::readable(), PollOpt::edge())
+        .expect("Failed to register shared memory listener.");
+      udp_sender.set_shm_sender(sender);
+      listener
+    });
+
     DPEventLoop {
       domain_info,
       poll,
       ddscache,
       discovery_db,
       udp_listeners,
+      shm_listener,
       udp_sender: Rc::new(udp_sender),
       message_receiver: MessageReceiver::new(participant_guid_prefix, acknack_sender),
       add_reader_receiver,
@@ -231,7 +244,11 @@ impl DPEventLoop {
                 DISCOVERY_LISTENER_TOKEN |
                 DISCOVERY_MUL_LISTENER_TOKEN |
                 USER_TRAFFIC_LISTENER_TOKEN |
-                USER_TRAFFIC_MUL_LISTENER_TOKEN => {
+                USER_TRAFFIC_MUL_LISTENER_TOKEN |
+                DISCOVERY_LISTENER_V6_TOKEN |
+                DISCOVERY_MUL_LISTENER_V6_TOKEN |
+                USER_TRAFFIC_LISTENER_V6_TOKEN |
+                USER_TRAFFIC_MUL_LISTENER_V6_TOKEN => {
                   let udp_messages = ev_wrapper.udp_listeners.get_mut(&event.token())
                     .map_or_else(
                         | | { error!("No listener with token {:?}", &event.token() ); vec![] }, 
@@ -241,6 +258,13 @@ impl DPEventLoop {
                     ev_wrapper.message_receiver.handle_received_packet(packet)
                   }
                 }
+                SHM_LISTENER_TOKEN => {
+                  let shm_messages = ev_wrapper.shm_listener.as_mut()
+                    .map_or_else(Vec::new, |l| l.get_messages());
+                  for packet in shm_messages.into_iter() {
+                    ev_wrapper.message_receiver.handle_received_packet(packet)
+                  }
+                }
                 ADD_READER_TOKEN | REMOVE_READER_TOKEN => {
                   ev_wrapper.handle_reader_action(&event);
                 }
@@ -273,6 +297,8 @@ impl DPEventLoop {
                         ev_wrapper.writers.get_mut(&writer_guid.entityId)
                           .map( |w| w.handle_heartbeat_tick(manual_assertion) ); 
                       }
+                      ParticipantLivelinessAsserted{ guid_prefix, manual_assertion } =>
+                        ev_wrapper.participant_liveliness_asserted(guid_prefix, manual_assertion),
                     }
                   }              
                 }
@@ -529,7 +555,9 @@ impl DPEventLoop {
                 EntityId::ENTITYID_SPDP_BUILTIN_PARTICIPANT_READER);
 
               reader_proxy.multicast_locator_list =
-                get_local_multicast_locators(get_spdp_well_known_multicast_port(self.domain_info.domain_id));
+                get_local_multicast_locators(
+                  get_spdp_well_known_multicast_port(self.domain_info.domain_id),
+                  self.domain_info.ip_mode);
             }
             // common processing for SPDP and SEDP
             writer.update_reader_proxy( reader_proxy , qos );
@@ -600,6 +628,16 @@ impl DPEventLoop {
     }
   }
 
+  fn participant_liveliness_asserted(&mut self, guid_prefix: GuidPrefix, manual_assertion: bool) {
+    for writer in self.writers.values_mut() {
+      writer.participant_liveliness_asserted(guid_prefix, manual_assertion)
+    }
+
+    for reader in self.message_receiver.available_readers.values_mut() {
+      reader.participant_liveliness_asserted(guid_prefix, manual_assertion)
+    }
+  }
+
   fn remote_reader_discovered(&mut self, drd: DiscoveredReaderData, 
       rtps_reader_proxy: RtpsReaderProxy , _needs_new_cache_change: bool) {
     for (_writer_guid, writer) in self.writers.iter_mut() {
@@ -672,7 +710,10 @@ mod tests {
     dds::qos::QosPolicies,
   };
   use crate::structure::dds_cache::DDSCache;
-  
+  use crate::dds::reader::{OwnershipCandidates, LostWriters, ReaderHistoryUsage};
+  use crate::dds::helpers::TaskWakers;
+  use std::sync::Mutex;
+
 
   #[test]
   fn dpew_add_and_remove_readers() {
@@ -695,6 +736,7 @@ mod tests {
       domain_participant_guid: GUID::default(),
       domain_id: 0,
       participant_id: 0,
+      ip_mode: IpMode::V4Only,
     };
 
     let (sender_stop, receiver_stop) = mio_channel::channel::<i32>();
@@ -703,6 +745,7 @@ mod tests {
       let dp_event_loop = DPEventLoop::new(
         domain_info,
         HashMap::new(),
+        None,
         ddshc,
         discovery_db,
         GuidPrefix::default(),
@@ -756,6 +799,10 @@ mod tests {
         topic_name: "test".to_string(),
         qos_policy: QosPolicies::qos_none(),
         data_reader_command_receiver: reader_command_receiver,
+        ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+        lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+        history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+        data_reader_wakers: TaskWakers::new(),
       };
 
       // let new_reader = Reader::new(
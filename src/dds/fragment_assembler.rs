@@ -1,6 +1,7 @@
 //use crate::structure::guid::{GUID, /*EntityId, GuidPrefix*/ };
 use crate::structure::time::Timestamp;
-use crate::structure::sequence_number::SequenceNumber;
+use crate::structure::sequence_number::{SequenceNumber, FragmentNumber};
+use crate::structure::duration::Duration;
 use crate::structure::cache_change::ChangeKind;
 use crate::messages::submessages::submessages::*;
 use crate::dds::ddsdata::DDSData;
@@ -12,6 +13,7 @@ use bytes::BytesMut;
 use std::collections::BTreeMap;
 use std::convert::{From,TryInto,};
 use std::fmt;
+use std::cmp::min;
 
 #[allow(unused_imports)]
 use log::{debug, error, warn, info, trace};
@@ -55,18 +57,31 @@ impl AssemblyBuffer {
 		// TODO: Sanity checks? E.g. datafrag.fragment_size == frag_size
 		let frag_size = usize::from(frag_size);
 		let frags_in_subm = usize::from(datafrag.fragments_in_submessage);
-		let start_frag_from_0 : usize = u32::from(datafrag.fragment_starting_num).try_into().unwrap();
-
+		let start_frag_from_1 : usize = u32::from(datafrag.fragment_starting_num).try_into().unwrap();
 		// unwrap: u32 should fit into usize
-		let from_byte = (start_frag_from_0 - 1) * frag_size;
-		let to_before_byte : usize = from_byte + (frags_in_subm * frag_size);
+
+		if start_frag_from_1 < 1 || start_frag_from_1 - 1 + frags_in_subm > self.fragment_count {
+			warn!("DATA_FRAG fragments {}..{} out of bounds. fragment_count={}", 
+				start_frag_from_1, start_frag_from_1 + frags_in_subm, self.fragment_count);
+			return
+		}
+
+		// The last fragment of the sample may be shorter than frag_size.
+		let from_byte = (start_frag_from_1 - 1) * frag_size;
+		let to_before_byte = min(from_byte + (frags_in_subm * frag_size), self.buffer_bytes.len());
+		let payload = &datafrag.serialized_payload;
+		if payload.len() < to_before_byte - from_byte {
+			warn!("DATA_FRAG payload too short: got {} bytes, expected {}", 
+				payload.len(), to_before_byte - from_byte);
+			return
+		}
 
 		self.buffer_bytes
 			.as_mut()[from_byte..to_before_byte]
-			.copy_from_slice(&datafrag.serialized_payload.value);
+			.copy_from_slice(&payload[..to_before_byte - from_byte]);
 
-		for f in from_byte..to_before_byte {
-			self.received_bitmap.set(f,true);
+		for f in 0..frags_in_subm {
+			self.received_bitmap.set(start_frag_from_1 - 1 + f, true);
 		}
 		self.modified_time = Timestamp::now();
 	}
@@ -75,10 +90,21 @@ impl AssemblyBuffer {
 		self.received_bitmap.all() // return if all are received
 	}
 
+	// Fragment numbers (counting from 1) not yet received
+	pub fn missing_frags(&self) -> impl Iterator<Item = FragmentNumber> + '_ {
+		self.received_bitmap.iter().enumerate()
+			.filter_map(|(i, received)| 
+				if received { None } else { Some(FragmentNumber::from(i as u32 + 1)) })
+	}
+
 }
 
 
 
+/// Incomplete samples are discarded, if no new fragments for them are received 
+/// within this time.
+pub(crate) const FRAGMENT_ASSEMBLY_TIMEOUT : Duration = Duration::from_secs(10);
+
 // Assembles fragments from a single (remote) Writer
 // So there is only one sequence of SNs
 pub(crate) struct FragmentAssembler {
@@ -106,8 +132,12 @@ impl FragmentAssembler {
 	pub fn new_datafrag(&mut self, datafrag:DataFrag, flags: BitFlags<DATAFRAG_Flags>) 
 		-> Option<DDSData>
 	{
-		let rep_id = datafrag.serialized_payload.representation_identifier;
 		let writer_sn = datafrag.writer_sn;
+		if datafrag.fragment_size == 0 || datafrag.data_size == 0 {
+			warn!("DATA_FRAG {:?} with fragment_size={} data_size={}. Discarding.",
+				writer_sn, datafrag.fragment_size, datafrag.data_size);
+			return None
+		}
 
 		let abuf = self.assembly_buffers.entry(datafrag.writer_sn)
 			.or_insert_with(|| AssemblyBuffer::new(datafrag.data_size, datafrag.fragment_size));
@@ -117,10 +147,15 @@ impl FragmentAssembler {
 		if abuf.is_complete() {
 			if let Some(abuf) = self.assembly_buffers.remove(&writer_sn) {
 				// Return what we have assembled.
-				let ser_data_or_key = SerializedPayload::new(
-					rep_id,
-					abuf.buffer_bytes.to_vec(),
-				);
+				// The SerializedPayload header is at the start of the first fragment.
+				let ser_data_or_key = 
+					match SerializedPayload::from_bytes(abuf.buffer_bytes.freeze()) {
+						Ok(sp) => sp,
+						Err(e) => {
+							warn!("Reassembled DATA_FRAG {:?} is not a valid SerializedPayload: {:?}", writer_sn, e);
+							return None
+						}
+					};
 				let ddsdata = 
 					if flags.contains(DATAFRAG_Flags::Key) {
 						DDSData::new_disposed_by_key(ChangeKind::NotAliveDisposed, ser_data_or_key)
@@ -138,4 +173,122 @@ impl FragmentAssembler {
 		}
 
 	}
-}
\ No newline at end of file
+
+	pub fn is_partially_received(&self, writer_sn: SequenceNumber) -> bool {
+		self.assembly_buffers.contains_key(&writer_sn)
+	}
+
+	// Returns the fragments still missing of a partially received sample, 
+	// or an empty Vec if we do not know of such a sample.
+	pub fn missing_frags_for(&self, writer_sn: SequenceNumber) -> Vec<FragmentNumber> {
+		self.assembly_buffers.get(&writer_sn)
+			.map( |abuf| abuf.missing_frags().collect() )
+			.unwrap_or_default()
+	}
+
+	// Drop partially received samples that are no longer relevant.
+	pub fn remove_before(&mut self, smallest_sn: SequenceNumber) {
+		self.assembly_buffers = self.assembly_buffers.split_off(&smallest_sn);
+	}
+
+	// Drop partially received samples, which have not received any new fragments 
+	// since expire_before.
+	pub fn garbage_collect_before(&mut self, expire_before: Timestamp) {
+		self.assembly_buffers.retain( |sn, abuf| {
+			let keep = abuf.modified_time >= expire_before;
+			if ! keep {
+				debug!("Discarding incomplete sample {:?} age {:?}: {} of {} fragments received.", 
+					sn, Timestamp::now().duration_since(abuf.created_time), 
+					abuf.fragment_count - abuf.missing_frags().count(), abuf.fragment_count);
+			}
+			keep
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serialization::{Message, MessageBuilder, submessage::SubmessageBody};
+	use crate::structure::cache_change::CacheChange;
+	use crate::structure::guid::{GUID, EntityId, EntityKind, GuidPrefix};
+	use crate::structure::sequence_number::FragmentNumber;
+	use crate::messages::submessages::submessage_elements::serialized_payload::RepresentationIdentifier;
+	use speedy::{Writable, Endianness};
+	use bytes::Bytes;
+
+	fn test_datafrag(cache_change: &CacheChange, serialized_data: &Bytes, 
+		fragment_size: u16, f: u32) -> (DataFrag, BitFlags<DATAFRAG_Flags>) 
+	{
+		let message = MessageBuilder::new()
+			.data_frag_msg(cache_change, serialized_data, 
+				EntityId::ENTITYID_UNKNOWN, EntityId::ENTITYID_UNKNOWN,
+				FragmentNumber::from(f), fragment_size, Endianness::LittleEndian)
+			.add_header_and_build(GuidPrefix::GUIDPREFIX_UNKNOWN);
+		let bytes = Bytes::from(message.write_to_vec_with_ctx(Endianness::LittleEndian).unwrap());
+		let parsed = Message::read_from_buffer(bytes).unwrap();
+		match &parsed.submessages[0].body {
+			SubmessageBody::Entity(EntitySubmessage::DataFrag(df, flags)) => (df.clone(), *flags),
+			wtf => panic!("Unexpected message structure {:?}", wtf),
+		}
+	}
+
+	fn test_change(sn: i64, payload_len: u32) -> (DDSData, CacheChange, Bytes) {
+		let payload : Vec<u8> = (0..payload_len).map(|i| (i % 251) as u8).collect();
+		let ddsdata = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, payload));
+		let cache_change = CacheChange::new(
+			GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED),
+			SequenceNumber::from(sn),
+			None,
+			ddsdata.clone(),
+		);
+		let serialized_data = Bytes::from(
+			ddsdata.serialized_payload().unwrap()
+				.write_to_vec_with_ctx(Endianness::LittleEndian).unwrap());
+		(ddsdata, cache_change, serialized_data)
+	}
+
+	#[test]
+	fn fragment_and_reassemble() {
+		// Payload that does not divide evenly into fragments
+		let (ddsdata, cache_change, serialized_data) = test_change(7, 5000);
+		let fragment_size = 1024;
+		let fragment_count = 5u32;
+
+		let mut assembler = FragmentAssembler::new(fragment_size);
+		let mut completed = None;
+		// deliver fragments in reverse order
+		for f in (1 ..= fragment_count).rev() {
+			let (df, flags) = test_datafrag(&cache_change, &serialized_data, fragment_size, f);
+			assert_eq!(df.writer_sn, SequenceNumber::from(7));
+			assert_eq!(df.data_size as usize, serialized_data.len());
+			assert!(completed.is_none());
+			completed = assembler.new_datafrag(df, flags);
+		}
+		assert_eq!(completed, Some(ddsdata));
+	}
+
+	#[test]
+	fn missing_and_expired_fragments() {
+		let (_ddsdata, cache_change, serialized_data) = test_change(3, 5000);
+		let fragment_size = 1024;
+		let sn = SequenceNumber::from(3);
+
+		let mut assembler = FragmentAssembler::new(fragment_size);
+		assert!(! assembler.is_partially_received(sn));
+		for f in &[1u32, 4] {
+			let (df, flags) = test_datafrag(&cache_change, &serialized_data, fragment_size, *f);
+			assert!(assembler.new_datafrag(df, flags).is_none());
+		}
+		assert!(assembler.is_partially_received(sn));
+		assert_eq!(assembler.missing_frags_for(sn),
+			vec![FragmentNumber::from(2u32), FragmentNumber::from(3u32), FragmentNumber::from(5u32)]);
+
+		// Recently modified buffers survive garbage collection, old ones do not.
+		assembler.garbage_collect_before(Timestamp::now() - FRAGMENT_ASSEMBLY_TIMEOUT);
+		assert!(assembler.is_partially_received(sn));
+		assembler.garbage_collect_before(Timestamp::now() + FRAGMENT_ASSEMBLY_TIMEOUT);
+		assert!(! assembler.is_partially_received(sn));
+		assert!(assembler.missing_frags_for(sn).is_empty());
+	}
+}
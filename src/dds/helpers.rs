@@ -2,6 +2,13 @@ use mio_extras::channel::{ SyncSender, TrySendError};
 use crate::structure::duration::Duration;
 use std::thread;
 use std::convert::From;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use futures::future;
+
+#[allow(unused_imports)]
+use log::{debug, error, warn, info, trace};
 
 
 const timeout_epsilon : Duration = Duration::from_nanos( 1000 );
@@ -9,6 +16,9 @@ const timeout_epsilon : Duration = Duration::from_nanos( 1000 );
 // Always give background thread 1 ms to react
 const timeout_fallback : Duration = Duration::from_nanos( 1_000_000 );
 
+// How long to wait for the event loop to reply to a query from a DataReader or DataWriter.
+pub(crate) const QUERY_REPLY_TIMEOUT : std::time::Duration = std::time::Duration::from_secs(1);
+
 pub fn try_send_timeout<T>(sender: &SyncSender<T>, t: T, timeout_opt:Option<Duration>) 
   -> Result<(), TrySendError<T>> 
 {
@@ -37,4 +47,122 @@ pub fn try_send_timeout<T>(sender: &SyncSender<T>, t: T, timeout_opt:Option<Dura
     }
   }
   Err(TrySendError::Full(mt))
-}
\ No newline at end of file
+}
+
+// Async counterpart of try_send_timeout: if the channel is full, the task waits until
+// the receiving side wakes it. Never returns TrySendError::Full.
+pub(crate) async fn async_send<T>(sender: &SyncSender<T>, wakers: &TaskWakers, t: T) 
+  -> Result<(), TrySendError<T>> 
+{
+  let mut pending = Some(t);
+  future::poll_fn( |cx| {
+    // Register before trying, so that a wakeup in between is not lost.
+    wakers.register(cx.waker());
+    let t = pending.take().expect("async_send polled after completion");
+    match sender.try_send(t) {
+      Err(TrySendError::Full(t)) => {
+        pending = Some(t);
+        Poll::Pending
+      }
+      other => Poll::Ready(other),
+    }
+  }).await
+}
+
+// Async tasks waiting for a DDS entity, e.g. for new data in a DataReader.
+// These are shared between the entity and its counterpart in the event loop, which
+// wakes the tasks whenever it sends something to the entity. Woken tasks check
+// their channels and register again, if there was nothing for them.
+#[derive(Clone, Default)]
+pub(crate) struct TaskWakers {
+  wakers: Arc<Mutex<Vec<Waker>>>,
+}
+
+impl TaskWakers {
+  pub fn new() -> TaskWakers {
+    TaskWakers::default()
+  }
+
+  pub fn register(&self, waker: &Waker) {
+    match self.wakers.lock() {
+      Ok(mut wakers) => 
+        if ! wakers.iter().any( |w| w.will_wake(waker) ) {
+          wakers.push(waker.clone())
+        },
+      Err(e) => error!("TaskWakers::register - wakers are poisoned: {:?}", e),
+    }
+  }
+
+  pub fn wake_all(&self) {
+    let wakers : Vec<Waker> = match self.wakers.lock() {
+      Ok(mut wakers) => wakers.drain(..).collect(),
+      Err(e) => { 
+        error!("TaskWakers::wake_all - wakers are poisoned: {:?}", e); 
+        return 
+      }
+    };
+    // Wake outside the lock, as the tasks may be polled right away.
+    for waker in wakers {
+      waker.wake()
+    }
+  }
+}
+
+// Wakes up an async task at a deadline, so that it can give up waiting. Futures have
+// no timers of their own without an async runtime, so a thread sleeps until the
+// deadline. The thread is started only when the task first has to wait.
+pub(crate) struct DeadlineWaker {
+  deadline: Option<std::time::Instant>, // None = never
+  waker: Arc<Mutex<Option<Waker>>>,
+  thread_started: bool,
+}
+
+impl DeadlineWaker {
+  pub fn new(timeout: std::time::Duration) -> DeadlineWaker {
+    DeadlineWaker {
+      deadline: std::time::Instant::now().checked_add(timeout),
+      waker: Arc::new(Mutex::new(None)),
+      thread_started: false,
+    }
+  }
+
+  pub fn has_expired(&self) -> bool {
+    self.deadline.map( |d| std::time::Instant::now() >= d ).unwrap_or(false)
+  }
+
+  // Wake this waker at the deadline.
+  pub fn register(&mut self, waker: &Waker) {
+    let deadline = match self.deadline {
+      Some(deadline) => deadline,
+      None => return,
+    };
+    match self.waker.lock() {
+      Ok(mut w) => *w = Some(waker.clone()),
+      Err(e) => error!("DeadlineWaker::register - waker is poisoned: {:?}", e),
+    }
+    if ! self.thread_started {
+      self.thread_started = true;
+      let shared_waker = self.waker.clone();
+      let spawned = thread::Builder::new()
+        .name("RustDDS deadline waker".to_string())
+        .spawn(move || {
+          thread::sleep(deadline.saturating_duration_since(std::time::Instant::now()));
+          if let Ok(Some(waker)) = shared_waker.lock().map( |mut w| w.take() ) {
+            waker.wake()
+          }
+        });
+      if let Err(e) = spawned {
+        error!("DeadlineWaker::register - cannot start thread: {:?}", e);
+      }
+    }
+  }
+}
+
+impl Drop for DeadlineWaker {
+  // The task no longer waits, so do not wake it.
+  fn drop(&mut self) {
+    if let Ok(mut w) = self.waker.lock() {
+      *w = None;
+    }
+  }
+}
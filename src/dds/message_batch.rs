@@ -0,0 +1,185 @@
+#[allow(unused_imports)]
+use log::{debug, error, warn, trace};
+use speedy::{Writable, Endianness};
+
+use std::collections::BTreeMap;
+
+use crate::{
+  network::udp_sender::UDPSender,
+  serialization::{Message, MessageBuilder},
+  messages::submessages::submessages::SubmessageKind,
+  structure::{guid::GuidPrefix, locator::Locator},
+};
+
+const RTPS_MESSAGE_HEADER_SIZE: usize = 20;
+// Batched datagrams are not packed larger than this. A single message larger
+// than this is still sent, alone.
+const MAX_BATCH_DATAGRAM_SIZE: usize = 64000;
+
+// Outgoing messages of the DataWriters of a suspended Publisher
+// (Publisher::suspend_publications). The Writers add messages here instead of sending them.
+// When publications are resumed, the submessages are packed into as few RTPS messages
+// as possible for each destination.
+//
+// All the Writers run in the same event loop thread, but the batch is created by
+// the Publisher, so it is shared as Arc<Mutex<MessageBatch>>.
+//
+// The batch is sent when the Publisher has resumed publications and every Writer
+// that joined the batch has either resumed or been removed.
+pub(crate) struct MessageBatch {
+  // RTPS message header. All Writers are in the same participant, so this is
+  // the same for all messages.
+  header: Vec<u8>,
+  // Serialized submessages of each message and where they should go. Each destination
+  // is a locator list for UDPSender::send_to_locator_list.
+  messages: Vec<(Vec<u8>, Vec<Vec<Locator>>)>,
+  // Publisher has not yet resumed publications
+  suspended: bool,
+  // Writers that are adding messages to this batch
+  pending_writers: usize,
+}
+
+impl MessageBatch {
+  pub fn new() -> MessageBatch {
+    MessageBatch {
+      header: Vec::new(),
+      messages: Vec::new(),
+      suspended: true,
+      pending_writers: 0,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.messages.is_empty()
+  }
+
+  // Called by the Publisher in resume_publications()
+  pub fn resume(&mut self) {
+    self.suspended = false;
+  }
+
+  // Called by a Writer, when it starts adding its messages to the batch
+  pub fn writer_suspended(&mut self) {
+    self.pending_writers += 1;
+  }
+
+  // Called by a Writer, when it stops adding its messages to the batch.
+  // Returns true, if the batch should now be sent.
+  pub fn writer_resumed(&mut self) -> bool {
+    self.pending_writers = self.pending_writers.saturating_sub(1);
+    ! self.suspended && self.pending_writers == 0
+  }
+
+  pub fn add_message(&mut self, endianness: Endianness, message: &Message, 
+      destinations: Vec<Vec<Locator>>) {
+    if destinations.is_empty() {
+      return
+    }
+    // INFO_TS and INFO_DST apply until the end of the message. Messages that do not
+    // set them before their first Entity submessage get an invalidating INFO_TS and
+    // an INFO_DST to any participant, so that they do not inherit the timestamp or
+    // destination of the preceding message in the same datagram.
+    let leading_kinds: Vec<SubmessageKind> = message.submessages.iter()
+      .map( |s| s.header.kind )
+      .take_while( |k| *k == SubmessageKind::INFO_TS || *k == SubmessageKind::INFO_DST )
+      .collect();
+    let mut resets = MessageBuilder::new();
+    if ! leading_kinds.contains(&SubmessageKind::INFO_DST) {
+      resets = resets.dst_submessage(endianness, GuidPrefix::GUIDPREFIX_UNKNOWN);
+    }
+    if ! leading_kinds.contains(&SubmessageKind::INFO_TS) {
+      resets = resets.ts_msg(endianness, None);
+    }
+    let mut message = message.clone();
+    let mut submessages = resets
+      .add_header_and_build(message.header.guid_prefix)
+      .submessages;
+    submessages.append(&mut message.submessages);
+    message.submessages = submessages;
+
+    let mut bytes = match message.write_to_vec_with_ctx(endianness) {
+      Ok(bytes) => bytes,
+      Err(e) => {
+        error!("MessageBatch: Cannot serialize message: {:?}", e);
+        return
+      }
+    };
+    let submessages = bytes.split_off(RTPS_MESSAGE_HEADER_SIZE);
+    if self.header.is_empty() {
+      self.header = bytes;
+    }
+    self.messages.push( (submessages, destinations) );
+  }
+
+  // Sends the batched messages and empties the batch.
+  pub fn send(&mut self, udp_sender: &UDPSender) {
+    let messages = std::mem::take(&mut self.messages);
+    let mut per_destination: BTreeMap<&[Locator], Vec<&[u8]>> = BTreeMap::new();
+    for (submessages, destinations) in messages.iter() {
+      for destination in destinations {
+        per_destination.entry(destination).or_default().push(submessages);
+      }
+    }
+
+    let header_len = self.header.len();
+    for (destination, submessage_list) in per_destination {
+      let mut datagram = self.header.clone();
+      for submessages in submessage_list {
+        if datagram.len() > header_len
+            && datagram.len() + submessages.len() > MAX_BATCH_DATAGRAM_SIZE {
+          udp_sender.send_to_locator_list(&datagram, destination);
+          datagram.truncate(header_len);
+        }
+        datagram.extend_from_slice(submessages);
+      }
+      if datagram.len() > header_len {
+        udp_sender.send_to_locator_list(&datagram, destination);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::network::udp_listener::UDPListener;
+  use crate::structure::time::Timestamp;
+  use mio::Token;
+  use std::net::SocketAddr;
+
+  #[test]
+  fn message_batch_packs_messages() {
+    let mut listener = UDPListener::new_unicast(Token(0), "127.0.0.1", 10501).unwrap();
+    let sender = UDPSender::new(11501).expect("failed to create UDPSender");
+    let locator = Locator::from(SocketAddr::new("127.0.0.1".parse().unwrap(), 10501));
+    let endianness = Endianness::LittleEndian;
+    let guid_prefix = GuidPrefix::new(&[1; 12]);
+
+    let mut batch = MessageBatch::new();
+    assert!(batch.is_empty());
+    batch.add_message(endianness, 
+      &MessageBuilder::new().ts_msg(endianness, Some(Timestamp::now()))
+        .add_header_and_build(guid_prefix),
+      vec![vec![locator.clone()]]);
+    // No INFO_TS of its own, but INFO_DST
+    batch.add_message(endianness, 
+      &MessageBuilder::new().dst_submessage(endianness, guid_prefix)
+        .add_header_and_build(guid_prefix),
+      vec![vec![locator.clone()]]);
+    batch.add_message(endianness, 
+      &MessageBuilder::new().ts_msg(endianness, Some(Timestamp::now()))
+        .add_header_and_build(guid_prefix),
+      vec![vec![locator]]);
+    batch.send(&sender);
+    assert!(batch.is_empty());
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let datagrams = listener.get_messages();
+    assert_eq!(datagrams.len(), 1);
+    let message = Message::read_from_buffer(datagrams[0].clone()).unwrap();
+    let kinds: Vec<SubmessageKind> = message.submessages.iter().map( |s| s.header.kind ).collect();
+    assert_eq!(kinds, vec![SubmessageKind::INFO_DST, SubmessageKind::INFO_TS, 
+                           SubmessageKind::INFO_TS, SubmessageKind::INFO_DST, 
+                           SubmessageKind::INFO_DST, SubmessageKind::INFO_TS]);
+  }
+}
@@ -251,6 +251,7 @@ impl MessageReceiver {
             reader.handle_heartbeat_msg(
               heartbeat.clone(),
               flags.contains(HEARTBEAT_Flags::Final),
+              flags.contains(HEARTBEAT_Flags::Liveliness),
               mr_state.clone(),
             );
           }
@@ -258,6 +259,7 @@ impl MessageReceiver {
           target_reader.handle_heartbeat_msg(
             heartbeat,
             flags.contains(HEARTBEAT_Flags::Final),
+            flags.contains(HEARTBEAT_Flags::Liveliness),
             mr_state,
           );
         }
@@ -279,7 +281,16 @@ impl MessageReceiver {
         }
       }
       EntitySubmessage::DataFrag(datafrag, flags) => {
-        if let Some(target_reader) = self.get_reader_mut(datafrag.reader_id) {
+        // If reader_id == ENTITYID_UNKNOWN, message should be sent to all matched readers
+        if datafrag.reader_id == EntityId::ENTITYID_UNKNOWN {
+          for reader in self
+            .available_readers
+            .values_mut()
+            .filter(|r| r.contains_writer(datafrag.writer_id))
+          {
+            reader.handle_datafrag_msg(datafrag.clone(), flags, mr_state.clone());
+          }
+        } else if let Some(target_reader) = self.get_reader_mut(datafrag.reader_id) {
           target_reader.handle_datafrag_msg(datafrag, flags, mr_state, );
         }
       }
@@ -297,7 +308,16 @@ impl MessageReceiver {
           target_reader.handle_heartbeatfrag_msg(heartbeatfrag, mr_state);
         }
       }
-      EntitySubmessage::NackFrag(_, _) => {}
+      EntitySubmessage::NackFrag(nackfrag, _) => {
+        // Same as AckNack: this must not block.
+        match self.acknack_sender.try_send((self.source_guid_prefix, 
+            AckSubmessage::NackFrag_Variant(nackfrag))) {
+          Ok(_) => (),
+          Err(TrySendError::Full(_)) => 
+            info!("AckNack pipe full. Looks like I am very busy. Discarding NackFrag submessage."),
+          Err(e) => warn!("AckNack pipe fail: {:?}", e),
+        }
+      }
     }
   }
 
@@ -383,10 +403,11 @@ use super::*;
     dds::writer::WriterCommand, messages::header::Header,
     dds::with_key::datareader::ReaderCommand,
   };
-  use crate::dds::reader::ReaderIngredients;
-  use crate::dds::writer::WriterIngredients;
+  use crate::dds::reader::{ReaderIngredients, OwnershipCandidates, LostWriters, ReaderHistoryUsage};
+  use crate::dds::writer::{WriterIngredients, ReaderFilters, SharedHistoryUsage};
+  use crate::dds::helpers::TaskWakers;
   use crate::network::udp_sender::UDPSender;
-  use crate::dds::statusevents::DataReaderStatus;
+  use crate::dds::statusevents::{DataReaderStatus, DataWriterStatusAccumulator};
   use crate::serialization::cdr_deserializer::deserialize_from_little_endian;
   use crate::serialization::cdr_serializer::to_bytes;
   use crate::dds::writer::Writer;
@@ -400,7 +421,7 @@ use super::*;
   use log::info;
   use serde::{Serialize, Deserialize};
   use mio_extras::channel as mio_channel;
-  use std::sync::{RwLock, Arc};
+  use std::sync::{RwLock, Arc, Mutex};
   use std::rc::Rc;
 
 
@@ -453,6 +474,10 @@ use super::*;
       topic_name: "test".to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
     let new_reader = Reader::new(reader_ing, dds_cache,
@@ -505,6 +530,10 @@ use super::*;
       topic_name: String::from("topicName1"),
       qos_policies: QosPolicies::qos_none(),
       status_sender,
+      status_accumulator: Arc::new(Mutex::new(DataWriterStatusAccumulator::new())),
+      reader_filters: Arc::new(Mutex::new(ReaderFilters::new())),
+      history_usage: Arc::new(SharedHistoryUsage::new()),
+      data_writer_wakers: TaskWakers::new(),
     };
 
     let mut _writerObject = Writer::new(
@@ -105,13 +105,15 @@
 //! let actual_data = data_sample.value();
 //! ```
 
+pub(crate) mod content_filter;
 mod datasample_cache;
 pub(crate) mod ddsdata;
 mod dp_event_loop;
 mod message_receiver;
 mod fragment_assembler;
+mod message_batch;
 mod sampleinfo;
-mod helpers;
+pub(crate) mod helpers;
 
 /// Participating in NoKey topics.
 pub mod no_key;
@@ -120,6 +122,7 @@ pub mod with_key;
 
 pub(crate) mod participant;
 pub(crate) mod pubsub;
+pub(crate) mod querycondition;
 pub(crate) mod readcondition;
 pub(crate) mod reader;
 pub(crate) mod rtps_reader_proxy;
@@ -140,11 +143,12 @@ pub mod statusevents;
 /// Datatypes needed for overall operability with this crate
 pub mod data_types {
   pub use crate::discovery::data_types::topic_data::{
-    DiscoveredTopicData, SubscriptionBuiltinTopicData,
+    DiscoveredTopicData, SubscriptionBuiltinTopicData, PublicationBuiltinTopicData,
   };
   #[doc(inline)]
   pub use crate::structure::duration::Duration as DDSDuration;
   pub use super::readcondition::ReadCondition;
+  pub use super::querycondition::{QueryCondition, SampleCondition};
   #[doc(inline)]
   pub use super::with_key::datareader::SelectByKey;
   #[doc(inline)]
@@ -156,6 +160,7 @@ pub mod data_types {
   #[doc(inline)]
   pub use crate::structure::topic_kind::TopicKind; // AKA dds::topic::TopicKind
   pub use super::traits::key::BuiltInTopicKey;
+  pub use super::traits::key::KeyHash;
 }
 
 /// DDS Error
@@ -163,8 +168,9 @@ pub mod error {
   pub use super::values::result::*;
 }
 
-pub use participant::DomainParticipant;
-pub use topic::Topic;
+pub use participant::{DomainParticipant, DiscoveryEventStream};
+pub use crate::network::util::IpMode;
+pub use topic::{Topic, ContentFilteredTopic};
 pub use pubsub::Subscriber;
 pub use pubsub::Publisher;
 
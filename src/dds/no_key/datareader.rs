@@ -1,7 +1,10 @@
 use std::io;
+use std::pin::Pin;
+use std::task::{self, Context};
 
 use serde::{de::DeserializeOwned};
 use mio::{Poll, Token, Ready, PollOpt, Evented};
+use futures::stream::{Stream, FusedStream};
 
 use crate::{
   structure::{
@@ -11,15 +14,16 @@ use crate::{
 use crate::dds::{
   traits::serde_adapters::no_key::*, 
   values::result::*, qos::*,
-  readcondition::*, data_types::*, 
+  readcondition::*, querycondition::SampleCondition, data_types::*, 
 };
 
 use crate::dds::with_key::datareader as datareader_with_key;
+use crate::dds::statusevents::DataReaderStatus;
 use crate::dds::with_key::datasample::DataSample as WithKeyDataSample;
 use crate::serialization::CDRDeserializerAdapter;
 use crate::dds::no_key::datasample::DataSample;
 use super::{
-  wrappers::{NoKeyWrapper, DAWrapper},
+  wrappers::{NoKeyWrapper, DAWrapper, NoKeyCondition},
 };
 
 // ----------------------------------------------------
@@ -73,7 +77,7 @@ where
   /// # Arguments
   ///
   /// * `max_samples` - Limits maximum amount of samples read
-  /// * `read_condition` - Limits results by condition
+  /// * `read_condition` - Limits results by condition, either a `ReadCondition` or a `QueryCondition`
   ///
   /// # Examples
   ///
@@ -99,13 +103,13 @@ where
   /// let mut data_reader = subscriber.create_datareader_no_key::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
   /// let data = data_reader.read(10, ReadCondition::not_read());
   /// ```
-  pub fn read(
+  pub fn read<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<Vec<DataSample<&D>>> {
     let values: Vec<WithKeyDataSample<&NoKeyWrapper<D>>> =
-      self.keyed_datareader.read(max_samples, read_condition)?;
+      self.keyed_datareader.read(max_samples, NoKeyCondition::new(read_condition))?;
     let mut result = Vec::with_capacity(values.len());
     for ks in values {
       if let Some(s) = DataSample::<D>::from_with_key_ref(ks) {
@@ -120,7 +124,7 @@ where
   /// # Arguments
   ///
   /// * `max_samples` - Limits maximum amount of samples read
-  /// * `read_condition` - Limits results by condition
+  /// * `read_condition` - Limits results by condition, either a `ReadCondition` or a `QueryCondition`
   ///
   /// # Examples
   ///
@@ -146,13 +150,13 @@ where
   /// let mut data_reader = subscriber.create_datareader_no_key::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
   /// let data = data_reader.take(10, ReadCondition::not_read());
   /// ```
-  pub fn take(
+  pub fn take<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<Vec<DataSample<D>>> {
     let values: Vec<WithKeyDataSample<NoKeyWrapper<D>>> =
-      self.keyed_datareader.take(max_samples, read_condition)?;
+      self.keyed_datareader.take(max_samples, NoKeyCondition::new(read_condition))?;
     let mut result = Vec::with_capacity(values.len());
     for ks in values {
       if let Some(s) = DataSample::<D>::from_with_key(ks) {
@@ -295,9 +299,9 @@ where
   ///   // Do something
   /// }
   /// ```
-  pub fn conditional_iterator(
+  pub fn conditional_iterator<C: SampleCondition<D>>(
     &mut self,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<impl Iterator<Item = &D>> {
     // TODO: We could come up with a more efficent implementation than wrapping a read call
     Ok(
@@ -379,9 +383,9 @@ where
   ///   // Do something
   /// }
   /// ```
-  pub fn into_conditional_iterator(
+  pub fn into_conditional_iterator<C: SampleCondition<D>>(
     &mut self,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<impl Iterator<Item = D>> {
     // TODO: We could come up with a more efficent implementation than wrapping a read call
     Ok(
@@ -425,6 +429,77 @@ where
     self.keyed_datareader.get_requested_deadline_missed_status()
   }
   */
+
+  /// Blocks until all matched durable DataWriters have delivered their historical data.
+  /// See [`with_key::DataReader::wait_for_historical_data`](../with_key/struct.DataReader.html#method.wait_for_historical_data)
+  pub fn wait_for_historical_data(&self, max_wait: DDSDuration) -> Result<bool> {
+    self.keyed_datareader.wait_for_historical_data(max_wait)
+  }
+
+  /// Gets the discovery data of the remote DataWriters currently matched with this DataReader.
+  /// See [`with_key::DataReader::get_matched_publications`](../with_key/struct.DataReader.html#method.get_matched_publications)
+  pub fn get_matched_publications(&self) -> impl Iterator<Item=PublicationBuiltinTopicData> {
+    self.keyed_datareader.get_matched_publications()
+  }
+
+  /// Converts the DataReader into an async `Stream` of samples.
+  /// See [`with_key::DataReader::async_sample_stream`](../with_key/struct.DataReader.html#method.async_sample_stream)
+  pub fn async_sample_stream(self) -> DataReaderStream<D, DA> {
+    DataReaderStream {
+      keyed_stream: self.keyed_datareader.async_sample_stream(),
+    }
+  }
+}
+
+/// Async `Stream` of samples from a no key [DataReader](struct.DataReader.html).
+pub struct DataReaderStream<
+  D: DeserializeOwned,
+  DA: DeserializerAdapter<D> = CDRDeserializerAdapter<D>,
+> {
+  keyed_stream: datareader_with_key::DataReaderStream<NoKeyWrapper<D>, DAWrapper<DA>>,
+}
+
+impl<D: 'static, DA> DataReaderStream<D, DA>
+where
+  D: DeserializeOwned,
+  DA: DeserializerAdapter<D>,
+{
+  /// Async `Stream` of the status changes of the DataReader.
+  pub fn async_status_stream(&self) -> impl Stream<Item = DataReaderStatus> {
+    self.keyed_stream.async_status_stream()
+  }
+}
+
+impl<D: 'static, DA> Stream for DataReaderStream<D, DA>
+where
+  D: DeserializeOwned,
+  DA: DeserializerAdapter<D>,
+{
+  type Item = Result<DataSample<D>>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<Option<Self::Item>> {
+    loop {
+      match Pin::new(&mut self.keyed_stream).poll_next(cx) {
+        task::Poll::Ready(Some(Ok(keyed))) => match DataSample::<D>::from_with_key(keyed) {
+          Some(sample) => return task::Poll::Ready(Some(Ok(sample))),
+          None => continue, // disposed or unregistered, i.e. no data without key
+        },
+        task::Poll::Ready(Some(Err(e))) => return task::Poll::Ready(Some(Err(e))),
+        task::Poll::Ready(None) => return task::Poll::Ready(None),
+        task::Poll::Pending => return task::Poll::Pending,
+      }
+    }
+  }
+}
+
+impl<D: 'static, DA> FusedStream for DataReaderStream<D, DA>
+where
+  D: DeserializeOwned,
+  DA: DeserializerAdapter<D>,
+{
+  fn is_terminated(&self) -> bool {
+    self.keyed_stream.is_terminated()
+  }
 }
 
 // This is  not part of DDS spec. We implement mio Eventd so that the application can asynchronously
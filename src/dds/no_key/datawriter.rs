@@ -14,6 +14,8 @@ use crate::structure::entity::{RTPSEntity};
 use crate::dds::pubsub::Publisher;
 use crate::dds::topic::Topic;
 use crate::dds::values::result::Result;
+use crate::dds::statusevents::{LivelinessLostStatus, OfferedDeadlineMissedStatus, 
+  OfferedIncompatibleQosStatus, PublicationMatchedStatus};
 
 use crate::dds::data_types::*;
 use crate::dds::traits::dds_entity::DDSEntity;
@@ -101,6 +103,14 @@ where
       .write(NoKeyWrapper::<D> { d: data }, source_timestamp)
   }
 
+  /// Async version of `write`. See the keyed DataWriter for details.
+  pub async fn async_write(&self, data: D, source_timestamp: Option<Timestamp>) -> Result<()> {
+    self
+      .keyed_datawriter
+      .async_write(NoKeyWrapper::<D> { d: data }, source_timestamp)
+      .await
+  }
+
   /// Waits for all acknowledgements to finish
   ///
   /// # Examples
@@ -130,14 +140,26 @@ where
   pub fn wait_for_acknowledgments(&self, max_wait: Duration) -> Result<bool> {
     self.keyed_datawriter.wait_for_acknowledgments(max_wait)
   }
-  /*
+
+  /// Async version of `wait_for_acknowledgments`. See the keyed DataWriter for details.
+  pub async fn async_wait_for_acknowledgments(&self) -> Result<()> {
+    self.keyed_datawriter.async_wait_for_acknowledgments().await
+  }
+
+  /// Sets the fragment size used for samples that are too large to be sent
+  /// in a single DATA submessage. 
+  /// See [With_Key_DataWriter::set_fragment_size](../with_key/struct.DataWriter.html#method.set_fragment_size).
+  pub fn set_fragment_size(&self, fragment_size: u16) -> Result<()> {
+    self.keyed_datawriter.set_fragment_size(fragment_size)
+  }
+
   // status queries
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the liveliness lost status.
+  /// See [With_Key_DataWriter::get_liveliness_lost_status](../with_key/struct.DataWriter.html#method.get_liveliness_lost_status).
   ///
   /// # Examples
   ///
-  // TODO: enable run when implemented
-  /// ```no_run
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -164,7 +186,8 @@ where
     self.keyed_datawriter.get_liveliness_lost_status()
   }
 
-  /// Should get latest offered deadline missed status. <b>Do not use yet</b> use `get_status_lister` instead for the moment.
+  /// Gets the offered deadline missed status.
+  /// See [With_Key_DataWriter::get_offered_deadline_missed_status](../with_key/struct.DataWriter.html#method.get_offered_deadline_missed_status).
   ///
   /// # Examples
   ///
@@ -196,12 +219,12 @@ where
     self.keyed_datawriter.get_offered_deadline_missed_status()
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the offered incompatible QoS status.
+  /// See [With_Key_DataWriter::get_offered_incompatible_qos_status](../with_key/struct.DataWriter.html#method.get_offered_incompatible_qos_status).
   ///
   /// # Examples
   ///
-  // TODO: enable run when implemented
-  /// ```no_run
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -228,12 +251,12 @@ where
     self.keyed_datawriter.get_offered_incompatible_qos_status()
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the publication matched status.
+  /// See [With_Key_DataWriter::get_publication_matched_status](../with_key/struct.DataWriter.html#method.get_publication_matched_status).
   ///
   /// # Examples
   ///
-  // TODO: enable run when implemented
-  /// ```no_run
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -259,7 +282,6 @@ where
   pub fn get_publication_matched_status(&self) -> Result<PublicationMatchedStatus> {
     self.keyed_datawriter.get_publication_matched_status()
   }
-  */
   /// Topic this DataWriter is connected to.
   ///
   /// # Examples
@@ -347,12 +369,11 @@ where
     self.keyed_datawriter.assert_liveliness()
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the discovery data of the remote DataReaders currently matched with this DataWriter.
   ///
   /// # Examples
   ///
-  // TODO: enable run when implemented
-  /// ```no_run
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
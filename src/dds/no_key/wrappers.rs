@@ -5,6 +5,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwn
 
 use crate::{
   dds::traits::key::Keyed, dds::traits::serde_adapters::*,
+  dds::{querycondition::SampleCondition, readcondition::ReadCondition},
   messages::submessages::submessages::RepresentationIdentifier,
   serialization::error::Result,
 };
@@ -68,6 +69,32 @@ where
   }
 }
 
+// Wrapper for sample selection conditions
+// * inside is a condition on NO_KEY data
+// * outside of wrapper is a condition on WITH_KEY data
+pub(crate) struct NoKeyCondition<C> {
+  no_key: C,
+}
+
+impl<C> NoKeyCondition<C> {
+  pub fn new(no_key: C) -> Self {
+    NoKeyCondition { no_key }
+  }
+}
+
+impl<D, C> SampleCondition<NoKeyWrapper<D>> for NoKeyCondition<C>
+where
+  C: SampleCondition<D>,
+{
+  fn read_condition(&self) -> &ReadCondition {
+    self.no_key.read_condition()
+  }
+
+  fn sample_matches(&self, sample: &NoKeyWrapper<D>) -> bool {
+    self.no_key.sample_matches(&sample.d)
+  }
+}
+
 // wrapper for SerializerAdapter
 // * inside is NO_KEY
 // * outside of wrapper is WITH_KEY
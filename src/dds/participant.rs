@@ -1,35 +1,43 @@
-//use mio::Token;
+use mio::Token;
 use mio_extras::channel as mio_channel;
 #[allow(unused_imports)]
 use log::{error, debug, info, warn, trace};
 
 use std::{
+  pin::Pin,
+  task::{self, Context},
   thread,
   thread::JoinHandle,
   collections::HashMap,
   time::Duration,
   sync::{Arc, RwLock, Mutex, Weak},
-  net::Ipv4Addr,
+  net::{IpAddr, Ipv6Addr},
 };
 
 use crate::log_and_err_internal;
 use crate::{
   discovery::data_types::topic_data::DiscoveredTopicData,
   discovery::discovery::DiscoveryCommand,
-  network::{udp_listener::UDPListener, constant::*},
+  network::{
+    udp_listener::UDPListener, shm_transport::ShmTransport, constant::*,
+    util::{IpMode, MULTICAST_GROUP_V4, MULTICAST_GROUP_V6, MULTICAST_GROUP_V6_SITE},
+  },
 };
 
 use crate::dds::{
   dp_event_loop::DPEventLoop, reader::*, writer::*, pubsub::*, topic::*, typedesc::*, qos::*,
-  values::result::*,
+  values::result::*, statusevents::{DiscoveryEvent, DiscoveryEventQueue}, helpers::TaskWakers,
 };
 
+use futures::stream::Stream;
+
 use crate::{
   discovery::{discovery::Discovery, discovery_db::DiscoveryDB},
   structure::{
     entity::{RTPSEntity},
     guid::GUID,
     dds_cache::DDSCache,
+    locator::Locator,
   },
 };
 
@@ -45,19 +53,60 @@ pub struct DomainParticipant {
 
 #[allow(clippy::new_without_default)]
 impl DomainParticipant {
+  /// Uses IPv4, and also IPv6, if it is available.
+  ///
+  /// Other RustDDS participants on the same host are reached through shared memory,
+  /// where it is available. See
+  /// [`new_without_shared_memory`](#method.new_without_shared_memory).
+  ///
   /// # Examples
   /// ```
   /// # use rustdds::dds::DomainParticipant;
   /// let domain_participant = DomainParticipant::new(0).unwrap();
   /// ```
   pub fn new(domain_id: u16) -> Result<DomainParticipant> {
+    Self::new_with_ip_mode(domain_id, IpMode::default())
+  }
+
+  /// Select the IP versions to use. E.g. `IpMode::V6Only` is for hosts that have
+  /// no IPv4 connectivity.
+  ///
+  /// # Examples
+  /// ```
+  /// # use rustdds::dds::{DomainParticipant, IpMode};
+  /// let domain_participant = DomainParticipant::new_with_ip_mode(0, IpMode::V4Only).unwrap();
+  /// assert_eq!(domain_participant.ip_mode(), IpMode::V4Only);
+  /// ```
+  pub fn new_with_ip_mode(domain_id: u16, ip_mode: IpMode) -> Result<DomainParticipant> {
+    Self::new_with_transports(domain_id, ip_mode, true)
+  }
+
+  /// Like `new_with_ip_mode`, but does not use the shared-memory transport. All messages
+  /// go over UDP, also to participants on the same host.
+  ///
+  /// By default, participants reach other RustDDS participants on the same host
+  /// through shared memory. Messages go over UDP, if shared memory is not available,
+  /// or delivery through it fails. The shared-memory transport is available on Unix only.
+  ///
+  /// # Examples
+  /// ```
+  /// # use rustdds::dds::{DomainParticipant, IpMode};
+  /// let domain_participant = DomainParticipant::new_without_shared_memory(0, IpMode::Dual).unwrap();
+  /// ```
+  pub fn new_without_shared_memory(domain_id: u16, ip_mode: IpMode) -> Result<DomainParticipant> {
+    Self::new_with_transports(domain_id, ip_mode, false)
+  }
+
+  fn new_with_transports(domain_id: u16, ip_mode: IpMode, shared_memory: bool)
+    -> Result<DomainParticipant> 
+  {
     trace!("DomainParticipant construct start");
 
     // Discovery join channel is used to just send a join handle into the inner participant,
     // so its .drop() can wait until discovery has had a chance to stop.
     let (djh_sender, djh_receiver) = mio_channel::channel();
 
-    let mut dpd = DomainParticipant_Disc::new(domain_id, djh_receiver)?;
+    let mut dpd = DomainParticipant_Disc::new(domain_id, ip_mode, shared_memory, djh_receiver)?;
 
     let discovery_updated_sender = match dpd.discovery_updated_sender.take() {
       Some(dus) => dus,
@@ -71,6 +120,9 @@ impl DomainParticipant {
       None => return log_and_err_internal!("Unable to get Discovery Command Receiver."),
     };
 
+    let discovery_events = dpd.discovery_events.clone();
+    let discovery_event_wakers = dpd.discovery_event_wakers.clone();
+
     let dp = DomainParticipant {
       dpi: Arc::new(Mutex::new(dpd)),
     };
@@ -90,6 +142,8 @@ impl DomainParticipant {
               discovery_started_sender,
               discovery_updated_sender,
               discovery_command_receiver,
+              discovery_events,
+              discovery_event_wakers,
             ) {
             Ok(mut discovery) => // run the event loop
               discovery.discovery_event_loop(),
@@ -193,6 +247,44 @@ impl DomainParticipant {
       .create_topic(&w, name, type_desc, qos, topic_kind)
   }
 
+  /// Create DDS ContentFilteredTopic
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - Name of the ContentFilteredTopic.
+  /// * `related_topic` - Topic, whose samples are filtered. Must belong to this DomainParticipant.
+  /// * `filter_expression` - DDS-SQL filter expression, e.g. `"a > %0 AND b LIKE 'x%'"`
+  /// * `expression_parameters` - Values of the parameters `%0`, `%1`, ... in the expression.
+  ///
+  /// Returns `BadParameter` if the expression is not valid.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// use rustdds::dds::data_types::TopicKind;
+  ///
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().build();
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let filtered_topic = domain_participant
+  ///   .create_contentfilteredtopic("some_filtered_topic", &topic, "a BETWEEN %0 AND %1",
+  ///     &["1".to_string(), "10".to_string()]);
+  /// ```
+  pub fn create_contentfilteredtopic(
+    &self,
+    name: &str,
+    related_topic: &Topic,
+    filter_expression: &str,
+    expression_parameters: &[String],
+  ) -> Result<ContentFilteredTopic> {
+    if related_topic.get_participant().as_ref() != Some(self) {
+      return Error::precondition_not_met("Related Topic belongs to another DomainParticipant")
+    }
+    ContentFilteredTopic::new(name, related_topic, filter_expression, expression_parameters)
+  }
+
   pub fn find_topic(&self, name: &str, timeout: Duration) -> Result<Option<Topic>> {
     let w = self.weak_clone();
     self.dpi.lock().unwrap().find_topic(&w, name, timeout)
@@ -220,6 +312,12 @@ impl DomainParticipant {
     self.dpi.lock().unwrap().participant_id()
   }
 
+  /// IP versions in use. If IPv6 was requested with `IpMode::Dual`, but it is not
+  /// available on this host, this is `IpMode::V4Only`.
+  pub fn ip_mode(&self) -> IpMode {
+    self.dpi.lock().unwrap().ip_mode()
+  }
+
   /// Gets all DiscoveredTopics from DDS network
   ///
   /// # Examples
@@ -251,6 +349,40 @@ impl DomainParticipant {
     self.dpi.lock().unwrap().assert_liveliness()
   }
 
+  /// Async `Stream` of [DiscoveryEvents](statusevents/enum.DiscoveryEvent.html), i.e.
+  /// DomainParticipants, DataReaders and DataWriters appearing and disappearing in
+  /// the DDS network. The stream does not depend on any particular async runtime.
+  /// It never ends.
+  ///
+  /// Events are collected only after the first stream has been requested. They
+  /// are buffered up to a limit, until they are consumed. If the application falls
+  /// behind, the oldest events are dropped, and a `Lagged` event tells how many.
+  /// If there are several streams, each event goes to only one of them.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// use futures::StreamExt;
+  ///
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let mut discovery_events = domain_participant.async_discovery_event_stream();
+  ///
+  /// let task = async move {
+  ///   while let Some(event) = discovery_events.next().await {
+  ///     println!("Discovery: {:?}", event);
+  ///   }
+  /// };
+  /// // ... and spawn the task on the runtime of your choice.
+  /// ```
+  pub fn async_discovery_event_stream(&self) -> DiscoveryEventStream {
+    match self.dpi.lock().unwrap().discovery_events.lock() {
+      Ok(mut events) => events.enable(),
+      Err(e) => error!("async_discovery_event_stream: DiscoveryEvent queue is poisoned: {:?}", e),
+    }
+    DiscoveryEventStream { domain_participant: self.clone() }
+  }
+
   pub(crate) fn weak_clone(&self) -> DomainParticipantWeak {
     DomainParticipantWeak::new(self.clone(), self.get_guid())
   }
@@ -271,6 +403,44 @@ impl DomainParticipant {
       .clone()
   }
 
+  // Shared-memory locator to be advertised in Discovery, if the transport is available.
+  pub(crate) fn shm_locator(&self) -> Option<Locator> {
+    self.dpi.lock().unwrap().shm_locator()
+  }
+
+}
+
+/// Async `Stream` of DiscoveryEvents. Created by
+/// [`DomainParticipant::async_discovery_event_stream`](struct.DomainParticipant.html#method.async_discovery_event_stream).
+pub struct DiscoveryEventStream {
+  domain_participant: DomainParticipant,
+}
+
+impl Stream for DiscoveryEventStream {
+  type Item = DiscoveryEvent;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<Option<Self::Item>> {
+    let dpd = match self.domain_participant.dpi.lock() {
+      Ok(dpd) => dpd,
+      Err(e) => {
+        error!("DiscoveryEventStream - DomainParticipant is poisoned: {:?}", e);
+        return task::Poll::Ready(None)
+      }
+    };
+    // Register before receiving, so that an event arriving in between is not missed.
+    dpd.discovery_event_wakers.register(cx.waker());
+    let event = match dpd.discovery_events.lock() {
+      Ok(mut events) => events.pop(),
+      Err(e) => {
+        error!("DiscoveryEventStream - DiscoveryEvent queue is poisoned: {:?}", e);
+        return task::Poll::Ready(None)
+      }
+    };
+    match event {
+      Some(event) => task::Poll::Ready(Some(event)),
+      None => task::Poll::Pending,
+    }
+  }
 }
 
 impl PartialEq for DomainParticipant {
@@ -374,17 +544,23 @@ pub(crate) struct DomainParticipant_Disc {
   discovery_command_receiver: Option<mio_channel::Receiver<DiscoveryCommand>>,
   discovery_command_channel: mio_channel::SyncSender<DiscoveryCommand>,
   discovery_join_handle: mio_channel::Receiver<JoinHandle<()>>,
+  // DiscoveryEvents to the application
+  discovery_events: Arc<Mutex<DiscoveryEventQueue>>,
+  discovery_event_wakers: TaskWakers,
 }
 
 impl DomainParticipant_Disc {
   pub fn new(
     domain_id: u16,
+    ip_mode: IpMode,
+    shared_memory: bool,
     discovery_join_handle: mio_channel::Receiver<JoinHandle<()>>,
   ) -> Result<DomainParticipant_Disc> {
     let (discovery_update_notification_sender, discovery_update_notification_receiver) =
       mio_channel::sync_channel::<DiscoveryNotificationType>(100);
 
-    let dpi = DomainParticipant_Inner::new(domain_id, discovery_update_notification_receiver)?;
+    let dpi = DomainParticipant_Inner::new(domain_id, ip_mode, shared_memory,
+      discovery_update_notification_receiver)?;
 
     let (discovery_command_sender, discovery_command_receiver) =
       mio_channel::sync_channel::<DiscoveryCommand>(10);
@@ -395,6 +571,8 @@ impl DomainParticipant_Disc {
       discovery_command_receiver: Some(discovery_command_receiver),
       discovery_command_channel: discovery_command_sender,
       discovery_join_handle,
+      discovery_events: Arc::new(Mutex::new(DiscoveryEventQueue::default())),
+      discovery_event_wakers: TaskWakers::new(),
     })
   }
 
@@ -455,6 +633,10 @@ impl DomainParticipant_Disc {
     self.dpi.lock().unwrap().participant_id()
   }
 
+  pub fn ip_mode(&self) -> IpMode {
+    self.dpi.lock().unwrap().ip_mode
+  }
+
   pub fn get_discovered_topics(&self) -> Vec<DiscoveredTopicData> {
     self.dpi.lock().unwrap().get_discovered_topics()
   }
@@ -467,6 +649,10 @@ impl DomainParticipant_Disc {
     return self.dpi.lock().unwrap().discovery_db.clone();
   }
 
+  pub(crate) fn shm_locator(&self) -> Option<Locator> {
+    self.dpi.lock().unwrap().shm_locator
+  }
+
   pub(crate) fn assert_liveliness(&self) -> Result<()> {
     // No point in checking for the LIVELINESS QoS of MANUAL_BY_PARTICIPANT,
     // the discovery command mutates a field which is only read
@@ -507,6 +693,7 @@ impl Drop for DomainParticipant_Disc {
 pub(crate) struct DomainParticipant_Inner {
   domain_id: u16,
   participant_id: u16,
+  ip_mode: IpMode,
 
   my_guid: GUID,
 
@@ -530,6 +717,7 @@ pub(crate) struct DomainParticipant_Inner {
   dds_cache: Arc<RwLock<DDSCache>>,
   discovery_db: Arc<RwLock<DiscoveryDB>>,
 
+  shm_locator: Option<Locator>,
 }
 
 impl Drop for DomainParticipant_Inner {
@@ -563,71 +751,111 @@ impl Drop for DomainParticipant_Inner {
 impl DomainParticipant_Inner {
   fn new(
     domain_id: u16,
+    ip_mode: IpMode,
+    shared_memory: bool,
     discovery_update_notification_receiver: mio_channel::Receiver<DiscoveryNotificationType>,
   ) -> Result<DomainParticipant_Inner> {
+    let ip_mode = match ip_mode {
+      IpMode::Dual if std::net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).is_err() => {
+        info!("IPv6 is not available. Using IPv4 only.");
+        IpMode::V4Only
+      }
+      ip_mode => ip_mode,
+    };
+
     let mut listeners = HashMap::new();
 
-    match UDPListener::new_multicast(
-        DISCOVERY_SENDER_TOKEN,
-        "0.0.0.0",
-        get_spdp_well_known_multicast_port(domain_id),
-        Ipv4Addr::new(239, 255, 0, 1) ) 
-    {
-      Ok(l) => { listeners.insert(DISCOVERY_MUL_LISTENER_TOKEN, l); }
-      Err(e) =>
-        warn!("Cannot get multicast discovery listener: {:?}",e),
+    let spdp_multicast_port = get_spdp_well_known_multicast_port(domain_id);
+    if ip_mode.uses_ipv4() {
+      match UDPListener::new_multicast(
+          DISCOVERY_SENDER_TOKEN,
+          "0.0.0.0",
+          spdp_multicast_port,
+          IpAddr::V4(MULTICAST_GROUP_V4) ) 
+      {
+        Ok(l) => { listeners.insert(DISCOVERY_MUL_LISTENER_TOKEN, l); }
+        Err(e) =>
+          warn!("Cannot get multicast discovery listener: {:?}",e),
+      }
+    }
+    if ip_mode.uses_ipv6() {
+      match Self::new_multicast_listener_v6(DISCOVERY_SENDER_TOKEN, spdp_multicast_port) {
+        Ok(l) => { listeners.insert(DISCOVERY_MUL_LISTENER_V6_TOKEN, l); }
+        Err(e) =>
+          warn!("Cannot get IPv6 multicast discovery listener: {:?}",e),
+      }
     }
-
 
     let mut participant_id = 0;
 
-    let mut discovery_listener = None;
+    let mut discovery_listeners = None;
 
     // Magic value 120 below is from RTPS spec Section "9.6.1.3 Default Port Numbers"
-    while discovery_listener.is_none() && participant_id < 120 {
-      discovery_listener = UDPListener::new_unicast(
+    while discovery_listeners.is_none() && participant_id < 120 {
+      discovery_listeners = Self::new_unicast_listeners(
           DISCOVERY_SENDER_TOKEN,
-          "0.0.0.0",
+          ip_mode,
           get_spdp_well_known_unicast_port(domain_id, participant_id),
-        ).ok();
-      if discovery_listener.is_none() {
+        );
+      if discovery_listeners.is_none() {
         participant_id += 1;
       }
     }
 
     info!("ParticipantId {} selected.", participant_id);
 
-    // here discovery_listener is redefinde (shadowed)
-    let discovery_listener = match discovery_listener {
-      Some(dl) => dl,
+    match discovery_listeners {
+      Some((v4, v6)) => {
+        listeners.extend(v4.map(|l| (DISCOVERY_LISTENER_TOKEN, l)));
+        listeners.extend(v6.map(|l| (DISCOVERY_LISTENER_V6_TOKEN, l)));
+      }
       None => return log_and_err_internal!("Could not find free ParticipantId"),
     };
-    listeners.insert(DISCOVERY_LISTENER_TOKEN, discovery_listener);
 
     // Now the user traffic listeners
 
-    match UDPListener::new_multicast(
-        USER_TRAFFIC_SENDER_TOKEN,
-      "0.0.0.0",
-      get_user_traffic_multicast_port(domain_id),
-      Ipv4Addr::new(239, 255, 0, 1) )
-    {
-      Ok(l) => { listeners.insert(USER_TRAFFIC_MUL_LISTENER_TOKEN, l); }
-      Err(e) =>
-        warn!("Cannot get multicast discovery listener: {:?}",e),
+    let user_traffic_multicast_port = get_user_traffic_multicast_port(domain_id);
+    if ip_mode.uses_ipv4() {
+      match UDPListener::new_multicast(
+          USER_TRAFFIC_SENDER_TOKEN,
+        "0.0.0.0",
+        user_traffic_multicast_port,
+        IpAddr::V4(MULTICAST_GROUP_V4) )
+      {
+        Ok(l) => { listeners.insert(USER_TRAFFIC_MUL_LISTENER_TOKEN, l); }
+        Err(e) =>
+          warn!("Cannot get multicast discovery listener: {:?}",e),
+      }
+    }
+    if ip_mode.uses_ipv6() {
+      match Self::new_multicast_listener_v6(USER_TRAFFIC_SENDER_TOKEN, user_traffic_multicast_port) {
+        Ok(l) => { listeners.insert(USER_TRAFFIC_MUL_LISTENER_V6_TOKEN, l); }
+        Err(e) =>
+          warn!("Cannot get IPv6 multicast user traffic listener: {:?}",e),
+      }
     }
 
-
-    let user_traffic_listener = UDPListener::new_unicast(
+    match Self::new_unicast_listeners(
             USER_TRAFFIC_SENDER_TOKEN,
-            "0.0.0.0",
+            ip_mode,
             get_user_traffic_unicast_port(domain_id, participant_id),
-          );
-    let user_traffic_listener = match user_traffic_listener {
-          Ok(l) => l,
-          Err(e) => return log_and_err_internal!("Could not open user traffic listener: {:?}",e),
-        };
-    listeners.insert(USER_TRAFFIC_LISTENER_TOKEN, user_traffic_listener);
+          ) {
+      Some((v4, v6)) => {
+        listeners.extend(v4.map(|l| (USER_TRAFFIC_LISTENER_TOKEN, l)));
+        listeners.extend(v6.map(|l| (USER_TRAFFIC_LISTENER_V6_TOKEN, l)));
+      }
+      None => return log_and_err_internal!("Could not open user traffic listener"),
+    };
+
+    // Shared memory transport for participants on the same host, unless opted out
+    let shm_transport = if shared_memory {
+      ShmTransport::new()
+        .map_err(|e| info!("Shared memory transport not available: {:?}", e))
+        .ok()
+    } else {
+      None
+    };
+    let shm_locator = shm_transport.as_ref().map(ShmTransport::locator);
 
     // Adding readers
     let (sender_add_reader, receiver_add_reader) = mio_channel::sync_channel::<ReaderIngredients>(100);
@@ -642,6 +870,7 @@ impl DomainParticipant_Inner {
       domain_participant_guid: new_guid,
       domain_id,
       participant_id,
+      ip_mode,
     };
 
     let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
@@ -659,6 +888,7 @@ impl DomainParticipant_Inner {
         let dp_event_loop = DPEventLoop::new(
           domain_info,
           listeners,
+          shm_transport,
           dds_cache_clone,
           disc_db_clone,
           new_guid.guidPrefix,
@@ -690,6 +920,7 @@ impl DomainParticipant_Inner {
     Ok(DomainParticipant_Inner {
       domain_id,
       participant_id,
+      ip_mode,
       my_guid: new_guid,
       // Adding readers
       sender_add_reader,
@@ -703,9 +934,34 @@ impl DomainParticipant_Inner {
       remove_writer_sender,
       dds_cache,
       discovery_db,
+      shm_locator,
     })
   }
 
+  // Unicast listeners for the IP versions in use, or None, if some of them
+  // cannot be bound, e.g. because the port is taken.
+  fn new_unicast_listeners(token: Token, ip_mode: IpMode, port: u16) 
+    -> Option<(Option<UDPListener>, Option<UDPListener>)> 
+  {
+    let v4 = if ip_mode.uses_ipv4() {
+      Some(UDPListener::new_unicast(token, "0.0.0.0", port).ok()?)
+    } else {
+      None
+    };
+    let v6 = if ip_mode.uses_ipv6() {
+      Some(UDPListener::new_unicast(token, "::", port).ok()?)
+    } else {
+      None
+    };
+    Some((v4, v6))
+  }
+
+  fn new_multicast_listener_v6(token: Token, port: u16) -> std::io::Result<UDPListener> {
+    let mut listener = UDPListener::new_multicast(token, "::", port, IpAddr::V6(MULTICAST_GROUP_V6))?;
+    listener.join_multicast(&IpAddr::V6(MULTICAST_GROUP_V6_SITE))?;
+    Ok(listener)
+  }
+
   pub fn get_dds_cache(&self) -> Arc<RwLock<DDSCache>> {
     self.dds_cache.clone()
   }
@@ -730,6 +986,7 @@ impl DomainParticipant_Inner {
     qos: &QosPolicies,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
   ) -> Result<Publisher> {
+    qos.check_supported()?;
     Ok(Publisher::new(
       domain_participant.clone(),
       self.discovery_db.clone(),
@@ -746,6 +1003,7 @@ impl DomainParticipant_Inner {
     qos: &QosPolicies,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
   ) -> Result<Subscriber> {
+    qos.check_supported()?;
     Ok(Subscriber::new(
       domain_participant.clone(),
       self.discovery_db.clone(),
@@ -1027,4 +1285,45 @@ mod tests {
     let locas = vec![loca];
     sender.send_to_locator_list(&_data, &locas);
   }
+
+  #[test]
+  fn discovery_event_queue_drops_oldest() {
+    use crate::dds::statusevents::{DiscoveryEvent, DiscoveryEventQueue};
+    use crate::structure::guid::GuidPrefix;
+
+    let lost = |n: u8| DiscoveryEvent::ParticipantLost {
+      participant: GUID::new(GuidPrefix::new(&[n; 12]), EntityId::ENTITYID_PARTICIPANT),
+    };
+    let mut queue = DiscoveryEventQueue::default();
+    // Nothing is queued, until a stream is requested.
+    assert!(!queue.push(lost(0)));
+    assert!(queue.pop().is_none());
+
+    queue.enable();
+    for n in 1..=102 {
+      assert!(queue.push(lost(n)));
+    }
+    assert!(matches!(queue.pop(), Some(DiscoveryEvent::Lagged { skipped: 2 })));
+    match queue.pop() {
+      Some(DiscoveryEvent::ParticipantLost { participant }) =>
+        assert_eq!(participant.guidPrefix, GuidPrefix::new(&[3; 12])),
+      other => panic!("Unexpected {:?}", other),
+    }
+    assert_eq!(std::iter::from_fn( || queue.pop() ).count(), 99);
+  }
+
+  #[test]
+  fn dp_shared_memory_is_default() {
+    use crate::network::util::IpMode;
+
+    let udp_only = DomainParticipant::new_without_shared_memory(0, IpMode::default())
+      .expect("Participant creation failed!");
+    assert!(udp_only.shm_locator().is_none());
+
+    let with_shm = DomainParticipant::new(0).expect("Participant creation failed!");
+    #[cfg(unix)]
+    assert_eq!(with_shm.shm_locator().map( |l| l.kind ), Some(LocatorKind::LOCATOR_KIND_SHM));
+    #[cfg(not(unix))]
+    assert!(with_shm.shm_locator().is_none());
+  }
 }
@@ -1,10 +1,13 @@
+use mio::{Poll, Events, Token, Ready, PollOpt};
 use mio_extras::channel as mio_channel;
-use log::error;
+#[allow(unused_imports)]
+use log::{error, debug};
 
 use std::{
   fmt::Debug,
-  sync::{RwLock, Arc},
-  time::Duration,
+  sync::{RwLock, Arc, Mutex},
+  time::{Duration, Instant},
+  collections::{BTreeMap, BTreeSet},
 };
 
 use serde::{Serialize, de::DeserializeOwned};
@@ -13,7 +16,7 @@ use byteorder::{LittleEndian};
 
 use crate::{
   discovery::discovery::DiscoveryCommand,
-  structure::{guid::GUID, entity::RTPSEntity, guid::EntityId},
+  structure::{guid::GUID, entity::RTPSEntity, guid::EntityId, time::Timestamp},
 };
 
 use crate::log_and_err_precondition_not_met;
@@ -23,8 +26,13 @@ use crate::dds::{
   participant::*,
   topic::*,
   qos::*,
-  reader::ReaderIngredients,
-  writer::WriterIngredients,
+  reader::{ReaderIngredients, OwnershipCandidates, LostWriters, ReaderHistoryUsage, SampleKeyHasher},
+  content_filter::SampleFilter,
+  writer::{WriterIngredients, ReaderFilters, SharedHistoryUsage},
+  message_batch::MessageBatch,
+  statusevents::DataWriterStatusAccumulator,
+  helpers::TaskWakers,
+  ddsdata::DDSData,
   with_key::datawriter::DataWriter as WithKeyDataWriter,
   no_key::datawriter::DataWriter as NoKeyDataWriter,
   with_key::datareader::DataReader as WithKeyDataReader,
@@ -38,6 +46,7 @@ use crate::{
   discovery::{
     discovery_db::DiscoveryDB,
     data_types::topic_data::DiscoveredWriterData,
+    content_filter_property::ContentFilterProperty,
   },
   structure::topic_kind::TopicKind,
   serialization::cdr_serializer::{CDRSerializerAdapter},
@@ -256,34 +265,111 @@ impl Publisher {
   // lookup datawriter: maybe not necessary? App should remember datawriters it has created.
 
   // Suspend and resume publications are preformance optimization methods.
-  // The minimal correct implementation is to do nothing. See DDS spec 2.2.2.4.1.8 and .9
-  /// Currently does nothing
+  // See DDS spec 2.2.2.4.1.8 and .9
+  /// Suspends sending data. Samples written by the DataWriters of this Publisher are 
+  /// queued until [`resume_publications`](#method.resume_publications). 
+  /// Then they are sent in as few RTPS messages as possible, with many submessages per
+  /// UDP datagram. This reduces the packet rate, when many Topics are updated at once.
+  ///
+  /// Calls can be nested. Sending resumes at the outermost `resume_publications`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().build();
+  /// let publisher = domain_participant.create_publisher(&qos).unwrap();
+  ///
+  /// publisher.suspend_publications().unwrap();
+  /// // write samples here
+  /// publisher.resume_publications().unwrap();
+  /// ```
   pub fn suspend_publications(&self) -> Result<()> {
-    Ok(())
+    self.inner.suspend_publications()
   }
 
-  /// Currently does nothing
+  /// Sends the data queued since [`suspend_publications`](#method.suspend_publications).
+  ///
+  /// Returns `PreconditionNotMet` if there is no matching `suspend_publications` call.
   pub fn resume_publications(&self) -> Result<()> {
-    Ok(())
+    self.inner.resume_publications()
   }
 
   // coherent change set
-  // In case such QoS is not supported, these should be no-ops.
-  // TODO: Implement these when coherent change-sets are supported.
-  /// Coherent set not implemented and currently does nothing
+  /// Begins a coherent set of changes. Samples written by the DataWriters of this Publisher
+  /// until the matching [`end_coherent_changes`](#method.end_coherent_changes) are delivered
+  /// to DataReaders with `coherent_access` only after the whole set has been received.
+  ///
+  /// Calls can be nested. The set ends at the outermost `end_coherent_changes`.
+  /// This does nothing, unless the Presentation QoS of this Publisher has `coherent_access`.
+  ///
+  /// The samples of each DataWriter are a set of their own. Sets spanning several DataWriters,
+  /// i.e. `coherent_access` with access scope GROUP, are not supported: creating a Publisher,
+  /// Subscriber, DataWriter or DataReader with such QoS fails with `Unsupported`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::{QosPolicyBuilder, policy};
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new()
+  ///   .presentation(policy::Presentation {
+  ///     access_scope: policy::PresentationAccessScope::Topic,
+  ///     coherent_access: true,
+  ///     ordered_access: false,
+  ///   })
+  ///   .build();
+  /// let publisher = domain_participant.create_publisher(&qos).unwrap();
+  ///
+  /// publisher.begin_coherent_changes().unwrap();
+  /// // write samples here
+  /// publisher.end_coherent_changes().unwrap();
+  /// ```
   pub fn begin_coherent_changes(&self) -> Result<()> {
-    Ok(())
+    self.inner.begin_coherent_changes()
   }
 
-  /// Coherent set not implemented and currently does nothing
+  /// Ends a coherent set of changes started with
+  /// [`begin_coherent_changes`](#method.begin_coherent_changes).
+  ///
+  /// Returns `PreconditionNotMet` if there is no matching `begin_coherent_changes` call.
   pub fn end_coherent_changes(&self) -> Result<()> {
-    Ok(())
+    self.inner.end_coherent_changes()
   }
 
-  // Wait for all matched reliable DataReaders acknowledge data written so far, or timeout.
-  // TODO: implement
-  pub(crate) fn wait_for_acknowledgments(&self, _max_wait: Duration) -> Result<()> {
-    unimplemented!();
+  /// This operation blocks the calling thread until either all data written by the 
+  /// reliable DataWriters of this Publisher is acknowledged by all matched reliable 
+  /// DataReaders, or else the duration specified by the `max_wait` parameter elapses, 
+  /// whichever happens first.
+  ///
+  /// See DDS Spec 1.4 Section 2.2.2.4.1.12 wait_for_acknowledgments.
+  ///
+  /// This is useful before shutting down, so that written data is not lost.
+  ///
+  /// Return values
+  /// * `Ok(true)` - all acknowledged 
+  /// * `Ok(false)`- timed out waiting for acknowledgments
+  /// * `Err(_)` - something went wrong 
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// #
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().build();
+  /// let publisher = domain_participant.create_publisher(&qos).unwrap();
+  ///
+  /// // create DataWriters and write data here
+  ///
+  /// assert_eq!(publisher.wait_for_acknowledgments(std::time::Duration::from_millis(100)).unwrap(), true);
+  /// ```
+  pub fn wait_for_acknowledgments(&self, max_wait: Duration) -> Result<bool> {
+    self.inner.wait_for_acknowledgments(max_wait)
   }
 
   // What is the use case for this? (is it useful in Rust style of programming? Should it be public?)
@@ -308,6 +394,11 @@ impl Publisher {
 
   // delete_contained_entities: We should not need this. Contained DataWriters should dispose themselves and notify publisher.
 
+  // DataWriter notifies us when it is dropped.
+  pub(crate) fn remove_writer(&self, guid: GUID) {
+    self.inner.remove_writer(guid)
+  }
+
   /// Returns default DataWriter qos. Currently default qos is not used.
   ///
   /// # Example
@@ -373,6 +464,32 @@ struct InnerPublisher {
   default_datawriter_qos: QosPolicies, // used when creating a new DataWriter
   add_writer_sender: mio_channel::SyncSender<WriterIngredients>,
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
+  writers: Arc<Mutex<PublisherWriters>>,
+}
+
+// DataWriters of a Publisher, so that Publisher-wide operations can be
+// sent to each Writer.
+#[derive(Default)]
+struct PublisherWriters {
+  command_senders: BTreeMap<GUID, mio_channel::SyncSender<WriterCommand>>,
+  // Writers with Reliable QoS. Only these get acknowledgments.
+  reliable_writers: BTreeSet<GUID>,
+  // Nesting level of begin_coherent_changes() calls. Zero means no coherent set.
+  coherent_changes_depth: u32,
+  // Nesting level of suspend_publications() calls
+  suspend_depth: u32,
+  // Messages from Writers while publications are suspended
+  suspended_batch: Option<Arc<Mutex<MessageBatch>>>,
+}
+
+impl PublisherWriters {
+  fn send_to_all(&self, make_command: impl Fn() -> WriterCommand) {
+    for (guid, sender) in self.command_senders.iter() {
+      if let Err(e) = sender.send(make_command()) {
+        debug!("Cannot send command to Writer {:?}: {:?}", guid, e);
+      }
+    }
+  }
 }
 
 // public interface for Publisher
@@ -392,6 +509,7 @@ impl InnerPublisher {
       default_datawriter_qos: default_dw_qos,
       add_writer_sender,
       discovery_command,
+      writers: Arc::new(Mutex::new(PublisherWriters::default())),
     }
   }
 
@@ -413,6 +531,10 @@ impl InnerPublisher {
 
     // Status reports back from Writer to DataWriter. 
     let (status_sender, status_receiver) = mio_channel::sync_channel(4);
+    let status_accumulator = Arc::new(Mutex::new(DataWriterStatusAccumulator::new()));
+    let reader_filters = Arc::new(Mutex::new(ReaderFilters::new()));
+    let history_usage = Arc::new(SharedHistoryUsage::new());
+    let data_writer_wakers = TaskWakers::new();
 
    
     // DDS Spec 2.2.2.4.1.5 create_datawriter:
@@ -421,9 +543,17 @@ impl InnerPublisher {
     // Topic QoS and use that.
 
     // Use Publisher QoS as basis, modify by Topic settings, and modify by specified QoS.
-    let writer_qos = self.default_datawriter_qos
+    let mut writer_qos = self.default_datawriter_qos
       .modify_by(&topic.get_qos())
       .modify_by(&optional_qos.unwrap_or_else( QosPolicies::qos_none ));
+    // Presentation and Partition are Publisher policies, so they apply to all its DataWriters.
+    if let Some(presentation) = self.my_qos_policies.presentation {
+      writer_qos.presentation = Some(presentation);
+    }
+    if let Some(partition) = self.my_qos_policies.partition.as_ref() {
+      writer_qos.partition = Some(partition.clone());
+    }
+    writer_qos.check_supported()?;
 
     let entity_id = unwrap_or_random_EntityId(entity_id_opt, EntityKind::WRITER_WITH_KEY_USER_DEFINED);
     let dp = self.get_participant()
@@ -436,21 +566,48 @@ impl InnerPublisher {
         guid,
         writer_command_receiver: hccc_download,
         topic_name: topic.get_name(),
-        qos_policies: writer_qos,
+        qos_policies: writer_qos.clone(),
         status_sender,
+        status_accumulator: status_accumulator.clone(),
+        reader_filters: reader_filters.clone(),
+        history_usage: history_usage.clone(),
+        data_writer_wakers: data_writer_wakers.clone(),
       };
 
     self.add_writer_sender.send(new_writer)
       .or_else(|e| log_and_err_internal!("Adding a new writer failed: {}",e))?;
 
+    {
+      let mut writers = self.writers.lock()?;
+      // A Writer created in the middle of a coherent set joins the set.
+      if writers.coherent_changes_depth > 0 {
+        dwcc_upload.send(WriterCommand::BeginCoherentSet)
+          .or_else(|e| log_and_err_internal!("Cannot begin coherent set: {}",e))?;
+      }
+      // Likewise for suspended publications
+      if let Some(ref batch) = writers.suspended_batch {
+        dwcc_upload.send(WriterCommand::SuspendPublications { batch: batch.clone() })
+          .or_else(|e| log_and_err_internal!("Cannot suspend publications: {}",e))?;
+      }
+      writers.command_senders.insert(guid, dwcc_upload.clone());
+      if matches!(writer_qos.reliability(), Some(policy::Reliability::Reliable{..})) {
+        writers.reliable_writers.insert(guid);
+      }
+    }
+
     let data_writer = WithKeyDataWriter::<D, SA>::new(
           outer.clone(),
           topic.clone(),
+          writer_qos,
           Some(guid),
           dwcc_upload,
           self.discovery_command.clone(),
           dp.get_dds_cache(),
           status_receiver,
+          status_accumulator,
+          reader_filters,
+          history_usage,
+          data_writer_wakers,
         )?;
 
     // notify Discovery DB
@@ -488,23 +645,119 @@ impl InnerPublisher {
   }
 
   pub fn suspend_publications(&self) -> Result<()> {
+    let mut writers = self.writers.lock()?;
+    writers.suspend_depth += 1;
+    if writers.suspend_depth == 1 {
+      let batch = Arc::new(Mutex::new(MessageBatch::new()));
+      writers.send_to_all( || WriterCommand::SuspendPublications { batch: batch.clone() } );
+      writers.suspended_batch = Some(batch);
+    }
     Ok(())
   }
 
   pub fn resume_publications(&self) -> Result<()> {
-    Ok(())
+    let mut writers = self.writers.lock()?;
+    match writers.suspend_depth {
+      0 => log_and_err_precondition_not_met!("resume_publications() without suspend_publications()"),
+      1 => {
+        writers.suspend_depth = 0;
+        // Writers send the batch, when all of them have resumed.
+        if let Some(batch) = writers.suspended_batch.take() {
+          batch.lock()?.resume();
+        }
+        writers.send_to_all( || WriterCommand::ResumePublications );
+        Ok(())
+      }
+      _ => {
+        writers.suspend_depth -= 1;
+        Ok(())
+      }
+    }
+  }
+
+  pub(crate) fn remove_writer(&self, guid: GUID) {
+    match self.writers.lock() {
+      Ok(mut writers) => {
+        writers.command_senders.remove(&guid);
+        writers.reliable_writers.remove(&guid);
+      }
+      Err(e) => error!("remove_writer: {:?}", e),
+    }
+  }
+
+  fn coherent_access(&self) -> bool {
+    self.my_qos_policies.presentation()
+      .map( |p| p.coherent_access )
+      .unwrap_or(false)
   }
 
   pub fn begin_coherent_changes(&self) -> Result<()> {
+    if ! self.coherent_access() {
+      return Ok(())
+    }
+    let mut writers = self.writers.lock()?;
+    writers.coherent_changes_depth += 1;
+    if writers.coherent_changes_depth == 1 {
+      writers.send_to_all( || WriterCommand::BeginCoherentSet );
+    }
     Ok(())
   }
 
   pub fn end_coherent_changes(&self) -> Result<()> {
-    Ok(())
+    if ! self.coherent_access() {
+      return Ok(())
+    }
+    let mut writers = self.writers.lock()?;
+    match writers.coherent_changes_depth {
+      0 => log_and_err_precondition_not_met!("end_coherent_changes() without begin_coherent_changes()"),
+      1 => {
+        writers.coherent_changes_depth = 0;
+        writers.send_to_all( || WriterCommand::EndCoherentSet );
+        Ok(())
+      }
+      _ => {
+        writers.coherent_changes_depth -= 1;
+        Ok(())
+      }
+    }
   }
 
-  pub(crate) fn wait_for_acknowledgments(&self, _max_wait: Duration) -> Result<()> {
-    unimplemented!();
+  pub fn wait_for_acknowledgments(&self, max_wait: Duration) -> Result<bool> {
+    let deadline = Instant::now() + max_wait;
+    let mut pending = 0;
+    let acked_receiver = {
+      let writers = self.writers.lock()?;
+      // Each reliable Writer replies once, when all its data is acknowledged. Best-effort
+      // Writers get no acknowledgments, so there is nothing to wait for.
+      let (acked_sender, acked_receiver) = 
+        mio_channel::sync_channel::<()>(writers.reliable_writers.len().max(1));
+      for (guid, sender) in writers.command_senders.iter()
+            .filter( |(guid, _)| writers.reliable_writers.contains(guid) ) {
+        match sender.send(WriterCommand::WaitForAcknowledgments { all_acked: acked_sender.clone(), 
+                                                                 deadline: Some(deadline) }) {
+          Ok(_) => pending += 1,
+          Err(e) => debug!("Cannot ask Writer {:?} to wait for acknowledgments: {:?}", guid, e),
+        }
+      }
+      acked_receiver
+    };
+
+    let poll = Poll::new()?;
+    poll.register(&acked_receiver, Token(0), Ready::readable(), PollOpt::edge() )?;
+    let mut events = Events::with_capacity(1);
+    loop {
+      while pending > 0 && acked_receiver.try_recv().is_ok() {
+        pending -= 1;
+      }
+      if pending == 0 {
+        return Ok(true)
+      }
+      let now = Instant::now();
+      if now >= deadline {
+        return Ok(false) // timed out
+      }
+      poll.poll(&mut events, Some(deadline - now) )?;
+    }
   }
 
   pub fn get_participant(&self) -> Option<DomainParticipant> {
@@ -635,7 +888,7 @@ impl Subscriber {
     SA: with_key::DeserializerAdapter<D>,
   {
     self.inner
-      .create_datareader(self,topic,None,qos)
+      .create_datareader(self,topic,None,qos,None)
   }
 
   pub fn create_datareader_CDR<D: 'static>(
@@ -664,7 +917,7 @@ impl Subscriber {
     SA: with_key::DeserializerAdapter<D>,
   {
     self.inner
-      .create_datareader(self,topic,Some(entity_id),qos)
+      .create_datareader(self,topic,Some(entity_id),qos,None)
   }
 
   pub(crate) fn create_datareader_CDR_with_entityid<D: 'static>(
@@ -721,7 +974,7 @@ impl Subscriber {
     SA: no_key::DeserializerAdapter<D>,
   {
     self.inner
-      .create_datareader_no_key(self,topic,None,qos)
+      .create_datareader_no_key(self,topic,None,qos,None)
   }
 
   pub fn create_datareader_no_key_CDR<D: 'static>(&self,
@@ -744,7 +997,7 @@ impl Subscriber {
     SA: no_key::DeserializerAdapter<D>,
   {
     self.inner
-      .create_datareader_no_key(self,topic,Some(entity_id),qos)
+      .create_datareader_no_key(self,topic,Some(entity_id),qos,None)
   }
 
   pub(crate) fn create_datareader_no_key_CDR_with_entityid<D: 'static>(&self,
@@ -758,6 +1011,74 @@ impl Subscriber {
     self.create_datareader_no_key_with_entityid::<D,CDRDeserializerAdapter<D>>(topic,entity_id,qos)
   }
 
+  /// Create a DataReader for a [ContentFilteredTopic](struct.ContentFilteredTopic.html)
+  ///
+  /// The DataReader gets only the samples of the related Topic that pass the filter. 
+  /// The filter examines the samples through their `Serialize` implementation, so the
+  /// data type must implement both `Serialize` and `Deserialize`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// use serde::{Serialize, Deserialize};
+  /// use rustdds::serialization::CDRDeserializerAdapter;
+  /// use rustdds::dds::traits::Keyed;
+  /// #
+  /// # let domain_participant = DomainParticipant::new(0).unwrap();
+  /// # let qos = QosPolicyBuilder::new().build();
+  /// let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  ///
+  /// #[derive(Serialize, Deserialize)]
+  /// struct SomeType { a: i32, b: String }
+  /// impl Keyed for SomeType {
+  ///   type K = i32;
+  ///   fn get_key(&self) -> Self::K { self.a }
+  /// }
+  ///
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let filtered_topic = domain_participant
+  ///   .create_contentfilteredtopic("some_filtered_topic", &topic, "a > %0 AND b LIKE 'x%'", &["10".to_string()])
+  ///   .unwrap();
+  /// let data_reader = subscriber
+  ///   .create_filtered_datareader::<SomeType, CDRDeserializerAdapter<_>>(filtered_topic, None);
+  /// ```
+  pub fn create_filtered_datareader<D, SA>(
+    &self,
+    topic: ContentFilteredTopic,
+    qos: Option<QosPolicies>,
+  ) -> Result<WithKeyDataReader<D, SA>>
+  where
+    D: 'static + DeserializeOwned + Serialize + Keyed,
+    <D as Keyed>::K: Key,
+    SA: with_key::DeserializerAdapter<D>,
+  {
+    let content_filter = 
+      (topic.content_filter_property(), topic.content_filter().clone().into_sample_filter());
+    self.inner
+      .create_datareader(self,topic.get_related_topic(),None,qos,Some(content_filter))
+  }
+
+  /// Create a NoKey DataReader for a [ContentFilteredTopic](struct.ContentFilteredTopic.html)
+  ///
+  /// See [`create_filtered_datareader`](#method.create_filtered_datareader).
+  pub fn create_filtered_datareader_no_key<D, SA>(
+    &self,
+    topic: ContentFilteredTopic,
+    qos: Option<QosPolicies>,
+  ) -> Result<NoKeyDataReader<D, SA>>
+  where
+    D: 'static + DeserializeOwned + Serialize,
+    SA: no_key::DeserializerAdapter<D>,
+  {
+    let content_filter = 
+      (topic.content_filter_property(), topic.content_filter().clone().into_sample_filter());
+    self.inner
+      .create_datareader_no_key(self,topic.get_related_topic(),None,qos,Some(content_filter))
+  }
+
 
   // Retrieves a previously created DataReader belonging to the Subscriber.
   // TODO: Is this even possible. Whould probably need to return reference and store references on creation
@@ -794,10 +1115,154 @@ impl Subscriber {
   pub fn get_participant(&self) -> Option<DomainParticipant> {
     self.inner.get_participant()
   }
+
+  /// Indicates that the application is about to access samples in the DataReaders of
+  /// this Subscriber.
+  ///
+  /// With Presentation QoS access scope GROUP and `ordered_access`, this must be called
+  /// before [`get_datareader_guids`](#method.get_datareader_guids). While access is in progress, the
+  /// DataReaders do not make visible any samples received after the (outermost)
+  /// `begin_access` call, so that the order given by `get_datareader_guids` stays valid.
+  ///
+  /// Calls can be nested.
+  pub fn begin_access(&self) -> Result<()> {
+    self.inner.begin_access()
+  }
+
+  /// Ends access started with [`begin_access`](#method.begin_access).
+  ///
+  /// Returns `PreconditionNotMet` if there is no matching `begin_access` call.
+  pub fn end_access(&self) -> Result<()> {
+    self.inner.end_access()
+  }
+
+  /// Returns the GUIDs of the DataReaders of this Subscriber that have unread samples.
+  ///
+  /// With Presentation QoS access scope GROUP and `ordered_access`, a DataReader is listed
+  /// once for each unread sample, in the order the samples were written. Taking (or reading)
+  /// one sample at a time from the DataReaders in the listed order accesses the samples in
+  /// the order they were written across all the DataReaders. This must be called between
+  /// [`begin_access`](#method.begin_access) and [`end_access`](#method.end_access).
+  ///
+  /// Otherwise, each DataReader with unread samples is listed once.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::{QosPolicyBuilder, policy};
+  /// # use rustdds::dds::data_types::{TopicKind, ReadCondition};
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::dds::traits::RTPSEntity;
+  /// # use rustdds::serialization::CDRDeserializerAdapter;
+  /// #
+  /// # #[derive(Serialize, Deserialize)]
+  /// # struct SomeType { a: i32 }
+  /// # impl Keyed for SomeType {
+  /// #   type K = i32;
+  /// #   fn get_key(&self) -> Self::K { self.a }
+  /// # }
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new()
+  ///   .presentation(policy::Presentation {
+  ///     access_scope: policy::PresentationAccessScope::Group,
+  ///     coherent_access: false,
+  ///     ordered_access: true,
+  ///   })
+  ///   .build();
+  /// let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  /// let topic_a = domain_participant.create_topic("topic_a", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let topic_b = domain_participant.create_topic("topic_b", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let mut reader_a = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic_a, None).unwrap();
+  /// let mut reader_b = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic_b, None).unwrap();
+  ///
+  /// subscriber.begin_access().unwrap();
+  /// for reader_guid in subscriber.get_datareader_guids().unwrap() {
+  ///   let sample = 
+  ///     if reader_guid == reader_a.get_guid() { reader_a.take_next_sample() } 
+  ///     else { reader_b.take_next_sample() };
+  ///   // process sample
+  /// }
+  /// subscriber.end_access().unwrap();
+  /// ```
+  pub fn get_datareader_guids(&self) -> Result<Vec<GUID>> {
+    self.inner.get_datareader_guids()
+  }
+
+  // DataReaders report to Subscriber what they have, so that Subscriber
+  // can answer get_datareader_guids().
+  pub(crate) fn add_reader(&self, guid: GUID, topic_name: String, latest_instant: Timestamp) {
+    self.inner.with_access( |access| {
+      access.readers.insert(guid, ReaderAccessInfo { 
+        topic_name, 
+        unread: BTreeMap::new(), 
+        latest_instant, 
+      });
+    })
+  }
+
+  pub(crate) fn remove_reader(&self, guid: GUID) {
+    self.inner.with_access( |access| { access.readers.remove(&guid); } )
+  }
+
+  // DataReader has fetched samples from DDSCache up to latest_instant, 
+  // and these are its unread samples.
+  pub(crate) fn update_reader_access(&self, guid: GUID, latest_instant: Timestamp, 
+      unread: Vec<(Timestamp, Timestamp)>) 
+  {
+    self.inner.with_access( |access| {
+      if let Some(info) = access.readers.get_mut(&guid) {
+        info.latest_instant = latest_instant;
+        info.unread = unread.into_iter().collect();
+      }
+    })
+  }
+
+  // DataReader is about to read or take these samples.
+  pub(crate) fn reader_samples_accessed(&self, guid: GUID, 
+      receive_timestamps: impl Iterator<Item=Timestamp>) 
+  {
+    self.inner.with_access( |access| {
+      if let Some(info) = access.readers.get_mut(&guid) {
+        for ts in receive_timestamps {
+          info.unread.remove(&ts);
+        }
+      }
+    })
+  }
+
+  // If access is in progress, DataReaders must not make visible samples received after this.
+  pub(crate) fn access_snapshot(&self) -> Option<Timestamp> {
+    self.inner.access_snapshot()
+  }
+}
+
+// DataReaders of a Subscriber and the state of begin_access() / end_access().
+#[derive(Default)]
+struct SubscriberAccess {
+  readers: BTreeMap<GUID, ReaderAccessInfo>,
+  // Nesting level of begin_access() calls. Zero means no access in progress.
+  access_depth: u32,
+  // Receive instant of the latest change visible during access
+  access_snapshot: Option<Timestamp>,
+}
+
+struct ReaderAccessInfo {
+  topic_name: String,
+  // NOT_READ samples in the DataReader: Receive instant -> source timestamp, or 
+  // receive instant, if the sample has no source timestamp.
+  unread: BTreeMap<Timestamp, Timestamp>,
+  // Changes in DDSCache received after this have not been fetched to the DataReader yet.
+  latest_instant: Timestamp,
 }
 
 
 
+// Filter of a DataReader created for a ContentFilteredTopic: The filter as announced in
+// Discovery, and as applied to received samples.
+type ReaderContentFilter<D> = (ContentFilterProperty, SampleFilter<D>);
+
 #[derive(Clone)]
 pub struct InnerSubscriber {
   domain_participant: DomainParticipantWeak,
@@ -806,6 +1271,7 @@ pub struct InnerSubscriber {
   sender_add_reader: mio_channel::SyncSender<ReaderIngredients>,
   sender_remove_reader: mio_channel::SyncSender<GUID>,
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
+  access: Arc<Mutex<SubscriberAccess>>,
 }
 
 impl InnerSubscriber {
@@ -824,6 +1290,92 @@ impl InnerSubscriber {
       sender_add_reader,
       sender_remove_reader,
       discovery_command,
+      access: Arc::new(Mutex::new(SubscriberAccess::default())),
+    }
+  }
+
+  fn with_access(&self, f: impl FnOnce(&mut SubscriberAccess)) {
+    match self.access.lock() {
+      Ok(mut access) => f(&mut access),
+      Err(e) => error!("Subscriber access state is poisoned: {:?}", e),
+    }
+  }
+
+  // Is Presentation access scope GROUP with ordered_access?
+  fn group_ordered_access(&self) -> bool {
+    match self.qos.presentation() {
+      Some(policy::Presentation { access_scope: policy::PresentationAccessScope::Group, 
+                                  ordered_access, .. }) => ordered_access,
+      _ => false,
+    }
+  }
+
+  pub fn begin_access(&self) -> Result<()> {
+    let mut access = self.access.lock()?;
+    if access.access_depth == 0 {
+      access.access_snapshot = Some(Timestamp::now());
+    }
+    access.access_depth += 1;
+    Ok(())
+  }
+
+  pub fn end_access(&self) -> Result<()> {
+    let mut access = self.access.lock()?;
+    match access.access_depth {
+      0 => log_and_err_precondition_not_met!("end_access() without begin_access()"),
+      1 => {
+        access.access_depth = 0;
+        access.access_snapshot = None;
+        Ok(())
+      }
+      _ => {
+        access.access_depth -= 1;
+        Ok(())
+      }
+    }
+  }
+
+  pub fn access_snapshot(&self) -> Option<Timestamp> {
+    if ! self.group_ordered_access() {
+      return None
+    }
+    self.access.lock().ok()
+      .and_then( |access| access.access_snapshot )
+  }
+
+  pub fn get_datareader_guids(&self) -> Result<Vec<GUID>> {
+    let group_ordered = self.group_ordered_access();
+    let access = self.access.lock()?;
+    if group_ordered && access.access_depth == 0 {
+      return log_and_err_precondition_not_met!("get_datareader_guids() with GROUP ordered access requires begin_access()")
+    }
+    let dp = match self.get_participant() {
+      Some(dp) => dp,
+      None => return 
+        log_and_err_precondition_not_met!("DomainParticipant doesn't exist anymore.") ,
+    };
+    let dds_cache = dp.get_dds_cache();
+    let dds_cache = dds_cache.read()
+      .or_else(|e| log_and_err_internal!("Cannot lock DDScache. Error: {}",e))?;
+    let until = access.access_snapshot.unwrap_or_else(Timestamp::now);
+
+    // (order timestamp, receive instant, DataReader) for each sample
+    let mut samples : Vec<(Timestamp, Timestamp, GUID)> = Vec::new();
+    for (reader_guid, info) in access.readers.iter() {
+      samples.extend( info.unread.iter().map( |(recv, order)| (*order, *recv, *reader_guid) ) );
+      // Samples not yet fetched by the DataReader
+      samples.extend( 
+        dds_cache.from_topic_get_changes_in_range(&info.topic_name, &info.latest_instant, &until)
+          .filter( |(_, cc)| cc.data_value != DDSData::CoherentSetEnd )
+          .map( |(recv, cc)| (cc.source_timestamp.unwrap_or(recv), recv, *reader_guid) ) );
+    }
+
+    if group_ordered {
+      samples.sort();
+      Ok( samples.into_iter().map( |(_, _, guid)| guid ).collect() )
+    } else {
+      let readers : BTreeSet<GUID> = samples.into_iter().map( |(_, _, guid)| guid ).collect();
+      Ok( readers.into_iter().collect() )
     }
   }
 
@@ -834,6 +1386,7 @@ impl InnerSubscriber {
     entity_id_opt: Option<EntityId>,
     topic: Topic,
     optional_qos: Option<QosPolicies>,
+    content_filter: Option<ReaderContentFilter<D>>,
   ) -> Result<WithKeyDataReader<D, SA>>
   where
     D: DeserializeOwned + Keyed,
@@ -849,9 +1402,17 @@ impl InnerSubscriber {
       mio_channel::sync_channel::<ReaderCommand>(4);
 
     // Use subscriber QoS as basis, modify by Topic settings, and modify by specified QoS.
-    let qos = self.qos
+    let mut qos = self.qos
       .modify_by(&topic.get_qos())
       .modify_by(&optional_qos.unwrap_or_else( QosPolicies::qos_none ));
+    // Presentation and Partition are Subscriber policies, so they apply to all its DataReaders.
+    if let Some(presentation) = self.qos.presentation {
+      qos.presentation = Some(presentation);
+    }
+    if let Some(partition) = self.qos.partition.as_ref() {
+      qos.partition = Some(partition.clone());
+    }
+    qos.check_supported()?;
 
     let entity_id = unwrap_or_random_EntityId(entity_id_opt, EntityKind::READER_WITH_KEY_USER_DEFINED);
 
@@ -866,6 +1427,17 @@ impl InnerSubscriber {
 
     let reader_guid = GUID::new_with_prefix_and_id(dp.get_guid_prefix(), reader_id);
 
+    let ownership_candidates = Arc::new(Mutex::new(OwnershipCandidates::new()));
+    let lost_writers = Arc::new(Mutex::new(LostWriters::new()));
+    // With ResourceLimits, the Reader needs to know the instance of each received sample.
+    let key_hasher = qos.resource_limits()
+      .map( |_| WithKeyDataReader::<D, SA>::sample_key_hash as SampleKeyHasher );
+    let history_usage = Arc::new(Mutex::new(ReaderHistoryUsage::new(key_hasher)));
+    let data_reader_wakers = TaskWakers::new();
+    let (content_filter_property, sample_filter) = match content_filter {
+      Some((property, filter)) => (Some(property), Some(filter)),
+      None => (None, None),
+    };
     let new_reader = ReaderIngredients {
       guid: reader_guid,
       notification_sender: send,
@@ -873,12 +1445,16 @@ impl InnerSubscriber {
       topic_name: topic.get_name(),
       qos_policy: qos.clone(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: ownership_candidates.clone(),
+      lost_writers: lost_writers.clone(),
+      history_usage: history_usage.clone(),
+      data_reader_wakers: data_reader_wakers.clone(),
     };
 
     {
       let mut db = self.discovery_db.write()
                 .or_else(|e| log_and_err_internal!("Cannot lock discovery_db. {}",e))?;
-      db.update_local_topic_reader(&dp, &topic, &new_reader);
+      db.update_local_topic_reader(&dp, &topic, &new_reader, content_filter_property);
       db.update_topic_data_p(&topic);
     }
 
@@ -892,6 +1468,11 @@ impl InnerSubscriber {
       self.discovery_command.clone(),
       status_receiver,
       reader_command_sender,
+      ownership_candidates,
+      lost_writers,
+      history_usage,
+      sample_filter,
+      data_reader_wakers,
     )?;
 
     // Create new topic to DDScache if one isn't present
@@ -919,6 +1500,7 @@ impl InnerSubscriber {
     topic: Topic,
     entity_id: Option<EntityId>,
     qos: Option<QosPolicies>,
+    content_filter: Option<ReaderContentFilter<D>>,
   ) -> Result<WithKeyDataReader<D, SA>>
   where
     D: DeserializeOwned + Keyed,
@@ -928,7 +1510,7 @@ impl InnerSubscriber {
     if topic.kind() != TopicKind::WithKey {
       return Error::precondition_not_met("Topic is NO_KEY, but attempted to create WITH_KEY Datareader") 
     }
-    self.create_datareader_internal(outer, entity_id, topic, qos)
+    self.create_datareader_internal(outer, entity_id, topic, qos, content_filter)
   }
 
   pub fn create_datareader_no_key<D: 'static, SA>(
@@ -937,6 +1519,7 @@ impl InnerSubscriber {
     topic: Topic,
     entity_id_opt: Option<EntityId>,
     qos: Option<QosPolicies>,
+    content_filter: Option<ReaderContentFilter<D>>,
   ) -> Result<NoKeyDataReader<D, SA>>
   where
     D: DeserializeOwned,
@@ -948,11 +1531,18 @@ impl InnerSubscriber {
 
     let entity_id = unwrap_or_random_EntityId(entity_id_opt, EntityKind::READER_NO_KEY_USER_DEFINED);
 
+    // The filter sees the data, not the wrapper
+    let content_filter = content_filter.map( |(property, filter)| {
+      let wrapper_filter: SampleFilter<NoKeyWrapper<D>> = Box::new( move |w| filter(&w.d) );
+      (property, wrapper_filter)
+    });
+
     let d = self.create_datareader_internal::<NoKeyWrapper<D>, DAWrapper<SA>>(
       outer,
       Some(entity_id),
       topic,
       qos,
+      content_filter,
     )?;
 
     Ok(NoKeyDataReader::<D, SA>::from_keyed(d))
@@ -974,4 +1564,247 @@ fn unwrap_or_random_EntityId(entity_id_opt: Option<EntityId>, entityKind: Entity
 // -------------------------------------------------------------------
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+  use super::*;
+  use crate::dds::{
+    qos::policy,
+    data_types::{TopicKind, ReadCondition},
+    sampleinfo::InstanceState,
+    statusevents::{DataReaderStatus, DataWriterStatus},
+  };
+  use crate::test::{random_data::*, wait_util::*};
+  use crate::serialization::{CDRSerializerAdapter, CDRDeserializerAdapter};
+  use crate::structure::duration::Duration as DDSDuration;
+
+  #[test]
+  fn publisher_wait_for_acknowledgments() {
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: DDSDuration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .build();
+
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(7).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    // No DataWriters, nothing to wait for
+    assert!(publisher.wait_for_acknowledgments(Duration::from_millis(10)).unwrap());
+
+    let dp_r = DomainParticipant::new(7).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+
+    let mut writers = Vec::new();
+    let mut readers = Vec::new();
+    for topic_name in &["publisher_ack_test_a", "publisher_ack_test_b"] {
+      let topic_w = dp_w
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      writers.push( publisher
+        .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+        .unwrap() );
+      let topic_r = dp_r
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      readers.push( subscriber
+        .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+        .unwrap() );
+    }
+    // A best-effort DataWriter gets no acknowledgments, and must not be waited for.
+    let best_effort_qos = QosPolicyBuilder::new().reliability(policy::Reliability::BestEffort).build();
+    let best_effort_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(
+        dp_w.create_topic("publisher_ack_test_best_effort", "RandomData", &qos, TopicKind::WithKey)
+          .unwrap(),
+        Some(best_effort_qos.clone()))
+      .unwrap();
+    readers.push( subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(
+        dp_r.create_topic("publisher_ack_test_best_effort", "RandomData", &qos, TopicKind::WithKey)
+          .unwrap(),
+        Some(best_effort_qos))
+      .unwrap() );
+
+    for data_reader in readers.iter_mut() {
+      assert!(wait_for_status(data_reader, Duration::from_secs(10),
+        |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+    }
+    for data_writer in writers.iter_mut() {
+      assert!(wait_for_status(data_writer, Duration::from_secs(10),
+        |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    }
+
+    for (i, data_writer) in writers.iter().enumerate() {
+      data_writer.write(RandomData { a: i as i64, b: "data".to_string() }, None).unwrap();
+    }
+    best_effort_writer.write(RandomData { a: 9, b: "data".to_string() }, None).unwrap();
+    assert!(publisher.wait_for_acknowledgments(Duration::from_secs(10)).unwrap());
+  }
+
+  #[test]
+  fn publisher_suspend_publications() {
+    // Own domain, so that tests running in parallel do not interfere.
+    check_suspend_publications(policy::Reliability::BestEffort, 8);
+  }
+
+  #[test]
+  fn publisher_suspend_publications_reliable() {
+    // Reliable Writers must not send HEARTBEATs or repairs of the suspended data either.
+    check_suspend_publications(
+      policy::Reliability::Reliable { max_blocking_time: crate::structure::duration::Duration::DURATION_ZERO }, 
+      9);
+  }
+
+  fn check_suspend_publications(reliability: policy::Reliability, domain_id: u16) {
+    let qos = QosPolicyBuilder::new()
+      .reliability(reliability)
+      .history(policy::History::KeepAll)
+      .build();
+
+    let dp_w = DomainParticipant::new(domain_id).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    assert!(publisher.resume_publications().is_err()); // not suspended
+    let dp_r = DomainParticipant::new(domain_id).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+
+    let mut writers = Vec::new();
+    let mut readers = Vec::new();
+    for topic_name in &["publisher_suspend_test_a", "publisher_suspend_test_b"] {
+      let topic_w = dp_w
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      writers.push( publisher
+        .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+        .unwrap() );
+      let topic_r = dp_r
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      readers.push( subscriber
+        .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+        .unwrap() );
+    }
+
+    for data_reader in readers.iter_mut() {
+      assert!(wait_for_status(data_reader, Duration::from_secs(10),
+        |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+    }
+    for data_writer in writers.iter_mut() {
+      assert!(wait_for_status(data_writer, Duration::from_secs(10),
+        |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    }
+
+    publisher.suspend_publications().unwrap();
+    publisher.suspend_publications().unwrap(); // nested
+    for (i, data_writer) in writers.iter().enumerate() {
+      data_writer.write(RandomData { a: i as i64, b: "data".to_string() }, None).unwrap();
+    }
+    // Nothing is sent while suspended. Waiting longer than the heartbeat period gives
+    // a reliable Writer the chance to announce and repair the data, if it would.
+    publisher.resume_publications().unwrap();
+    for data_reader in readers.iter_mut() {
+      assert!(wait_for_sample(data_reader, Duration::from_millis(1500)).is_none());
+    }
+
+    publisher.resume_publications().unwrap();
+    for data_reader in readers.iter_mut() {
+      assert!(wait_for_sample(data_reader, Duration::from_secs(10)).is_some());
+    }
+  }
+
+  #[test]
+  fn partition_matching() {
+    let qos = QosPolicyBuilder::new().build();
+    let dp_w = DomainParticipant::new(0).expect("Participant creation failed");
+    let publisher = dp_w
+      .create_publisher(&QosPolicyBuilder::new()
+        .partition(policy::Partition::new(&["sensor_front"]))
+        .build())
+      .unwrap();
+    let topic_w = dp_w
+      .create_topic("partition_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+
+    let dp_r = DomainParticipant::new(0).expect("Participant creation failed");
+    let topic_r = dp_r
+      .create_topic("partition_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut readers = Vec::new();
+    for partitions in &[ vec!["sensor*"], vec!["robot", "sensor_front"], vec!["robot"], vec![] ] {
+      let subscriber = dp_r
+        .create_subscriber(&QosPolicyBuilder::new()
+          .partition(policy::Partition::new(partitions))
+          .build())
+        .unwrap();
+      let data_reader = subscriber
+        .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r.clone(), None)
+        .unwrap();
+      readers.push(data_reader);
+    }
+
+    for _ in 0..50 {
+      if data_writer.get_matched_subscriptions().len() >= 2 {
+        break
+      }
+      std::thread::sleep(Duration::from_millis(100));
+    }
+    // Give also the non-matching readers a chance to be discovered
+    std::thread::sleep(Duration::from_millis(500));
+    let mut matched: Vec<GUID> = data_writer.get_matched_subscriptions().iter()
+      .map( |s| s.key() ).collect();
+    matched.sort();
+    let mut expected = vec![readers[0].get_guid(), readers[1].get_guid()];
+    expected.sort();
+    assert_eq!(matched, expected);
+  }
+
+  #[test]
+  fn liveliness_manual_by_topic() {
+    let qos = QosPolicyBuilder::new()
+      .liveliness(policy::Liveliness::ManualByTopic { lease_duration: DDSDuration::from_millis(500) })
+      .build();
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(15).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("liveliness_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+
+    let dp_r = DomainParticipant::new(15).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("liveliness_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+      .unwrap();
+
+    assert!(wait_for_status(&mut data_reader, Duration::from_secs(10),
+      |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+    assert!(wait_for_status(&mut data_writer, Duration::from_secs(10),
+      |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    let data = RandomData { a: 1, b: "alive".to_string() };
+    data_writer.write(data.clone(), None).unwrap();
+
+    // LivelinessChanged with the given (alive, not_alive) counts
+    let liveliness = |alive, not_alive| move |status: &DataReaderStatus| match status {
+      DataReaderStatus::LivelinessChanged { alive_total, not_alive_total } =>
+        alive_total.count() == alive && not_alive_total.count() == not_alive,
+      _ => false,
+    };
+
+    // No more writes or assertions, so the lease expires.
+    assert!(wait_for_status(&mut data_reader, Duration::from_secs(5), liveliness(0, 1)).is_some());
+    assert!(wait_for_status(&mut data_writer, Duration::from_secs(5),
+      |s| matches!(s, DataWriterStatus::LivelinessLost{..}) ).is_some());
+    let samples = data_reader.read(10, ReadCondition::any()).unwrap();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].sample_info().instance_state, InstanceState::NotAlive_NoWriters);
+
+    data_writer.assert_liveliness().unwrap();
+    assert!(wait_for_status(&mut data_reader, Duration::from_secs(5), liveliness(1, 0)).is_some());
+  }
+}
@@ -1,4 +1,4 @@
-use log::{trace,};
+use log::{trace, error};
 
 use crate::{
   dds::values::result::*,
@@ -6,8 +6,9 @@ use crate::{
   messages::submessages::submessage_elements::{
     parameter_list::ParameterList, RepresentationIdentifier,
   },
-  structure::{parameter_id::ParameterId, inline_qos::StatusInfo},
+  structure::{parameter_id::ParameterId, inline_qos::StatusInfo, sequence_number::SequenceNumber},
 };
+use speedy::{Endianness, Readable};
 
 // This is to be implemented by all DomanParticipant, Publisher, Subscriber, DataWriter, DataReader, Topic
 /// Trait that is implemented by all necessary DDS Entities that are required to provide QosPolicies.
@@ -34,7 +35,7 @@ pub enum QosPolicyId {
   //OwnershipStrength, // 7
   Liveliness,
   TimeBasedFilter, // 9
-  //Partition,
+  Partition,
   Reliability, // 11
   DestinationOrder,
   History, // 13
@@ -63,6 +64,7 @@ pub struct QosPolicyBuilder {
   history: Option<policy::History>,
   resource_limits: Option<policy::ResourceLimits>,
   lifespan: Option<policy::Lifespan>,
+  partition: Option<policy::Partition>,
 }
 
 impl QosPolicyBuilder {
@@ -80,6 +82,7 @@ impl QosPolicyBuilder {
       history: None,
       resource_limits: None,
       lifespan: None,
+      partition: None,
     }
   }
 
@@ -152,7 +155,12 @@ impl QosPolicyBuilder {
     self
   }
 
-  pub const fn build(self) -> QosPolicies {
+  pub fn partition(mut self, partition: policy::Partition) -> QosPolicyBuilder {
+    self.partition = Some(partition);
+    self
+  }
+
+  pub fn build(self) -> QosPolicies {
     QosPolicies {
       durability: self.durability,
       presentation: self.presentation,
@@ -166,6 +174,7 @@ impl QosPolicyBuilder {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      partition: self.partition,
     }
   }
 }
@@ -186,6 +195,7 @@ pub struct QosPolicies {
   pub(crate) history: Option<policy::History>,
   pub(crate) resource_limits: Option<policy::ResourceLimits>,
   pub(crate) lifespan: Option<policy::Lifespan>,
+  pub(crate) partition: Option<policy::Partition>,
 }
 
 impl QosPolicies {
@@ -204,6 +214,7 @@ impl QosPolicies {
       history: None,
       resource_limits: None,
       lifespan: None,
+      partition: None,
     }
   }
 
@@ -259,6 +270,10 @@ impl QosPolicies {
     self.lifespan
   }
 
+  pub fn partition(&self) -> Option<&policy::Partition> {
+    self.partition.as_ref()
+  }
+
   pub fn modify_by(&self,other: &QosPolicies) -> QosPolicies {
     QosPolicies {
       durability: other.durability.or(self.durability),
@@ -273,9 +288,20 @@ impl QosPolicies {
       history: other.history.or(self.history),
       resource_limits: other.resource_limits.or(self.resource_limits),
       lifespan: other.lifespan.or(self.lifespan),      
+      partition: other.partition.clone().or_else( || self.partition.clone() ),
     }
   }
 
+  // Check if the Partitions of two endpoints allow them to communicate.
+  // Partition is not part of the Requested/Offered compatibility check,
+  // because a mismatch means that the endpoints are not even supposed to see
+  // each other. Missing Partition is the default partition.
+  pub fn partitions_match(&self, other: &QosPolicies) -> bool {
+    let default_partition = policy::Partition::default();
+    self.partition.as_ref().unwrap_or(&default_partition)
+      .matches( other.partition.as_ref().unwrap_or(&default_partition) )
+  }
+
   // Check if policy self commplies to other.
   //
   // "self" is the "offered" (publisher) QoS
@@ -283,6 +309,19 @@ impl QosPolicies {
   // yes => None (no failure, i.e. are compatible)
   // no => Some(policyId) , where policyId is (any) one of the policies
   // causing incompliance 
+  // Coherent sets span the samples of one DataWriter only, so coherent access with
+  // GROUP access scope, i.e. sets spanning the DataWriters of a Publisher, is not supported.
+  pub(crate) fn check_supported(&self) -> Result<()> {
+    match self.presentation {
+      Some(policy::Presentation {
+          access_scope: policy::PresentationAccessScope::Group, coherent_access: true, .. }) => {
+        error!("Presentation QoS coherent_access with access scope GROUP is not supported.");
+        Err(Error::Unsupported)
+      }
+      _ => Ok(()),
+    }
+  }
+
   // Compliance (comaptibility) is defined in the table in DDS spec v1.4
   // Section "2.2.3 Supported QoS"
   // This is not symmetric.
@@ -308,7 +347,13 @@ impl QosPolicies {
     // * If coherent_access is requsted, it must be offered also. AND
     // * Same for ordered_access. AND
     // * Offered access scope is broader than requested.
-    if let (Some(off),Some(req)) = (self.presentation, other.presentation) {
+    // Not offered means the default: INSTANCE scope without coherent or ordered access.
+    if let Some(req) = other.presentation {
+      let off = self.presentation.unwrap_or(policy::Presentation {
+        access_scope: policy::PresentationAccessScope::Instance,
+        coherent_access: false,
+        ordered_access: false,
+      });
       if   (req.coherent_access && ! off.coherent_access)
         || (req.ordered_access && ! off.ordered_access)
         || (req.access_scope > off.access_scope) {
@@ -334,9 +379,9 @@ impl QosPolicies {
     }
 
     // check Ownership:
-    // offered kind == requested kind
+    // offered kind == requested kind. Strength is not compared.
     if let (Some(off),Some(req)) = (self.ownership, other.ownership) {
-      if off != req {
+      if std::mem::discriminant(&off) != std::mem::discriminant(&req) {
         return Some(QosPolicyId::Ownership)
       }
     }
@@ -504,11 +549,91 @@ pub mod policy {
     pub minimum_separation: Duration,
   }
 
-  /*
+  /// DDS 2.2.3.13 PARTITION
+  ///
+  /// The names may contain wildcards as in POSIX fnmatch: `*`, `?` and `[...]`.
+  /// Empty list means the default partition, which has the empty name "".
+  #[derive(Clone, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
   pub struct Partition {
-    pub name: Vec<Vec<u8>>,
+    pub name: Vec<String>,
+  }
+
+  impl Partition {
+    pub fn new(names: &[&str]) -> Partition {
+      Partition { name: names.iter().map( |n| n.to_string() ).collect() }
+    }
+
+    // Two Partition lists match, if there is any pair of names that match.
+    // Names match, if they are equal, or if one of them is a wildcard pattern
+    // matching the other. Two wildcard patterns are not matched against each other.
+    pub fn matches(&self, other: &Partition) -> bool {
+      let default_name = [String::new()];
+      let names = |p: &Partition| 
+        if p.name.is_empty() { default_name.to_vec() } else { p.name.clone() };
+      let (my_names, other_names) = (names(self), names(other));
+      my_names.iter().any( |mine| other_names.iter().any( |theirs| 
+          mine == theirs
+          || ( is_pattern(mine) && ! is_pattern(theirs) && pattern_match(mine.as_bytes(), theirs.as_bytes()) )
+          || ( is_pattern(theirs) && ! is_pattern(mine) && pattern_match(theirs.as_bytes(), mine.as_bytes()) )
+        ))
+    }
+  }
+
+  fn is_pattern(name: &str) -> bool {
+    name.contains( |c| c == '*' || c == '?' || c == '[' )
+  }
+
+  // fnmatch-style matching of name against pattern
+  fn pattern_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+      None => name.is_empty(),
+      Some((b'*', rest)) => 
+        (0..=name.len()).any( |skip| pattern_match(rest, &name[skip..]) ),
+      Some((b'?', rest)) => 
+        ! name.is_empty() && pattern_match(rest, &name[1..]),
+      Some((b'[', rest)) => {
+        match (name.split_first(), bracket_match(rest, name.first().copied())) {
+          (Some((_, name_rest)), Some((true, pattern_rest))) => pattern_match(pattern_rest, name_rest),
+          (_, None) => // no closing bracket, so '[' is an ordinary character
+            name.first() == Some(&b'[') && pattern_match(rest, &name[1..]),
+          _ => false,
+        }
+      }
+      Some((b'\\', rest)) if ! rest.is_empty() =>
+        name.first() == Some(&rest[0]) && pattern_match(&rest[1..], &name[1..]),
+      Some((c, rest)) => 
+        name.first() == Some(c) && pattern_match(rest, &name[1..]),
+    }
+  }
+
+  // Matches a character against a bracket expression, e.g. "[a-c]" or "[!0-9]". 
+  // The pattern starts after the opening bracket. 
+  // Returns match result and the rest of the pattern after the closing bracket,
+  // or None if the bracket expression is not closed.
+  fn bracket_match(pattern: &[u8], c: Option<u8>) -> Option<(bool,&[u8])> {
+    let (negate, mut p) = match pattern.first() {
+      Some(b'!') => (true, &pattern[1..]),
+      _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+      match p {
+        [] => return None,
+        [b']', rest @ ..] if ! first => 
+          return Some( (c.is_some() && matched != negate, rest) ),
+        [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+          matched |= c.map( |c| *lo <= c && c <= *hi ).unwrap_or(false);
+          p = rest;
+        }
+        [x, rest @ ..] => {
+          matched |= c == Some(*x);
+          p = rest;
+        }
+      }
+      first = false;
+    }
   }
-  */
 
   /// DDS 2.2.3.14 RELIABILITY
   #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -562,6 +687,27 @@ pub mod policy {
     pub max_samples_per_instance: i32,
   }
 
+  impl ResourceLimits {
+    /// Limit value meaning "no limit". Any non-positive value is treated the same.
+    pub const LENGTH_UNLIMITED: i32 = -1;
+
+    fn as_limit(value: i32) -> Option<usize> {
+      if value > 0 { Some(value as usize) } else { None }
+    }
+
+    pub(crate) fn samples_limit(&self) -> Option<usize> {
+      Self::as_limit(self.max_samples)
+    }
+
+    pub(crate) fn instances_limit(&self) -> Option<usize> {
+      Self::as_limit(self.max_instances)
+    }
+
+    pub(crate) fn samples_per_instance_limit(&self) -> Option<usize> {
+      Self::as_limit(self.max_samples_per_instance)
+    }
+  }
+
   #[derive(Serialize, Deserialize)]
   pub(crate) struct QosData<D>
   where
@@ -656,6 +802,30 @@ impl InlineQos {
         None => None,
       })
   }
+
+  // First SequenceNumber of the coherent set, if the sample belongs to one.
+  pub fn coherent_set(
+    params: &ParameterList,
+    rep_id: RepresentationIdentifier,
+  ) -> std::result::Result<Option<SequenceNumber>, crate::serialization::error::Error> {
+    let endianness = 
+      if rep_id == RepresentationIdentifier::CDR_BE || rep_id == RepresentationIdentifier::PL_CDR_BE {
+        Endianness::BigEndian
+      } else {
+        Endianness::LittleEndian
+      };
+    let coherent_set = params
+      .parameters
+      .iter()
+      .find(|p| p.parameter_id == ParameterId::PID_COHERENT_SET);
+    match coherent_set {
+      Some(p) => 
+        SequenceNumber::read_from_buffer_with_ctx(endianness, &p.value)
+          .map( Some )
+          .map_err( |e| crate::serialization::error::Error::Message(e.to_string()) ),
+      None => Ok(None),
+    }
+  }
 }
 
 
@@ -663,3 +833,32 @@ impl InlineQos {
 
 // TODO: helper function to check if two QosPolicies: Reequested and Offered are
 // compatible, according to DDS spec 2.2.3
+
+#[cfg(test)]
+mod tests {
+  use super::policy::Partition;
+
+  #[test]
+  fn partition_matches() {
+    let p = |names: &[&str]| Partition::new(names);
+    // default partition
+    assert!(p(&[]).matches(&p(&[])));
+    assert!(p(&[]).matches(&p(&[""])));
+    assert!(! p(&[]).matches(&p(&["a"])));
+    // intersection
+    assert!(p(&["a", "b"]).matches(&p(&["c", "b"])));
+    assert!(! p(&["a", "b"]).matches(&p(&["c", "d"])));
+    // wildcards, either side
+    assert!(p(&["sensor*"]).matches(&p(&["sensor_left"])));
+    assert!(p(&["x", "sensor_left"]).matches(&p(&["sensor*"])));
+    assert!(! p(&["sensor*"]).matches(&p(&["motor"])));
+    assert!(p(&["fleet?"]).matches(&p(&["fleet1"])));
+    assert!(! p(&["fleet?"]).matches(&p(&["fleet12"])));
+    assert!(p(&["fleet[0-3]"]).matches(&p(&["fleet2"])));
+    assert!(! p(&["fleet[!0-3]"]).matches(&p(&["fleet2"])));
+    assert!(! p(&["a\\*"]).matches(&p(&["ab"])));
+    // two patterns are not matched against each other, unless equal
+    assert!(! p(&["s*"]).matches(&p(&["se*"])));
+    assert!(p(&["s*"]).matches(&p(&["s*"])));
+  }
+}
@@ -0,0 +1,151 @@
+use serde::Serialize;
+
+use crate::dds::{
+  content_filter::ContentFilter,
+  readcondition::ReadCondition,
+  values::result::Result,
+};
+
+/// Selection of samples in DataReader `read` and `take` calls.
+///
+/// Implemented by [ReadCondition](struct.ReadCondition.html), which selects by sample, view and
+/// instance state only, and [QueryCondition](struct.QueryCondition.html), which also examines
+/// the sample contents.
+pub trait SampleCondition<D> {
+  /// Sample, view and instance states to be selected.
+  fn read_condition(&self) -> &ReadCondition;
+
+  /// Does the (deserialized) sample pass the condition? Samples without data, e.g.
+  /// dispose notifications, are selected by the states only.
+  fn sample_matches(&self, sample: &D) -> bool;
+}
+
+impl<D> SampleCondition<D> for ReadCondition {
+  fn read_condition(&self) -> &ReadCondition {
+    self
+  }
+
+  fn sample_matches(&self, _sample: &D) -> bool {
+    true
+  }
+}
+
+impl<D, C: SampleCondition<D>> SampleCondition<D> for &C {
+  fn read_condition(&self) -> &ReadCondition {
+    (*self).read_condition()
+  }
+
+  fn sample_matches(&self, sample: &D) -> bool {
+    (*self).sample_matches(sample)
+  }
+}
+
+/// DDS QueryCondition 2.2.2.5.9
+///
+/// A [ReadCondition](struct.ReadCondition.html) extended with a query over the sample
+/// contents. The query is a DDS-SQL expression, e.g. `"x > %0 AND color = 'RED'"`,
+/// where `%n` refers to the n:th query parameter. Field names refer to the members of the
+/// data type, as seen by Serde serialization, and nested members are accessed as `a.b`.
+///
+/// # Examples
+///
+/// ```
+/// # use serde::{Serialize, Deserialize};
+/// # use rustdds::dds::DomainParticipant;
+/// # use rustdds::dds::qos::QosPolicyBuilder;
+/// # use rustdds::dds::traits::Keyed;
+/// # use rustdds::serialization::CDRDeserializerAdapter;
+/// use rustdds::dds::data_types::{TopicKind, ReadCondition, QueryCondition};
+///
+/// # let domain_participant = DomainParticipant::new(0).unwrap();
+/// # let qos = QosPolicyBuilder::new().build();
+/// # let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+/// #
+/// #[derive(Serialize, Deserialize)]
+/// struct SomeType { a: i32, b: String }
+/// # impl Keyed for SomeType {
+/// #   type K = i32;
+/// #   fn get_key(&self) -> Self::K { self.a }
+/// # }
+///
+/// # let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+/// let mut data_reader = subscriber
+///   .create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
+///
+/// let query = QueryCondition::new(ReadCondition::not_read(), "a > %0", &["10".to_string()]).unwrap();
+/// if let Ok(datas) = data_reader.read(10, &query) {
+///   // only samples with a > 10
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryCondition {
+  read_condition: ReadCondition,
+  query_expression: String,
+  query_parameters: Vec<String>,
+  query: ContentFilter,
+}
+
+impl QueryCondition {
+  /// Fails with BadParameter, if the query expression cannot be parsed, or does not match
+  /// the parameters.
+  pub fn new(read_condition: ReadCondition, query_expression: &str, query_parameters: &[String])
+    -> Result<QueryCondition>
+  {
+    Ok(QueryCondition {
+      read_condition,
+      query_expression: query_expression.to_string(),
+      query_parameters: query_parameters.to_vec(),
+      query: ContentFilter::new(query_expression, query_parameters)?,
+    })
+  }
+
+  pub fn get_query_expression(&self) -> &str {
+    &self.query_expression
+  }
+
+  pub fn get_query_parameters(&self) -> &[String] {
+    &self.query_parameters
+  }
+
+  /// Replace the query parameters. On failure, the old parameters remain in effect.
+  pub fn set_query_parameters(&mut self, query_parameters: &[String]) -> Result<()> {
+    self.query = ContentFilter::new(&self.query_expression, query_parameters)?;
+    self.query_parameters = query_parameters.to_vec();
+    Ok(())
+  }
+}
+
+impl<D: Serialize> SampleCondition<D> for QueryCondition {
+  fn read_condition(&self) -> &ReadCondition {
+    &self.read_condition
+  }
+
+  fn sample_matches(&self, sample: &D) -> bool {
+    self.query.matches(sample)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Serialize)]
+  struct Sample {
+    a: i32,
+  }
+
+  #[test]
+  fn querycondition_parameters() {
+    let mut query =
+      QueryCondition::new(ReadCondition::any(), "a BETWEEN %0 AND %1", &["1".to_string(), "5".to_string()])
+        .unwrap();
+    assert!(query.sample_matches(&Sample { a: 3 }));
+    assert!(!query.sample_matches(&Sample { a: 7 }));
+
+    query.set_query_parameters(&["6".to_string(), "9".to_string()]).unwrap();
+    assert!(query.sample_matches(&Sample { a: 7 }));
+    assert!(query.set_query_parameters(&["6".to_string()]).is_err());
+    assert_eq!(query.get_query_parameters(), &["6".to_string(), "9".to_string()]);
+    assert!(QueryCondition::new(ReadCondition::any(), "a BETWEEN", &[]).is_err());
+  }
+}
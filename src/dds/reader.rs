@@ -10,14 +10,15 @@ use crate::dds::ddsdata::DDSData;
 use crate::dds::statusevents::*;
 use crate::dds::rtps_writer_proxy::RtpsWriterProxy;
 use crate::structure::guid::{GUID, EntityId, GuidPrefix};
-use crate::structure::sequence_number::{SequenceNumber, SequenceNumberSet};
+use crate::structure::sequence_number::{SequenceNumber, SequenceNumberSet, 
+  FragmentNumber, FragmentNumberSet};
 #[cfg(test)] use crate::structure::locator::LocatorList;
 use crate::structure::{duration::Duration, time::Timestamp};
 
 use std::{
   collections::BTreeSet,
   iter::FromIterator,
-  sync::{Arc, RwLock},
+  sync::{Arc, RwLock, Mutex},
   rc::Rc,
 };
 use crate::structure::dds_cache::{DDSCache};
@@ -38,16 +39,21 @@ use crate::dds::qos::{QosPolicies, HasQoSPolicy, policy};
 use crate::network::udp_sender::UDPSender;
 
 use crate::serialization::message::Message;
+use crate::serialization::SubMessage;
 use crate::messages::header::Header;
 use crate::messages::protocol_id::ProtocolId;
 use crate::messages::protocol_version::ProtocolVersion;
 use crate::messages::vendor_id::VendorId;
 use crate::messages::submessages::submessage_elements::parameter_list::ParameterList;
+use crate::structure::parameter_id::ParameterId;
 
 use speedy::{Writable, Endianness};
 
 use super::{
   with_key::datareader::ReaderCommand,
+  datasample_cache::{SampleRejection, instance_keep_count},
+  traits::key::KeyHash,
+  helpers::TaskWakers,
 };
 
 use super::qos::InlineQos;
@@ -55,6 +61,150 @@ use super::qos::InlineQos;
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum TimedEvent {
   DeadlineMissedCheck,
+  LivelinessCheck,
+}
+
+// Matched Writers that are currently allowed to own instances, with their ownership strengths.
+// The Reader keeps this up to date, and the DataReader uses it to decide which Writer
+// owns each instance, when Ownership is EXCLUSIVE.
+pub(crate) type OwnershipCandidates = BTreeMap<GUID, i32>;
+
+// Writers that have lost their liveliness or have been unmatched. The Reader adds them here,
+// and the DataReader takes them out to detect instances that have no live Writers left.
+pub(crate) type LostWriters = BTreeSet<GUID>;
+
+// Finds out which instance a received sample belongs to, if it can.
+pub(crate) type SampleKeyHasher = fn(&DDSData) -> Option<KeyHash>;
+
+// How much of ResourceLimits the DataReader is using. The Reader admits received samples
+// only within the limits, before acknowledging them, so that a reliable Writer keeps
+// a rejected sample and sends it again later. The counts are kept up to date incrementally:
+// the Reader adds the samples it admits, and the DataReader reports the instances whose
+// samples it has stored, taken, or dropped since its previous report.
+#[derive(Default)]
+pub(crate) struct ReaderHistoryUsage {
+  // Set only when ResourceLimits apply
+  key_hasher: Option<SampleKeyHasher>,
+  // Samples per instance, including live instances with no samples
+  instances: BTreeMap<KeyHash, InstanceUsage>,
+  // Sum of the samples of all instances
+  total_samples: usize,
+  // Samples admitted by the Reader, but not yet fetched by the DataReader, by receive time
+  unfetched: BTreeMap<Timestamp, KeyHash>,
+  // Samples that the DataReader could not store after all. The Reader reports these.
+  rejected: Vec<SampleRejection>,
+}
+
+#[derive(Default)]
+struct InstanceUsage {
+  stored: usize,    // in the DataReader
+  unfetched: usize, // admitted by the Reader
+}
+
+impl ReaderHistoryUsage {
+  pub fn new(key_hasher: Option<SampleKeyHasher>) -> ReaderHistoryUsage {
+    ReaderHistoryUsage { key_hasher, ..ReaderHistoryUsage::default() }
+  }
+
+  // Reserves room for a received sample. The checks are the same as in DataSampleCache.
+  // The key hash of the sample is computed only if the Writer did not send it.
+  pub fn admit(&mut self, ddsdata: &DDSData, key_hash: Option<KeyHash>, writer_guid: GUID, 
+    sequence_number: SequenceNumber, receive_timestamp: Timestamp, qos: &QosPolicies) 
+    -> std::result::Result<(), SampleRejection> 
+  {
+    let limits = match (qos.resource_limits(), self.key_hasher) {
+      (Some(limits), Some(_)) => limits,
+      _ => return Ok(()), // not limited
+    };
+    let instance_key = match key_hash.or_else( || self.key_hasher.and_then( |kh| kh(ddsdata) ) ) {
+      Some(instance_key) => instance_key,
+      None => return Ok(()), // not a sample of any known instance
+    };
+    let reject = |reason| Err(SampleRejection { writer_guid, sequence_number, reason, instance_key });
+    let instance_samples = match self.instances.get(&instance_key) {
+      Some(usage) => usage.stored + usage.unfetched,
+      None => {
+        if let Some(max_instances) = limits.instances_limit() {
+          if self.instances.len() >= max_instances {
+            return reject(SampleRejectedStatusKind::ByInstancesLimit)
+          }
+        }
+        0
+      }
+    };
+    match instance_keep_count(qos) {
+      // KEEP_LAST: the new sample replaces the oldest one of the instance, so it needs
+      // no more room.
+      Some(keep_count) if instance_samples >= keep_count => return Ok(()),
+      Some(_) => (),
+      None => if let Some(max_samples_per_instance) = limits.samples_per_instance_limit() {
+        if instance_samples >= max_samples_per_instance {
+          return reject(SampleRejectedStatusKind::BySamplesPerInstanceLimit)
+        }
+      }
+    }
+    if let Some(max_samples) = limits.samples_limit() {
+      if self.total_samples >= max_samples {
+        return reject(SampleRejectedStatusKind::BySamplesLimit)
+      }
+    }
+    self.instances.entry(instance_key).or_default().unfetched += 1;
+    self.total_samples += 1;
+    self.unfetched.insert(receive_timestamp, instance_key);
+    Ok(())
+  }
+
+  // The DataReader has fetched samples from DDSCache up to `fetched_until`. `changed_instances`
+  // gives the number of samples it now stores of each instance that has changed, or None, if the
+  // instance no longer takes up room. It could not store the `rejected` samples.
+  pub fn datareader_updated(&mut self, changed_instances: Vec<(KeyHash, Option<usize>)>, 
+    fetched_until: Timestamp, rejected: Vec<SampleRejection>) 
+  {
+    let mut fetched = std::mem::take(&mut self.unfetched);
+    self.unfetched = fetched.split_off(&fetched_until);
+    if let Some(key_hash) = self.unfetched.remove(&fetched_until) {
+      fetched.insert(fetched_until, key_hash);
+    }
+    for key_hash in fetched.values() {
+      if let Some(usage) = self.instances.get_mut(key_hash) {
+        usage.unfetched -= 1;
+        self.total_samples -= 1;
+      }
+    }
+    for (key_hash, stored) in changed_instances {
+      let usage = self.instances.entry(key_hash).or_default();
+      self.total_samples = self.total_samples - usage.stored + stored.unwrap_or(0);
+      usage.stored = stored.unwrap_or(0);
+      if stored.is_none() && usage.unfetched == 0 {
+        self.instances.remove(&key_hash);
+      }
+    }
+    self.rejected.extend(rejected);
+  }
+
+  pub fn take_rejected(&mut self) -> Vec<SampleRejection> {
+    std::mem::take(&mut self.rejected)
+  }
+}
+
+// Ways a remote Writer can show that it is alive, from weakest to strongest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LivelinessAssertion {
+  // The DDS implementation of the Writer is running: HEARTBEAT or automatic ParticipantMessage.
+  Automatic,
+  // The application asserted liveliness of the whole remote Participant.
+  ManualByParticipant,
+  // The Writer itself wrote data or asserted its liveliness.
+  ManualByWriter,
+}
+
+impl LivelinessAssertion {
+  fn asserts(self, liveliness: Option<policy::Liveliness>) -> bool {
+    matches!((self, liveliness),
+      (LivelinessAssertion::ManualByWriter, _)
+      | (_, None) | (_, Some(policy::Liveliness::Automatic{..}))
+      | (LivelinessAssertion::ManualByParticipant, Some(policy::Liveliness::ManualByParticipant{..})))
+  }
 }
 
 // Some pieces necessary to contruct a reader.
@@ -66,6 +216,10 @@ pub (crate) struct ReaderIngredients {
   pub topic_name: String,
   pub qos_policy: QosPolicies,
   pub data_reader_command_receiver: mio_channel::Receiver<ReaderCommand>, 
+  pub ownership_candidates: Arc<Mutex<OwnershipCandidates>>,
+  pub lost_writers: Arc<Mutex<LostWriters>>,
+  pub history_usage: Arc<Mutex<ReaderHistoryUsage>>,
+  pub data_reader_wakers: TaskWakers,
 }
 
 impl fmt::Debug for ReaderIngredients {
@@ -102,6 +256,7 @@ pub(crate) struct Reader {
   heartbeat_supression_duration: StdDuration,
 
   sent_ack_nack_count: i32,
+  sent_nack_frag_count: i32,
   received_hearbeat_count: i32,
 
   matched_writers: BTreeMap<GUID, RtpsWriterProxy>,
@@ -109,10 +264,22 @@ pub(crate) struct Reader {
 
   requested_deadline_missed_count: i32,
   offered_incompatible_qos_count: i32,
+  sample_rejected_count: i32,
 
   pub(crate) timed_event_timer: Timer<TimedEvent>,
   pub(crate) data_reader_command_receiver: mio_channel::Receiver<ReaderCommand>,
 
+  // DataReaders waiting for historical data from durable Writers, and until when
+  // they wait.
+  historical_data_waiters: Vec<(mio_channel::SyncSender<()>, Option<std::time::Instant>)>,
+
+  ownership_candidates: Arc<Mutex<OwnershipCandidates>>,
+  lost_writers: Arc<Mutex<LostWriters>>,
+  history_usage: Arc<Mutex<ReaderHistoryUsage>>,
+  // Async tasks waiting for data or status from DataReader
+  data_reader_wakers: TaskWakers,
+  // When the next liveliness check is due, if one is scheduled.
+  liveliness_check_at: Option<Timestamp>,
 } 
 
 impl Reader {
@@ -140,13 +307,21 @@ impl Reader {
       heartbeat_response_delay: StdDuration::new(0, 500_000_000), // 0,5sec
       heartbeat_supression_duration: StdDuration::new(0, 0),
       sent_ack_nack_count: 0,
+      sent_nack_frag_count: 0,
       received_hearbeat_count: 0,
       matched_writers: BTreeMap::new(),
       writer_match_count_total: 0,
       requested_deadline_missed_count: 0,
       offered_incompatible_qos_count: 0,
+      sample_rejected_count: 0,
       timed_event_timer,
       data_reader_command_receiver: i.data_reader_command_receiver,
+      historical_data_waiters: Vec::new(),
+      ownership_candidates: i.ownership_candidates,
+      lost_writers: i.lost_writers,
+      history_usage: i.history_usage,
+      data_reader_wakers: i.data_reader_wakers,
+      liveliness_check_at: None,
     }
   }
   // TODO: check if it's necessary to implement different handlers for discovery
@@ -189,7 +364,7 @@ impl Reader {
 
   pub fn send_status_change(&self, change: DataReaderStatus) {
     match self.status_sender.try_send(change) {
-      Ok(()) => (), // expected result
+      Ok(()) => self.data_reader_wakers.wake_all(), // expected result
       Err(mio_channel::TrySendError::Full(_)) => {
         trace!("Reader cannot send new status changes, datareader is full.");
         // It is perfectly normal to fail due to full channel, because
@@ -220,8 +395,9 @@ impl Reader {
       
       Some(policy::Deadline(deadline_duration)) => {
         let mut changes: Vec<DataReaderStatus> = vec![];
+        let mut late_writers = Vec::new();
         let now = Timestamp::now();
-        for (_g, writer_proxy) in self.matched_writers.iter_mut() {
+        for (g, writer_proxy) in self.matched_writers.iter_mut() {
           match writer_proxy.last_change_timestamp() {
             Some(last_change) => {
               let since_last = now.duration_since(last_change);
@@ -229,6 +405,7 @@ impl Reader {
               trace!("Comparing deadlines: {:?} - {:?}", since_last, deadline_duration);
               if since_last > deadline_duration{
                 debug!("Deadline missed: {:?} - {:?}", since_last, deadline_duration);
+                late_writers.push(*g);
                 self.requested_deadline_missed_count += 1;
                 changes.push( DataReaderStatus::RequestedDeadlineMissed {
                                 count: CountWithChange::start_from(self.requested_deadline_missed_count,1)
@@ -247,6 +424,10 @@ impl Reader {
             } 
           }
         } // for
+        // Writers that miss their deadline lose the ownership of instances.
+        for writer_guid in late_writers {
+          self.update_ownership_candidate(writer_guid, None);
+        }
         changes
       } // Some
     } // match
@@ -259,6 +440,10 @@ impl Reader {
           self.handle_requested_deadline_event();
           self.set_requested_deadline_check_timer(); // re-prime timer
         }
+        TimedEvent::LivelinessCheck => {
+          self.liveliness_check_at = None;
+          self.handle_liveliness_check();
+        }
       }
     }
   }
@@ -273,6 +458,20 @@ impl Reader {
           //TODO: This should be implemented.
         }
 
+        Ok(ReaderCommand::WaitForHistoricalData{ complete, deadline }) => {
+          self.historical_data_waiters.push((complete, deadline));
+          self.check_historical_data_waiters();
+        }
+
+        Ok(ReaderCommand::GetMatchedWriters{ reply }) => {
+          // may fail, if receiver has timeouted
+          let _ = reply.try_send( self.matched_writers.keys().copied().collect() );
+        }
+
+        Ok(ReaderCommand::SamplesRejected) => {
+          self.report_datareader_rejections();
+        }
+
         // Disconnected is normal when terminating
         Err(TryRecvError::Disconnected) => { 
           trace!("DataReader disconnected");
@@ -284,6 +483,162 @@ impl Reader {
         }
       }
     }
+    // The DataReader may not have been able to send SamplesRejected, if the
+    // command channel was full.
+    self.report_datareader_rejections();
+  }
+
+  // Notify DataReaders waiting for historical data, if all durable Writers
+  // have delivered it. Waiters that have timed out are forgotten, as historical
+  // data may never be complete.
+  fn check_historical_data_waiters(&mut self) {
+    let now = std::time::Instant::now();
+    self.historical_data_waiters
+      .retain( |(_, deadline)| deadline.map( |d| now < d ).unwrap_or(true) );
+    if self.historical_data_waiters.is_empty() {
+      return
+    }
+    if self.matched_writers.values().all( |wp| wp.historical_data_received() ) {
+      for (waiter, _) in self.historical_data_waiters.drain(..) {
+        // it is normal for the send to fail, because receiver may have timeouted
+        let _ = waiter.try_send( () );
+      }
+    }
+  }
+
+  // Writers whose lease has expired are no longer alive.
+  fn handle_liveliness_check(&mut self) {
+    let now = Timestamp::now();
+    let expired: Vec<GUID> = self.matched_writers.iter()
+      .filter( |(_, wp)| wp.lease_expiry().map( |expiry| expiry < now ).unwrap_or(false) )
+      .map( |(g, _)| *g )
+      .collect();
+    for writer_guid in expired {
+      self.writer_liveliness_lost(writer_guid);
+    }
+    self.schedule_liveliness_check();
+  }
+
+  // Set timer to check liveliness, when the next lease is due to expire.
+  fn schedule_liveliness_check(&mut self) {
+    let next_expiry = match self.matched_writers.values().filter_map( |wp| wp.lease_expiry() ).min() {
+      Some(expiry) => expiry,
+      None => return, // no leases to check
+    };
+    if self.liveliness_check_at.map( |at| at <= next_expiry ).unwrap_or(false) {
+      return // already checking early enough
+    }
+    let delay = next_expiry.duration_since(Timestamp::now());
+    let delay = if delay > Duration::DURATION_ZERO { delay } else { Duration::from_millis(1) };
+    self.timed_event_timer.set_timeout(delay.to_std(), TimedEvent::LivelinessCheck);
+    self.liveliness_check_at = Some(next_expiry);
+  }
+
+  fn assert_writer_liveliness(&mut self, writer_guid: GUID, assertion: LivelinessAssertion) {
+    let regained = match self.matched_writers.get_mut(&writer_guid) {
+      Some(wp) if assertion.asserts(wp.liveliness) => wp.assert_liveliness(Timestamp::now()),
+      _ => return,
+    };
+    if regained {
+      info!("Writer {:?} regained liveliness on topic={:?}", writer_guid, self.topic_name);
+      self.update_alive_writer(writer_guid, true);
+      self.send_liveliness_changed(1);
+      self.schedule_liveliness_check();
+    }
+  }
+
+  fn writer_liveliness_lost(&mut self, writer_guid: GUID) {
+    match self.matched_writers.get_mut(&writer_guid) {
+      Some(wp) if wp.is_alive => wp.is_alive = false,
+      _ => return,
+    }
+    info!("Writer {:?} lost liveliness on topic={:?}", writer_guid, self.topic_name);
+    self.update_alive_writer(writer_guid, false);
+    self.update_ownership_candidate(writer_guid, None);
+    self.send_liveliness_changed(-1);
+  }
+
+  // alive_change is +1, when a Writer became alive, and -1 when it became not alive.
+  fn send_liveliness_changed(&self, alive_change: i32) {
+    let alive_count = self.matched_writers.values().filter( |wp| wp.is_alive ).count() as i32;
+    let not_alive_count = self.matched_writers.len() as i32 - alive_count;
+    self.send_status_change(DataReaderStatus::LivelinessChanged {
+      alive_total: CountWithChange::new(alive_count, alive_change),
+      not_alive_total: CountWithChange::new(not_alive_count, -alive_change),
+    });
+  }
+
+  fn update_alive_writer(&self, writer_guid: GUID, is_alive: bool) {
+    match self.lost_writers.lock() {
+      Ok(mut lost_writers) => 
+        if is_alive { lost_writers.remove(&writer_guid); } 
+        else { lost_writers.insert(writer_guid); },
+      Err(e) => error!("update_alive_writer: lost writers are poisoned: {:?}", e),
+    }
+  }
+
+  // Remote Participant has asserted liveliness, either automatically or manually
+  // by application. This covers its Writers, unless they have MANUAL_BY_TOPIC Liveliness.
+  pub fn participant_liveliness_asserted(&mut self, guid_prefix: GuidPrefix, manual_assertion: bool) {
+    let writers: Vec<GUID> = 
+      self.matched_writers.range( guid_prefix.range() )
+        .map(|(g,_)| *g)
+        .collect();
+    let assertion = 
+      if manual_assertion { LivelinessAssertion::ManualByParticipant } 
+      else { LivelinessAssertion::Automatic };
+    for writer_guid in writers {
+      self.assert_writer_liveliness(writer_guid, assertion);
+    }
+  }
+
+  // Samples were rejected because of ResourceLimits.
+  fn samples_rejected(&mut self, rejections: Vec<SampleRejection>) {
+    let last = match rejections.last() {
+      Some(last) => *last,
+      None => return,
+    };
+    debug!("Rejected {} samples, last {:?}", rejections.len(), last);
+    self.sample_rejected_count += rejections.len() as i32;
+    self.send_status_change(DataReaderStatus::SampleRejected {
+      count: CountWithChange::new(self.sample_rejected_count, rejections.len() as i32),
+      last_reason: last.reason,
+      last_instance_key: last.instance_key,
+    });
+  }
+
+  // The DataReader could not store these samples, although the Reader admitted them.
+  // They have been acknowledged already, so they are only reported.
+  fn report_datareader_rejections(&mut self) {
+    let rejections = match self.history_usage.lock() {
+      Ok(mut usage) => usage.take_rejected(),
+      Err(e) => {
+        error!("report_datareader_rejections: History usage is poisoned: {:?}", e);
+        return
+      }
+    };
+    self.samples_rejected(rejections);
+  }
+
+  // Checks ResourceLimits of the DataReader for a received sample.
+  fn admit_sample(&mut self, ddsdata: &DDSData, key_hash: Option<KeyHash>, writer_guid: GUID, 
+    writer_sn: SequenceNumber, receive_timestamp: Timestamp) -> bool 
+  {
+    let admission = match self.history_usage.lock() {
+      Ok(mut usage) => 
+        usage.admit(ddsdata, key_hash, writer_guid, writer_sn, receive_timestamp, &self.qos_policy),
+      Err(e) => {
+        error!("admit_sample: History usage is poisoned: {:?}", e);
+        Ok(())
+      }
+    };
+    match admission {
+      Ok(()) => true,
+      Err(rejection) => {
+        self.samples_rejected(vec![rejection]);
+        false
+      }
+    }
   }
 
   fn handle_requested_deadline_event(&mut self) {
@@ -318,12 +673,30 @@ impl Reader {
   // updates or adds a new writer proxy, doesn't touch changes
   pub fn update_writer_proxy(&mut self, proxy: RtpsWriterProxy, offered_qos: QosPolicies) {
     debug!("update_writer_proxy topic={:?}",self.topic_name);
+    if ! offered_qos.partitions_match( &self.qos_policy ) {
+      // Not in our partitions. Remove, in case the writer has changed its partitions.
+      debug!("update_writer_proxy - Partition mismatch writer={:?}", proxy.remote_writer_guid);
+      self.remove_writer_proxy(proxy.remote_writer_guid);
+      return
+    }
     match offered_qos.compliance_failure_wrt( &self.qos_policy ) {
       None => { // success, update or insert
+        let mut proxy = proxy;
+        proxy.is_durable = 
+          matches!(offered_qos.reliability(), Some(policy::Reliability::Reliable{..}))
+          && offered_qos.durability().map( |d| d >= policy::Durability::TransientLocal ).unwrap_or(false);
+        proxy.ownership_strength = match offered_qos.ownership() {
+          Some(policy::Ownership::Exclusive{ strength }) => strength,
+          _ => 0,
+        };
+        proxy.liveliness = offered_qos.liveliness();
         let writer_id = proxy.remote_writer_guid;
+        self.update_ownership_candidate(writer_id, Some(proxy.ownership_strength));
         let count_change =
           self.matched_writer_update(proxy); 
+        self.schedule_liveliness_check();
         if count_change > 0 {
+          self.update_alive_writer(writer_id, true);
           self.writer_match_count_total += count_change;
           self.send_status_change(DataReaderStatus::SubscriptionMatched{
               total: CountWithChange::new(self.writer_match_count_total, count_change ), 
@@ -360,8 +733,10 @@ impl Reader {
   }
 
   pub fn remove_writer_proxy(&mut self, writer_guid:GUID) {
+    self.update_ownership_candidate(writer_guid, None);
     if self.matched_writers.contains_key(&writer_guid) {
       self.matched_writers.remove(&writer_guid);
+      self.update_alive_writer(writer_guid, false);
       self.send_status_change(DataReaderStatus::SubscriptionMatched { 
                 total: CountWithChange::new(self.writer_match_count_total , 0 ),
                 current: CountWithChange::new(self.matched_writers.len() as i32 , -1)
@@ -381,6 +756,24 @@ impl Reader {
     }
   }
 
+  fn exclusive_ownership(&self) -> bool {
+    matches!(self.qos_policy.ownership, Some(policy::Ownership::Exclusive{..}))
+  }
+
+  // Writer becomes (Some(strength)) or stops being (None) a candidate for instance ownership.
+  fn update_ownership_candidate(&self, writer_guid: GUID, strength: Option<i32>) {
+    if ! self.exclusive_ownership() {
+      return
+    }
+    match self.ownership_candidates.lock() {
+      Ok(mut candidates) => match strength {
+        Some(strength) => { candidates.insert(writer_guid, strength); }
+        None => { candidates.remove(&writer_guid); }
+      }
+      Err(e) => error!("update_ownership_candidate: candidates are poisoned: {:?}", e),
+    }
+  }
+
   pub fn contains_writer(&self, entity_id: EntityId) -> bool {
     self
       .matched_writers
@@ -431,11 +824,14 @@ impl Reader {
 
     let writer_guid = GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, data.writer_id);
     let writer_seq_num = data.writer_sn; // for borrow checker
+    let coherent_set = Self::coherent_set(&data.inline_qos, 
+      data_flags.contains(DATA_Flags::Endianness));
+    let key_hash = Self::key_hash(&data.inline_qos);
 
     match Self::data_to_ddsdata(data,data_flags) {
       Ok(ddsdata) => {
         self.process_received_data(ddsdata, receive_timestamp, mr_state.timestamp, 
-          writer_guid, writer_seq_num)    
+          writer_guid, writer_seq_num, coherent_set, key_hash)    
       }
       Err(e) => debug!("Parsing DATA to DDSData failed: {}",e),
     }
@@ -460,10 +856,14 @@ impl Reader {
       }
     }
     let writer_seq_num = datafrag.writer_sn; // for borrow checker
+    let coherent_set = Self::coherent_set(&datafrag.inline_qos, 
+      datafrag_flags.contains(DATAFRAG_Flags::Endianness));
+    let key_hash = Self::key_hash(&datafrag.inline_qos);
     if let Some(writer_proxy) = self.matched_writer_lookup(writer_guid) {
       if let Some(complete_ddsdata) = writer_proxy.handle_datafrag(datafrag, datafrag_flags) {
         // Source timestamp (if any) will be the timestamp of the last fragment (that completes the sample).
-        self.process_received_data(complete_ddsdata, receive_timestamp, mr_state.timestamp, writer_guid, writer_seq_num );  
+        self.process_received_data(complete_ddsdata, receive_timestamp, mr_state.timestamp, 
+          writer_guid, writer_seq_num, coherent_set, key_hash);  
       } else {
         // not yet complete, nothing more to do
       }
@@ -473,9 +873,11 @@ impl Reader {
   }
 
   // common parts of processing DATA or a completed DATAFRAG (when all frags are received)
+  #[allow(clippy::too_many_arguments)]
   fn process_received_data(&mut self, ddsdata:DDSData, receive_timestamp: Timestamp,
       source_timestamp: Option<Timestamp>, 
-      writer_guid:GUID, writer_sn: SequenceNumber) 
+      writer_guid:GUID, writer_sn: SequenceNumber, coherent_set: Option<SequenceNumber>,
+      key_hash: Option<KeyHash>) 
   {
     trace!("handle_data_msg from {:?} seq={:?} topic={:?} stateful={:?}", 
         &writer_guid, writer_sn, self.topic_name, self.is_stateful,);
@@ -493,8 +895,22 @@ impl Reader {
             return 
           }
         }
+        // ResourceLimits are checked before the change counts as received. A rejected change
+        // is not acknowledged, so a reliable Writer sends it again later.
+        if ! self.admit_sample(&ddsdata, key_hash, writer_guid, writer_sn, receive_timestamp) {
+          return
+        }
+        let writer_proxy = match self.matched_writer_lookup(writer_guid) {
+          Some(wp) => wp,
+          None => return, // cannot happen, we just looked it up
+        };
         // Add the change and get the instant
         writer_proxy.received_changes_add(writer_sn, receive_timestamp);
+        // Writer may have lost ownership by missing its deadline, but now it is back.
+        let strength = writer_proxy.ownership_strength;
+        self.update_ownership_candidate(writer_guid, Some(strength));
+        // Writing data asserts liveliness of any kind.
+        self.assert_writer_liveliness(writer_guid, LivelinessAssertion::ManualByWriter);
       } else {
         // no writer proxy found
         info!("handle_data_msg in stateful Reader {:?} has no writer proxy for {:?} topic={:?}",
@@ -505,13 +921,42 @@ impl Reader {
       todo!()
     }
 
-    self.make_cache_change(ddsdata, receive_timestamp, source_timestamp, writer_guid, writer_sn);
+    self.make_cache_change(ddsdata, receive_timestamp, source_timestamp, writer_guid, writer_sn, 
+      coherent_set);
 
     // Add to own track-keeping datastructure
     #[cfg(test)]
     self.seqnum_instant_map.insert(writer_sn, receive_timestamp);
 
     self.notify_cache_change();
+    self.check_historical_data_waiters();
+  }
+
+  // Key hash of the instance, if the Writer sent it in PID_KEY_HASH
+  fn key_hash(inline_qos: &Option<ParameterList>) -> Option<KeyHash> {
+    inline_qos.as_ref()
+      .map( InlineQos::key_hash )
+      .transpose()
+      .unwrap_or_else( |e| {
+        warn!("Cannot parse PID_KEY_HASH: {:?}", e);
+        None
+      })
+      .flatten()
+  }
+
+  // Which coherent set, if any, does a DATA or DATAFRAG belong to?
+  fn coherent_set(inline_qos: &Option<ParameterList>, little_endian: bool) -> Option<SequenceNumber> {
+    let representation_identifier = 
+      if little_endian { RepresentationIdentifier::CDR_LE } 
+      else { RepresentationIdentifier::CDR_BE };
+    inline_qos.as_ref()
+      .map( |iqos| InlineQos::coherent_set(iqos, representation_identifier) )
+      .transpose()
+      .unwrap_or_else( |e| {
+        warn!("Cannot parse PID_COHERENT_SET: {:?}", e);
+        None
+      })
+      .flatten()
   }
 
   fn data_to_ddsdata(data:Data, data_flags:BitFlags<DATA_Flags>) -> Result<DDSData,String> {
@@ -540,9 +985,17 @@ impl Reader {
               .flatten().flatten() {
             Some(h) => Ok(h),
             None => {
-              info!("Received DATA that has no payload and no key_hash inline QoS - discarding");
-              // Note: This case is normal when handling coherent sets.
+              // This case is normal when handling coherent sets.
               // The coherent set end marker is sent as DATA with no payload and not key, only Inline QoS.
+              let is_coherent_set_end = data.inline_qos
+                .as_ref()
+                .map( |iqos| iqos.parameters.iter()
+                  .any( |p| p.parameter_id == ParameterId::PID_COHERENT_SET) )
+                .unwrap_or(false);
+              if is_coherent_set_end {
+                return Ok(DDSData::CoherentSetEnd)
+              }
+              info!("Received DATA that has no payload and no key_hash inline QoS - discarding");
               Err("DATA with no contents".to_string())
             }
           }?;
@@ -577,6 +1030,7 @@ impl Reader {
     &mut self,
     heartbeat: Heartbeat,
     final_flag_set: bool,
+    liveliness_flag_set: bool,
     mr_state: MessageReceiverState,
   ) -> bool {
     let writer_guid =
@@ -593,6 +1047,12 @@ impl Reader {
         writer_guid, self.topic_name, self.my_guid);
       return false
     }
+    // Liveliness flag means that the Writer manually asserts its liveliness. 
+    // RTPS spec 8.3.7.5 Heartbeat
+    self.assert_writer_liveliness(writer_guid,
+      if liveliness_flag_set { LivelinessAssertion::ManualByWriter } 
+      else { LivelinessAssertion::Automatic });
+
     // sanity check
     if heartbeat.first_sn < SequenceNumber::default() {
       warn!("Writer {:?} advertised SequenceNumbers from {:?} to {:?}!",
@@ -616,6 +1076,8 @@ impl Reader {
     }
     writer_proxy.received_heartbeat_count = heartbeat.count;
 
+    writer_proxy.set_historical_data_last(heartbeat.last_sn);
+
     // remove fragmented changes until first_sn.
     let removed_instances = writer_proxy.irrelevant_changes_up_to(heartbeat.first_sn);
 
@@ -634,6 +1096,7 @@ impl Reader {
         }
       }
     }
+    self.check_historical_data_waiters();
 
     // this is duplicate code from above, but needed, because we need another mutable borrow.
     // TODO: Maybe could be written in some sensible way.
@@ -645,9 +1108,19 @@ impl Reader {
       } // Matching writer not found
     };
 
+    writer_proxy.discard_expired_fragments();
+
     // See if ACKNACK is needed, and generate one.
     let missing_seqnums =
         writer_proxy.get_missing_sequence_numbers(heartbeat.first_sn, heartbeat.last_sn);
+
+    // Samples of which we have received some fragments are requested using NACKFRAG,
+    // so that only the missing fragments are resent. The rest are requested with ACKNACK.
+    let partially_received : Vec<(SequenceNumber, Vec<FragmentNumber>)> = 
+      missing_seqnums.iter()
+        .filter( |sn| writer_proxy.is_partially_received(**sn) )
+        .map( |sn| (*sn, writer_proxy.missing_frags_for(*sn)) )
+        .collect();
     
     // Interpretation of final flag in RTPS spec 
     // 8.4.2.3.1 Readers must respond eventually after receiving a HEARTBEAT with final flag not set
@@ -669,7 +1142,8 @@ impl Reader {
             SequenceNumberSet
               ::from_base_and_set(first_missing, 
                 &BTreeSet::from_iter(missing_seqnums.iter().copied()
-                                      .take_while( |sn| sn < &(first_missing + SequenceNumber::from(256)) ))
+                                      .take_while( |sn| sn < &(first_missing + SequenceNumber::from(256)) )
+                                      .filter( |sn| ! partially_received.iter().any( |(p,_)| p == sn ) ))
                 )
             }
 
@@ -695,6 +1169,12 @@ impl Reader {
       }
 
 
+      for (writer_sn, missing_frags) in partially_received {
+        if let Some(nackfrag) = self.make_nackfrag(heartbeat.writer_id, writer_sn, &missing_frags) {
+          self.send_nackfrag(nackfrag, &mr_state);
+        }
+      }
+
       // The acknack can be sent now or later. The rest of the RTPS message
       // needs to be constructed. p. 48
       self.send_acknack(response_ack_nack, mr_state);
@@ -769,6 +1249,8 @@ impl Reader {
     for instant in &removed_changes {
       cache.from_topic_remove_change(&self.topic_name, instant);
     }
+    drop(cache);
+    self.check_historical_data_waiters();
 
     // Is this needed?
     // self.notify_cache_change();
@@ -777,10 +1259,77 @@ impl Reader {
 
   pub fn handle_heartbeatfrag_msg(
     &mut self,
-    _heartbeatfrag: HeartbeatFrag,
-    _mr_state: MessageReceiverState,
+    heartbeatfrag: HeartbeatFrag,
+    mr_state: MessageReceiverState,
   ) {
-    todo!()
+    let writer_guid =
+      GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, heartbeatfrag.writer_id);
+
+    if ! self.is_stateful {
+      debug!("HEARTBEAT_FRAG from {:?}, reader is stateless. Ignoring. topic={:?} reader={:?}", 
+        writer_guid,self.topic_name, self.my_guid);
+      return
+    }
+
+    let writer_proxy = match self.matched_writer_lookup(writer_guid) {
+      Some(wp) => wp,
+      None => {
+        info!("HEARTBEAT_FRAG from {:?}, but no writer proxy available. topic={:?} reader={:?}", 
+          writer_guid, self.topic_name, self.my_guid);
+        return
+      }
+    };
+
+    if heartbeatfrag.count <= writer_proxy.received_heartbeatfrag_count {
+      // This heartbeat was already seen an processed.
+      return
+    }
+    writer_proxy.received_heartbeatfrag_count = heartbeatfrag.count;
+
+    let writer_sn = heartbeatfrag.writer_sn;
+    if writer_proxy.contains_change(writer_sn) || writer_sn < writer_proxy.all_ackable_before() {
+      // We have the complete sample already, or it is not relevant.
+      return
+    }
+
+    // Writer has fragments 1..=last_fragment_num available. Request what we are missing.
+    let missing_frags : Vec<FragmentNumber> =
+      if writer_proxy.is_partially_received(writer_sn) {
+        writer_proxy.missing_frags_for(writer_sn).into_iter()
+          .take_while( |f| *f <= heartbeatfrag.last_fragment_num )
+          .collect()
+      } else {
+        (1 ..= u32::from(heartbeatfrag.last_fragment_num))
+          .map( FragmentNumber::from )
+          .collect()
+      };
+
+    let mut mr_state = mr_state;
+    mr_state.unicast_reply_locator_list = writer_proxy.unicast_locator_list.clone();
+
+    if let Some(nackfrag) = self.make_nackfrag(heartbeatfrag.writer_id, writer_sn, &missing_frags) {
+      self.send_nackfrag(nackfrag, &mr_state);
+    }
+  }
+
+  // Construct NACKFRAG requesting the given fragments. 
+  // Returns None if nothing is requested.
+  fn make_nackfrag(&mut self, writer_id: EntityId, writer_sn: SequenceNumber, 
+      missing_frags: &[FragmentNumber]) -> Option<NackFrag> 
+  {
+    let first_missing = *missing_frags.first()?;
+    // Limit the set to maximum that can be sent in NACKFRAG submessage.
+    let fragment_number_state = FragmentNumberSet::from_base_and_set(first_missing, 
+      &BTreeSet::from_iter(missing_frags.iter().copied()
+                            .take_while( |f| *f < first_missing + FragmentNumber::from(256u32) )));
+    self.sent_nack_frag_count += 1;
+    Some(NackFrag {
+      reader_id: self.get_entity_id(),
+      writer_id,
+      writer_sn,
+      fragment_number_state,
+      count: self.sent_nack_frag_count,
+    })
   }
 
   // This is used to determine exact change kind in case we do not get a data payload in DATA submessage
@@ -809,9 +1358,11 @@ impl Reader {
     source_timestamp: Option<Timestamp>,
     writer_guid: GUID,
     writer_sn: SequenceNumber,
+    coherent_set: Option<SequenceNumber>,
   ) {
 
-    let cache_change = CacheChange::new(writer_guid, writer_sn, source_timestamp, data);
+    let mut cache_change = CacheChange::new(writer_guid, writer_sn, source_timestamp, data);
+    cache_change.coherent_set = coherent_set;
     let mut cache = match self.dds_cache.write() {
       Ok(rwlock) => rwlock,
       // TODO: Should we panic here? Are we allowed to continue with poisoned DDSCache?
@@ -823,6 +1374,8 @@ impl Reader {
   // notifies DataReaders (or any listeners that history cache has changed for this reader)
   // likely use of mio channel
   pub fn notify_cache_change(&self) {
+    // Async tasks do not wait on the channel, so wake them in any case.
+    self.data_reader_wakers.wake_all();
     match self.notification_sender.try_send(()) {
       Ok(()) => (),
       Err(mio_channel::TrySendError::Full(_)) => (), // This is harmless. There is a notification in already.
@@ -842,6 +1395,21 @@ impl Reader {
     let flags = BitFlags::<ACKNACK_Flags>::from_flag(ACKNACK_Flags::Endianness)
       | BitFlags::<ACKNACK_Flags>::from_flag(ACKNACK_Flags::Final);
 
+    if let Some(m) = acknack.create_submessage(flags) {
+      self.send_to_writer(m, &mr_state)
+    }
+  }
+
+  fn send_nackfrag(&self, nackfrag: NackFrag, mr_state: &MessageReceiverState) {
+    let flags = BitFlags::<NACKFRAG_Flags>::from_flag(NACKFRAG_Flags::Endianness);
+
+    if let Some(m) = nackfrag.create_submessage(flags) {
+      self.send_to_writer(m, mr_state)
+    }
+  }
+
+  // Sends the submessage to the writer that sent us the message we are responding to.
+  fn send_to_writer(&self, submessage: SubMessage, mr_state: &MessageReceiverState) {
     let infodst_flags =
       BitFlags::<INFODESTINATION_Flags>::from_flag(INFODESTINATION_Flags::Endianness);
 
@@ -861,13 +1429,8 @@ impl Reader {
       None => return,
     };
 
-    match acknack.create_submessage(flags) {
-      Some(m) => message.add_submessage(m),
-      None => return,
-    };
+    message.add_submessage(submessage);
 
-    /*let mut bytes = message.serialize_header();
-    bytes.extend_from_slice(&submessage.serialize_msg()); */
     let bytes = message
       .write_to_vec_with_ctx(Endianness::LittleEndian)
       .unwrap();
@@ -984,6 +1547,8 @@ mod tests {
   use crate::dds::statusevents::DataReaderStatus;
   use crate::structure::topic_kind::TopicKind;
   use crate::dds::typedesc::TypeDesc;
+  use crate::dds::traits::key::Key;
+  use crate::messages::submessages::submessage_elements::parameter::Parameter;
 
   #[test]
   fn rtpsreader_notification() {
@@ -1009,6 +1574,10 @@ mod tests {
       topic_name: "test".to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
     let mut reader = Reader::new(
       reader_ing,
@@ -1068,6 +1637,10 @@ mod tests {
       topic_name: "test".to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
     let mut new_reader = Reader::new(
       reader_ing,
@@ -1137,6 +1710,10 @@ mod tests {
       topic_name: "test".to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
     let mut new_reader = Reader::new(
       reader_ing,
@@ -1174,7 +1751,7 @@ mod tests {
       last_sn: SequenceNumber::from(0),
       count: 1,
     };
-    assert!(!new_reader.handle_heartbeat_msg(hb_new, true, mr_state.clone())); // should be false, no ack
+    assert!(!new_reader.handle_heartbeat_msg(hb_new, true, false, mr_state.clone())); // should be false, no ack
 
     let hb_one = Heartbeat {
       reader_id: new_reader.get_entity_id(),
@@ -1183,7 +1760,7 @@ mod tests {
       last_sn: SequenceNumber::from(1),
       count: 2,
     };
-    assert!(new_reader.handle_heartbeat_msg(hb_one, false, mr_state.clone())); // Should send an ack_nack
+    assert!(new_reader.handle_heartbeat_msg(hb_one, false, false, mr_state.clone())); // Should send an ack_nack
 
     // After ack_nack, will receive the following change
     let change = CacheChange::new(
@@ -1207,7 +1784,7 @@ mod tests {
       last_sn: SequenceNumber::from(1),
       count: 2,
     };
-    assert!(!new_reader.handle_heartbeat_msg(hb_one2, false, mr_state.clone())); // No acknack
+    assert!(!new_reader.handle_heartbeat_msg(hb_one2, false, false, mr_state.clone())); // No acknack
 
     let hb_3_1 = Heartbeat {
       reader_id: new_reader.get_entity_id(),
@@ -1216,7 +1793,7 @@ mod tests {
       last_sn: SequenceNumber::from(3),  // writer has written 3 samples
       count: 3,
     };
-    assert!(new_reader.handle_heartbeat_msg(hb_3_1, false, mr_state.clone())); // Should send an ack_nack
+    assert!(new_reader.handle_heartbeat_msg(hb_3_1, false, false, mr_state.clone())); // Should send an ack_nack
 
     // After ack_nack, will receive the following changes
     let change = CacheChange::new(
@@ -1252,11 +1829,274 @@ mod tests {
       last_sn: SequenceNumber::from(3),  // writer has written 3 samples
       count: 4,
     };
-    assert!(new_reader.handle_heartbeat_msg(hb_none, false, mr_state)); // Should sen acknack
+    assert!(new_reader.handle_heartbeat_msg(hb_none, false, false, mr_state)); // Should sen acknack
 
     assert_eq!(new_reader.sent_ack_nack_count, 3);
   }
 
+  #[test]
+  fn rtpsreader_heartbeatfrag_requests_missing_fragments() {
+    use bytes::Bytes;
+    use crate::network::udp_listener::UDPListener;
+    use crate::serialization::submessage::SubmessageBody;
+    use crate::structure::locator::Locator;
+
+    let mut listener = UDPListener::new_unicast(Token(0), "127.0.0.1", 10701).unwrap();
+    let new_guid = GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED);
+    let (send, _rec) = mio_channel::sync_channel::<()>(100);
+    let (status_sender, _status_reciever) = mio_extras::channel::sync_channel::<DataReaderStatus>(100);
+    let (_reader_command_sender, reader_command_receiver) =
+      mio_channel::sync_channel::<ReaderCommand>(10);
+
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    dds_cache.write().unwrap().add_new_topic(
+      &"test".to_string(),
+      TopicKind::NoKey,
+      TypeDesc::new("testi"),
+    );
+    let reader_ing = ReaderIngredients {
+      guid: new_guid,
+      notification_sender: send,
+      status_sender,
+      topic_name: "test".to_string(),
+      qos_policy: QosPolicies::qos_none(),
+      data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
+    };
+    let mut reader = Reader::new(
+      reader_ing,
+      dds_cache,
+      Rc::new(UDPSender::new_with_random_port().unwrap()),
+      mio_extras::timer::Builder::default().build(),
+    );
+
+    let writer_guid = GUID {
+      guidPrefix: GuidPrefix::new(&[1; 12]),
+      entityId: EntityId::createCustomEntityID([1; 3], EntityKind::WRITER_NO_KEY_USER_DEFINED),
+    };
+    let mr_state = MessageReceiverState {
+      source_guid_prefix: writer_guid.guidPrefix,
+      ..Default::default()
+    };
+    let writer_locator = Locator::from(std::net::SocketAddr::new("127.0.0.1".parse().unwrap(), 10701));
+    reader.matched_writer_add(writer_guid, EntityId::ENTITYID_UNKNOWN, 
+      vec![writer_locator], LocatorList::new());
+
+    // Fragments 1 and 3 of 4 arrive.
+    for frag_num in &[1u32, 3] {
+      let datafrag = DataFrag {
+        writer_id: writer_guid.entityId,
+        writer_sn: SequenceNumber::from(1),
+        fragment_starting_num: FragmentNumber::from(*frag_num),
+        fragments_in_submessage: 1,
+        data_size: 400,
+        fragment_size: 100,
+        serialized_payload: Bytes::from(vec![0u8; 100]),
+        ..Default::default()
+      };
+      reader.handle_datafrag_msg(datafrag, BitFlags::from_flag(DATAFRAG_Flags::Endianness), 
+        mr_state.clone());
+    }
+    let heartbeatfrag = HeartbeatFrag {
+      reader_id: reader.get_entity_id(),
+      writer_id: writer_guid.entityId,
+      writer_sn: SequenceNumber::from(1),
+      last_fragment_num: FragmentNumber::from(4u32),
+      count: 1,
+    };
+    reader.handle_heartbeatfrag_msg(heartbeatfrag, mr_state);
+
+    std::thread::sleep(StdDuration::from_millis(100));
+    let nackfrags: Vec<NackFrag> = listener.get_messages().into_iter()
+      .flat_map( |datagram| Message::read_from_buffer(datagram).unwrap().submessages )
+      .filter_map( |submessage| match submessage.body {
+        SubmessageBody::Entity(EntitySubmessage::NackFrag(nackfrag, _)) => Some(nackfrag),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(nackfrags.len(), 1);
+    assert_eq!(nackfrags[0].writer_sn, SequenceNumber::from(1));
+    assert_eq!(nackfrags[0].fragment_number_state.iter().collect::<Vec<_>>(),
+      vec![FragmentNumber::from(2u32), FragmentNumber::from(4u32)]);
+  }
+
+  #[test]
+  fn rtpsreader_forgets_timed_out_historical_data_waiters() {
+    let new_guid = GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED);
+    let (send, _rec) = mio_channel::sync_channel::<()>(100);
+    let (status_sender, _status_reciever) = mio_extras::channel::sync_channel::<DataReaderStatus>(100);
+    let (reader_command_sender, reader_command_receiver) =
+      mio_channel::sync_channel::<ReaderCommand>(10);
+
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    dds_cache.write().unwrap().add_new_topic(
+      &"test".to_string(),
+      TopicKind::NoKey,
+      TypeDesc::new("testi"),
+    );
+    let qos = QosPolicies::builder()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .durability(policy::Durability::TransientLocal)
+      .build();
+    let reader_ing = ReaderIngredients {
+      guid: new_guid,
+      notification_sender: send,
+      status_sender,
+      topic_name: "test".to_string(),
+      qos_policy: qos.clone(),
+      data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
+    };
+    let mut reader = Reader::new(
+      reader_ing,
+      dds_cache,
+      Rc::new(UDPSender::new_with_random_port().unwrap()),
+      mio_extras::timer::Builder::default().build(),
+    );
+
+    // A durable Writer, which has not yet told what historical data it has.
+    let writer_guid = GUID {
+      guidPrefix: GuidPrefix::new(&[1; 12]),
+      entityId: EntityId::createCustomEntityID([1; 3], EntityKind::WRITER_NO_KEY_USER_DEFINED),
+    };
+    let proxy = RtpsWriterProxy::new(writer_guid, LocatorList::new(), LocatorList::new(), 
+      EntityId::ENTITYID_UNKNOWN);
+    reader.update_writer_proxy(proxy, qos);
+
+    let mut waiting = Vec::new();
+    for deadline in &[Some(std::time::Instant::now()), None] {
+      let (complete, complete_receiver) = mio_channel::sync_channel::<()>(1);
+      reader_command_sender
+        .send(ReaderCommand::WaitForHistoricalData { complete, deadline: *deadline })
+        .unwrap();
+      waiting.push(complete_receiver);
+      std::thread::sleep(StdDuration::from_millis(10));
+      reader.process_command();
+    }
+    // The first one has timed out.
+    assert_eq!(reader.historical_data_waiters.len(), 1);
+    assert!(waiting.iter().all( |receiver| receiver.try_recv().is_err() ));
+  }
+
+  // The first payload byte is the key of the test samples.
+  fn test_key_hasher(ddsdata: &DDSData) -> Option<KeyHash> {
+    ddsdata.serialized_payload().map( |sp| (sp.value[0] as i32).into_hash_key() )
+  }
+
+  #[test]
+  fn rtpsreader_resource_limits_reject_before_ack() {
+    let new_guid = GUID::dummy_test_guid(EntityKind::READER_WITH_KEY_USER_DEFINED);
+    let (send, _rec) = mio_channel::sync_channel::<()>(100);
+    let (status_sender, status_receiver) = mio_extras::channel::sync_channel::<DataReaderStatus>(100);
+    let (_reader_command_sender, reader_command_receiver) =
+      mio_channel::sync_channel::<ReaderCommand>(10);
+
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    dds_cache.write().unwrap().add_new_topic(
+      &"test".to_string(),
+      TopicKind::WithKey,
+      TypeDesc::new("testi"),
+    );
+    let qos = QosPolicies::builder()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .resource_limits(policy::ResourceLimits {
+        max_samples: 1,
+        max_instances: 2,
+        max_samples_per_instance: 1,
+      })
+      .build();
+    let history_usage = Arc::new(Mutex::new(ReaderHistoryUsage::new(Some(test_key_hasher))));
+    let reader_ing = ReaderIngredients {
+      guid: new_guid,
+      notification_sender: send,
+      status_sender,
+      topic_name: "test".to_string(),
+      qos_policy: qos.clone(),
+      data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: history_usage.clone(),
+      data_reader_wakers: TaskWakers::new(),
+    };
+    let mut reader = Reader::new(
+      reader_ing,
+      dds_cache,
+      Rc::new(UDPSender::new(0).unwrap()),
+      mio_extras::timer::Builder::default().build(),
+    );
+
+    let writer_guid = GUID {
+      guidPrefix: GuidPrefix::new(&[1; 12]),
+      entityId: EntityId::createCustomEntityID([1; 3], EntityKind::WRITER_WITH_KEY_USER_DEFINED),
+    };
+    let mr_state = MessageReceiverState {
+      source_guid_prefix: writer_guid.guidPrefix,
+      ..Default::default()
+    };
+    let proxy = RtpsWriterProxy::new(writer_guid, LocatorList::new(), LocatorList::new(), 
+      EntityId::ENTITYID_UNKNOWN);
+    reader.update_writer_proxy(proxy, qos);
+    let data = |sn: i64, key: u8| Data {
+      writer_id: writer_guid.entityId,
+      writer_sn: SequenceNumber::from(sn),
+      serialized_payload: Some(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![key])),
+      ..Default::default()
+    };
+    let flags = DATA_Flags::Endianness | DATA_Flags::Data;
+
+    reader.handle_data_msg(data(1, 1), flags, mr_state.clone());
+    reader.handle_data_msg(data(2, 2), flags, mr_state.clone());
+
+    // The second sample is not stored, and is not acknowledged.
+    let writer_proxy = reader.matched_writer_lookup(writer_guid).unwrap();
+    assert!(writer_proxy.contains_change(SequenceNumber::from(1)));
+    assert!(! writer_proxy.contains_change(SequenceNumber::from(2)));
+    assert_eq!(writer_proxy.all_ackable_before(), SequenceNumber::from(2));
+    assert!(! reader.seqnum_instant_map.contains_key(&SequenceNumber::from(2)));
+    let rejected_status = std::iter::from_fn( || status_receiver.try_recv().ok() )
+      .find( |status| matches!(status, DataReaderStatus::SampleRejected{..}) );
+    match rejected_status {
+      Some(DataReaderStatus::SampleRejected { count, last_reason, last_instance_key }) => {
+        assert_eq!((count.count(), count.count_change()), (1, 1));
+        assert_eq!(last_reason, SampleRejectedStatusKind::BySamplesLimit);
+        assert_eq!(last_instance_key, 2i32.into_hash_key());
+      }
+      other => panic!("Expected SampleRejected, got {:?}", other),
+    }
+
+    // When the DataReader has made room, the resent sample is accepted.
+    history_usage.lock().unwrap()
+      .datareader_updated(vec![(1i32.into_hash_key(), None)], Timestamp::now(), Vec::new());
+    reader.handle_data_msg(data(2, 2), flags, mr_state.clone());
+    assert!(reader.matched_writer_lookup(writer_guid).unwrap().contains_change(SequenceNumber::from(2)));
+
+    // The key hash sent by the Writer is used instead of deserializing the key.
+    let key_hash_param = Parameter {
+      parameter_id: ParameterId::PID_KEY_HASH,
+      value: 3i32.into_hash_key().to_vec(),
+    };
+    let data_with_key_hash = Data {
+      inline_qos: Some(ParameterList { parameters: vec![key_hash_param] }),
+      ..data(3, 2)
+    };
+    reader.handle_data_msg(data_with_key_hash, flags | DATA_Flags::InlineQos, mr_state);
+    assert!(! reader.matched_writer_lookup(writer_guid).unwrap().contains_change(SequenceNumber::from(3)));
+    match status_receiver.try_recv() {
+      Ok(DataReaderStatus::SampleRejected { last_reason, last_instance_key, .. }) => {
+        assert_eq!(last_reason, SampleRejectedStatusKind::BySamplesLimit);
+        assert_eq!(last_instance_key, 3i32.into_hash_key());
+      }
+      other => panic!("Expected SampleRejected, got {:?}", other),
+    }
+  }
+
   #[test]
   fn rtpsreader_handle_gap() {
     let new_guid = GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED);
@@ -1279,6 +2119,10 @@ mod tests {
       topic_name: "test".to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
     let mut reader = Reader::new(
       reader_ing,
@@ -6,18 +6,20 @@ use crate::{
   network::constant::get_user_traffic_unicast_port,
   network::util::get_local_multicast_locators,
   network::util::get_local_unicast_socket_address,
+  network::util::IpMode,
   structure::{
     guid::{EntityId, GUID, EntityKind},
     locator::{Locator, LocatorList},
-    sequence_number::{SequenceNumber},
+    sequence_number::{SequenceNumber, FragmentNumber},
   },
   dds::qos::{QosPolicies,},
   messages::submessages::{submessage::AckSubmessage},
   discovery::data_types::topic_data::DiscoveredReaderData,
+  discovery::content_filter_property::ContentFilterProperty,
 };
 
 use std::{
-  collections::BTreeSet,
+  collections::{BTreeSet, BTreeMap},
   net::{SocketAddr, Ipv4Addr},
 };
 
@@ -47,10 +49,19 @@ pub(crate) struct RtpsReaderProxy {
   // List of SequenceNumbers to be sent to Reader. Both unsent and requested by ACKNACK.
   pub unsent_changes: BTreeSet<SequenceNumber>,
 
+  // Fragments of (fragmented) changes requested by NACKFRAG.
+  pub requested_fragments: BTreeMap<SequenceNumber, BTreeSet<FragmentNumber>>,
+
   // true = send repair data messages due to NACKs, buffer messages by DataWriter
   // false = send data messages directly from DataWriter
   pub repair_mode : bool,
   pub qos : QosPolicies,
+
+  /// Content filter of the Reader, if it reads a ContentFilteredTopic.
+  pub content_filter: Option<ContentFilterProperty>,
+  // Changes that did not pass the content filter of the Reader. These are not
+  // relevant to the Reader, and are sent to it as GAP instead of DATA.
+  pub irrelevant_changes: BTreeSet<SequenceNumber>,
 }
 
 impl RtpsReaderProxy {
@@ -64,8 +75,11 @@ impl RtpsReaderProxy {
       is_active: true,
       all_acked_before: SequenceNumber::zero(),
       unsent_changes: BTreeSet::new(),
+      requested_fragments: BTreeMap::new(),
       repair_mode: false,
       qos,
+      content_filter: None,
+      irrelevant_changes: BTreeSet::new(),
     }
   }
 
@@ -73,12 +87,14 @@ impl RtpsReaderProxy {
     &self.qos
   }
 
-  pub fn from_reader(reader: &ReaderIngredients, domain_id: u16, participant_id: u16) -> RtpsReaderProxy {
+  pub fn from_reader(reader: &ReaderIngredients, domain_id: u16, participant_id: u16, ip_mode: IpMode)
+    -> RtpsReaderProxy
+  {
     let unicast_locator_list =
-      get_local_unicast_socket_address(get_user_traffic_unicast_port(domain_id, participant_id));
+      get_local_unicast_socket_address(get_user_traffic_unicast_port(domain_id, participant_id), ip_mode);
 
     let multicast_locator_list =
-      get_local_multicast_locators(get_user_traffic_multicast_port(domain_id));
+      get_local_multicast_locators(get_user_traffic_multicast_port(domain_id), ip_mode);
 
     RtpsReaderProxy {
       remote_reader_guid: reader.guid,
@@ -89,8 +105,11 @@ impl RtpsReaderProxy {
       is_active: true,
       all_acked_before: SequenceNumber::zero(),
       unsent_changes: BTreeSet::new(),
+      requested_fragments: BTreeMap::new(),
       repair_mode: false,
       qos: reader.qos_policy.clone(),
+      content_filter: None,
+      irrelevant_changes: BTreeSet::new(),
     }
   }
 
@@ -123,8 +142,11 @@ impl RtpsReaderProxy {
       is_active: true,
       all_acked_before: SequenceNumber::zero(),
       unsent_changes: BTreeSet::new(),
+      requested_fragments: BTreeMap::new(),
       repair_mode: false,
       qos: discovered_reader_data.subscription_topic_data.generate_qos(),
+      content_filter: discovered_reader_data.content_filter.clone(),
+      irrelevant_changes: BTreeSet::new(),
     }
   }
 
@@ -153,14 +175,17 @@ impl RtpsReaderProxy {
       is_active: true,
       all_acked_before: SequenceNumber::zero(),
       unsent_changes: BTreeSet::new(),
+      requested_fragments: BTreeMap::new(),
       repair_mode: false,
       qos: QosPolicies::qos_none(),
+      content_filter: None,
+      irrelevant_changes: BTreeSet::new(),
     }
   }
 
 
   pub fn can_send(&self) -> bool {
-    ! self.unsent_changes.is_empty()
+    ! self.unsent_changes.is_empty() || ! self.requested_fragments.is_empty()
   }
 
   pub fn handle_ack_nack(&mut self, ack_submessage: &AckSubmessage, last_available:SequenceNumber) {
@@ -170,6 +195,8 @@ impl RtpsReaderProxy {
         // clean up unsent_changes: 
         // The handy split_off function "Returns everything after the given key, including the key."
         self.unsent_changes = self.unsent_changes.split_off(&self.all_acked_before);
+        self.requested_fragments = self.requested_fragments.split_off(&self.all_acked_before);
+        self.irrelevant_changes = self.irrelevant_changes.split_off(&self.all_acked_before);
 
         // Insert the requested changes.
         for nack_sn in acknack.reader_sn_state.iter() {
@@ -184,9 +211,23 @@ impl RtpsReaderProxy {
         }
       }
 
-      AckSubmessage::NackFrag_Variant(_nack_frag) => {
-        // TODO
-        error!("NACKFRAG not implemented")
+      AckSubmessage::NackFrag_Variant(nack_frag) => {
+        if nack_frag.writer_sn > last_available {
+          warn!("ReaderProxy {:?} asks fragments of {:?} but I have only up to {:?}. NACKFRAG = {:?}", 
+            self.remote_reader_guid, nack_frag.writer_sn, last_available, nack_frag);
+          return
+        }
+        if nack_frag.writer_sn < self.all_acked_before {
+          // Reader has acked this already. Nothing to do.
+          return
+        }
+        // The Reader has the rest of the fragments, so only the requested ones are sent,
+        // not the whole change.
+        self.unsent_changes.remove(&nack_frag.writer_sn);
+        self.requested_fragments
+          .entry(nack_frag.writer_sn)
+          .or_default()
+          .extend( nack_frag.fragment_number_state.iter() );
       }
     }
   }
@@ -1,20 +1,20 @@
-use core::ops::Bound::Excluded;
-use core::ops::Bound::Unbounded;
 use crate::structure::locator::LocatorList;
 use crate::structure::guid::{EntityId, GUID};
 use crate::{
   discovery::data_types::topic_data::DiscoveredWriterData,
-  structure::sequence_number::{SequenceNumber},
+  structure::sequence_number::{SequenceNumber, FragmentNumber},
   structure::time::Timestamp,
+  structure::duration::Duration,
 };
-use crate::dds::fragment_assembler::FragmentAssembler;
+use crate::dds::qos::policy::Liveliness;
+use crate::dds::fragment_assembler::{FragmentAssembler, FRAGMENT_ASSEMBLY_TIMEOUT};
 use crate::messages::submessages::submessages::DataFrag;
 use crate::messages::submessages::submessages::DATAFRAG_Flags;
 use crate::dds::ddsdata::DDSData;
 
 use std::collections::BTreeMap;
 use enumflags2::BitFlags;
-use std::cmp::max;
+use std::cmp::{max, min};
 
 #[allow(unused_imports)]
 use log::{error,warn,debug,trace,info};
@@ -39,14 +39,39 @@ pub(crate) struct RtpsWriterProxy {
   changes: BTreeMap<SequenceNumber, Timestamp>,
   // The changes map is cleaned on heartbeat messages. The changes no longer available are dropped.
 
+  /// SequenceNumbers that the Writer has declared irrelevant (by GAP), but are at or above ack_base.
+  /// These are disjoint ranges, from the key until before the value, so that a large GAP
+  /// takes no more room than a small one.
+  irrelevant_changes: BTreeMap<SequenceNumber, SequenceNumber>,
+
   pub received_heartbeat_count: i32,
 
+  pub received_heartbeatfrag_count: i32,
+
   pub sent_ack_nack_count: i32,
 
   ack_base : SequenceNumber, // We can ACK everything before this number.
   // ack_base can be increased from N-1 to N, if we receive DATA with SequenceNumber N-1
   // heartbeat(first,last) => ack_base can be increased to first.
   // GAP is treated like receiving a message.
+
+  /// The Writer is reliable and offers TRANSIENT_LOCAL or stronger Durability,
+  /// i.e. it sends us the historical data it had when we were matched.
+  pub is_durable: bool,
+
+  /// Strength offered by the Writer, if it has EXCLUSIVE Ownership. Zero otherwise.
+  pub ownership_strength: i32,
+
+  /// Liveliness offered by the Writer. None means the Writer never loses liveliness.
+  pub liveliness: Option<Liveliness>,
+  // When the Writer last asserted its liveliness, by any means.
+  last_liveliness_assertion: Timestamp,
+  /// Has the Writer asserted its liveliness within its lease duration?
+  pub is_alive: bool,
+
+  // Last SequenceNumber the Writer had available when we were matched. This is
+  // learned from the first HEARTBEAT. None = not known yet.
+  historical_data_last: Option<SequenceNumber>,
   
   //pub qos : QosPolicies,
   fragment_assembler: Option<FragmentAssembler>,
@@ -65,9 +90,17 @@ impl RtpsWriterProxy {
       multicast_locator_list,
       remote_group_entity_id,
       changes: BTreeMap::new(),
+      irrelevant_changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
       ack_base: SequenceNumber::default(),
+      is_durable: false,
+      ownership_strength: 0,
+      liveliness: None,
+      last_liveliness_assertion: Timestamp::now(),
+      is_alive: true,
+      historical_data_last: None,
       fragment_assembler: None,
     }
   }
@@ -80,6 +113,36 @@ impl RtpsWriterProxy {
     self.unicast_locator_list = other.unicast_locator_list;
     self.multicast_locator_list = other.multicast_locator_list;
     self.remote_group_entity_id = other.remote_group_entity_id;
+    self.is_durable = other.is_durable;
+    self.ownership_strength = other.ownership_strength;
+    self.liveliness = other.liveliness;
+  }
+
+  pub fn lease_duration(&self) -> Option<Duration> {
+    match self.liveliness {
+      Some(Liveliness::Automatic { lease_duration })
+      | Some(Liveliness::ManualByParticipant { lease_duration })
+      | Some(Liveliness::ManualByTopic { lease_duration }) 
+        if lease_duration != Duration::DURATION_INFINITE => Some(lease_duration),
+      _ => None,
+    }
+  }
+
+  // Returns true, if the Writer was not alive before, i.e. liveliness was regained.
+  pub fn assert_liveliness(&mut self, now: Timestamp) -> bool {
+    self.last_liveliness_assertion = now;
+    let regained = ! self.is_alive;
+    self.is_alive = true;
+    regained
+  }
+
+  // When will the lease of an alive Writer expire, if it does not assert liveliness before that.
+  pub fn lease_expiry(&self) -> Option<Timestamp> {
+    if self.is_alive {
+      self.lease_duration().map( |lease| self.last_liveliness_assertion + lease )
+    } else {
+      None
+    }
   }
 
   pub fn last_change_timestamp(&self) -> Option<Timestamp> {
@@ -97,12 +160,20 @@ impl RtpsWriterProxy {
   ) -> Vec<SequenceNumber> {
     let mut missing_seqnums = Vec::with_capacity(32); // out of hat value
 
+    // Everything before ack_base has been received or declared irrelevant.
+    let hb_first_sn = max(hb_first_sn, self.ack_base);
+    if hb_last_sn < hb_first_sn {
+      return missing_seqnums
+    }
     let mut we_have = self.changes
         .range(SequenceNumber::range_inclusive(hb_first_sn,hb_last_sn))
         .map(|e| *e.0 );
     let mut have_head = we_have.next();
 
     for s in SequenceNumber::range_inclusive(hb_first_sn,hb_last_sn)  {
+      if self.irrelevant_range_end(s).is_some() {
+        continue // Writer told us not to expect this.
+      }
       match have_head {
         None => missing_seqnums.push(s),
         Some(have_sn) =>
@@ -125,11 +196,7 @@ impl RtpsWriterProxy {
       return false
     }
 
-    let we_have = self.changes
-      .range(SequenceNumber::range_inclusive(hb_first_sn,hb_last_sn))
-      .map(|e| *e.0 );
-    SequenceNumber::range_inclusive(hb_first_sn,hb_last_sn)
-      .ne(we_have)
+    ! self.get_missing_sequence_numbers(hb_first_sn, hb_last_sn).is_empty()
   }
 
   pub fn contains_change(&self, seqnum: SequenceNumber) -> bool {
@@ -142,20 +209,55 @@ impl RtpsWriterProxy {
     // We get to advance ack_base if it was equal to seq_num
     // If ack_base < seq_num, we are still missing seq_num-1 or others below
     // If ack_base > seq_num, this is either a duplicate or ack_base was wrong.
-    // Remember, ack_base is the SN one past the last received/irrelevant SN.
     if seq_num == self.ack_base {
-      let mut s = seq_num;
-      for (&sn,_) in self.changes.range((Excluded(&seq_num), Unbounded)) {
-        if sn == s + SequenceNumber::new(1) {
-          // got consecutive number from previous
-          s = s + SequenceNumber::new(1) // and continue looping
-        } else {
-          break // not consecutive
-        }
-      } // end for
-      // Now we have received everything up to and including s. Ack base is one up from that.
-      self.ack_base = s + SequenceNumber::new(1)
+      self.advance_ack_base()
+    }
+  }
+
+  // Remember, ack_base is the SN one past the last received/irrelevant SN.
+  // Move it over any consecutive received or irrelevant SNs.
+  fn advance_ack_base(&mut self) {
+    let mut s = self.ack_base;
+    loop {
+      if self.changes.contains_key(&s) {
+        s = s + SequenceNumber::new(1)
+      } else if let Some(end) = self.irrelevant_range_end(s) {
+        s = end
+      } else {
+        break // not consecutive
+      }
     }
+    // Now we have received everything before s. No irrelevant range contains s, so
+    // the ranges starting before it are wholly before it.
+    self.ack_base = s;
+    self.irrelevant_changes = self.irrelevant_changes.split_off(&self.ack_base);
+  }
+
+  // If the SequenceNumber is irrelevant, where does its irrelevant range end?
+  fn irrelevant_range_end(&self, seq_num: SequenceNumber) -> Option<SequenceNumber> {
+    self.irrelevant_changes.range(..=seq_num).next_back()
+      .filter( |(_, end)| seq_num < **end )
+      .map( |(_, end)| *end )
+  }
+
+  // Marks SequenceNumbers from start until before end as irrelevant. Overlapping and
+  // adjacent ranges are merged, so that the ranges stay disjoint.
+  fn add_irrelevant_range(&mut self, start: SequenceNumber, end: SequenceNumber) {
+    let (mut start, mut end) = (max(start, self.ack_base), end);
+    if end <= start {
+      return
+    }
+    let merged: Vec<SequenceNumber> = self.irrelevant_changes.range(..=end).rev()
+      .take_while( |(_, range_end)| **range_end >= start )
+      .map( |(range_start, _)| *range_start )
+      .collect();
+    for range_start in merged {
+      if let Some(range_end) = self.irrelevant_changes.remove(&range_start) {
+        start = min(start, range_start);
+        end = max(end, range_end);
+      }
+    }
+    self.irrelevant_changes.insert(start, end);
   }
 
   pub fn available_changes_max(&self) -> Option<SequenceNumber> {
@@ -169,8 +271,10 @@ impl RtpsWriterProxy {
   }
 
   pub fn set_irrelevant_change(&mut self, seq_num: SequenceNumber) -> Option<Timestamp> {
-    self.changes.remove(&seq_num)
-    // TODO: Update ack_base
+    self.add_irrelevant_range(seq_num, seq_num + SequenceNumber::new(1));
+    let removed = self.changes.remove(&seq_num);
+    self.advance_ack_base();
+    removed
   }
 
   pub fn irrelevant_changes_range(&mut self, 
@@ -182,9 +286,8 @@ impl RtpsWriterProxy {
     let removed = removed_and_after;
     self.changes.append(&mut after);
 
-    if self.ack_base >= remove_from {
-      self.ack_base = max( remove_until_before , self.ack_base)
-    }
+    self.add_irrelevant_range(remove_from, remove_until_before);
+    self.advance_ack_base();
 
     removed
   }
@@ -198,7 +301,13 @@ impl RtpsWriterProxy {
     let remaining_changes = self.changes.split_off(&smallest_seqnum);
     let irrelevant = std::mem::replace(&mut self.changes, remaining_changes); 
 
+    // Also partially received samples are now irrelevant.
+    if let Some(fa) = self.fragment_assembler.as_mut() {
+      fa.remove_before(smallest_seqnum)
+    }
+
     self.ack_base = max( smallest_seqnum , self.ack_base);
+    self.advance_ack_base();
 
     irrelevant
   }
@@ -218,9 +327,17 @@ impl RtpsWriterProxy {
         .multicast_locator_list
         .clone(),
       changes: BTreeMap::new(),
+      irrelevant_changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
       ack_base: SequenceNumber::default(),
+      is_durable: false,
+      ownership_strength: 0,
+      liveliness: None,
+      last_liveliness_assertion: Timestamp::now(),
+      is_alive: true,
+      historical_data_last: None,
       fragment_assembler: None,
     }
   } // fn
@@ -231,7 +348,10 @@ impl RtpsWriterProxy {
     -> Option<DDSData> 
   {
     match self.fragment_assembler {
-      Some(ref mut fa) => fa.new_datafrag(datafrag, flags),
+      Some(ref mut fa) => {
+        fa.garbage_collect_before(Timestamp::now() - FRAGMENT_ASSEMBLY_TIMEOUT);
+        fa.new_datafrag(datafrag, flags)
+      }
       None => {
         let mut fa = FragmentAssembler::new(datafrag.fragment_size);
         //TODO: Test that the fragment size is not zero
@@ -242,4 +362,68 @@ impl RtpsWriterProxy {
     }
   } // fn
 
+  // Some, but not all, fragments of this sample have been received.
+  pub fn is_partially_received(&self, seq_num: SequenceNumber) -> bool {
+    self.fragment_assembler.as_ref()
+      .map( |fa| fa.is_partially_received(seq_num) )
+      .unwrap_or(false)
+  }
+
+  pub fn missing_frags_for(&self, seq_num: SequenceNumber) -> Vec<FragmentNumber> {
+    self.fragment_assembler.as_ref()
+      .map( |fa| fa.missing_frags_for(seq_num) )
+      .unwrap_or_default()
+  }
+
+  // Called on HEARTBEAT. The first one tells how much historical data the Writer has.
+  pub fn set_historical_data_last(&mut self, last_sn: SequenceNumber) {
+    if self.historical_data_last.is_none() {
+      self.historical_data_last = Some(last_sn);
+    }
+  }
+
+  // Have we received (or found irrelevant) all the historical data from a durable Writer?
+  // Non-durable Writers do not have historical data to send.
+  pub fn historical_data_received(&self) -> bool {
+    if ! self.is_durable {
+      return true
+    }
+    match self.historical_data_last {
+      None => false, // No HEARTBEAT yet, so do not know.
+      Some(last) => last < self.ack_base,
+    }
+  }
+
+  pub fn discard_expired_fragments(&mut self) {
+    if let Some(fa) = self.fragment_assembler.as_mut() {
+      fa.garbage_collect_before(Timestamp::now() - FRAGMENT_ASSEMBLY_TIMEOUT)
+    }
+  }
+
 } // impl
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn irrelevant_ranges_advance_ack_base() {
+    let sn = |n: i64| SequenceNumber::from(n);
+    let mut wp = RtpsWriterProxy::new(GUID::default(), vec![], vec![], EntityId::ENTITYID_UNKNOWN);
+
+    // A huge GAP after a missing change is kept as a single range.
+    wp.irrelevant_changes_range(sn(3), sn(1_000_000_000_000));
+    assert_eq!(wp.irrelevant_changes.len(), 1);
+    assert_eq!(wp.get_missing_sequence_numbers(sn(1), sn(5)), vec![sn(1), sn(2)]);
+
+    // Overlapping and adjacent ranges are merged.
+    wp.set_irrelevant_change(sn(2));
+    wp.irrelevant_changes_range(sn(10), sn(20));
+    assert_eq!(wp.irrelevant_changes.len(), 1);
+    assert_eq!(wp.all_ackable_before(), sn(1));
+
+    wp.received_changes_add(sn(1), Timestamp::now());
+    assert_eq!(wp.all_ackable_before(), sn(1_000_000_000_000));
+    assert!(wp.irrelevant_changes.is_empty());
+  }
+}
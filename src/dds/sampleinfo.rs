@@ -132,68 +132,54 @@ pub struct SampleInfo {
 
 #[allow(clippy::new_without_default)]
 impl SampleInfo {
-
-  /* These accessor methods should not be needed, as the SampleInfo structure is likely
-     not about to change too much, as it is specified by the DDS spec.
-
   pub fn sample_state(&self) -> SampleState {
     self.sample_state
   }
 
-  pub fn set_sample_state(&mut self, sample_state: SampleState) {
-    self.sample_state = sample_state
-  }
-
   pub fn view_state(&self) -> ViewState {
     self.view_state
   }
 
-  pub fn set_view_state(&mut self, view_state: ViewState) {
-    self.view_state = view_state
-  }
-
   pub fn instance_state(&self) -> InstanceState {
     self.instance_state
   }
 
-  pub fn set_instance_state(&mut self, instance_state: InstanceState) {
-    self.instance_state = instance_state
-  }
-
+  /// How many times the instance had become alive after being disposed, when this
+  /// sample was received.
   pub fn disposed_generation_count(&self) -> i32 {
-    self.disposed_generation_count
+    self.generation_counts.disposed_generation_count
   }
 
+  /// How many times the instance had become alive after losing all its writers, when
+  /// this sample was received.
   pub fn no_writers_generation_count(&self) -> i32 {
-    self.no_writers_generation_count
+    self.generation_counts.no_writers_generation_count
   }
 
+  /// Number of samples of the same instance that follow this one in the collection
+  /// returned by `read` or `take`.
   pub fn sample_rank(&self) -> i32 {
     self.sample_rank
   }
 
+  /// Generations of the instance between this sample and the most recent sample of
+  /// the instance in the collection returned by `read` or `take`.
   pub fn generation_rank(&self) -> i32 {
     self.generation_rank
   }
 
+  /// Generations of the instance between this sample and the most recent sample of
+  /// the instance received by the DataReader.
   pub fn absolute_generation_rank(&self) -> i32 {
     self.absolute_generation_rank
   }
 
-  pub fn source_timestamp(&self) -> Timestamp {
+  pub fn source_timestamp(&self) -> Option<Timestamp> {
     self.source_timestamp
   }
 
-  pub fn set_source_timestamp(&mut self, source_timestamp: Timestamp) {
-    self.source_timestamp = source_timestamp
-  }
-
+  /// GUID of the DataWriter that wrote the sample
   pub fn publication_handle(&self) -> GUID {
     self.publication_handle
   }
-
-  pub fn set_publication_handle(&mut self, publication_handle: GUID) {
-    self.publication_handle = publication_handle
-  }
-  */
 }
@@ -8,8 +8,14 @@
 // in DDS Specification v1.4
 
 use crate::dds::qos::QosPolicyId;
+use crate::dds::traits::key::KeyHash;
+use crate::discovery::data_types::topic_data::{
+	SubscriptionBuiltinTopicData, PublicationBuiltinTopicData,
+};
+use crate::structure::guid::GUID;
 use mio::{Evented};
 use mio_extras::channel as mio_channel;
+use std::collections::VecDeque;
 
 
 /// This trait corresponds to set_listener() of the Entity class in DDS spec. 
@@ -30,6 +36,11 @@ impl<E> StatusReceiver<E> {
 	pub fn new(channel_receiver: mio_channel::Receiver<E>) -> StatusReceiver<E> {
 		StatusReceiver::<E> {	channel_receiver, enabled: false }
 	}
+
+	// Status is consumed by the application, e.g. by an async stream, instead of a mio Poll.
+	pub fn enable(&mut self) {
+		self.enabled = true;
+	}
 }
 
 impl<E> StatusEvented<E> for StatusReceiver<E> {
@@ -73,7 +84,8 @@ pub enum DataReaderStatus {
 	SampleRejected { 
 		count: CountWithChange,
 		last_reason: SampleRejectedStatusKind,
-		//last_instance_key:  
+		/// Hash of the key of the instance that the rejected sample belongs to.
+		last_instance_key: KeyHash,
 	},
 	/// Remote Writer has become active or inactive.
 	LivelinessChanged { 
@@ -117,9 +129,11 @@ pub enum DataWriterStatus {
 	LivelinessLost { 
 		count: CountWithChange 
 	},
+	/// Deadline offered by this DataWriter was missed, i.e. the instance was not
+	/// written within the Deadline period.
 	OfferedDeadlineMissed { 
 		count: CountWithChange,
-		//last_instance_key:  
+		last_instance_key: KeyHash,
 	},
 	OfferedIncompatibleQos { 
 		count: CountWithChange,
@@ -133,8 +147,69 @@ pub enum DataWriterStatus {
 	},
 }
 
+/// Changes in the DDS network, as seen by the Discovery of a DomainParticipant.
+/// These are not part of the DDS spec, which offers the built-in topics instead.
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent {
+	/// A new DomainParticipant has appeared.
+	ParticipantDiscovered { participant: GUID },
+	/// A DomainParticipant has been disposed, or its lease has expired. This 
+	/// implies that its DataReaders and DataWriters are lost, too.
+	ParticipantLost { participant: GUID },
+	/// A new DataReader has appeared, or its QoS has changed.
+	ReaderDiscovered { reader: SubscriptionBuiltinTopicData },
+	ReaderLost { reader: GUID },
+	/// A new DataWriter has appeared, or its QoS has changed.
+	WriterDiscovered { writer: PublicationBuiltinTopicData },
+	WriterLost { writer: GUID },
+	/// The application did not consume events fast enough, and this many of
+	/// the oldest ones were dropped.
+	Lagged { skipped: u64 },
+}
+
+// This many DiscoveryEvents are buffered for the application.
+const DISCOVERY_EVENT_QUEUE_LENGTH: usize = 100;
+
+// DiscoveryEvents on their way from Discovery to the application. Nothing is
+// queued before the application asks for a DiscoveryEventStream. When the
+// queue is full, the oldest event is dropped and a Lagged event is reported instead.
+#[derive(Default)]
+pub(crate) struct DiscoveryEventQueue {
+	enabled: bool,
+	events: VecDeque<DiscoveryEvent>,
+	// Events dropped since the application last received one
+	skipped: u64,
+}
+
+impl DiscoveryEventQueue {
+	pub fn enable(&mut self) {
+		self.enabled = true;
+	}
+
+	// Returns false, if the event was not queued, because no-one is listening.
+	pub fn push(&mut self, event: DiscoveryEvent) -> bool {
+		if ! self.enabled {
+			return false
+		}
+		if self.events.len() >= DISCOVERY_EVENT_QUEUE_LENGTH {
+			self.events.pop_front();
+			self.skipped += 1;
+		}
+		self.events.push_back(event);
+		true
+	}
+
+	pub fn pop(&mut self) -> Option<DiscoveryEvent> {
+		if self.skipped > 0 {
+			let skipped = std::mem::take(&mut self.skipped);
+			return Some(DiscoveryEvent::Lagged { skipped })
+		}
+		self.events.pop_front()
+	}
+}
+
 /// Helper to contain same count actions across statuses
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct CountWithChange {
 	// 2.3. Platform Specific Model defines these as "long", which appears to be 32-bit signed.
   count: i32,
@@ -160,6 +235,16 @@ impl CountWithChange {
     self.count_change
   }
 
+  // Take the count from a newer status, and add up the changes.
+  pub(crate) fn accumulate(&mut self, newer: CountWithChange) {
+    self.count = newer.count;
+    self.count_change += newer.count_change;
+  }
+
+  pub(crate) fn reset_change(&mut self) {
+    self.count_change = 0;
+  }
+
   // does this make sense?
   // pub fn increase(&mut self) {
   //   self.count += 1;
@@ -182,3 +267,115 @@ pub struct QosPolicyCount {
 	policy_id: QosPolicyId,
 	count: i32,	
 }
+
+impl QosPolicyCount {
+	pub fn policy_id(&self) -> QosPolicyId {
+		self.policy_id
+	}
+
+	pub fn count(&self) -> i32 {
+		self.count
+	}
+}
+
+// DataWriter status getters
+// These are the plain status structures from DDS spec Section 2.2.4.1
+
+/// See [`get_liveliness_lost_status`](../struct.With_Key_DataWriter.html#method.get_liveliness_lost_status)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LivelinessLostStatus {
+	pub total_count: CountWithChange,
+}
+
+/// See [`get_offered_deadline_missed_status`](../struct.With_Key_DataWriter.html#method.get_offered_deadline_missed_status)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OfferedDeadlineMissedStatus {
+	pub total_count: CountWithChange,
+	/// Hash of the key of the latest instance that missed its deadline.
+	/// None, if no deadline has been missed.
+	pub last_instance_key: Option<KeyHash>,
+}
+
+/// See [`get_offered_incompatible_qos_status`](../struct.With_Key_DataWriter.html#method.get_offered_incompatible_qos_status)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OfferedIncompatibleQosStatus {
+	pub total_count: CountWithChange,
+	/// None, if there has been no incompatible DataReader.
+	pub last_policy_id: Option<QosPolicyId>,
+	/// How many times each policy has been found incompatible.
+	pub policies: Vec<QosPolicyCount>,
+}
+
+/// See [`get_publication_matched_status`](../struct.With_Key_DataWriter.html#method.get_publication_matched_status)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PublicationMatchedStatus {
+	pub total_count: CountWithChange,
+	pub current_count: CountWithChange,
+}
+
+// Writer accumulates all its DataWriterStatus events here, so that the DataWriter
+// status getters do not depend on anyone reading the status channel.
+#[derive(Debug, Default)]
+pub(crate) struct DataWriterStatusAccumulator {
+	liveliness_lost: LivelinessLostStatus,
+	offered_deadline_missed: OfferedDeadlineMissedStatus,
+	offered_incompatible_qos: OfferedIncompatibleQosStatus,
+	publication_matched: PublicationMatchedStatus,
+}
+
+impl DataWriterStatusAccumulator {
+	pub fn new() -> DataWriterStatusAccumulator {
+		DataWriterStatusAccumulator::default()
+	}
+
+	pub fn update(&mut self, status: &DataWriterStatus) {
+		match status {
+			DataWriterStatus::LivelinessLost { count } =>
+				self.liveliness_lost.total_count.accumulate(*count),
+			DataWriterStatus::OfferedDeadlineMissed { count, last_instance_key } => {
+				self.offered_deadline_missed.total_count.accumulate(*count);
+				self.offered_deadline_missed.last_instance_key = Some(*last_instance_key);
+			}
+			DataWriterStatus::OfferedIncompatibleQos { count, last_policy_id, .. } => {
+				let status = &mut self.offered_incompatible_qos;
+				status.total_count.accumulate(*count);
+				status.last_policy_id = Some(*last_policy_id);
+				match status.policies.iter_mut().find( |p| p.policy_id == *last_policy_id ) {
+					Some(policy_count) => policy_count.count += 1,
+					None => status.policies.push(QosPolicyCount { policy_id: *last_policy_id, count: 1 }),
+				}
+			}
+			DataWriterStatus::PublicationMatched { total, current } => {
+				self.publication_matched.total_count.accumulate(*total);
+				self.publication_matched.current_count.accumulate(*current);
+			}
+		}
+	}
+
+	// The getters reset the change counts, as DDS spec requires.
+
+	pub fn liveliness_lost(&mut self) -> LivelinessLostStatus {
+		let status = self.liveliness_lost.clone();
+		self.liveliness_lost.total_count.reset_change();
+		status
+	}
+
+	pub fn offered_deadline_missed(&mut self) -> OfferedDeadlineMissedStatus {
+		let status = self.offered_deadline_missed.clone();
+		self.offered_deadline_missed.total_count.reset_change();
+		status
+	}
+
+	pub fn offered_incompatible_qos(&mut self) -> OfferedIncompatibleQosStatus {
+		let status = self.offered_incompatible_qos.clone();
+		self.offered_incompatible_qos.total_count.reset_change();
+		status
+	}
+
+	pub fn publication_matched(&mut self) -> PublicationMatchedStatus {
+		let status = self.publication_matched.clone();
+		self.publication_matched.total_count.reset_change();
+		self.publication_matched.current_count.reset_change();
+		status
+	}
+}
@@ -3,6 +3,9 @@ use std::sync::{Arc};
 
 use crate::{
   dds::{participant::*, typedesc::*, qos::*, traits::dds_entity::DDSEntity},
+  dds::content_filter::{ContentFilter, DDSSQL_FILTER_CLASS},
+  dds::values::result::{Error, Result},
+  discovery::content_filter_property::ContentFilterProperty,
 };
 
 pub use crate::structure::topic_kind::TopicKind;
@@ -132,6 +135,124 @@ impl HasQoSPolicy for Topic {
 
 //impl DDSEntity for Topic {}
 
+// ------------------------------ ContentFilteredTopic ---------------------------
+
+/// DDS ContentFilteredTopic (DDS spec 2.2.2.3.3)
+///
+/// Selects the samples of a related [Topic](struct.Topic.html) that pass a filter.
+/// The filter is a DDS-SQL expression, whose `%n` parameters are replaced with
+/// `expression_parameters[n]`. DataReaders created for a ContentFilteredTopic only
+/// get samples that pass the filter. The filter is also announced to remote Writers,
+/// so that they do not send samples that would not pass.
+///
+/// The expression is evaluated against the fields of the samples, as seen by Serde.
+/// Nested fields are referred to with `.` and sequence elements with `[n]`. Enumerations
+/// are compared by the names of the enumerators. A predicate on a field that the sample
+/// does not have does not pass.
+///
+/// # Examples
+///
+/// ```
+/// use rustdds::dds::DomainParticipant;
+/// use rustdds::dds::qos::QosPolicyBuilder;
+/// use rustdds::dds::data_types::TopicKind;
+///
+/// let domain_participant = DomainParticipant::new(0).unwrap();
+/// let qos = QosPolicyBuilder::new().build();
+/// let topic = domain_participant
+///       .create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey)
+///       .unwrap();
+/// let filtered_topic = domain_participant
+///       .create_contentfilteredtopic("large_ones", &topic, "size > %0 AND color = 'RED'", 
+///           &["100".to_string()])
+///       .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ContentFilteredTopic {
+  name: String,
+  related_topic: Topic,
+  filter_expression: String,
+  expression_parameters: Vec<String>,
+  filter: ContentFilter,
+}
+
+impl ContentFilteredTopic {
+  pub(crate) fn new(
+    name: &str,
+    related_topic: &Topic,
+    filter_expression: &str,
+    expression_parameters: &[String],
+  ) -> Result<ContentFilteredTopic> {
+    if name.is_empty() {
+      return Error::bad_parameter("ContentFilteredTopic name must not be empty")
+    }
+    let filter = ContentFilter::new(filter_expression, expression_parameters)?;
+    Ok(ContentFilteredTopic {
+      name: name.to_string(),
+      related_topic: related_topic.clone(),
+      filter_expression: filter_expression.to_string(),
+      expression_parameters: expression_parameters.to_vec(),
+      filter,
+    })
+  }
+
+  /// The Topic whose samples are filtered
+  pub fn get_related_topic(&self) -> Topic {
+    self.related_topic.clone()
+  }
+
+  pub fn get_filter_expression(&self) -> &str {
+    &self.filter_expression
+  }
+
+  pub fn get_expression_parameters(&self) -> &[String] {
+    &self.expression_parameters
+  }
+
+  pub(crate) fn content_filter(&self) -> &ContentFilter {
+    &self.filter
+  }
+
+  // The filter as announced to remote Writers in Discovery
+  pub(crate) fn content_filter_property(&self) -> ContentFilterProperty {
+    ContentFilterProperty {
+      contentFilteredTopicName: self.name.clone(),
+      relatedTopicName: self.related_topic.get_name(),
+      filterClassName: DDSSQL_FILTER_CLASS.to_string(),
+      filterExpression: self.filter_expression.clone(),
+      expressionParameters: self.expression_parameters.clone(),
+    }
+  }
+}
+
+impl Debug for ContentFilteredTopic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ContentFilteredTopic")
+      .field("name", &self.name)
+      .field("related_topic", &self.related_topic.get_name())
+      .field("filter_expression", &self.filter_expression)
+      .field("expression_parameters", &self.expression_parameters)
+      .finish()
+  }
+}
+
+impl TopicDescription for ContentFilteredTopic {
+  /// Gets [DomainParticipant](struct.DomainParticipant.html) of the related Topic, if it is still alive.
+  fn get_participant(&self) -> Option<DomainParticipant> {
+    self.related_topic.get_participant()
+  }
+
+  /// Gets type description of the related Topic
+  fn get_type(&self) -> TypeDesc {
+    self.related_topic.get_type()
+  }
+
+  /// Gets name of this ContentFilteredTopic
+  fn get_name(&self) -> String {
+    self.name.clone()
+  }
+}
+
 
 
 // -------------------------------- InnerTopic -----------------------------
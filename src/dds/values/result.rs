@@ -15,7 +15,8 @@ pub type Result<T> = result::Result<T, Error>;
 /// * `OK` is not included. It is not an error. Ok/Error should be distinguished with the `Result` type.
 /// * `Error` is too unspecific.
 /// * `AlreadyDeleted` We should use Rust type system to avoid these, so no need for run-time error.
-/// * `Timeout`  This is normal operation and should be encoded as `Option` or `Result`
+/// * `Timeout`  This is normal operation and should be encoded as `Option` or `Result`.
+///   Only blocking operations, e.g. DataWriter `write`, that cannot complete in time return it.
 /// * `NoData`  This should be encoded as `Option<SomeData>`, not an error code.
 #[derive(Debug)]
 pub enum Error {
@@ -39,6 +40,8 @@ pub enum Error {
   /// precondition that could be changed to make the operation
   /// succeed.
   IllegalOperation { reason: String },
+  /// A blocking operation could not complete within its maximum blocking time.
+  Timeout,
 
   // Our own additions to the DDS spec below:
 
@@ -1,13 +1,16 @@
 use std::{io};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, Mutex};
 use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{self, Context};
 
 //use itertools::Itertools;
 use serde::de::DeserializeOwned;
 use mio_extras::channel as mio_channel;
 #[allow(unused_imports)]
 use log::{error, debug, info, warn};
-use mio::{Evented, Poll, PollOpt, Ready, Token};
+use mio::{Evented, Events, Poll, PollOpt, Ready, Token};
+use futures::stream::{Stream, FusedStream};
 
 use crate::{
   serialization::CDRDeserializerAdapter,
@@ -23,7 +26,7 @@ use crate::{
 
   },
 };
-use crate::log_and_err_precondition_not_met;
+use crate::{log_and_err_precondition_not_met, log_and_err_internal};
 use crate::dds::{
   traits::{key::*, TopicDescription},
   traits::serde_adapters::with_key::*,
@@ -31,10 +34,14 @@ use crate::dds::{
   qos::*,
   with_key::datasample::*,
   datasample_cache::DataSampleCache,
+  reader::{OwnershipCandidates, LostWriters, ReaderHistoryUsage},
+  content_filter::SampleFilter,
   ddsdata::DDSData,
   pubsub::Subscriber,
   topic::Topic,
   readcondition::*,
+  querycondition::SampleCondition,
+  helpers::{QUERY_REPLY_TIMEOUT, TaskWakers},
 };
 use crate::dds::statusevents::*;
 
@@ -51,9 +58,12 @@ pub enum SelectByKey {
   Next,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum ReaderCommand {
   RESET_REQUESTED_DEADLINE_STATUS,
+  // The DataReader stops waiting at deadline. None = never.
+  WaitForHistoricalData { complete: mio_channel::SyncSender<()>, deadline: Option<std::time::Instant> },
+  GetMatchedWriters { reply: std::sync::mpsc::SyncSender<Vec<GUID>> },
+  SamplesRejected, // the DataReader has put rejected samples into ReaderHistoryUsage
 }
 /*
 struct CurrentStatusChanges {
@@ -132,6 +142,11 @@ pub struct DataReader
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
   status_receiver: StatusReceiver<DataReaderStatus>,
   reader_command: mio_channel::SyncSender<ReaderCommand>,
+  ownership_candidates: Arc<Mutex<OwnershipCandidates>>,
+  lost_writers: Arc<Mutex<LostWriters>>,
+  history_usage: Arc<Mutex<ReaderHistoryUsage>>,
+  content_filter: Option<SampleFilter<D>>,
+  data_reader_wakers: TaskWakers,
 }
 
 impl<D, DA> Drop for DataReader<D, DA>
@@ -140,6 +155,8 @@ where
   DA: DeserializerAdapter<D>,
 {
   fn drop(&mut self) {
+    self.my_subscriber.remove_reader(self.get_guid());
+
     match self
       .discovery_command
       .send(DiscoveryCommand::REMOVE_LOCAL_READER {
@@ -173,6 +190,11 @@ where
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
     status_channel_rec: mio_channel::Receiver<DataReaderStatus>,
     reader_command: mio_channel::SyncSender<ReaderCommand>,
+    ownership_candidates: Arc<Mutex<OwnershipCandidates>>,
+    lost_writers: Arc<Mutex<LostWriters>>,
+    history_usage: Arc<Mutex<ReaderHistoryUsage>>,
+    content_filter: Option<SampleFilter<D>>,
+    data_reader_wakers: TaskWakers,
   ) -> Result<Self> {
     let dp = match subscriber.get_participant() {
       Some(dp) => dp,
@@ -181,24 +203,31 @@ where
     };
 
     let my_guid = GUID::new_with_prefix_and_id(dp.get_guid_prefix(), my_id);
+    // The reader is created before the datareader, hence initializing the
+    // latest_instant to now should be fine. There should be no smaller instants
+    // added by the reader.
+    let latest_instant = Timestamp::now();
+    subscriber.add_reader(my_guid, topic.get_name().to_string(), latest_instant);
 
     Ok(Self {
       my_subscriber: subscriber,
-      qos_policy,
+      qos_policy: qos_policy.clone(),
       my_guid,
       notification_receiver,
       dds_cache,
-      datasample_cache: DataSampleCache::new(topic.get_qos()),
-      // The reader is created before the datareader, hence initializing the
-      // latest_instant to now should be fine. There should be no smaller instants
-      // added by the reader.
+      datasample_cache: DataSampleCache::new(qos_policy.clone()),
       my_topic: topic,
-      latest_instant: Timestamp::now(),
+      latest_instant,
       deserializer_type: PhantomData,
       discovery_command,
       status_receiver: StatusReceiver::new(status_channel_rec) ,
       //current_status: CurrentStatusChanges::new(),
       reader_command,
+      ownership_candidates,
+      lost_writers,
+      history_usage,
+      content_filter,
+      data_reader_wakers,
     })
   }
 
@@ -207,7 +236,7 @@ where
   /// # Arguments
   ///
   /// * `max_samples` - Limits maximum amount of samples read
-  /// * `read_condition` - Limits results by condition
+  /// * `read_condition` - Limits results by condition, either a `ReadCondition` or a `QueryCondition`
   ///
   /// # Examples
   ///
@@ -247,10 +276,10 @@ where
   ///   }
   /// }
   /// ```
-  pub fn read(
+  pub fn read<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<Vec<DataSample<&D>>> {
     // Clear notification buffer. This must be done first to avoid race conditions.
     while self.notification_receiver.try_recv().is_ok() {}
@@ -260,6 +289,7 @@ where
     let mut selected = self.datasample_cache.select_keys_for_access(read_condition);
     selected.truncate(max_samples);
 
+    self.report_access(&selected);
     let result = self.datasample_cache.read_by_keys(&selected);
 
     Ok(result)
@@ -270,7 +300,7 @@ where
   /// # Arguments
   ///
   /// * `max_samples` - Limits maximum amount of samples read
-  /// * `read_condition` - Limits results by condition
+  /// * `read_condition` - Limits results by condition, either a `ReadCondition` or a `QueryCondition`
   ///
   /// # Examples
   ///
@@ -310,10 +340,10 @@ where
   ///   }
   /// }
   /// ```
-  pub fn take(
+  pub fn take<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<Vec<DataSample<D>>> {
     // Clear notification buffer. This must be done first to avoid race conditions.
     while self.notification_receiver.try_recv().is_ok() {}
@@ -323,8 +353,10 @@ where
     debug!("take selected count = {}", selected.len() );
     selected.truncate(max_samples);
 
+    self.report_access(&selected);
     let result = self.datasample_cache.take_by_keys(&selected);
     debug!("take taken count = {}", result.len() );
+    self.update_history_usage();
     
     Ok(result)
   }
@@ -503,9 +535,9 @@ where
   ///   // do something
   /// }
   /// ```
-  pub fn conditional_iterator(
+  pub fn conditional_iterator<C: SampleCondition<D>>(
     &mut self,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<impl Iterator<Item = std::result::Result<&D, D::K>>> {
     // TODO: We could come up with a more efficent implementation than wrapping a read call
     Ok(
@@ -608,9 +640,9 @@ where
   ///   // do something
   /// }
   /// ```
-  pub fn into_conditional_iterator(
+  pub fn into_conditional_iterator<C: SampleCondition<D>>(
     &mut self,
-    read_condition: ReadCondition,
+    read_condition: C,
   ) -> Result<impl Iterator<Item = std::result::Result<D, D::K>>> {
     // TODO: We could come up with a more efficent implementation than wrapping a read call
     Ok(
@@ -625,6 +657,10 @@ where
   // the serialized payload and stores the DataSamples (the actual data and the
   // samplestate) to local container, datasample_cache.
   fn fill_local_datasample_cache(&mut self) {
+    // Subscriber access state is locked before DDSCache, as in Subscriber::get_datareader_guids.
+    // While Subscriber access is in progress, do not fetch anything newer.
+    let until = self.my_subscriber.access_snapshot().unwrap_or_else(Timestamp::now);
+
     let dds_cache = match self.dds_cache.read() {
       Ok(rwlock) => rwlock,
       // TODO: Should we panic here? Are we allowed to continue with poisoned DDSCache?
@@ -637,25 +673,31 @@ where
     let cache_changes = dds_cache.from_topic_get_changes_in_range(
       &self.my_topic.get_name(),
       &self.latest_instant,
-      &Timestamp::now(),
+      &until,
     );
 
+    // Get the ownership candidates after the changes, so that they are at least as recent.
+    if let Some(policy::Ownership::Exclusive{..}) = self.qos_policy.ownership() {
+      match self.ownership_candidates.lock() {
+        Ok(candidates) => self.datasample_cache.set_ownership_candidates(candidates.clone()),
+        Err(e) => error!("fill_local_datasample_cache: Ownership candidates are poisoned: {:?}", e),
+      }
+    }
+
     for ( instant,
-          CacheChange { writer_guid, sequence_number: _ , source_timestamp, data_value }
+          CacheChange { writer_guid, sequence_number, source_timestamp, data_value, coherent_set }
         ) in cache_changes
     {
       self.latest_instant = instant; // update our time pointer
 
-      match data_value {
+      let new_sample = match data_value {
         DDSData::DisposeByKey { key: serialized_key , .. } => {
           // TODO: Should be parameterizable by DeserializerAdapter
           match DA::key_from_bytes(
             &serialized_key.value, 
             serialized_key.representation_identifier) 
           {
-            Ok(key) => self
-              .datasample_cache
-              .add_sample(Err(key), *writer_guid, instant, *source_timestamp),
+            Ok(key) => Err(key),
             Err(e) => {
               warn!("Failed to deserialize key {}, Topic = {}, Type = {:?}", 
                       e, self.my_topic.get_name(), self.my_topic.get_type() );
@@ -668,11 +710,12 @@ where
         DDSData::DisposeByKeyHash { key_hash , .. } => {
           /* TODO: Instance to be disposed could be specified by serialized payload also, not only key_hash? */
           match self.datasample_cache.get_key_by_hash(*key_hash) {
-            Some(key) => self
-              .datasample_cache
-              .add_sample(Err(key), *writer_guid, instant, *source_timestamp),
+            Some(key) => Err(key),
             /* TODO: How to get source timestamps other then None ?? */
-            None => warn!("Tried to dispose with unkonwn key hash: {:x?}", key_hash),
+            None => {
+              warn!("Tried to dispose with unkonwn key hash: {:x?}", key_hash);
+              continue
+            }
           }
         }
         DDSData::Data { serialized_payload } => {
@@ -682,11 +725,7 @@ where
                 .find(|r| **r == serialized_payload.representation_identifier)
           {
             match DA::from_bytes(&serialized_payload.value, *recognized_rep_id) {
-              Ok(payload) => {
-                self
-                .datasample_cache
-                .add_sample(Ok(payload), *writer_guid, instant, *source_timestamp)
-              }
+              Ok(payload) => Ok(payload),
               Err(e) => {
                 error!("Failed to deserialize bytes: {}, Topic = {}, Type = {:?}", 
                         e, self.my_topic.get_name(), self.my_topic.get_type() );
@@ -700,6 +739,12 @@ where
               continue // skip this sample, as we cannot decode it                
           }
         }
+        DDSData::CoherentSetEnd => {
+          if let Some(coherent_set) = coherent_set {
+            self.datasample_cache.end_coherent_set(*writer_guid, *coherent_set)
+          }
+          continue // end marker is not a sample
+        }
         /*
         DDSData::DataFrags { representation_identifier, bytes_frags } => {
           // what is our data serialization format (representation identifier) ?
@@ -725,8 +770,87 @@ where
               continue // skip this sample, as we cannot decode it                
           }
         } */
+      };
+
+      // Samples that do not pass the ContentFilteredTopic filter are dropped. Disposes
+      // and unregisters concern the whole instance, so they always pass.
+      if let (Ok(sample), Some(filter)) = (&new_sample, &self.content_filter) {
+        if ! filter(sample) {
+          continue
+        }
+      }
+
+      match coherent_set {
+        Some(coherent_set) => self.datasample_cache
+          .add_coherent_sample(*coherent_set, new_sample, *writer_guid, *sequence_number, instant, 
+            *source_timestamp),
+        None => self.datasample_cache
+          .add_sample(new_sample, *writer_guid, *sequence_number, instant, *source_timestamp),
       }
     }
+    // Release DDSCache before touching Subscriber access state below.
+    drop(dds_cache);
+
+    self.datasample_cache.remove_expired_samples(Timestamp::now());
+
+    // Instances written only by lost Writers are no longer alive.
+    match self.lost_writers.lock() {
+      Ok(mut lost_writers) => if ! lost_writers.is_empty() {
+        self.datasample_cache.writers_lost(&lost_writers);
+        lost_writers.clear();
+      }
+      Err(e) => error!("fill_local_datasample_cache: Lost writers are poisoned: {:?}", e),
+    }
+
+    self.my_subscriber.update_reader_access(self.my_guid, self.latest_instant, 
+      self.datasample_cache.unread_sample_order());
+    self.update_history_usage();
+  }
+
+  // Tell the Reader how much of ResourceLimits is in use, and which samples could not be stored.
+  fn update_history_usage(&mut self) {
+    if self.qos_policy.resource_limits().is_none() {
+      return
+    }
+    let rejected = self.datasample_cache.take_rejected_samples();
+    let has_rejected = ! rejected.is_empty();
+    match self.history_usage.lock() {
+      Ok(mut usage) => usage.datareader_updated(self.datasample_cache.take_instance_usage_changes(),
+        self.latest_instant, rejected),
+      Err(e) => error!("update_history_usage: History usage is poisoned: {:?}", e),
+    }
+    // If the command channel is full, the Reader reports the rejections after
+    // processing the commands.
+    if has_rejected {
+      if let Err(mio_channel::TrySendError::Disconnected(_)) = 
+          self.reader_command.try_send(ReaderCommand::SamplesRejected) {
+        debug!("update_history_usage: Reader is gone");
+      }
+    }
+  }
+
+  // Which instance does a received sample belong to? The Reader uses this to check
+  // ResourceLimits before it acknowledges the sample.
+  pub(crate) fn sample_key_hash(ddsdata: &DDSData) -> Option<KeyHash> {
+    match ddsdata {
+      DDSData::Data { serialized_payload } => 
+        DA::from_bytes(&serialized_payload.value, serialized_payload.representation_identifier)
+          .ok()
+          .map( |d| d.get_key().into_hash_key() ),
+      DDSData::DisposeByKey { key, .. } => 
+        DA::key_from_bytes(&key.value, key.representation_identifier)
+          .ok()
+          .map( |k| k.into_hash_key() ),
+      DDSData::DisposeByKeyHash { key_hash, .. } => Some(*key_hash),
+      DDSData::CoherentSetEnd => None,
+    }
+  }
+
+  // Tell Subscriber which samples are being accessed, so that get_datareader_guids()
+  // no longer lists them.
+  fn report_access(&self, selected: &[(Timestamp, <D as Keyed>::K)]) {
+    self.my_subscriber.reader_samples_accessed(self.my_guid, 
+      selected.iter().map( |(ts, _)| *ts ));
   }
 
   fn infer_key(
@@ -794,10 +918,10 @@ where
   ///   }
   /// }
   /// ```
-  pub fn read_instance(
+  pub fn read_instance<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
     // Select only samples from instance specified by key. In case of None, select the
     // "smallest" instance as specified by the key type Ord trait.
     instance_key: Option<<D as Keyed>::K>,
@@ -820,6 +944,7 @@ where
       .select_instance_keys_for_access(key, read_condition);
     selected.truncate(max_samples);
 
+    self.report_access(&selected);
     let result = self.datasample_cache.read_by_keys(&selected);
 
     Ok(result)
@@ -867,10 +992,10 @@ where
   ///   }
   /// }
   /// ```
-  pub fn take_instance(
+  pub fn take_instance<C: SampleCondition<D>>(
     &mut self,
     max_samples: usize,
-    read_condition: ReadCondition,
+    read_condition: C,
     // Select only samples from instance specified by key. In case of None, select the
     // "smallest" instance as specified by the key type Ord trait.
     instance_key: Option<<D as Keyed>::K>,
@@ -893,7 +1018,9 @@ where
       .select_instance_keys_for_access(key, read_condition);
     selected.truncate(max_samples);
 
+    self.report_access(&selected);
     let result = self.datasample_cache.take_by_keys(&selected);
+    self.update_history_usage();
 
     Ok(result)
   }
@@ -1086,11 +1213,75 @@ where
     return Ok(value_before_reset);
   } */
 
-  /// Return values:
-  /// true - got all historical data
-  /// false - timeout before all historical data was received
-  pub fn wait_for_historical_data(&self, _max_wait: Duration) -> bool {
-    todo!()
+  /// Blocks until all matched reliable DataWriters with TRANSIENT_LOCAL or stronger
+  /// Durability have delivered the samples they had available when they were matched.
+  ///
+  /// See DDS Spec 1.4 Section 2.2.2.5.2.12 wait_for_historical_data.
+  ///
+  /// If this DataReader has VOLATILE Durability, the call succeeds imediately.
+  ///
+  /// Return values
+  /// * `Ok(true)` - got all historical data
+  /// * `Ok(false)` - timed out before all historical data was received
+  /// * `Err(_)` - something went wrong
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::{QosPolicyBuilder, policy::Durability};
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRDeserializerAdapter;
+  /// # use rustdds::dds::data_types::DDSDuration;
+  /// #
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().durability(Durability::TransientLocal).build();
+  /// let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  ///
+  /// #[derive(Serialize, Deserialize)]
+  /// struct SomeType { a: i32 }
+  /// impl Keyed for SomeType {
+  ///   type K = i32;
+  ///
+  ///   fn get_key(&self) -> Self::K {
+  ///     self.a
+  ///   }
+  /// }
+  ///
+  /// // WithKey is important
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let data_reader = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
+  ///
+  /// if data_reader.wait_for_historical_data(DDSDuration::from_millis(100)).unwrap() {
+  ///   // take or read historical data
+  /// }
+  /// ```
+  pub fn wait_for_historical_data(&self, max_wait: Duration) -> Result<bool> {
+    match self.qos_policy.durability {
+      None | Some(policy::Durability::Volatile) => Ok(true),
+      Some(_) => {
+        let (complete_sender, complete_receiver) = mio_channel::sync_channel::<()>(1);
+        let poll = Poll::new()?;
+        poll.register(&complete_receiver, Token(0), Ready::readable(), PollOpt::edge() )?;
+        let deadline = std::time::Instant::now().checked_add(std::time::Duration::from(max_wait));
+        self.reader_command.try_send(
+          ReaderCommand::WaitForHistoricalData { complete: complete_sender, deadline })?;
+        let mut events = Events::with_capacity(1);
+        poll.poll(&mut events, Some(std::time::Duration::from(max_wait)) )?;
+        if let Some( _event ) = events.iter().next() {
+          let _ = complete_receiver.try_recv()
+            .or_else(|_e| log_and_err_internal!(
+              "wait_for_historical_data - Spurious poll event?"));
+          // got reply
+          Ok(true)
+        } else {
+          // no token, so presumably timed out
+          Ok(false)
+        }
+      }
+    } // match
   }
 
   // Spec calls for two separate functions:
@@ -1101,12 +1292,266 @@ where
   // only thing that could be done with the handles would be counting how many
   // we got. 
 
+  /// Gets the discovery data of the remote DataWriters currently matched with this DataReader.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRDeserializerAdapter;
+  /// #
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().build();
+  /// let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  /// #
+  /// # #[derive(Serialize, Deserialize)]
+  /// # struct SomeType { a: i32 }
+  /// # impl Keyed for SomeType {
+  /// #   type K = i32;
+  /// #
+  /// #   fn get_key(&self) -> Self::K {
+  /// #     self.a
+  /// #   }
+  /// # }
+  ///
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let data_reader = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
+  ///
+  /// for publication in data_reader.get_matched_publications() {
+  ///   println!("Matched with {:?}", publication.key);
+  /// }
+  /// ```
   pub fn get_matched_publications(&self) -> impl Iterator<Item=PublicationBuiltinTopicData> {
-    vec![].into_iter()
+    let (reply_sender, reply_receiver) = std::sync::mpsc::sync_channel(1);
+    let writer_guids = 
+      match self.reader_command.try_send(ReaderCommand::GetMatchedWriters { reply: reply_sender }) {
+        Ok(()) => reply_receiver.recv_timeout(QUERY_REPLY_TIMEOUT)
+          .unwrap_or_else( |e| {
+            warn!("get_matched_publications: No reply from Reader: {:?}", e);
+            Vec::new() 
+          }),
+        Err(e) => {
+          error!("get_matched_publications: Cannot send command to Reader: {:?}", e);
+          Vec::new()
+        }
+      };
+
+    let publications : Vec<PublicationBuiltinTopicData> = 
+      match self.my_subscriber.get_participant() {
+        Some(dp) => match dp.discovery_db().read() {
+          Ok(db) => writer_guids.iter()
+            .filter_map( |guid| db.find_remote_writer(*guid) )
+            .map( |dwd| dwd.publication_topic_data.clone() )
+            .collect(),
+          Err(e) => {
+            error!("get_matched_publications: DiscoveryDB is poisoned: {:?}", e);
+            Vec::new()
+          }
+        }
+        None => {
+          error!("get_matched_publications: DomainParticipant doesn't exist anymore.");
+          Vec::new()
+        }
+      };
+    publications.into_iter()
+  }
+
+  /// Gets the GUID of the DataWriter that currently owns the instance.
+  ///
+  /// With EXCLUSIVE Ownership, only samples from the owning DataWriter are accepted to an instance.
+  /// The owner is the strongest DataWriter writing the instance that is still matched and has
+  /// not missed its deadline. Returns `None` if Ownership is SHARED, or the instance is unknown
+  /// or currently has no owner.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::{QosPolicyBuilder, policy::Ownership};
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRDeserializerAdapter;
+  /// #
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new()
+  ///   .ownership(Ownership::Exclusive { strength: 0 })
+  ///   .build();
+  /// let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  /// #
+  /// # #[derive(Serialize, Deserialize)]
+  /// # struct SomeType { a: i32 }
+  /// # impl Keyed for SomeType {
+  /// #   type K = i32;
+  /// #
+  /// #   fn get_key(&self) -> Self::K {
+  /// #     self.a
+  /// #   }
+  /// # }
+  ///
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let mut data_reader = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
+  ///
+  /// if let Some(owner) = data_reader.get_instance_owner(&1) {
+  ///   println!("Instance 1 is owned by {:?}", owner);
+  /// }
+  /// ```
+  pub fn get_instance_owner(&mut self, instance_key: &D::K) -> Option<GUID> {
+    self.fill_local_datasample_cache();
+    self.datasample_cache.get_instance_owner(instance_key)
+  }
+
+  /// Converts the DataReader into an async `Stream` of samples. This is an alternative
+  /// to polling the DataReader with mio. The stream does not depend on any particular
+  /// async runtime.
+  ///
+  /// The stream takes samples one at a time, in the same order as
+  /// [`take`](#method.take) with `ReadCondition::not_read()` would. It never ends.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRDeserializerAdapter;
+  /// use futures::StreamExt;
+  ///
+  /// # let domain_participant = DomainParticipant::new(0).unwrap();
+  /// # let qos = QosPolicyBuilder::new().build();
+  /// # let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+  /// #
+  /// # #[derive(Serialize, Deserialize)]
+  /// # struct SomeType { a: i32 }
+  /// # impl Keyed for SomeType {
+  /// #   type K = i32;
+  /// #   fn get_key(&self) -> Self::K { self.a }
+  /// # }
+  /// #
+  /// # let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let data_reader = subscriber.create_datareader::<SomeType, CDRDeserializerAdapter<_>>(topic, None).unwrap();
+  /// let mut sample_stream = data_reader.async_sample_stream();
+  /// let mut status_stream = sample_stream.async_status_stream();
+  ///
+  /// let task = async move {
+  ///   while let Some(sample) = sample_stream.next().await {
+  ///     match sample {
+  ///       Ok(sample) => println!("Got sample {:?}", sample.sample_info()),
+  ///       Err(e) => println!("Oh no: {:?}", e),
+  ///     }
+  ///   }
+  /// };
+  /// // ... and spawn the task on the runtime of your choice.
+  /// ```
+  pub fn async_sample_stream(self) -> DataReaderStream<D, DA> {
+    DataReaderStream { datareader: Arc::new(Mutex::new(self)) }
   }
 
 } // impl
 
+/// Async `Stream` of samples from a [DataReader](struct.DataReader.html). Created by
+/// [`DataReader::async_sample_stream`](struct.DataReader.html#method.async_sample_stream).
+pub struct DataReaderStream
+  < D: Keyed + DeserializeOwned,
+    DA: DeserializerAdapter<D> = CDRDeserializerAdapter<D>
+  >
+{
+  datareader: Arc<Mutex<DataReader<D, DA>>>,
+}
+
+impl<D: 'static, DA> DataReaderStream<D, DA>
+where
+  D: DeserializeOwned + Keyed,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  /// Async `Stream` of the status changes of the DataReader. This can be used
+  /// concurrently with the sample stream. It never ends.
+  pub fn async_status_stream(&self) -> DataReaderStatusStream<D, DA> {
+    match self.datareader.lock() {
+      Ok(mut datareader) => datareader.status_receiver.enable(),
+      Err(e) => error!("async_status_stream - DataReader is poisoned: {:?}", e),
+    }
+    DataReaderStatusStream { datareader: self.datareader.clone() }
+  }
+}
+
+impl<D: 'static, DA> Stream for DataReaderStream<D, DA>
+where
+  D: DeserializeOwned + Keyed,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  type Item = Result<DataSample<D>>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<Option<Self::Item>> {
+    let mut datareader = match self.datareader.lock() {
+      Ok(datareader) => datareader,
+      Err(_) => return task::Poll::Ready(Some(Err(Error::LockPoisoned))),
+    };
+    // Register before taking, so that a sample arriving in between is not missed.
+    datareader.data_reader_wakers.register(cx.waker());
+    match datareader.take(1, ReadCondition::not_read()) {
+      Ok(mut samples) => match samples.pop() {
+        Some(sample) => task::Poll::Ready(Some(Ok(sample))),
+        None => task::Poll::Pending,
+      },
+      Err(e) => task::Poll::Ready(Some(Err(e))),
+    }
+  }
+}
+
+impl<D: 'static, DA> FusedStream for DataReaderStream<D, DA>
+where
+  D: DeserializeOwned + Keyed,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  fn is_terminated(&self) -> bool {
+    false // never ends
+  }
+}
+
+/// Async `Stream` of [DataReaderStatus](../statusevents/enum.DataReaderStatus.html)
+/// changes. Created by
+/// [`DataReaderStream::async_status_stream`](struct.DataReaderStream.html#method.async_status_stream).
+pub struct DataReaderStatusStream
+  < D: Keyed + DeserializeOwned,
+    DA: DeserializerAdapter<D> = CDRDeserializerAdapter<D>
+  >
+{
+  datareader: Arc<Mutex<DataReader<D, DA>>>,
+}
+
+impl<D, DA> Stream for DataReaderStatusStream<D, DA>
+where
+  D: DeserializeOwned + Keyed,
+  DA: DeserializerAdapter<D>,
+{
+  type Item = DataReaderStatus;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<Option<Self::Item>> {
+    let datareader = match self.datareader.lock() {
+      Ok(datareader) => datareader,
+      Err(e) => {
+        error!("DataReaderStatusStream - DataReader is poisoned: {:?}", e);
+        return task::Poll::Ready(None)
+      }
+    };
+    datareader.data_reader_wakers.register(cx.waker());
+    match datareader.status_receiver.try_recv_status() {
+      Some(status) => task::Poll::Ready(Some(status)),
+      None => task::Poll::Pending,
+    }
+  }
+}
+
 
 // This is  not part of DDS spec. We implement mio Eventd so that the application can asynchronously
 // poll DataReader(s).
@@ -1185,6 +1630,7 @@ mod tests {
   use crate::dds::{participant::DomainParticipant, topic::TopicKind};
   use crate::test::random_data::*;
   use crate::dds::traits::key::Keyed;
+  use crate::dds::querycondition::QueryCondition;
   use bytes::Bytes;
   use mio_extras::channel as mio_channel;
   use log::info;
@@ -1196,6 +1642,7 @@ mod tests {
   use crate::structure::guid::{GuidPrefix, EntityKind};
   use crate::structure::sequence_number::SequenceNumber;
   use crate::serialization::{cdr_deserializer::CDRDeserializerAdapter, cdr_serializer::to_bytes};
+  use crate::serialization::cdr_serializer::CDRSerializerAdapter;
   use byteorder::LittleEndian;
   use crate::messages::submessages::submessage_elements::serialized_payload::{
     SerializedPayload, RepresentationIdentifier,
@@ -1204,6 +1651,363 @@ mod tests {
   //use mio::{Events};
   use crate::messages::submessages::submessage_flag::*;
 
+  #[test]
+  fn dr_wait_for_historical_data() {
+    use crate::test::wait_util::*;
+
+    let qos = QosPolicyBuilder::new()
+      .durability(policy::Durability::TransientLocal)
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepLast { depth: 1 })
+      .build();
+
+    // Writer has written data before Reader participant exists.
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(5).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("historical_data_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+    for (a, b) in &[(1, "first"), (1, "second"), (2, "third")] {
+      data_writer.write(RandomData { a: *a, b: b.to_string() }, None).unwrap();
+    }
+
+    let dp_r = DomainParticipant::new(5).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("historical_data_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+      .unwrap();
+
+    // Historical data is waited for from matched writers only, so wait for discovery first.
+    assert!(wait_for_status(&mut data_reader, std::time::Duration::from_secs(10),
+      |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+
+    assert!(data_reader.wait_for_historical_data(Duration::from_secs(10)).unwrap());
+
+    // History depth is 1, so we expect only the latest sample of each instance.
+    let mut received : Vec<String> = data_reader.take(10, ReadCondition::any()).unwrap()
+      .into_iter()
+      .filter_map( |s| s.into_value().ok() )
+      .map( |d| d.b )
+      .collect();
+    received.sort();
+    assert_eq!(received, vec!["second".to_string(), "third".to_string()]);
+  }
+
+  #[test]
+  fn dr_coherent_set() {
+    use crate::dds::statusevents::DataWriterStatus;
+    use crate::test::wait_util::*;
+
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .presentation(policy::Presentation {
+        access_scope: policy::PresentationAccessScope::Topic,
+        coherent_access: true,
+        ordered_access: false,
+      })
+      .build();
+
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(25).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("coherent_set_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+
+    let dp_r = DomainParticipant::new(25).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("coherent_set_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+      .unwrap();
+
+    // Data written before the Writer has matched the Reader would not reach the Reader.
+    assert!(wait_for_status(&mut data_writer, std::time::Duration::from_secs(10),
+      |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+
+    assert!(publisher.end_coherent_changes().is_err()); // not begun
+
+    publisher.begin_coherent_changes().unwrap();
+    data_writer.write(RandomData { a: 1, b: "first".to_string() }, None).unwrap();
+    data_writer.write(RandomData { a: 2, b: "second".to_string() }, None).unwrap();
+
+    // Incomplete set has reached the Reader, but is not visible to the application.
+    assert!(data_writer.wait_for_acknowledgments(std::time::Duration::from_secs(10)).unwrap());
+    assert!(data_reader.take(10, ReadCondition::any()).unwrap().is_empty());
+
+    publisher.end_coherent_changes().unwrap();
+
+    // The whole set becomes available at once.
+    let first = wait_for_sample(&mut data_reader, std::time::Duration::from_secs(10))
+      .expect("Coherent set was not delivered");
+    let mut received : Vec<String> = std::iter::once(first)
+      .chain(data_reader.take(10, ReadCondition::any()).unwrap())
+      .filter_map( |s| s.into_value().ok() )
+      .map( |d| d.b )
+      .collect();
+    received.sort();
+    assert_eq!(received, vec!["first".to_string(), "second".to_string()]);
+  }
+
+  #[test]
+  fn dr_group_coherent_access_is_unsupported() {
+    let qos = QosPolicyBuilder::new()
+      .presentation(policy::Presentation {
+        access_scope: policy::PresentationAccessScope::Group,
+        coherent_access: true,
+        ordered_access: false,
+      })
+      .build();
+    let dp = DomainParticipant::new(0).expect("Participant creation failed");
+    assert!(matches!(dp.create_publisher(&qos), Err(Error::Unsupported)));
+    assert!(matches!(dp.create_subscriber(&qos), Err(Error::Unsupported)));
+
+    // Nor can the QoS of a single DataReader ask for it.
+    let subscriber = dp.create_subscriber(&QosPolicyBuilder::new().build()).unwrap();
+    let topic = dp
+      .create_topic("group_coherent_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    assert!(matches!(
+      subscriber.create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic, None),
+      Err(Error::Unsupported)));
+  }
+
+  #[test]
+  fn dr_query_condition() {
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .build();
+
+    let dp_w = DomainParticipant::new(0).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("query_condition_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+
+    let dp_r = DomainParticipant::new(0).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("query_condition_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+      .unwrap();
+
+    data_reader.as_status_evented(); // enables status reporting
+    let mut matched = false;
+    for _ in 0..100 {
+      if let Some(DataReaderStatus::SubscriptionMatched{..}) = data_reader.try_recv_status() {
+        matched = true;
+        break
+      }
+      std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(matched);
+
+    for (a, b) in &[(1, "one"), (2, "two"), (3, "three"), (4, "four"), (5, "five"), (6, "six")] {
+      data_writer.write(RandomData { a: *a, b: b.to_string() }, None).unwrap();
+    }
+    for _ in 0..50 {
+      if data_reader.read(10, ReadCondition::any()).unwrap().len() == 6 {
+        break
+      }
+      std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    fn values(samples: Vec<DataSample<&RandomData>>) -> Vec<i64> {
+      let mut values : Vec<i64> = samples.into_iter()
+        .filter_map( |s| s.value().as_ref().ok().map( |d| d.a ) )
+        .collect();
+      values.sort_unstable();
+      values
+    }
+
+    let mut query = 
+      QueryCondition::new(ReadCondition::any(), "a > %0", &["3".to_string()]).unwrap();
+    assert_eq!(values(data_reader.read(10, &query).unwrap()), vec![4, 5, 6]);
+
+    query.set_query_parameters(&["4".to_string()]).unwrap();
+    let mut taken : Vec<i64> = data_reader.take(10, &query).unwrap()
+      .into_iter()
+      .filter_map( |s| s.into_value().ok() )
+      .map( |d| d.a )
+      .collect();
+    taken.sort_unstable();
+    assert_eq!(taken, vec![5, 6]);
+    assert_eq!(values(data_reader.read(10, ReadCondition::any()).unwrap()), vec![1, 2, 3, 4]);
+
+    let name_query = QueryCondition::new(ReadCondition::any(), "b LIKE 'f%'", &[]).unwrap();
+    assert_eq!(values(data_reader.read_instance(10, &name_query, Some(4), SelectByKey::This).unwrap()),
+      vec![4]);
+    assert!(data_reader.read_instance(10, &name_query, Some(3), SelectByKey::This).unwrap().is_empty());
+
+    let range_query = 
+      QueryCondition::new(ReadCondition::any(), "a BETWEEN 2 AND 3", &[]).unwrap();
+    let mut iterated : Vec<i64> = data_reader.conditional_iterator(range_query).unwrap()
+      .filter_map( |d| d.ok() )
+      .map( |d| d.a )
+      .collect();
+    iterated.sort_unstable();
+    assert_eq!(iterated, vec![2, 3]);
+  }
+
+  #[test]
+  fn dr_async_streams() {
+    use futures::{StreamExt, executor::block_on};
+    use crate::dds::statusevents::{DiscoveryEvent, DataWriterStatus};
+    use crate::test::wait_util::*;
+
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .build();
+
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_r = DomainParticipant::new(18).expect("Participant creation failed");
+    let mut discovery_events = dp_r.async_discovery_event_stream();
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("async_stream_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let data_reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+      .unwrap();
+    let mut sample_stream = data_reader.async_sample_stream();
+    let mut status_stream = sample_stream.async_status_stream();
+
+    let dp_w = DomainParticipant::new(18).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("async_stream_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut data_writer = publisher
+      .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+      .unwrap();
+
+    // DataWriter has no status stream. Data written before the Writer has matched
+    // the Reader would not reach the Reader.
+    assert!(wait_for_status(&mut data_writer, std::time::Duration::from_secs(10),
+      |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+
+    // Run the tasks in another thread, so that a failure to wake them up does not
+    // hang the test.
+    let (done_sender, done_receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+      block_on(async {
+        loop {
+          if let Some(DiscoveryEvent::WriterDiscovered { writer }) = discovery_events.next().await {
+            if writer.topic_name == "async_stream_test" { break }
+          }
+        }
+        loop {
+          if let Some(DataReaderStatus::SubscriptionMatched{..}) = status_stream.next().await {
+            break
+          }
+        }
+        for (a, b) in &[(1, "one"), (2, "two")] {
+          data_writer.async_write(RandomData { a: *a, b: b.to_string() }, None).await.unwrap();
+        }
+        for expected in &["one", "two"] {
+          let sample = sample_stream.next().await.unwrap().unwrap();
+          assert_eq!(sample.into_value().ok().map( |d| d.b ).as_deref(), Some(*expected));
+        }
+        data_writer.async_wait_for_acknowledgments().await.unwrap();
+      });
+      done_sender.send(()).unwrap();
+    });
+    assert_eq!(done_receiver.recv_timeout(std::time::Duration::from_secs(20)), Ok(()));
+  }
+
+  #[test]
+  fn dr_group_ordered_access() {
+    use crate::dds::statusevents::DataWriterStatus;
+    use crate::test::wait_util::*;
+
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(policy::History::KeepAll)
+      .presentation(policy::Presentation {
+        access_scope: policy::PresentationAccessScope::Group,
+        coherent_access: false,
+        ordered_access: true,
+      })
+      .build();
+
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(6).expect("Participant creation failed");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let dp_r = DomainParticipant::new(6).expect("Participant creation failed");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+
+    let mut writers = Vec::new();
+    let mut readers = Vec::new();
+    for topic_name in &["group_access_test_a", "group_access_test_b"] {
+      let topic_w = dp_w
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      writers.push( publisher
+        .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(topic_w, None)
+        .unwrap() );
+      let topic_r = dp_r
+        .create_topic(topic_name, "RandomData", &qos, TopicKind::WithKey)
+        .unwrap();
+      readers.push( subscriber
+        .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic_r, None)
+        .unwrap() );
+    }
+
+    // Data written before the Writers have matched the Readers would not reach them.
+    for data_writer in writers.iter_mut() {
+      assert!(wait_for_status(data_writer, std::time::Duration::from_secs(10),
+        |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    }
+
+    assert!(subscriber.get_datareader_guids().is_err()); // access not begun
+    assert!(subscriber.end_access().is_err());
+
+    // Write alternately to the two Topics
+    for i in 0..4 {
+      writers[i % 2].write(RandomData { a: i as i64, b: i.to_string() }, None).unwrap();
+    }
+    // Once acknowledged, the samples are in the DDSCache of the reading side.
+    for data_writer in writers.iter() {
+      assert!(data_writer.wait_for_acknowledgments(std::time::Duration::from_secs(10)).unwrap());
+    }
+
+    subscriber.begin_access().unwrap();
+    let reader_guids = subscriber.get_datareader_guids().unwrap();
+    let (guid_a, guid_b) = (readers[0].get_guid(), readers[1].get_guid());
+    assert_eq!(reader_guids, vec![guid_a, guid_b, guid_a, guid_b]);
+
+    // Taking from the listed DataReaders gives the samples in write order.
+    let mut received = Vec::new();
+    for reader_guid in reader_guids {
+      let data_reader = readers.iter_mut().find( |r| r.get_guid() == reader_guid ).unwrap();
+      let sample = data_reader.take_next_sample().unwrap().unwrap();
+      received.push( sample.into_value().ok().unwrap().a );
+    }
+    assert_eq!(received, vec![0, 1, 2, 3]);
+    assert!(subscriber.get_datareader_guids().unwrap().is_empty());
+    subscriber.end_access().unwrap();
+  }
+
   #[test]
   fn dr_get_samples_from_ddschache() {
     let dp = DomainParticipant::new(0).expect("Participant creation failed");
@@ -1231,6 +2035,10 @@ mod tests {
       topic_name: topic.get_name(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
     let mut new_reader = Reader::new(reader_ing, dp.get_dds_cache(), 
@@ -1368,6 +2176,10 @@ mod tests {
       topic_name: topic.get_name(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
     let mut reader = Reader::new(reader_ing, 
@@ -1626,6 +2438,10 @@ mod tests {
       topic_name: topic.get_name().to_string(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
     let reader = Reader::new(reader_ing, dp.get_dds_cache(), Rc::new(UDPSender::new_with_random_port().unwrap()));
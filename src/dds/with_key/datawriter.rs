@@ -1,9 +1,14 @@
 use std::{
   marker::PhantomData,
-  sync::{Arc, RwLock},
-  time::Duration,
+  sync::{Arc, RwLock, Mutex},
+  time::{Duration, Instant},
+  collections::BTreeSet,
+  sync::mpsc::TryRecvError,
+  task,
 };
 
+use futures::future;
+
 use mio::{Poll, Events, Token, Ready, PollOpt, Evented};
 use mio_extras::channel::{self as mio_channel, Receiver, SendError,};
 
@@ -47,7 +52,7 @@ use crate::dds::traits::serde_adapters::with_key::SerializerAdapter;
 use crate::messages::submessages::submessage_elements::serialized_payload::SerializedPayload;
 
 use crate::{discovery::data_types::topic_data::SubscriptionBuiltinTopicData, dds::ddsdata::DDSData};
-use super::super::{datasample_cache::DataSampleCache, writer::WriterCommand, };
+use super::super::{datasample_cache::DataSampleCache, writer::{WriterCommand, ReaderFilters, SharedHistoryUsage, MAX_FRAGMENT_SIZE}, };
 
 /// Simplified type for CDR encoding
 pub type DataWriter_CDR<D> = DataWriter<D,CDRSerializerAdapter<D>>;
@@ -95,6 +100,10 @@ pub struct DataWriter<D: Keyed + Serialize, SA: SerializerAdapter<D> = CDRSerial
   datasample_cache: DataSampleCache<D>,
   phantom: PhantomData<SA>,
   status_receiver: StatusReceiver<DataWriterStatus>,
+  status_accumulator: Arc<Mutex<DataWriterStatusAccumulator>>,
+  reader_filters: Arc<Mutex<ReaderFilters>>,
+  history_usage: Arc<SharedHistoryUsage>,
+  data_writer_wakers: TaskWakers,
 }
 
 impl<D, SA> Drop for DataWriter<D, SA>
@@ -103,6 +112,7 @@ where
   SA: SerializerAdapter<D>,
 {
   fn drop(&mut self) {
+    self.my_publisher.remove_writer(self.get_guid());
     match self
       .discovery_command
       .send(DiscoveryCommand::REMOVE_LOCAL_WRITER {
@@ -126,14 +136,20 @@ where
   <D as Keyed>::K: Key,
   SA: SerializerAdapter<D>,
 {
+  #[allow(clippy::too_many_arguments)]
   pub(crate) fn new(
     publisher: Publisher,
     topic: Topic,
+    qos: QosPolicies,
     guid: Option<GUID>,
     cc_upload: mio_channel::SyncSender<WriterCommand>,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
     dds_cache: Arc<RwLock<DDSCache>>,
     status_receiver_rec: Receiver<DataWriterStatus>,
+    status_accumulator: Arc<Mutex<DataWriterStatusAccumulator>>,
+    reader_filters: Arc<Mutex<ReaderFilters>>,
+    history_usage: Arc<SharedHistoryUsage>,
+    data_writer_wakers: TaskWakers,
   ) -> Result<DataWriter<D, SA>> {
     let entity_id = match guid {
       Some(g) => g.entityId,
@@ -157,7 +173,7 @@ where
       Err(e) => panic!("DDSCache is poisoned. {:?}", e),
     };
 
-    if let Some(lv) = topic.get_qos().liveliness { match lv {
+    if let Some(lv) = qos.liveliness { match lv {
       Liveliness::Automatic { lease_duration: _ } => (),
       Liveliness::ManualByParticipant { lease_duration: _ } => {
         match discovery_command.send(DiscoveryCommand::MANUAL_ASSERT_LIVELINESS) {
@@ -169,7 +185,6 @@ where
       }
       Liveliness::ManualByTopic { lease_duration: _ } => (),
     } };
-    let qos = topic.get_qos();
     Ok(DataWriter {
       my_publisher: publisher,
       my_topic: topic,
@@ -181,9 +196,28 @@ where
       datasample_cache: DataSampleCache::new(qos),
       phantom: PhantomData,
       status_receiver: StatusReceiver::new(status_receiver_rec),
+      status_accumulator,
+      reader_filters,
+      history_usage,
+      data_writer_wakers,
     })
   }
 
+  // Matched Readers whose content filter the sample does not pass. If a filter cannot 
+  // examine the sample, the sample is sent, and the Reader filters it again.
+  fn filtered_readers(&self, data: &D) -> BTreeSet<GUID> {
+    match self.reader_filters.lock() {
+      Ok(filters) => filters.iter()
+        .filter( |(_, filter)| filter.evaluate(data) == Some(false) )
+        .map( |(guid, _)| *guid )
+        .collect(),
+      Err(e) => {
+        error!("DataWriter reader filters are poisoned: {:?}", e);
+        BTreeSet::new()
+      }
+    }
+  }
+
   // This one function provides both get_matched_subscrptions and get_matched_subscription_data
   // TODO: Maybe we could return references to the subscription data to avoid copying?
   // But then what if the result set changes while the application processes it?
@@ -243,6 +277,15 @@ where
 
   /// Writes single data instance to a topic.
   ///
+  /// Fails with `OutOfResources`, if the sample would exceed ResourceLimits QoS: 
+  /// `max_instances` always, and with KEEP_ALL History also `max_samples` and 
+  /// `max_samples_per_instance`, counting the samples not yet acknowledged by all 
+  /// reliable DataReaders.
+  ///
+  /// With Reliable QoS, a KEEP_ALL DataWriter whose history is full of unacknowledged
+  /// samples blocks until the DataReaders acknowledge enough of them. If that does not
+  /// happen within `max_blocking_time`, the call fails with `Timeout`.
+  ///
   /// # Examples
   ///
   /// ```
@@ -276,11 +319,9 @@ where
   /// data_writer.write(some_data, None).unwrap();
   /// ```
   pub fn write(&self, data: D, source_timestamp: Option<Timestamp>) -> Result<()> {
-
-    let send_buffer = SA::to_Bytes( &data )?; // serialize
-
-    let ddsdata = DDSData::new( SerializedPayload::new_from_Bytes( SA::output_encoding() , send_buffer) );
-    let writer_command = WriterCommand::DDSData { data: ddsdata , source_timestamp };
+    let key_hash = data.get_key().into_hash_key();
+    let writer_command = self.data_command(data, source_timestamp)?;
+    self.admit_to_history(key_hash, false)?;
 
     let timeout =
       match self.get_qos().reliability() {
@@ -297,11 +338,154 @@ where
       Err(e) => {
         warn!("Failed to write new data: topic={:?}  reason={:?}  timeout={:?}", 
              self.my_topic.get_name(), e, timeout, );
+        self.cancel_history_admission(key_hash);
+        Err(Error::OutOfResources)
+      }
+    }
+  }
+
+  /// Async version of [`write`](#method.write). Instead of blocking the thread,
+  /// waits up to `max_blocking_time` for the Writer to have room for the sample,
+  /// and then fails with `Timeout`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRSerializerAdapter;
+  /// #
+  /// # let domain_participant = DomainParticipant::new(0).unwrap();
+  /// # let qos = QosPolicyBuilder::new().build();
+  /// # let publisher = domain_participant.create_publisher(&qos).unwrap();
+  /// #
+  /// #[derive(Serialize, Deserialize)]
+  /// struct SomeType { a: i32 }
+  /// # impl Keyed for SomeType {
+  /// #   type K = i32;
+  /// #   fn get_key(&self) -> Self::K { self.a }
+  /// # }
+  ///
+  /// # let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
+  ///
+  /// futures::executor::block_on(async {
+  ///   data_writer.async_write(SomeType { a: 1 }, None).await.unwrap();
+  ///   data_writer.async_wait_for_acknowledgments().await.unwrap();
+  /// });
+  /// ```
+  pub async fn async_write(&self, data: D, source_timestamp: Option<Timestamp>) -> Result<()> {
+    let key_hash = data.get_key().into_hash_key();
+    let writer_command = self.data_command(data, source_timestamp)?;
+    self.async_admit_to_history(key_hash, false).await?;
+    match async_send(&self.cc_upload, &self.data_writer_wakers, writer_command).await {
+      Ok(_) => {
+        self.refresh_manual_liveliness();
+        Ok(())
+      }
+      Err(e) => {
+        warn!("Failed to write new data: topic={:?}  reason={:?}", self.my_topic.get_name(), e);
+        self.cancel_history_admission(key_hash);
         Err(Error::OutOfResources)
       }
     }
   }
 
+  fn data_command(&self, data: D, source_timestamp: Option<Timestamp>) -> Result<WriterCommand> {
+    let send_buffer = SA::to_Bytes( &data )?; // serialize
+
+    let key_hash = data.get_key().into_hash_key();
+    let ddsdata = DDSData::new( SerializedPayload::new_from_Bytes( SA::output_encoding() , send_buffer) );
+    // DDS spec: If no source timestamp is given, the current time is used.
+    let source_timestamp = source_timestamp.or_else( || Some(Timestamp::now()) );
+    let filtered_readers = self.filtered_readers(&data);
+    Ok(WriterCommand::DDSData { data: ddsdata , source_timestamp, key_hash, filtered_readers })
+  }
+
+  // ResourceLimits: Reserve room in the Writer history for a sample of the instance.
+  // If the history is full of unacknowledged samples, a reliable DataWriter waits up to
+  // max_blocking_time for the Writer to release some.
+  fn admit_to_history(&self, key_hash: KeyHash, is_dispose: bool) -> Result<()> {
+    let max_blocking_time =
+      match self.qos_policy.reliability() {
+        Some(Reliability::Reliable { max_blocking_time }) => Duration::from(max_blocking_time),
+        _ => Duration::from_secs(0),
+      };
+    let deadline = Instant::now() + max_blocking_time;
+    let mut usage = self.history_usage.usage.lock()?;
+    loop {
+      match usage.admit(key_hash, is_dispose, &self.qos_policy) {
+        Ok(()) => return Ok(()),
+        Err(reason) if ! self.can_wait_for_history(reason) => {
+          debug!("Writer history is full: topic={:?} reason={:?}", self.my_topic.get_name(), reason);
+          return Err(Error::OutOfResources)
+        }
+        Err(reason) => {
+          let now = Instant::now();
+          if now >= deadline {
+            debug!("Writer history is still full after max_blocking_time: topic={:?} reason={:?}", 
+                   self.my_topic.get_name(), reason);
+            return Err(Error::Timeout)
+          }
+          usage = self.history_usage.released.wait_timeout(usage, deadline - now)?.0;
+        }
+      }
+    }
+  }
+
+  // Async version of admit_to_history. Waits up to max_blocking_time without blocking
+  // the thread.
+  async fn async_admit_to_history(&self, key_hash: KeyHash, is_dispose: bool) -> Result<()> {
+    let max_blocking_time =
+      match self.qos_policy.reliability() {
+        Some(Reliability::Reliable { max_blocking_time }) => Duration::from(max_blocking_time),
+        _ => Duration::from_secs(0),
+      };
+    let mut deadline = DeadlineWaker::new(max_blocking_time);
+    future::poll_fn( |cx| {
+      // Register before trying, so that a release in between is not lost.
+      self.data_writer_wakers.register(cx.waker());
+      let mut usage = match self.history_usage.usage.lock() {
+        Ok(usage) => usage,
+        Err(e) => return task::Poll::Ready(Err(Error::from(e))),
+      };
+      match usage.admit(key_hash, is_dispose, &self.qos_policy) {
+        Ok(()) => task::Poll::Ready(Ok(())),
+        Err(reason) if ! self.can_wait_for_history(reason) => {
+          debug!("Writer history is full: topic={:?} reason={:?}", self.my_topic.get_name(), reason);
+          task::Poll::Ready(Err(Error::OutOfResources))
+        }
+        Err(reason) if deadline.has_expired() => {
+          debug!("Writer history is still full after max_blocking_time: topic={:?} reason={:?}", 
+                 self.my_topic.get_name(), reason);
+          task::Poll::Ready(Err(Error::Timeout))
+        }
+        Err(_reason) => {
+          deadline.register(cx.waker());
+          task::Poll::Pending
+        }
+      }
+    }).await
+  }
+
+  // Only acknowledgments from reliable Readers release samples. They do not release 
+  // instances, as instances are released by disposing them.
+  fn can_wait_for_history(&self, reason: SampleRejectedStatusKind) -> bool {
+    reason != SampleRejectedStatusKind::ByInstancesLimit
+      && matches!(self.qos_policy.reliability(), Some(Reliability::Reliable { .. }))
+  }
+
+  // The admitted sample never reached the Writer.
+  fn cancel_history_admission(&self, key_hash: KeyHash) {
+    match self.history_usage.usage.lock() {
+      Ok(mut usage) => usage.release(key_hash),
+      Err(e) => error!("History usage is poisoned: {:?}", e),
+    }
+  }
+
   /// This operation blocks the calling thread until either all data written by the 
   /// reliable DataWriter entities is acknowledged by all
   /// matched reliable DataReader entities, or else the duration specified by the 
@@ -358,8 +542,9 @@ where
         let (acked_sender,acked_receiver) = mio_channel::sync_channel::<()>(1);
         let poll = Poll::new()?;
         poll.register(&acked_receiver, Token(0), Ready::readable(), PollOpt::edge() )?;
+        let deadline = Instant::now().checked_add(max_wait);
         self.cc_upload.try_send(
-          WriterCommand::WaitForAcknowledgments { all_acked: acked_sender })?;
+          WriterCommand::WaitForAcknowledgments { all_acked: acked_sender, deadline })?;
         let mut events = Events::with_capacity(1);
         poll.poll(&mut events, Some(max_wait) )?;
         if let Some( _event ) = events.iter().next() {
@@ -375,6 +560,77 @@ where
       }
     } // match
   }
+
+  /// Async version of [`wait_for_acknowledgments`](#method.wait_for_acknowledgments).
+  /// Completes when all data written so far is acknowledged by all matched reliable
+  /// DataReaders. There is no `max_wait`: use the timeouts of your async runtime instead.
+  pub async fn async_wait_for_acknowledgments(&self) -> Result<()> {
+    match &self.qos_policy.reliability {
+      None => Ok(()),
+      Some(Reliability::BestEffort) => Ok(()),
+      Some(Reliability::Reliable { .. }) => {
+        let (acked_sender,acked_receiver) = mio_channel::sync_channel::<()>(1);
+        async_send(&self.cc_upload, &self.data_writer_wakers,
+          WriterCommand::WaitForAcknowledgments { all_acked: acked_sender, deadline: None }).await?;
+        future::poll_fn( |cx| {
+          self.data_writer_wakers.register(cx.waker());
+          match acked_receiver.try_recv() {
+            Ok(()) => task::Poll::Ready(Ok(())),
+            Err(TryRecvError::Empty) => task::Poll::Pending,
+            Err(TryRecvError::Disconnected) => task::Poll::Ready(log_and_err_precondition_not_met!(
+              "async_wait_for_acknowledgments - Writer stopped waiting.")),
+          }
+        }).await
+      }
+    } // match
+  }
+
+  /// Sets the fragment size used for samples that are too large to be sent
+  /// in a single DATA submessage. Such samples are split into DATA_FRAG submessages
+  /// of `fragment_size` bytes each. The default is 64000 bytes, the largest allowed.
+  /// A smaller size avoids IP fragmentation, but sends more messages.
+  ///
+  /// Returns `BadParameter` error, if `fragment_size` is zero or larger than 64000,
+  /// because a fragment must fit into a single UDP datagram.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use serde::{Serialize, Deserialize};
+  /// # use rustdds::dds::DomainParticipant;
+  /// # use rustdds::dds::qos::QosPolicyBuilder;
+  /// # use rustdds::dds::data_types::TopicKind;
+  /// # use rustdds::dds::With_Key_DataWriter as DataWriter;
+  /// # use rustdds::dds::traits::Keyed;
+  /// # use rustdds::serialization::CDRSerializerAdapter;
+  /// #
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// let qos = QosPolicyBuilder::new().build();
+  /// let publisher = domain_participant.create_publisher(&qos).unwrap();
+  ///
+  /// #[derive(Serialize, Deserialize)]
+  /// struct SomeType { a: i32 }
+  /// impl Keyed for SomeType {
+  ///   type K = i32;
+  ///
+  ///   fn get_key(&self) -> Self::K {
+  ///     self.a
+  ///   }
+  /// }
+  ///
+  /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
+  /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
+  ///
+  /// data_writer.set_fragment_size(8000).unwrap();
+  /// ```
+  pub fn set_fragment_size(&self, fragment_size: u16) -> Result<()> {
+    if fragment_size == 0 || fragment_size > MAX_FRAGMENT_SIZE {
+      return Error::bad_parameter("Fragment size must be between 1 and 64000")
+    }
+    self.cc_upload.try_send( WriterCommand::SetFragmentSize{ fragment_size } )
+      .or_else( |e| log_and_err_internal!("Cannot set fragment size. {:?}", e) )
+  }
+
   /*
   /// Gets mio Receiver for all status changes
   ///
@@ -425,12 +681,16 @@ where
     &self.status_receiver
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  */
+
+  /// Gets the liveliness lost status: How many times this DataWriter has failed to
+  /// assert its liveliness within the lease duration of Liveliness QoS.
+  ///
+  /// The `count_change` is the change since the previous call. Calling this resets it to zero.
   ///
   /// # Examples
   ///
-  /// ```no_run
-  // TODO: enable when functional
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -457,17 +717,19 @@ where
   /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
   /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
   ///
-  /// // Liveliness lost status has changed
-  ///
-  /// if let Ok(lls) = data_writer.get_liveliness_lost_status() {
-  ///   // do something
-  /// }
+  /// let status = data_writer.get_liveliness_lost_status().unwrap();
+  /// assert_eq!(status.total_count.count(), 0);
   /// ```
   pub fn get_liveliness_lost_status(&self) -> Result<LivelinessLostStatus> {
-    todo!()
+    Ok(self.status_accumulator.lock()?.liveliness_lost())
   }
 
-  /// Should get latest offered deadline missed status. <b>Do not use yet</b> use `get_status_lister` instead for the moment.
+  /// Gets the offered deadline missed status: How many times this DataWriter has failed
+  /// to write a sample within the period of Deadline QoS.
+  /// Deadline is tracked separately for each instance. The `last_instance_key`
+  /// can be compared to `Key::into_hash_key()` of the instance.
+  ///
+  /// The `count_change` is the change since the previous call. Calling this resets it to zero.
   ///
   /// # Examples
   ///
@@ -498,40 +760,21 @@ where
   /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
   /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
   ///
-  /// // Deadline missed status has changed
-  ///
-  /// if let Ok(odms) = data_writer.get_offered_deadline_missed_status() {
-  ///   // do something
-  /// }
+  /// let status = data_writer.get_offered_deadline_missed_status().unwrap();
+  /// assert_eq!(status.total_count.count(), 0);
   /// ```
   pub fn get_offered_deadline_missed_status(&self) -> Result<OfferedDeadlineMissedStatus> {
-    let mut fstatus = OfferedDeadlineMissedStatus::new();
-    while let Ok(status) = self.status_receiver.try_recv() {
-      match status {
-        StatusChange::OfferedDeadlineMissedStatus(status) => fstatus = status,
-        // TODO: possibly save old statuses
-        _ => (),
-      }
-    }
-
-    match self
-      .cc_upload
-      .try_send(WriterCommand::ResetOfferedDeadlineMissedStatus {
-        writer_guid: self.get_guid(),
-      }) {
-      Ok(_) => (),
-      Err(e) => error!("Unable to send ResetOfferedDeadlineMissedStatus. {:?}", e),
-    };
-
-    Ok(fstatus)
+    Ok(self.status_accumulator.lock()?.offered_deadline_missed())
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the offered incompatible QoS status: How many times a DataReader requesting
+  /// QoS incompatible with this DataWriter has been found, and which policies were incompatible.
+  ///
+  /// The `count_change` is the change since the previous call. Calling this resets it to zero.
   ///
   /// # Examples
   ///
-  /// ```no_run
-  // TODO: enable when functional
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -558,22 +801,22 @@ where
   /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
   /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
   ///
-  /// // Liveliness lost status has changed
-  ///
-  /// if let Ok(oiqs) = data_writer.get_offered_incompatible_qos_status() {
-  ///   // do something
-  /// }
+  /// let status = data_writer.get_offered_incompatible_qos_status().unwrap();
+  /// assert_eq!(status.last_policy_id, None);
   /// ```
   pub fn get_offered_incompatible_qos_status(&self) -> Result<OfferedIncompatibleQosStatus> {
-    todo!()
+    Ok(self.status_accumulator.lock()?.offered_incompatible_qos())
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the publication matched status: How many DataReaders this DataWriter has
+  /// been matched with in total, and how many are matched currently.
+  ///
+  /// The `count_change` values are changes since the previous call. 
+  /// Calling this resets them to zero.
   ///
   /// # Examples
   ///
-  /// ```no_run
-  // TODO: enable when functional
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -600,17 +843,12 @@ where
   /// let topic = domain_participant.create_topic("some_topic", "SomeType", &qos, TopicKind::WithKey).unwrap();
   /// let data_writer = publisher.create_datawriter::<SomeType, CDRSerializerAdapter<_>>(topic, None).unwrap();
   ///
-  /// // Liveliness lost status has changed
-  ///
-  /// if let Ok(pms) = data_writer.get_publication_matched_status() {
-  ///   // do something
-  /// }
+  /// let status = data_writer.get_publication_matched_status().unwrap();
+  /// assert_eq!(status.current_count.count(), 0);
   /// ```
   pub fn get_publication_matched_status(&self) -> Result<PublicationMatchedStatus> {
-    todo!()
+    Ok(self.status_accumulator.lock()?.publication_matched())
   }
-  
-  */
 
   /// Topic assigned to this DataWriter
   ///
@@ -738,12 +976,11 @@ where
     Ok(())
   }
 
-  /// Unimplemented. <b>Do not use</b>.
+  /// Gets the discovery data of the remote DataReaders currently matched with this DataWriter.
   ///
   /// # Examples
   ///
-  /// ```no_run
-  // TODO: enable when available
+  /// ```
   /// # use serde::{Serialize, Deserialize};
   /// # use rustdds::dds::DomainParticipant;
   /// # use rustdds::dds::qos::QosPolicyBuilder;
@@ -773,8 +1010,38 @@ where
   /// for sub in data_writer.get_matched_subscriptions().iter() {
   ///   // do something
   /// }
+  /// ```
   pub fn get_matched_subscriptions(&self) -> Vec<SubscriptionBuiltinTopicData> {
-    todo!()
+    let (reply_sender, reply_receiver) = std::sync::mpsc::sync_channel(1);
+    let reader_guids = 
+      match self.cc_upload.try_send(WriterCommand::GetMatchedReaders { reply: reply_sender }) {
+        Ok(()) => reply_receiver.recv_timeout(QUERY_REPLY_TIMEOUT)
+          .unwrap_or_else( |e| {
+            warn!("get_matched_subscriptions: No reply from Writer: {:?}", e);
+            Vec::new() 
+          }),
+        Err(e) => {
+          error!("get_matched_subscriptions: Cannot send command to Writer: {:?}", e);
+          Vec::new()
+        }
+      };
+
+    match self.my_publisher.get_participant() {
+      Some(dp) => match dp.discovery_db().read() {
+        Ok(db) => reader_guids.iter()
+          .filter_map( |guid| db.find_remote_reader(*guid) )
+          .map( |drd| drd.subscription_topic_data.clone() )
+          .collect(),
+        Err(e) => {
+          error!("get_matched_subscriptions: DiscoveryDB is poisoned: {:?}", e);
+          Vec::new()
+        }
+      }
+      None => {
+        error!("get_matched_subscriptions: DomainParticipant doesn't exist anymore.");
+        Vec::new()
+      }
+    }
   }
 
   /// Disposes data instance with specified key
@@ -835,10 +1102,16 @@ where
       ChangeKind::NotAliveDisposed,
       SerializedPayload::new_from_Bytes( SA::output_encoding() , send_buffer) 
     );
+    let source_timestamp = source_timestamp.or_else( || Some(Timestamp::now()) );
+    let key_hash = key.into_hash_key();
+    self.admit_to_history(key_hash, true)?;
     self.cc_upload
-      .send(WriterCommand::DDSData { data: ddsdata , source_timestamp })
-      .or_else(|huh| 
-        log_and_err_internal!("Cannot send dispose command: {:?}", huh))?;
+      .send(WriterCommand::DDSData { data: ddsdata , source_timestamp, key_hash, 
+                                     filtered_readers: BTreeSet::new() })
+      .or_else(|huh| {
+        self.cancel_history_admission(key_hash);
+        log_and_err_internal!("Cannot send dispose command: {:?}", huh)
+      })?;
 
     self.refresh_manual_liveliness();
     Ok(())
@@ -1004,4 +1277,279 @@ mod tests {
     assert!(res) // we should get "true" immediately, because we have
                            // no Reliable QoS
   }
+  #[test]
+  fn dw_status_getters() {
+    use crate::dds::qos::{QosPolicyBuilder, QosPolicyId, policy::Reliability};
+    use crate::serialization::CDRDeserializerAdapter;
+    use crate::structure::duration::Duration as DDSDuration;
+
+    let reliable = QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable { max_blocking_time: DDSDuration::DURATION_ZERO })
+      .build();
+    let best_effort = QosPolicyBuilder::new()
+      .reliability(Reliability::BestEffort)
+      .build();
+
+    let dp_w = DomainParticipant::new(0).expect("Participant creation failed!");
+    let publisher = dp_w.create_publisher(&reliable).unwrap();
+    let topic_matched = dp_w
+      .create_topic("status_test_matched", "RandomData", &reliable, TopicKind::WithKey)
+      .unwrap();
+    let topic_incompatible = dp_w
+      .create_topic("status_test_incompatible", "RandomData", &best_effort, TopicKind::WithKey)
+      .unwrap();
+    let writer_matched: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic_matched, None).unwrap();
+    let writer_incompatible: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic_incompatible, Some(best_effort)).unwrap();
+
+    // Nothing has happened yet
+    assert_eq!(writer_matched.get_liveliness_lost_status().unwrap().total_count.count(), 0);
+    assert_eq!(writer_matched.get_offered_deadline_missed_status().unwrap().total_count.count(), 0);
+
+    let dp_r = DomainParticipant::new(0).expect("Participant creation failed!");
+    let subscriber = dp_r.create_subscriber(&reliable).unwrap();
+    let mut readers = Vec::new();
+    for topic_name in &["status_test_matched", "status_test_incompatible"] {
+      let topic = dp_r
+        .create_topic(topic_name, "RandomData", &reliable, TopicKind::WithKey)
+        .unwrap();
+      readers.push( subscriber
+        .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic, None)
+        .unwrap() );
+    }
+
+    let mut matched = writer_matched.get_publication_matched_status().unwrap();
+    let mut incompatible = writer_incompatible.get_offered_incompatible_qos_status().unwrap();
+    for _ in 0..100 {
+      if matched.current_count.count() > 0 && incompatible.total_count.count() > 0 {
+        break
+      }
+      thread::sleep(Duration::from_millis(100));
+      if matched.current_count.count() == 0 {
+        matched = writer_matched.get_publication_matched_status().unwrap();
+      }
+      if incompatible.total_count.count() == 0 {
+        incompatible = writer_incompatible.get_offered_incompatible_qos_status().unwrap();
+      }
+    }
+    assert_eq!(matched.total_count, CountWithChange::new(1, 1));
+    assert_eq!(matched.current_count, CountWithChange::new(1, 1));
+    assert_eq!(incompatible.total_count, CountWithChange::new(1, 1));
+    assert_eq!(incompatible.last_policy_id, Some(QosPolicyId::Reliability));
+    assert_eq!(incompatible.policies.len(), 1);
+    assert_eq!(incompatible.policies[0].count(), 1);
+
+    // Reading resets the changes
+    let matched = writer_matched.get_publication_matched_status().unwrap();
+    assert_eq!(matched.current_count, CountWithChange::new(1, 0));
+    let incompatible = writer_incompatible.get_offered_incompatible_qos_status().unwrap();
+    assert_eq!(incompatible.total_count, CountWithChange::new(1, 0));
+    assert_eq!(writer_incompatible.get_publication_matched_status().unwrap().total_count.count(), 0);
+  }
+  #[test]
+  fn dw_matched_subscriptions() {
+    use crate::dds::qos::QosPolicyBuilder;
+    use crate::serialization::CDRDeserializerAdapter;
+
+    let qos = QosPolicyBuilder::new().build();
+    let dp_w = DomainParticipant::new(0).expect("Participant creation failed!");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic = dp_w
+      .create_topic("matched_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let writer: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic, None).unwrap();
+    assert!(writer.get_matched_subscriptions().is_empty());
+
+    let dp_r = DomainParticipant::new(0).expect("Participant creation failed!");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic = dp_r
+      .create_topic("matched_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic, None)
+      .unwrap();
+
+    let mut subscriptions = Vec::new();
+    let mut publications = Vec::new();
+    for _ in 0..100 {
+      if ! subscriptions.is_empty() && ! publications.is_empty() {
+        break
+      }
+      thread::sleep(Duration::from_millis(100));
+      subscriptions = writer.get_matched_subscriptions();
+      publications = reader.get_matched_publications().collect();
+    }
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].key(), reader.get_guid());
+    assert_eq!(publications.len(), 1);
+    assert_eq!(publications[0].key, writer.get_guid());
+  }
+
+  #[test]
+  fn dw_offered_deadline_missed() {
+    use crate::dds::qos::{QosPolicyBuilder, policy::Deadline};
+    use crate::structure::duration::Duration as DDSDuration;
+
+    let dp = DomainParticipant::new(0).expect("Participant creation failed!");
+    let qos = QosPolicyBuilder::new()
+      .deadline(Deadline(DDSDuration::from_millis(100)))
+      .build();
+    let publisher = dp.create_publisher(&qos).unwrap();
+    let topic = dp
+      .create_topic("deadline_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let writer: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic, None).unwrap();
+
+    // Nothing written, so nothing can be late.
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(writer.get_offered_deadline_missed_status().unwrap().total_count.count(), 0);
+
+    let data = RandomData { a: 1, b: "late".to_string() };
+    writer.write(data.clone(), None).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    let status = writer.get_offered_deadline_missed_status().unwrap();
+    assert!(status.total_count.count() > 0);
+    assert_eq!(status.last_instance_key, Some(data.get_key().into_hash_key()));
+
+    // Disposed instance has no deadline
+    writer.dispose(data.get_key(), None).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let count = writer.get_offered_deadline_missed_status().unwrap().total_count.count();
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(writer.get_offered_deadline_missed_status().unwrap().total_count.count(), count);
+  }
+
+  #[test]
+  fn dw_content_filtered_reader() {
+    use crate::dds::qos::{QosPolicyBuilder, policy::History};
+    use crate::dds::data_types::{ReadCondition, DDSDuration};
+    use crate::dds::statusevents::{DataReaderStatus, DataWriterStatus};
+    use crate::serialization::CDRDeserializerAdapter;
+    use crate::test::wait_util::*;
+
+    let qos = QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable { max_blocking_time: DDSDuration::from_millis(100) })
+      .history(History::KeepAll)
+      .build();
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(16).expect("Participant creation failed!");
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let topic_w = dp_w
+      .create_topic("content_filter_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let mut writer: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic_w, None).unwrap();
+
+    let dp_r = DomainParticipant::new(16).expect("Participant creation failed!");
+    let subscriber = dp_r.create_subscriber(&qos).unwrap();
+    let topic_r = dp_r
+      .create_topic("content_filter_test", "RandomData", &qos, TopicKind::WithKey)
+      .unwrap();
+    let filtered_topic = dp_r
+      .create_contentfilteredtopic("content_filter_test_cft", &topic_r, "a > %0", &["10".to_string()])
+      .unwrap();
+    let mut reader = subscriber
+      .create_filtered_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(filtered_topic, None)
+      .unwrap();
+
+    let small = |a| RandomData { a, b: "small".to_string() };
+    let large = |a| RandomData { a, b: "large".to_string() };
+    // The Writer knows the filter of the Reader, when it has matched the Reader.
+    assert!(wait_for_status(&mut writer, Duration::from_secs(10),
+      |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    assert!(wait_for_status(&mut reader, Duration::from_secs(10),
+      |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+    assert_eq!(writer.filtered_readers(&small(5)).into_iter().collect::<Vec<_>>(), vec![reader.get_guid()]);
+    assert!(writer.filtered_readers(&large(20)).is_empty());
+
+    for data in vec![small(5), large(20), small(7), large(30)] {
+      writer.write(data, None).unwrap();
+    }
+    let received: Vec<i64> = (0..2)
+      .filter_map( |_| wait_for_sample(&mut reader, Duration::from_secs(5)) )
+      .filter_map( |s| s.into_value().ok().map( |d| d.a ) )
+      .collect();
+    assert_eq!(received, vec![20, 30]);
+
+    // Filtered samples are acknowledged by the Reader through GAP.
+    assert!(writer.wait_for_acknowledgments(std::time::Duration::from_secs(2)).unwrap());
+    assert!(reader.take(10, ReadCondition::any()).unwrap().is_empty());
+  }
+
+  #[test]
+  fn dw_keep_all_blocks_on_full_history() {
+    use crate::dds::qos::{QosPolicyBuilder, policy::{History, ResourceLimits}};
+    use crate::dds::statusevents::{DataReaderStatus, DataWriterStatus};
+    use crate::serialization::CDRDeserializerAdapter;
+    use crate::structure::duration::Duration as DDSDuration;
+    use crate::test::wait_util::*;
+    use futures::executor::block_on;
+
+    let qos = |max_blocking_time| QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable { max_blocking_time })
+      .history(History::KeepAll)
+      .resource_limits(ResourceLimits {
+        max_samples: 1,
+        max_instances: ResourceLimits::LENGTH_UNLIMITED,
+        max_samples_per_instance: ResourceLimits::LENGTH_UNLIMITED,
+      })
+      .build();
+    // Own domain, so that tests running in parallel do not interfere.
+    let dp_w = DomainParticipant::new(22).expect("Participant creation failed!");
+    let publisher = dp_w.create_publisher(&qos(DDSDuration::DURATION_ZERO)).unwrap();
+    let data = |a| RandomData { a, b: "blocked".to_string() };
+
+    // A sample that is never acknowledged makes write time out.
+    let timeout_qos = qos(DDSDuration::from_millis(200));
+    let topic = dp_w
+      .create_topic("keep_all_timeout_test", "RandomData", &timeout_qos, TopicKind::WithKey)
+      .unwrap();
+    let writer: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic, Some(timeout_qos.clone())).unwrap();
+    let unacked_key = 99i64.into_hash_key();
+    writer.history_usage.usage.lock().unwrap().admit(unacked_key, false, &timeout_qos).unwrap();
+    let start = Instant::now();
+    assert!(matches!(writer.write(data(1), None), Err(Error::Timeout)));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    let start = Instant::now();
+    assert!(matches!(block_on(writer.async_write(data(1), None)), Err(Error::Timeout)));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // The acknowledgments of a reliable Reader release the history, and let blocked
+    // writes through.
+    let blocking_qos = qos(DDSDuration::from_secs(10));
+    let topic = dp_w
+      .create_topic("keep_all_blocking_test", "RandomData", &blocking_qos, TopicKind::WithKey)
+      .unwrap();
+    let mut writer: DataWriter<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>> =
+      publisher.create_datawriter(topic, Some(blocking_qos.clone())).unwrap();
+    let dp_r = DomainParticipant::new(22).expect("Participant creation failed!");
+    let reader_qos = QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable { max_blocking_time: DDSDuration::DURATION_ZERO })
+      .history(History::KeepAll)
+      .build();
+    let subscriber = dp_r.create_subscriber(&reader_qos).unwrap();
+    let topic = dp_r
+      .create_topic("keep_all_blocking_test", "RandomData", &reader_qos, TopicKind::WithKey)
+      .unwrap();
+    let mut reader = subscriber
+      .create_datareader::<RandomData, CDRDeserializerAdapter<RandomData>>(topic, None)
+      .unwrap();
+    assert!(wait_for_status(&mut writer, Duration::from_secs(10),
+      |s| matches!(s, DataWriterStatus::PublicationMatched{..}) ).is_some());
+    assert!(wait_for_status(&mut reader, Duration::from_secs(10),
+      |s| matches!(s, DataReaderStatus::SubscriptionMatched{..}) ).is_some());
+
+    writer.write(data(1), None).unwrap();
+    // History is full until the Reader acknowledges the previous sample.
+    writer.write(data(2), None).unwrap();
+    block_on(writer.async_write(data(3), None)).unwrap();
+    for a in 1..=3 {
+      let sample = wait_for_sample(&mut reader, Duration::from_secs(10)).unwrap();
+      assert_eq!(sample.into_value().ok().map( |d| d.a ), Some(a));
+    }
+  }
 }
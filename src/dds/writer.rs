@@ -4,15 +4,16 @@ use std::ops::Bound::Included;
 use log::{debug, error, warn, info, trace};
 
 use speedy::{Writable, Endianness};
+use bytes::Bytes;
 use mio_extras::channel::{self as mio_channel, SyncSender, TrySendError};
 use mio_extras::timer::Timer;
 use mio::Token;
 use std::{
-  sync::{RwLock, Arc},
+  sync::{RwLock, Arc, Mutex, Condvar},
   rc::Rc,
-  collections::{HashSet, BTreeMap, BTreeSet},
+  collections::{HashSet, BTreeMap, BTreeSet, VecDeque},
   iter::FromIterator,
-  cmp::max,
+  cmp::{max, min},
 };
 
 use crate::{
@@ -22,20 +23,23 @@ use crate::{
 use crate::structure::time::Timestamp;
 use crate::structure::duration::Duration;
 
-use crate::structure::guid::{GuidPrefix, EntityId, GUID};
-use crate::structure::sequence_number::{SequenceNumber};
+use crate::structure::guid::{GuidPrefix, EntityId, EntityKind, GUID};
+use crate::structure::sequence_number::{SequenceNumber, FragmentNumber};
 use crate::{
   // messages::submessages::submessages::{
   //   AckNack,
   // },
   messages::submessages::submessages::AckSubmessage,
+  messages::submessages::submessage_elements::serialized_payload::RepresentationIdentifier,
 
-  structure::cache_change::{CacheChange},
+  structure::cache_change::{CacheChange, ChangeKind},
   serialization::{Message},
   dds::dp_event_loop::NACK_RESPONSE_DELAY,
+  dds::content_filter::ContentFilter,
+  discovery::content_filter_property::ContentFilterProperty,
 };
 
-use crate::dds::{ddsdata::DDSData, qos::HasQoSPolicy};
+use crate::dds::{ddsdata::DDSData, qos::HasQoSPolicy, traits::key::KeyHash};
 use crate::{
   network::udp_sender::UDPSender,
   structure::{
@@ -46,11 +50,24 @@ use crate::{
   },
 };
 use super::{
+  message_batch::MessageBatch,
   qos::{policy, QosPolicies},
   rtps_reader_proxy::RtpsReaderProxy,
   statusevents::*,
+  helpers::TaskWakers,
 };
-use policy::{History, Reliability};
+use policy::{History, Reliability, Durability};
+
+/// Largest allowed fragment size. A DATA_FRAG message must fit into a single
+/// UDP datagram (at most 65507 bytes of payload), leaving room for the RTPS header,
+/// INFO submessages, DATA_FRAG header and inline QoS.
+pub const MAX_FRAGMENT_SIZE: u16 = 64_000;
+
+/// Default size of fragments, when a sample is too large to be sent in
+/// a single DATA submessage. Samples up to this size go in a single DATA, and
+/// larger ones are split into as few DATA_FRAGs as a UDP datagram allows.
+/// IP fragments the datagrams to fit the link MTU.
+pub const DEFAULT_FRAGMENT_SIZE: u16 = MAX_FRAGMENT_SIZE;
 
 #[derive(PartialEq,Eq,Clone,Copy)]
 pub enum DeliveryMode {
@@ -63,6 +80,9 @@ pub(crate) enum TimedEvent {
   Heartbeat,
   CacheCleaning,
   SendRepairData { to_reader: GUID },
+  DeadlineMissedCheck,
+  LivelinessLostCheck,
+  LifespanExpiry,
 }
 
 
@@ -74,8 +94,99 @@ pub(crate) struct WriterIngredients {
   pub topic_name: String,
   pub qos_policies: QosPolicies,
   pub status_sender: SyncSender<DataWriterStatus>,
+  pub status_accumulator: Arc<Mutex<DataWriterStatusAccumulator>>,
+  pub reader_filters: Arc<Mutex<ReaderFilters>>,
+  pub history_usage: Arc<SharedHistoryUsage>,
+  pub data_writer_wakers: TaskWakers,
+}
+
+/// Content filters of matched Readers. The Writer keeps this up to date, and the
+/// DataWriter evaluates the filters on the samples it writes.
+pub(crate) type ReaderFilters = BTreeMap<GUID, ContentFilter>;
+
+/// How much of ResourceLimits the DataWriter is using. The DataWriter admits new samples
+/// only within the limits. With KEEP_ALL History, the Writer releases samples when all
+/// reliable Readers have acknowledged them.
+#[derive(Debug, Default)]
+pub(crate) struct HistoryUsage {
+  // Instances written, and not disposed since
+  live_instances: BTreeSet<KeyHash>,
+  // KEEP_ALL History: samples not yet acknowledged, per instance and in total
+  unacked_instance_samples: BTreeMap<KeyHash, usize>,
+  unacked_samples: usize,
+}
+
+impl HistoryUsage {
+  pub fn new() -> HistoryUsage {
+    HistoryUsage::default()
+  }
+
+  // Reserves room for a new sample, or a dispose, of an instance. With KEEP_LAST History
+  // new samples supersede old ones, so then only the number of instances is limited.
+  pub fn admit(&mut self, key_hash: KeyHash, is_dispose: bool, qos: &QosPolicies) 
+    -> std::result::Result<(), SampleRejectedStatusKind> 
+  {
+    let limits = match qos.resource_limits() {
+      Some(limits) => limits,
+      None => return Ok(()), // nothing to keep track of
+    };
+    let keep_all = qos.history() == Some(History::KeepAll);
+    if let Some(max_instances) = limits.instances_limit() {
+      if ! is_dispose && ! self.live_instances.contains(&key_hash) 
+          && self.live_instances.len() >= max_instances {
+        return Err(SampleRejectedStatusKind::ByInstancesLimit)
+      }
+    }
+    if keep_all {
+      let instance_samples = self.unacked_instance_samples.get(&key_hash).copied().unwrap_or(0);
+      if let Some(max_samples_per_instance) = limits.samples_per_instance_limit() {
+        if instance_samples >= max_samples_per_instance {
+          return Err(SampleRejectedStatusKind::BySamplesPerInstanceLimit)
+        }
+      }
+      if let Some(max_samples) = limits.samples_limit() {
+        if self.unacked_samples >= max_samples {
+          return Err(SampleRejectedStatusKind::BySamplesLimit)
+        }
+      }
+    }
+    if is_dispose {
+      self.live_instances.remove(&key_hash);
+    } else {
+      self.live_instances.insert(key_hash);
+    }
+    if keep_all {
+      *self.unacked_instance_samples.entry(key_hash).or_insert(0) += 1;
+      self.unacked_samples += 1;
+    }
+    Ok(())
+  }
+
+  // A KEEP_ALL sample has been acknowledged, or it was never sent to the Writer.
+  pub fn release(&mut self, key_hash: KeyHash) {
+    if let Some(count) = self.unacked_instance_samples.get_mut(&key_hash) {
+      *count -= 1;
+      if *count == 0 {
+        self.unacked_instance_samples.remove(&key_hash);
+      }
+      self.unacked_samples -= 1;
+    }
+  }
 }
 
+/// HistoryUsage shared by the DataWriter and the Writer. A DataWriter blocked on
+/// a full history waits on `released` until the Writer releases acknowledged samples.
+#[derive(Debug, Default)]
+pub(crate) struct SharedHistoryUsage {
+  pub usage: Mutex<HistoryUsage>,
+  pub released: Condvar,
+}
+
+impl SharedHistoryUsage {
+  pub fn new() -> SharedHistoryUsage {
+    SharedHistoryUsage::default()
+  }
+}
 
 pub(crate) struct Writer {
   pub endianness: Endianness,
@@ -123,6 +234,9 @@ pub(crate) struct Writer {
   ///SerializedPayload that may be
   ///sent by the Writer
   pub data_max_size_serialized: u64,
+  /// Samples whose serialized size exceeds this are sent as DATA_FRAG
+  /// submessages with fragments of this size.
+  pub fragment_size: u16,
   endpoint_attributes: EndpointAttributes,
   my_guid: GUID,
   pub(crate) writer_command_receiver: mio_channel::Receiver<WriterCommand>,
@@ -152,6 +266,33 @@ pub(crate) struct Writer {
   /// Useful when reader requires some sample with acknack.
   disposed_sequence_numbers: HashSet<SequenceNumber>,
 
+  /// With TRANSIENT_LOCAL (or stronger) Durability, the SequenceNumbers of the
+  /// latest samples of each instance, up to History depth. These are sent to
+  /// late-joining Readers.
+  durable_history: BTreeMap<KeyHash, VecDeque<SequenceNumber>>,
+  /// All SequenceNumbers in durable_history
+  durable_sequence_numbers: BTreeSet<SequenceNumber>,
+
+  /// Is a coherent set (Publisher::begin_coherent_changes) being written?
+  coherent_set_active: bool,
+  /// First SequenceNumber of the coherent set being written. This identifies the set.
+  /// None if nothing has been written in the set yet.
+  coherent_set_first: Option<SequenceNumber>,
+  /// SequenceNumbers of coherent set end markers. These are never superseded like samples
+  /// in durable_history, so they are sent to Readers that ask for them.
+  coherent_set_ends: BTreeSet<SequenceNumber>,
+
+  /// When the Publisher has suspended publications, new data is added to this batch
+  /// instead of sending it.
+  suspended_batch: Option<Arc<Mutex<MessageBatch>>>,
+  /// Last SequenceNumber written before publications were suspended. Later ones are
+  /// not announced in HEARTBEATs nor repaired until publications are resumed.
+  suspended_after: Option<SequenceNumber>,
+  /// Periodic HEARTBEAT not sent while suspended, and its liveliness flag.
+  held_back_heartbeat: Option<bool>,
+  /// Readers that asked for repairs to suspended changes while suspended.
+  held_back_repairs: BTreeSet<GUID>,
+
   //When dataWriter sends cacheChange message with cacheKind is NotAlive_Disposed
   //this is set true. If Datawriter after disposing sends new cahceChanges this falg is then
   //turned true.
@@ -167,15 +308,54 @@ pub(crate) struct Writer {
 
   // Used for sending status info about messages sent
   status_sender: SyncSender<DataWriterStatus>,
+  // All statuses are also accumulated here for the DataWriter status getters
+  status_accumulator: Arc<Mutex<DataWriterStatusAccumulator>>,
   //offered_deadline_status: OfferedDeadlineMissedStatus,
 
-  ack_waiter: Option<AckWaiter>,
+  // Each wait_for_acknowledgments call in progress
+  ack_waiters: Vec<AckWaiter>,
+
+  /// Latest write time of each live instance, for checking the offered Deadline.
+  instance_write_times: BTreeMap<KeyHash, Timestamp>,
+  offered_deadline_missed_count: i32,
+
+  /// When the application last asserted liveliness of this Writer. Only used with
+  /// MANUAL_BY_PARTICIPANT and MANUAL_BY_TOPIC Liveliness.
+  last_liveliness_assertion: Timestamp,
+  /// Lease has expired and no assertion since.
+  liveliness_lost: bool,
+  liveliness_lost_count: i32,
+
+  reader_filters: Arc<Mutex<ReaderFilters>>,
+
+  /// Shared with the DataWriter, which admits samples within ResourceLimits
+  history_usage: Arc<SharedHistoryUsage>,
+  /// KEEP_ALL History: Samples not yet acknowledged by all reliable Readers. These
+  /// are released from history_usage once acknowledged.
+  unacked_history: BTreeMap<SequenceNumber, KeyHash>,
 
+  /// Lifespan: When the samples in history expire
+  lifespan_expiries: BTreeMap<SequenceNumber, Timestamp>,
+  /// When the LifespanExpiry timer is set to fire, if it is set
+  lifespan_expiry_scheduled: Option<Timestamp>,
+
+  /// Async tasks of the DataWriter waiting for command channel space, acknowledgments
+  /// or status changes.
+  data_writer_wakers: TaskWakers,
 }
 
 pub(crate) enum WriterCommand {
-  DDSData { data: DDSData , source_timestamp : Option<Timestamp>, },
-  WaitForAcknowledgments { all_acked : mio_channel::SyncSender<()> },
+  // filtered_readers did not pass the sample through their content filter
+  DDSData { data: DDSData , source_timestamp : Option<Timestamp>, key_hash: KeyHash, 
+            filtered_readers: BTreeSet<GUID>, },
+  // The DataWriter stops waiting at deadline. None = never.
+  WaitForAcknowledgments { all_acked : mio_channel::SyncSender<()>, deadline: Option<std::time::Instant> },
+  SetFragmentSize { fragment_size: u16 },
+  BeginCoherentSet,
+  EndCoherentSet,
+  SuspendPublications { batch: Arc<Mutex<MessageBatch>> },
+  ResumePublications,
+  GetMatchedReaders { reply: std::sync::mpsc::SyncSender<Vec<GUID>> },
   //ResetOfferedDeadlineMissedStatus { writer_guid: GUID },
 }
 
@@ -183,6 +363,7 @@ struct AckWaiter {
   wait_until: SequenceNumber,
   complete_channel: SyncSender<()>,
   readers_pending:  BTreeSet<GUID>,
+  deadline: Option<std::time::Instant>,
 }
 
 
@@ -228,6 +409,14 @@ impl Writer {
     timed_event_timer.set_timeout(
       std::time::Duration::from(cache_cleaning_period), 
       TimedEvent::CacheCleaning);
+    // start checking offered deadline
+    if let Some(policy::Deadline(deadline)) = i.qos_policies.deadline {
+      timed_event_timer.set_timeout(deadline.to_std(), TimedEvent::DeadlineMissedCheck);
+    }
+    // start checking if the application keeps us alive
+    if let Some(lease_duration) = Self::manual_liveliness_lease(&i.qos_policies) {
+      timed_event_timer.set_timeout(lease_duration.to_std(), TimedEvent::LivelinessLostCheck);
+    }
     
 
     Writer {
@@ -241,6 +430,7 @@ impl Writer {
       first_change_sequence_number: SequenceNumber::from(1), // first = 1, last = 0
       last_change_sequence_number: SequenceNumber::from(0),  // means we have nothing to write
       data_max_size_serialized: 999999999, // TODO: this is not reasonable
+      fragment_size: DEFAULT_FRAGMENT_SIZE,
       my_guid: i.guid,
       writer_command_receiver: i.writer_command_receiver ,
       readers: BTreeMap::new(),
@@ -252,11 +442,32 @@ impl Writer {
       my_topic_name: i.topic_name,
       sequence_number_to_instant: BTreeMap::new(),
       disposed_sequence_numbers: HashSet::new(),
+      durable_history: BTreeMap::new(),
+      durable_sequence_numbers: BTreeSet::new(),
+      coherent_set_active: false,
+      coherent_set_first: None,
+      coherent_set_ends: BTreeSet::new(),
+      suspended_batch: None,
+      suspended_after: None,
+      held_back_heartbeat: None,
+      held_back_repairs: BTreeSet::new(),
       timed_event_timer,
       qos_policies: i.qos_policies,
       status_sender: i.status_sender,
+      status_accumulator: i.status_accumulator,
       //offered_deadline_status: OfferedDeadlineMissedStatus::new(),
-      ack_waiter: None,
+      ack_waiters: Vec::new(),
+      instance_write_times: BTreeMap::new(),
+      offered_deadline_missed_count: 0,
+      last_liveliness_assertion: Timestamp::now(),
+      liveliness_lost: false,
+      liveliness_lost_count: 0,
+      reader_filters: i.reader_filters,
+      history_usage: i.history_usage,
+      unacked_history: BTreeMap::new(),
+      lifespan_expiries: BTreeMap::new(),
+      lifespan_expiry_scheduled: None,
+      data_writer_wakers: i.data_writer_wakers,
     }
   }
 
@@ -334,29 +545,227 @@ impl Writer {
             }
           }
         }        
+        TimedEvent::DeadlineMissedCheck => {
+          self.handle_offered_deadline_check();
+          if let Some(policy::Deadline(deadline)) = self.qos_policies.deadline {
+            self.timed_event_timer
+              .set_timeout(deadline.to_std(), TimedEvent::DeadlineMissedCheck);
+          }
+        }
+        TimedEvent::LivelinessLostCheck => {
+          self.handle_liveliness_lost_check();
+        }
+        TimedEvent::LifespanExpiry => {
+          self.lifespan_expiry_scheduled = None;
+          self.handle_lifespan_expiry();
+        }
       }
     }
   }
 
+  // Lease duration, if the application is responsible for asserting our liveliness.
+  // AUTOMATIC Liveliness is asserted by Discovery, so it is never lost while we are running.
+  fn manual_liveliness_lease(qos: &QosPolicies) -> Option<Duration> {
+    match qos.liveliness {
+      Some(policy::Liveliness::ManualByParticipant { lease_duration })
+      | Some(policy::Liveliness::ManualByTopic { lease_duration }) 
+        if lease_duration != Duration::DURATION_INFINITE => Some(lease_duration),
+      _ => None,
+    }
+  }
+
+  fn assert_liveliness(&mut self) {
+    self.last_liveliness_assertion = Timestamp::now();
+    self.liveliness_lost = false;
+  }
+
+  // Liveliness of the Participant was asserted. With MANUAL_BY_PARTICIPANT Liveliness,
+  // this asserts our liveliness also.
+  pub fn participant_liveliness_asserted(&mut self, guid_prefix: GuidPrefix, manual_assertion: bool) {
+    if manual_assertion && guid_prefix == self.my_guid.guidPrefix 
+      && matches!(self.qos_policies.liveliness, Some(policy::Liveliness::ManualByParticipant{..})) {
+      self.assert_liveliness();
+    }
+  }
+
+  fn handle_liveliness_lost_check(&mut self) {
+    let lease_duration = match Self::manual_liveliness_lease(&self.qos_policies) {
+      Some(lease_duration) => lease_duration,
+      None => return,
+    };
+    let now = Timestamp::now();
+    let lease_expiry = self.last_liveliness_assertion + lease_duration;
+    if ! self.liveliness_lost && now > lease_expiry {
+      info!("Writer {:?} topic={:?} lost liveliness", self.my_guid, self.my_topic_name);
+      self.liveliness_lost = true;
+      self.liveliness_lost_count += 1;
+      self.send_status( DataWriterStatus::LivelinessLost { 
+        count: CountWithChange::start_from(self.liveliness_lost_count, 1),
+      });
+    }
+    // Check again when the lease expires next time, if not asserted before that.
+    let next_check = 
+      if self.liveliness_lost || now >= lease_expiry { lease_duration } 
+      else { lease_expiry.duration_since(now) };
+    self.timed_event_timer.set_timeout(next_check.to_std(), TimedEvent::LivelinessLostCheck);
+  }
+
+  // Every live instance must be written within the Deadline period. Each instance that
+  // was not is reported as a missed deadline, once per check period.
+  fn handle_offered_deadline_check(&mut self) {
+    let deadline = match self.qos_policies.deadline {
+      Some(policy::Deadline(deadline)) => deadline,
+      None => return,
+    };
+    let now = Timestamp::now();
+    let late_instances: Vec<KeyHash> = self.instance_write_times.iter()
+      .filter( |(_, last_write)| now.duration_since(**last_write) > deadline )
+      .map( |(key_hash, _)| *key_hash )
+      .collect();
+    for last_instance_key in late_instances {
+      debug!("Writer {:?} missed offered deadline of instance {:?}", self.my_guid, last_instance_key);
+      self.offered_deadline_missed_count += 1;
+      self.send_status( DataWriterStatus::OfferedDeadlineMissed {
+        count: CountWithChange::start_from(self.offered_deadline_missed_count, 1),
+        last_instance_key,
+      });
+    }
+  }
+
   /// This is called by dp_wrapper everytime cacheCleaning message is received.
   fn handle_cache_cleaning(&mut self) {
-    let resource_limit = 32; // TODO: This limit should be obtained
-    // from Topic and Writer QoS. There should be some reasonable default limit
-    // in case some suppied QoS setting does not specify a larger value.
-    // In any case, there has to be some limit to avoid memory leak.
+    self.release_acked_history();
+    let depth = self.history_depth();
+    self.remove_all_acked_changes_but_keep_depth(depth);
+  }
 
-    match self.qos_policies.history {
-      None => {
-        self.remove_all_acked_changes_but_keep_depth(1);
-      }
-      Some(History::KeepAll) => {
-        self.remove_all_acked_changes_but_keep_depth(resource_limit);
+  // All reliable Readers have acknowledged changes before this. If there are no
+  // reliable Readers, nobody is waiting for acknowledgments.
+  fn acked_by_all_reliable_readers(&self) -> SequenceNumber {
+    self.readers.values()
+      .filter( |rp| matches!(rp.qos().reliability(), Some(Reliability::Reliable{..})) )
+      .map( |rp| rp.acked_up_to_before() )
+      .min()
+      .unwrap_or(self.last_change_sequence_number + SequenceNumber::from(1))
+  }
+
+  // KEEP_ALL History: Acknowledged samples no longer count against ResourceLimits.
+  fn release_acked_history(&mut self) {
+    if self.unacked_history.is_empty() {
+      return
+    }
+    let still_unacked = self.unacked_history.split_off(&self.acked_by_all_reliable_readers());
+    let acked = std::mem::replace(&mut self.unacked_history, still_unacked);
+    self.release_history(acked.values());
+  }
+
+  fn release_history<'a>(&self, key_hashes: impl ExactSizeIterator<Item = &'a KeyHash>) {
+    if key_hashes.len() == 0 {
+      return
+    }
+    match self.history_usage.usage.lock() {
+      Ok(mut usage) => for key_hash in key_hashes {
+        usage.release(*key_hash)
       }
-      Some(History::KeepLast { depth: d }) => {
-        self.remove_all_acked_changes_but_keep_depth(d as usize);
+      Err(e) => error!("release_history: History usage is poisoned: {:?}", e),
+    }
+    // Wake up DataWriters blocked in write
+    self.history_usage.released.notify_all();
+    self.data_writer_wakers.wake_all();
+  }
+
+  // Lifespan: Remove expired samples from history. Reliable Readers that have not 
+  // acknowledged them get a GAP, as the samples will no longer be sent.
+  fn handle_lifespan_expiry(&mut self) {
+    let now = Timestamp::now();
+    let expired: BTreeSet<SequenceNumber> = self.lifespan_expiries.iter()
+      .filter( |(_, expiry)| **expiry <= now )
+      .map( |(sn, _)| *sn )
+      .collect();
+    if ! expired.is_empty() {
+      debug!("Lifespan expired {:?} in topic {:?}", expired, self.my_topic_name);
+      self.expire_changes(&expired);
+    }
+    self.schedule_lifespan_expiry();
+  }
+
+  fn expire_changes(&mut self, expired: &BTreeSet<SequenceNumber>) {
+    // The instant map entries stay, so that requests for the changes are answered by GAP.
+    match self.dds_cache.write() {
+      Ok(mut dds_cache) => 
+        for instant in expired.iter().filter_map( |sn| self.sequence_number_to_instant.get(sn) ) {
+          dds_cache.from_topic_remove_change(&self.my_topic_name, instant);
+        }
+      Err(e) => error!("expire_changes: DDSCache is poisoned: {:?}", e),
+    }
+    let mut released = Vec::new();
+    for sn in expired {
+      self.lifespan_expiries.remove(sn);
+      self.durable_sequence_numbers.remove(sn);
+      released.extend(self.unacked_history.remove(sn));
+    }
+    self.durable_history.retain( |_, instance_history| {
+      instance_history.retain( |sn| ! expired.contains(sn) );
+      ! instance_history.is_empty()
+    });
+    self.release_history(released.iter());
+
+    for reader_proxy in self.readers.values_mut() {
+      reader_proxy.unsent_changes.retain( |sn| ! expired.contains(sn) );
+    }
+    for reader_proxy in self.readers.values()
+          .filter( |rp| matches!(rp.qos().reliability(), Some(Reliability::Reliable{..})) ) {
+      let unacked: BTreeSet<SequenceNumber> = 
+        expired.range(reader_proxy.all_acked_before ..).copied().collect();
+      if unacked.is_empty() {
+        continue
       }
+      let reader_guid = reader_proxy.remote_reader_guid;
+      let gap_message = MessageBuilder::new()
+        .dst_submessage(self.endianness, reader_guid.guidPrefix)
+        .gap_msg(unacked, self, reader_guid)
+        .add_header_and_build(self.my_guid.guidPrefix);
+      self.send_message_to_readers(DeliveryMode::Unicast, &gap_message, 
+        &mut std::iter::once(reader_proxy));
+    }
+  }
+
+  // Set the LifespanExpiry timer to fire when the next sample in history expires, 
+  // unless it is already set to fire before that.
+  fn schedule_lifespan_expiry(&mut self) {
+    let next_expiry = match self.lifespan_expiries.values().min() {
+      Some(&next_expiry) => next_expiry,
+      None => return,
+    };
+    if matches!(self.lifespan_expiry_scheduled, Some(scheduled) if scheduled <= next_expiry) {
+      return
+    }
+    let delay = max(next_expiry - Timestamp::now(), Duration::DURATION_ZERO);
+    self.timed_event_timer.set_timeout(delay.to_std(), TimedEvent::LifespanExpiry);
+    self.lifespan_expiry_scheduled = Some(next_expiry);
+  }
+
+  // How many samples we keep in history, according to History QoS policy.
+  fn history_depth(&self) -> usize {
+    // There has to be some limit to avoid memory leak, also if ResourceLimits
+    // do not specify one.
+    let resource_limit = self.qos_policies.resource_limits()
+      .and_then( |rl| rl.samples_limit() )
+      .unwrap_or(32);
+
+    match self.qos_policies.history {
+      None => 1,
+      Some(History::KeepAll) => resource_limit,
+      Some(History::KeepLast { depth: d }) => max(d, 1) as usize,
     }
+  }
 
+  // Do we need to keep samples for late-joining Readers?
+  fn keeps_durable_history(&self) -> bool {
+    match self.qos_policies.durability {
+      None | Some(Durability::Volatile) => false,
+      Some(_) => true, // TransientLocal or stronger
+    }
   }
 
   // --------------------------------------------------------------
@@ -367,55 +776,18 @@ impl Writer {
   pub fn process_writer_command(&mut self) {
     while let Ok(cc) = self.writer_command_receiver.try_recv() {
       match cc {
-        WriterCommand::DDSData { data, source_timestamp } => {
-          // We have a new sample here. Things to do:
-          // 1. Insert it to history cache and get it sequence numbered
-          // 2. Send out data. 
-          //    If we are pushing data, send the DATA submessage and HEARTBEAT.
-          //    If we are not pushing, send out HEARTBEAT only. Readers will then ask the DATA with ACKNACK.
-          let timestamp = self.insert_to_history_cache(data, source_timestamp);
-
-          self.increase_heartbeat_counter();
-
-          let partial_message = MessageBuilder::new();
-          // If DataWriter sent us a source timestamp, then add that.
-          let partial_message = 
-            if let Some(src_ts) = source_timestamp {
-              partial_message.ts_msg(self.endianness, Some(src_ts) )
-            } else {
-              partial_message
-            };
-          // the beef: DATA submessage
-          let data_hb_message_builder = 
-            if self.push_mode {
-              if let Some(cache_change) = 
-                  self.dds_cache.read().unwrap()
-                    .from_topic_get_change(&self.my_topic_name, &timestamp) { 
-                partial_message
-                  .data_msg(cache_change.clone(), 
-                            // Now that payload contains Bytes, it is relatively cheap to clone
-                            EntityId::ENTITYID_UNKNOWN, // reader
-                            self.my_guid.entityId, // writer
-                            self.endianness ) 
-              } else { partial_message }
-            } else { partial_message };
-          let final_flag = false;
-          let liveliness_flag = false;
-          let data_hb_message = data_hb_message_builder
-               .heartbeat_msg(self, EntityId::ENTITYID_UNKNOWN, final_flag, liveliness_flag)
-               .add_header_and_build(self.my_guid.guidPrefix);
-          self.send_message_to_readers(DeliveryMode::Multicast, 
-            &data_hb_message, &mut self.readers.values() );
+        WriterCommand::DDSData { data, source_timestamp, key_hash, filtered_readers } => {
+          self.write_new_change(data, source_timestamp, Some(key_hash), filtered_readers);
         }
 
         // WriterCommand::ResetOfferedDeadlineMissedStatus { writer_guid: _, } => {
         //   self.reset_offered_deadline_missed_status();
         // }
-        WriterCommand::WaitForAcknowledgments{ all_acked } => {
+        WriterCommand::WaitForAcknowledgments{ all_acked, deadline } => {
           let wait_until = self.last_change_sequence_number;
           let readers_pending: BTreeSet<_> = self.readers.iter()
               .filter_map( |(guid,rp)| {
-                  if rp.qos().reliability().is_some() {
+                  if matches!(rp.qos().reliability(), Some(Reliability::Reliable { .. })) {
                     if rp.all_acked_before <= wait_until { Some(*guid) } else { None } // already acked
                   } else { None } // not reliable reader
                 } )
@@ -423,20 +795,202 @@ impl Writer {
           if readers_pending.is_empty() {
             // all acked already
             let _ = all_acked.try_send(()); // may fail, if receiver has timeouted
-            self.ack_waiter = None;
+            self.data_writer_wakers.wake_all();
           } else {
-            self.ack_waiter = Some(AckWaiter {
+            self.ack_waiters.push(AckWaiter {
               wait_until, 
               complete_channel: all_acked,
               readers_pending,
+              deadline,
             });
           }
+          self.forget_timed_out_ack_waiters();
+        }
+
+        WriterCommand::GetMatchedReaders{ reply } => {
+          // may fail, if receiver has timeouted
+          let _ = reply.try_send( self.readers.keys().copied().collect() );
+        }
+
+        WriterCommand::SetFragmentSize{ fragment_size } => {
+          debug!("Writer {:?} topic={:?} fragment size set to {}", 
+            self.my_guid.entityId, self.my_topic_name, fragment_size);
+          self.fragment_size = fragment_size;
+        }
+
+        WriterCommand::BeginCoherentSet => {
+          self.coherent_set_active = true;
+          self.coherent_set_first = None;
+        }
+
+        WriterCommand::EndCoherentSet => {
+          // If anything was written in the set, tell Readers that the set is now complete.
+          // The end marker is a DATA with no contents, but PID_COHERENT_SET inline QoS.
+          if self.coherent_set_first.is_some() {
+            self.write_new_change(DDSData::CoherentSetEnd, Some(Timestamp::now()), None, 
+              BTreeSet::new());
+            self.coherent_set_ends.insert(self.last_change_sequence_number);
+          }
+          self.coherent_set_active = false;
+          self.coherent_set_first = None;
+        }
+
+        WriterCommand::SuspendPublications{ batch } => {
+          match batch.lock() {
+            Ok(mut b) => b.writer_suspended(),
+            Err(e) => error!("Publication batch is poisoned: {:?}", e),
+          }
+          self.suspended_batch = Some(batch);
+          if self.suspended_after.is_none() {
+            self.suspended_after = Some(self.last_change_sequence_number);
+          }
+        }
+
+        WriterCommand::ResumePublications => self.resume_publications(),
+      }
+    }
+    // There is now space in the command channel.
+    self.data_writer_wakers.wake_all();
+  }
+
+  // The Publisher has already resumed the batch. The last Writer to resume
+  // sends it, so that it contains the data of all the Writers.
+  // HEARTBEATs and repairs held back while suspended follow the batched data.
+  fn resume_publications(&mut self) {
+    if let Some(liveliness_flag) = self.held_back_heartbeat.take() {
+      let hb_message = MessageBuilder::new()
+        .ts_msg(self.endianness, Some(Timestamp::now()) )
+        .heartbeat_msg(self, EntityId::ENTITYID_UNKNOWN, false, liveliness_flag)
+        .add_header_and_build(self.my_guid.guidPrefix);
+      self.send_or_batch_message_to_readers(DeliveryMode::Multicast, &hb_message, 
+                                              &mut self.readers.values());
+    }
+    if let Some(batch) = self.suspended_batch.take() {
+      match batch.lock() {
+        Ok(mut batch) => 
+          if batch.writer_resumed() {
+            batch.send(&self.udp_sender)
+          }
+        Err(e) => error!("Publication batch is poisoned: {:?}", e),
+      }
+    }
+    self.suspended_after = None;
+    for reader_guid in std::mem::take(&mut self.held_back_repairs) {
+      if let Some(reader_proxy) = self.readers.get_mut(&reader_guid) {
+        if reader_proxy.can_send() {
+          reader_proxy.repair_mode = true;
+          self.timed_event_timer
+            .set_timeout(NACK_RESPONSE_DELAY, TimedEvent::SendRepairData{ to_reader: reader_guid });
         }
       }
     }
   }
 
-  fn insert_to_history_cache(&mut self, data: DDSData, source_timestamp: Option<Timestamp>) -> Timestamp {
+  // key_hash is None for changes that do not belong to any instance.
+  // filtered_readers get a GAP instead of the change.
+  fn write_new_change(&mut self, data: DDSData, source_timestamp: Option<Timestamp>, 
+      key_hash: Option<KeyHash>, filtered_readers: BTreeSet<GUID>) {
+    // We have a new sample here. Things to do:
+    // 1. Insert it to history cache and get it sequence numbered
+    // 2. Send out data. 
+    //    If we are pushing data, send the DATA submessage and HEARTBEAT.
+    //    If we are not pushing, send out HEARTBEAT only. Readers will then ask the DATA with ACKNACK.
+    // Writing asserts liveliness, also with MANUAL Liveliness.
+    self.assert_liveliness();
+    if let Some(key_hash) = key_hash {
+      // Disposed and unregistered instances are no longer bound by the Deadline.
+      if data.change_kind() == ChangeKind::Alive {
+        self.instance_write_times.insert(key_hash, Timestamp::now());
+      } else {
+        self.instance_write_times.remove(&key_hash);
+      }
+    }
+    let timestamp = self.insert_to_history_cache(data, source_timestamp, key_hash);
+    let sequence_number = self.last_change_sequence_number;
+    if let (Some(key_hash), Some(History::KeepAll)) = (key_hash, self.qos_policies.history) {
+      self.unacked_history.insert(sequence_number, key_hash);
+    }
+    if let (Some(_), Some(policy::Lifespan { duration })) = (key_hash, self.qos_policies.lifespan) {
+      // Lifespan counts from the source timestamp, as Readers also check it against that.
+      let expiry = source_timestamp.unwrap_or(timestamp) + duration;
+      self.lifespan_expiries.insert(sequence_number, expiry);
+      self.schedule_lifespan_expiry();
+    }
+    for reader_guid in filtered_readers.iter() {
+      if let Some(reader_proxy) = self.readers.get_mut(reader_guid) {
+        reader_proxy.irrelevant_changes.insert(sequence_number);
+      }
+    }
+
+    self.increase_heartbeat_counter();
+
+    // Multicast would deliver the change also to the filtered Readers.
+    let delivery_mode = 
+      if filtered_readers.is_empty() { DeliveryMode::Multicast } else { DeliveryMode::Unicast };
+    let relevant = |reader: &&RtpsReaderProxy| ! filtered_readers.contains(&reader.remote_reader_guid);
+
+    let partial_message = MessageBuilder::new();
+    // If DataWriter sent us a source timestamp, then add that.
+    let partial_message = 
+      if let Some(src_ts) = source_timestamp {
+        partial_message.ts_msg(self.endianness, Some(src_ts) )
+      } else {
+        partial_message
+      };
+    // the beef: DATA submessage
+    let data_hb_message_builder = 
+      if self.push_mode {
+        // Now that payload contains Bytes, it is relatively cheap to clone
+        let cache_change = self.dds_cache.read().unwrap()
+              .from_topic_get_change(&self.my_topic_name, &timestamp)
+              .cloned();
+        match cache_change {
+          Some(cache_change) => match self.fragmented_payload(&cache_change) {
+            None => 
+              partial_message
+                .data_msg(cache_change, 
+                          EntityId::ENTITYID_UNKNOWN, // reader
+                          self.my_guid.entityId, // writer
+                          self.endianness ),
+            Some(serialized_data) => {
+              // Too large for DATA. Send fragments in separate messages,
+              // and then heartbeat only.
+              let fragments = self.all_fragments(&serialized_data);
+              for frag_message in self.data_frag_messages(&cache_change, &serialized_data, 
+                                    None, source_timestamp, fragments) {
+                self.send_or_batch_message_to_readers(delivery_mode, 
+                  &frag_message, &mut self.readers.values().filter(relevant) );
+              }
+              MessageBuilder::new()
+            }
+          }
+          None => partial_message,
+        }
+      } else { partial_message };
+    let final_flag = false;
+    let liveliness_flag = false;
+    let data_hb_message = data_hb_message_builder
+         .heartbeat_msg(self, EntityId::ENTITYID_UNKNOWN, final_flag, liveliness_flag)
+         .add_header_and_build(self.my_guid.guidPrefix);
+    self.send_or_batch_message_to_readers(delivery_mode, 
+      &data_hb_message, &mut self.readers.values().filter(relevant) );
+
+    for reader_proxy in self.readers.values().filter( |r| ! relevant(r) ) {
+      let reader_guid = reader_proxy.remote_reader_guid;
+      let gap_hb_message = MessageBuilder::new()
+        .dst_submessage(self.endianness, reader_guid.guidPrefix)
+        .gap_msg(BTreeSet::from_iter(Some(sequence_number)), self, reader_guid)
+        .heartbeat_msg(self, reader_guid.entityId, final_flag, liveliness_flag)
+        .add_header_and_build(self.my_guid.guidPrefix);
+      self.send_or_batch_message_to_readers(DeliveryMode::Unicast, 
+        &gap_hb_message, &mut std::iter::once(reader_proxy) );
+    }
+    // Without reliable Readers, the sample needs no acknowledgment.
+    self.release_acked_history();
+  }
+
+  fn insert_to_history_cache(&mut self, data: DDSData, source_timestamp: Option<Timestamp>, 
+      key_hash: Option<KeyHash>) -> Timestamp {
     // first increasing last SequenceNumber
     let new_sequence_number = self.last_change_sequence_number + SequenceNumber::from(1);
     self.last_change_sequence_number = new_sequence_number;
@@ -454,16 +1008,37 @@ impl Writer {
           max( self.last_change_sequence_number - SequenceNumber::from((depth - 1) as i64)
              , SequenceNumber::from(1) ),
       };
+
+    if let (Some(key_hash), true) = (key_hash, self.keeps_durable_history()) {
+      // Keep History depth of latest samples per instance. Superseded samples are
+      // no longer offered to Readers.
+      let depth = self.history_depth();
+      let instance_history = self.durable_history.entry(key_hash).or_default();
+      instance_history.push_back(new_sequence_number);
+      self.durable_sequence_numbers.insert(new_sequence_number);
+      while instance_history.len() > depth {
+        if let Some(superseded) = instance_history.pop_front() {
+          self.durable_sequence_numbers.remove(&superseded);
+        }
+      }
+      if let Some(&oldest) = self.durable_sequence_numbers.iter().next() {
+        self.first_change_sequence_number = min(self.first_change_sequence_number, oldest);
+      }
+    }
     assert!(self.first_change_sequence_number > SequenceNumber::zero() );
     assert!(self.last_change_sequence_number > SequenceNumber::zero() );
 
     // create new CacheChange from DDSData
-    let new_cache_change = CacheChange::new(
+    let mut new_cache_change = CacheChange::new(
       self.get_guid(),
       self.last_change_sequence_number,
       source_timestamp,
       data,
     );
+    if self.coherent_set_active {
+      let coherent_set = *self.coherent_set_first.get_or_insert(new_sequence_number);
+      new_cache_change.coherent_set = Some(coherent_set);
+    }
 
     // inserting to DDSCache
     // timestamp taken here is used as a unique(!) key in the DDSCache.
@@ -505,8 +1080,18 @@ impl Writer {
     // TODO: This produces same heartbeat count for all messages sent, but
     // then again, they represent the same writer status.
 
-    if self.readers.values().all(|rp| self.last_change_sequence_number < rp.all_acked_before ) {
+    if is_manual_assertion {
+      self.assert_liveliness();
+    }
+
+    // Manual liveliness assertion must be sent even if there is no new data.
+    if ! is_manual_assertion 
+        && self.readers.values().all(|rp| self.last_change_sequence_number < rp.all_acked_before ) {
       trace!("heartbeat tick: all readers have all available data.");
+    } else if self.suspended_batch.is_some() {
+      // HEARTBEAT would announce the suspended changes. Send it when resumed.
+      trace!("heartbeat tick: publications suspended, holding back HEARTBEAT.");
+      self.held_back_heartbeat = Some(liveliness_flag || self.held_back_heartbeat.unwrap_or(false));
     } else {
       let hb_message = MessageBuilder::new()
         .ts_msg(self.endianness, Some(Timestamp::now()) )
@@ -586,33 +1171,55 @@ impl Writer {
                    TimedEvent::SendRepairData{to_reader: reader_guid});
         }
       }
+      self.release_acked_history();
     }
-    AckSubmessage::NackFrag_Variant(_nackfrag) => {
-      // TODO
-      error!("NACKFRAG Not implemented")
+    AckSubmessage::NackFrag_Variant(ref nackfrag) => {
+      if !self.is_reliable() {
+        warn!("Writer {:x?} is best effort! It should not handle NACKFRAG messages!", 
+                self.get_entity_id());
+        return
+      }
+      let last_seq = self.last_change_sequence_number;
+      if let Some(reader_proxy) = self.lookup_readerproxy_mut(reader_guid_prefix, nackfrag.reader_id) {
+        // Mark requested fragments for repair
+        reader_proxy.handle_ack_nack(&ack_submessage, last_seq);
+        if reader_proxy.can_send() {
+          let reader_guid = reader_proxy.remote_reader_guid;
+          reader_proxy.repair_mode = true;
+          self.timed_event_timer
+            .set_timeout(NACK_RESPONSE_DELAY,
+                   TimedEvent::SendRepairData{to_reader: reader_guid});
+        }
+      }
     }
   }
 }
 
+  // acked_before None means that the Reader is gone, so it need not be waited for.
   fn update_ack_waiters(&mut self, guid:GUID, acked_before:Option<SequenceNumber>) {
-    let mut completed = false;
-    match &mut self.ack_waiter {
-      Some(aw) => match acked_before { 
-        None => { aw.readers_pending.remove(&guid); }
-        Some(acked_before) => {
-          if aw.wait_until < acked_before { 
-            aw.readers_pending.remove(&guid);
-          }
-          if aw.readers_pending.is_empty() {
-            // it is normal for the send to fail, because receiver may have timeouted
-            let _ = aw.complete_channel.try_send( () );
-            completed = true;
-          }
-        }
+    for aw in self.ack_waiters.iter_mut() {
+      if acked_before.map( |acked_before| aw.wait_until < acked_before ).unwrap_or(true) {
+        aw.readers_pending.remove(&guid);
       }
-      None => (),
     }
-    if completed { self.ack_waiter = None; }
+    let mut completed = false;
+    for aw in self.ack_waiters.iter().filter( |aw| aw.readers_pending.is_empty() ) {
+      // it is normal for the send to fail, because receiver may have timeouted
+      let _ = aw.complete_channel.try_send( () );
+      completed = true;
+    }
+    if completed { 
+      self.ack_waiters.retain( |aw| ! aw.readers_pending.is_empty() );
+      self.data_writer_wakers.wake_all();
+    }
+    self.forget_timed_out_ack_waiters();
+  }
+
+  // The DataWriter has stopped waiting for these.
+  fn forget_timed_out_ack_waiters(&mut self) {
+    let now = std::time::Instant::now();
+    self.ack_waiters
+      .retain( |aw| aw.deadline.map( |d| now < d ).unwrap_or(true) );
   }
 
   // Send out missing data
@@ -639,35 +1246,61 @@ impl Writer {
     // Note: The reader_proxy is now removed from readers map
     let reader_guid = reader_proxy.remote_reader_guid;
     let mut partial_message = MessageBuilder::new()
-      .dst_submessage(self.endianness, reader_guid.guidPrefix)
-      .ts_msg(self.endianness, Some(Timestamp::now())); 
-      // TODO: This timestamp should probably not be
-      // the current (retransmit) time, but the initial sample production timestamp,
-      // i.e. should be read from DDSCache (and be implemented there)
+      .dst_submessage(self.endianness, reader_guid.guidPrefix);
     debug!("Repair data send due to ACKNACK. ReaderProxy Unsent changes: {:?}",
             reader_proxy.unsent_changes);
 
+    // Changes written while publications are suspended are not repaired until resumed.
+    let suspended_after = self.suspended_after;
+    let is_suspended = |sn: &SequenceNumber| suspended_after.map( |after| *sn > after ).unwrap_or(false);
+    let held_back = reader_proxy.unsent_changes.iter().next().map(is_suspended).unwrap_or(false)
+      || reader_proxy.requested_fragments.keys().next().map(is_suspended).unwrap_or(false);
+    if held_back {
+      self.held_back_repairs.insert(reader_guid);
+    }
+
     let mut no_longer_relevant = Vec::new();
     let mut found_data = false;
-    if let Some(&unsent_sn) = reader_proxy.unsent_changes.iter().next() {
+    if let Some(&unsent_sn) = reader_proxy.unsent_changes.iter().next().filter( |sn| ! is_suspended(sn) ) {
       // There are unsent changes.
       match self.sequence_number_to_instant(unsent_sn) {
+        Some(_) if reader_proxy.irrelevant_changes.contains(&unsent_sn) => {
+          // Did not pass the content filter of the Reader
+          no_longer_relevant.push(unsent_sn);
+        }
+        Some(_) if self.keeps_durable_history() 
+                    && ! self.durable_sequence_numbers.contains(&unsent_sn)
+                    && ! self.coherent_set_ends.contains(&unsent_sn) => {
+          // Superseded by later samples of the same instance.
+          no_longer_relevant.push(unsent_sn);
+        }
         Some(timestamp) => {
           // Try to find the cache change from DDSCache
-          if let Some(cache_change) = self.dds_cache.read().unwrap()
-              .from_topic_get_change(&self.my_topic_name, &timestamp) {
-            // CacheChange found, construct DATA submessage
-            partial_message = partial_message
-                .data_msg(cache_change.clone(), // TODO: We should not clone, too much copying
-                          reader_guid.entityId, // reader
-                          self.my_guid.entityId, // writer
-                          self.endianness); 
-            // TODO: Here we are cloning the entire payload. We need to rewrite the transmit path to avoid copying.
-
-            // CC will be sent. Remove from unsent list.
-          } else {
-            // Change not in cache anymore, mark SN as not relevant anymore
-            no_longer_relevant.push(unsent_sn);
+          let cache_change = self.dds_cache.read().unwrap()
+              .from_topic_get_change(&self.my_topic_name, &timestamp)
+              .cloned();
+          match cache_change {
+            Some(cache_change) => match self.fragmented_payload(&cache_change) {
+              None => {
+                // CacheChange found, construct DATA submessage
+                partial_message = partial_message
+                    .ts_msg(self.endianness, Some(Self::production_timestamp(&cache_change)))
+                    .data_msg(cache_change, 
+                              reader_guid.entityId, // reader
+                              self.my_guid.entityId, // writer
+                              self.endianness); 
+              }
+              Some(serialized_data) => {
+                // Too large for DATA. Send all fragments.
+                let fragments = self.all_fragments(&serialized_data);
+                self.send_data_frags_to_reader(&reader_proxy, &cache_change, 
+                    &serialized_data, fragments);
+              }
+            }
+            None => {
+              // Change not in cache anymore, mark SN as not relevant anymore
+              no_longer_relevant.push(unsent_sn);
+            }
           }
         }
         None => {
@@ -678,8 +1311,36 @@ impl Writer {
       } // match
 
       // This SN will be sent or found no longer relevant => remove
-      // from unsent list.
+      // from unsent list. Fragments requested of it are now sent also.
       reader_proxy.unsent_changes.remove(&unsent_sn);
+      reader_proxy.requested_fragments.remove(&unsent_sn);
+      found_data = true;
+    } else if let Some(frag_sn) = reader_proxy.requested_fragments.keys().next().copied()
+                                    .filter( |sn| ! is_suspended(sn) ) {
+      // No complete changes to send, but some fragments were requested by NACKFRAG.
+      let requested = reader_proxy.requested_fragments.remove(&frag_sn).unwrap_or_default();
+      match self.sequence_number_to_instant(frag_sn)
+              .and_then( |ts| self.find_cache_change(&ts) ) {
+        Some(cache_change) => match self.fragmented_payload(&cache_change) {
+          Some(serialized_data) => {
+            let frag_count = self.fragment_count(&serialized_data);
+            let fragments : Vec<FragmentNumber> = requested.into_iter()
+              .filter(|f| *f >= FragmentNumber::from(1u32) && *f <= frag_count )
+              .collect();
+            self.send_data_frags_to_reader(&reader_proxy, &cache_change, 
+                &serialized_data, fragments);
+          }
+          None => 
+            // Not fragmented by us. Maybe fragment size was changed. Send the whole thing.
+            partial_message = partial_message
+              .ts_msg(self.endianness, Some(Self::production_timestamp(&cache_change)))
+              .data_msg(cache_change, 
+                        reader_guid.entityId, // reader
+                        self.my_guid.entityId, // writer
+                        self.endianness),
+        }
+        None => no_longer_relevant.push(frag_sn),
+      }
       found_data = true;
     }
     // Add GAP submessage, if some chache changes could not be found.
@@ -702,22 +1363,98 @@ impl Writer {
     reader_proxy
   } // fn
 
+  // Returns complete serialized payload (including SerializedPayload header)
+  // of a CacheChange, if it is too large to be sent in a single DATA submessage.
+  fn fragmented_payload(&self, cache_change: &CacheChange) -> Option<Bytes> {
+    if cache_change.data_value.payload_size() <= usize::from(self.fragment_size) {
+      return None
+    }
+    let mut serialized_payload = cache_change.data_value.serialized_payload()?.clone();
+    // Same as in MessageBuilder::data_msg
+    if self.my_guid.entityId.kind() == EntityKind::WRITER_WITH_KEY_BUILT_IN {
+      serialized_payload.representation_identifier = RepresentationIdentifier::PL_CDR_LE
+    }
+    serialized_payload.write_to_vec_with_ctx(self.endianness)
+      .map( Bytes::from )
+      .map_err( |e| error!("Cannot serialize payload of {:?}: {:?}", cache_change.sequence_number, e))
+      .ok()
+  }
+
+  fn fragment_count(&self, serialized_data: &Bytes) -> FragmentNumber {
+    let frag_size = usize::from(self.fragment_size);
+    FragmentNumber::from( ((serialized_data.len() + frag_size - 1) / frag_size) as u32 )
+  }
+
+  fn all_fragments(&self, serialized_data: &Bytes) -> Vec<FragmentNumber> {
+    (1 ..= u32::from(self.fragment_count(serialized_data)))
+      .map( FragmentNumber::from )
+      .collect()
+  }
+
+  // Builds one message per fragment. If reader_guid is given, messages are addressed to 
+  // that Reader, otherwise to all Readers.
+  fn data_frag_messages(&self, cache_change: &CacheChange, serialized_data: &Bytes,
+      reader_guid: Option<GUID>, source_timestamp: Option<Timestamp>, 
+      fragments: Vec<FragmentNumber>) -> Vec<Message> 
+  {
+    fragments.into_iter()
+      .map( |frag_num| {
+        let builder = match reader_guid {
+          Some(guid) => MessageBuilder::new().dst_submessage(self.endianness, guid.guidPrefix),
+          None => MessageBuilder::new(),
+        };
+        let builder = match source_timestamp {
+          Some(ts) => builder.ts_msg(self.endianness, Some(ts)),
+          None => builder,
+        };
+        builder
+          .data_frag_msg(cache_change, serialized_data, 
+              reader_guid.map( |g| g.entityId ).unwrap_or(EntityId::ENTITYID_UNKNOWN), 
+              self.my_guid.entityId, frag_num, self.fragment_size, self.endianness)
+          .add_header_and_build(self.my_guid.guidPrefix)
+      })
+      .collect()
+  }
+
+  // Resent samples carry their original source timestamp, so that Readers
+  // ordering by source timestamp see them in the order they were written.
+  fn production_timestamp(cache_change: &CacheChange) -> Timestamp {
+    cache_change.source_timestamp.unwrap_or_else(Timestamp::now)
+  }
+
+  fn send_data_frags_to_reader(&self, reader_proxy: &RtpsReaderProxy, cache_change: &CacheChange,
+      serialized_data: &Bytes, fragments: Vec<FragmentNumber>) 
+  {
+    debug!("Sending {} fragments of {:?} to {:?}", 
+      fragments.len(), cache_change.sequence_number, reader_proxy.remote_reader_guid);
+    for frag_message in self.data_frag_messages(cache_change, serialized_data, 
+        Some(reader_proxy.remote_reader_guid), Some(Self::production_timestamp(cache_change)), 
+        fragments) {
+      self.send_message_to_readers(DeliveryMode::Unicast, &frag_message, 
+        &mut std::iter::once(reader_proxy));
+    }
+  }
+
   /// Removes permanently cacheChanges from DDSCache.
-  /// CacheChanges can be safely removed only if they are acked by all readers. (Reliable)
+  /// CacheChanges can be safely removed only if they are acked by all reliable readers.
+  /// Best-effort readers do not acknowledge, so they do not hold back removal.
   /// Depth is QoS policy History depth.
   /// Returns SequenceNumbers of removed CacheChanges
   /// This is called repeadedly by handle_cache_cleaning action.
   fn remove_all_acked_changes_but_keep_depth(&mut self, depth: usize)  {
-    // All readers have acked up to this point (SequenceNumber)
-    let acked_by_all_readers = self.readers.values()
-          .map(|r| r.acked_up_to_before())
-          .min()
-          .unwrap_or_else(SequenceNumber::zero);
+    // All reliable readers have acked up to this point (SequenceNumber)
+    let acked_by_all_readers = self.acked_by_all_reliable_readers();
     // If all readers have acked all up to before 5, and depth is 5, we need
     // to keep samples 0..4, i.e. from acked_up_to_before - depth .
     let first_keeper = 
       max( acked_by_all_readers - SequenceNumber::from(depth) , 
             self.first_change_sequence_number );
+    // Samples still in durable history must be kept for late-joining Readers.
+    let first_keeper =
+      match self.durable_sequence_numbers.iter().next() {
+        Some(&oldest_durable) => min(first_keeper, oldest_durable),
+        None => first_keeper,
+      };
 
     // We notify the DDSCache that it can release older samples
     // as far as this Writer is concenrned.
@@ -730,6 +1467,13 @@ impl Writer {
     self.first_change_sequence_number = first_keeper;
     self.sequence_number_to_instant = 
       self.sequence_number_to_instant.split_off(&first_keeper);
+    self.coherent_set_ends = self.coherent_set_ends.split_off(&first_keeper);
+    self.lifespan_expiries = self.lifespan_expiries.split_off(&first_keeper);
+    // Best-effort Readers never acknowledge, so their filtered changes are forgotten
+    // here, as the changes leave history.
+    for reader_proxy in self.readers.values_mut() {
+      reader_proxy.irrelevant_changes = reader_proxy.irrelevant_changes.split_off(&first_keeper);
+    }
   }
 
   fn increase_heartbeat_counter(&mut self) {
@@ -738,10 +1482,34 @@ impl Writer {
 
   fn send_message_to_readers(&self, preferred_mode: DeliveryMode, message: &Message, 
         readers: &mut dyn Iterator<Item = &RtpsReaderProxy>) {
+    let buffer = message.write_to_vec_with_ctx(self.endianness).unwrap();
+    for destination in self.reader_destinations(preferred_mode, readers) {
+      self.udp_sender.send_to_locator_list(&buffer, &destination);
+    }
+  }
+
+  // Like send_message_to_readers, but if publications are suspended, the message
+  // is added to the batch instead.
+  fn send_or_batch_message_to_readers(&self, preferred_mode: DeliveryMode, message: &Message, 
+        readers: &mut dyn Iterator<Item = &RtpsReaderProxy>) {
+    match self.suspended_batch {
+      Some(ref batch) => match batch.lock() {
+        Ok(mut batch) => 
+          batch.add_message(self.endianness, message, self.reader_destinations(preferred_mode, readers)),
+        Err(e) => error!("Publication batch is poisoned: {:?}", e),
+      }
+      None => self.send_message_to_readers(preferred_mode, message, readers),
+    }
+  }
+
+  // Where to send a message so that it reaches all the given readers. 
+  // Each destination is a locator list for UDPSender::send_to_locator_list.
+  fn reader_destinations(&self, preferred_mode: DeliveryMode, 
+        readers: &mut dyn Iterator<Item = &RtpsReaderProxy>) -> Vec<Vec<Locator>> {
     // TODO: This is a stupid transmit algorithm. We should compute a preferred
     // unicast and multicast locators for each reader only on every reader update, and
     // not find it dynamically on every message.
-    let buffer = message.write_to_vec_with_ctx(self.endianness).unwrap();
+    let mut destinations = Vec::new();
     let mut already_sent_to = BTreeSet::new();
 
     macro_rules! send_unless_sent_and_mark {
@@ -750,7 +1518,7 @@ impl Writer {
           if already_sent_to.contains(loc) {
             trace!("Already sent to {:?}", loc);
           } else {
-            self.udp_sender.send_to_locator(&buffer, loc);
+            destinations.push(vec![loc.clone()]);
             already_sent_to.insert(loc.clone());
           }
         }
@@ -758,6 +1526,16 @@ impl Writer {
     }
 
     for reader in readers {
+      // Readers on the same host are reached through shared memory, if possible.
+      // The other unicast locators are used, if delivery through shared memory fails.
+      if let Some(shm_locator) = reader.unicast_locator_list.iter()
+          .find(|l| self.udp_sender.can_reach_via_shm(l)) {
+        if already_sent_to.insert(*shm_locator) {
+          already_sent_to.extend(reader.unicast_locator_list.iter().cloned());
+          destinations.push(reader.unicast_locator_list.clone());
+        }
+        continue
+      }
       match ( preferred_mode, 
               reader.unicast_locator_list.iter().find(|l| Locator::isUDP(l) ), 
               reader.multicast_locator_list.iter().find(|l| Locator::isUDP(l) ) ) {
@@ -778,11 +1556,15 @@ impl Writer {
         }
       } // match
     }
-
+    destinations
   }
  
   // Send status to DataWriter or however is listening
   fn send_status(&self, status: DataWriterStatus) {
+    match self.status_accumulator.lock() {
+      Ok(mut accumulator) => accumulator.update(&status),
+      Err(e) => error!("send_status - status accumulator is poisoned: {:?}", e),
+    }
     self.status_sender.try_send(status)
       .unwrap_or_else( |e| match e {
         TrySendError::Full(_) => (), // This is normal in case there is no receiver
@@ -792,15 +1574,23 @@ impl Writer {
         TrySendError::Io(e) => {
           warn!("send_status - io error {:?}",e);
         } 
-      })
+      });
+    self.data_writer_wakers.wake_all();
 
   }
 
   pub fn update_reader_proxy(&mut self, reader_proxy: RtpsReaderProxy, requested_qos:QosPolicies) {
     debug!("update_reader_proxy topic={:?}",self.my_topic_name);
+    if ! self.qos_policies.partitions_match(&requested_qos) {
+      // Not in our partitions. Remove, in case the reader has changed its partitions.
+      debug!("update_reader_proxy - Partition mismatch reader={:?}", reader_proxy.remote_reader_guid);
+      self.reader_lost(reader_proxy.remote_reader_guid);
+      return
+    }
     match  self.qos_policies.compliance_failure_wrt(&requested_qos) {
       // matched QoS
       None => {
+        self.update_reader_filter(reader_proxy.remote_reader_guid, reader_proxy.content_filter.as_ref());
         let change =
           self.matched_reader_update( reader_proxy.clone() );
         if change > 0 {
@@ -811,6 +1601,7 @@ impl Writer {
               });
           // send out hearbeat, so that new reader can catch up
           if let Some(Reliability::Reliable{..}) = self.qos_policies.reliability { self.notify_new_data_to_all_readers() }
+          self.send_durable_history(reader_proxy.remote_reader_guid);
           info!("Matched new remote reader on topic={:?} reader= {:?}", 
                 self.topic_name(), &reader_proxy);
 
@@ -829,6 +1620,46 @@ impl Writer {
     } // match
   }
 
+  // Filters are taken into use for the following writes. Durable history sent to
+  // a late-joining Reader is not filtered, but the Reader filters it.
+  fn update_reader_filter(&mut self, reader_guid: GUID, property: Option<&ContentFilterProperty>) {
+    let filter = property.and_then(ContentFilter::from_property);
+    match self.reader_filters.lock() {
+      Ok(mut filters) => match filter {
+        Some(filter) => { filters.insert(reader_guid, filter); }
+        None => { filters.remove(&reader_guid); }
+      }
+      Err(e) => error!("update_reader_filter: Reader filters are poisoned: {:?}", e),
+    }
+  }
+
+  // Send samples in durable history to a newly matched Reader. This is done only
+  // if both we and the Reader are reliable, and the Reader requests TRANSIENT_LOCAL
+  // or stronger Durability. The samples are sent as repair data.
+  fn send_durable_history(&mut self, reader_guid: GUID) {
+    if ! self.is_reliable() || self.durable_sequence_numbers.is_empty() {
+      return
+    }
+    let durable_sequence_numbers = &self.durable_sequence_numbers;
+    let reader_proxy = match self.readers.get_mut(&reader_guid) {
+      Some(rp) => rp,
+      None => return,
+    };
+    let reader_qos = reader_proxy.qos();
+    let reader_wants_history = 
+      matches!(reader_qos.reliability(), Some(Reliability::Reliable{..}))
+      && reader_qos.durability().map( |d| d >= Durability::TransientLocal ).unwrap_or(false);
+    if ! reader_wants_history {
+      return
+    }
+    debug!("Sending durable history {:?} to late-joining reader {:?} topic={:?}",
+      durable_sequence_numbers, reader_guid, self.my_topic_name);
+    reader_proxy.unsent_changes.extend( durable_sequence_numbers.iter() );
+    reader_proxy.repair_mode = true;
+    self.timed_event_timer
+      .set_timeout(NACK_RESPONSE_DELAY, TimedEvent::SendRepairData{to_reader: reader_guid});
+  }
+
   // Update the given reader. Preserve data we are tracking.
   // return 0 if the reader already existed
   // return 1 if it was new
@@ -841,7 +1672,9 @@ impl Writer {
               is_active: existing_reader.is_active,
               all_acked_before: existing_reader.all_acked_before,
               unsent_changes: existing_reader.unsent_changes,
+              requested_fragments: existing_reader.requested_fragments,
               repair_mode: existing_reader.repair_mode,
+              irrelevant_changes: existing_reader.irrelevant_changes,
               .. reader_proxy
             }
           , 0 )
@@ -863,9 +1696,12 @@ impl Writer {
   }
 
   fn matched_reader_remove(&mut self, guid: GUID,) -> Option<RtpsReaderProxy> {
+    self.update_reader_filter(guid, None);
     let removed = self.readers.remove(&guid);
     if removed.is_some() {
       info!("Removed reader proxy. topic={:?} reader={:?}", self.topic_name(), removed );
+      // The removed Reader may have been the only one not acknowledging.
+      self.release_acked_history();
     }
     removed
   }
@@ -882,7 +1718,7 @@ impl Writer {
                 current: CountWithChange::new(self.readers.len() as i32 , -1)
               });
     }
-    // also remember to remove reader from ack_waiter
+    // also remember to remove reader from ack_waiters
     self.update_ack_waiters(guid,None)
   }
 
@@ -954,6 +1790,13 @@ impl HasQoSPolicy for Writer {
   }
 }
 
+impl Drop for Writer {
+  // A removed Writer must not hold back the batch of a suspended Publisher.
+  fn drop(&mut self) {
+    self.resume_publications();
+  }
+}
+
 // -------------------------------------------------------------------------------------
 // -------------------------------------------------------------------------------------
 // -------------------------------------------------------------------------------------
@@ -971,6 +1814,10 @@ mod tests {
   use crate::serialization::cdr_serializer::CDRSerializerAdapter;
   use byteorder::LittleEndian;
   use log::info;
+  use super::*;
+  use crate::dds::traits::key::Key;
+  use crate::dds::typedesc::TypeDesc;
+  use crate::messages::submessages::submessage_elements::serialized_payload::SerializedPayload;
 
   #[test]
   fn test_writer_recieves_datawriter_cache_change_notifications() {
@@ -1019,4 +1866,265 @@ mod tests {
     thread::sleep(std::time::Duration::from_millis(100));
     info!("writerResult:  {:?}", writeResult);
   }
+
+  // Writer with its own topic in a new DDSCache, and the channel for sending it commands.
+  fn test_writer(topic_name: &str, qos: &QosPolicies) 
+    -> (Writer, mio_channel::SyncSender<WriterCommand>) 
+  {
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    dds_cache.write().unwrap()
+      .add_new_topic(topic_name, TopicKind::WithKey, TypeDesc::new("TestType"));
+    let (command_sender, writer_command_receiver) = mio_channel::sync_channel(16);
+    let (status_sender, _status_receiver) = mio_channel::sync_channel(16);
+    let writer = Writer::new(
+      WriterIngredients {
+        guid: GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED),
+        writer_command_receiver,
+        topic_name: topic_name.to_string(),
+        qos_policies: qos.clone(),
+        status_sender,
+        status_accumulator: Arc::new(Mutex::new(DataWriterStatusAccumulator::new())),
+        reader_filters: Arc::new(Mutex::new(ReaderFilters::new())),
+        history_usage: Arc::new(SharedHistoryUsage::new()),
+        data_writer_wakers: TaskWakers::new(),
+      },
+      dds_cache,
+      Rc::new(UDPSender::new_with_random_port().unwrap()),
+      Timer::default(),
+    );
+    (writer, command_sender)
+  }
+
+  #[test]
+  fn transient_local_history_to_late_joiner() {
+    let qos = QosPolicies::builder()
+      .durability(Durability::TransientLocal)
+      .reliability(Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(History::KeepLast { depth: 2 })
+      .build();
+    let (mut writer, command_sender) = test_writer("TransientLocalTopic", &qos);
+
+    // Instance 1 gets SNs 1,2,3 and instance 2 gets SN 4.
+    for key in &[1i32, 1, 1, 2] {
+      let data = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![0; 4]));
+      command_sender
+        .send(WriterCommand::DDSData { data, source_timestamp: None, key_hash: key.into_hash_key(),
+                                        filtered_readers: BTreeSet::new() })
+        .unwrap();
+    }
+    writer.process_writer_command();
+    assert_eq!(writer.first_change_sequence_number, SequenceNumber::from(2));
+
+    let reader_guid = GUID::new(GuidPrefix::new(b"LateJoiner"), EntityId::ENTITYID_UNKNOWN);
+    writer.update_reader_proxy(RtpsReaderProxy::new(reader_guid, qos.clone()), qos);
+
+    let reader_proxy = writer.readers.get(&reader_guid).unwrap();
+    assert!(reader_proxy.repair_mode);
+    assert_eq!(reader_proxy.unsent_changes,
+      [2, 3, 4].iter().map(|sn| SequenceNumber::from(*sn)).collect::<BTreeSet<_>>());
+  }
+
+  #[test]
+  fn lifespan_expires_history() {
+    let qos = QosPolicies::builder()
+      .durability(Durability::TransientLocal)
+      .reliability(Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(History::KeepLast { depth: 1 })
+      .lifespan(policy::Lifespan { duration: Duration::from_millis(100) })
+      .build();
+    let (mut writer, command_sender) = test_writer("LifespanTopic", &qos);
+
+    // Instance 1 was written long ago (SN 1), instance 2 just now (SN 2).
+    let now = Timestamp::now();
+    for (key, source_timestamp) in &[(1i32, now - Duration::from_secs(1)), (2, now)] {
+      let data = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![0; 4]));
+      command_sender
+        .send(WriterCommand::DDSData { data, source_timestamp: Some(*source_timestamp), 
+                                        key_hash: key.into_hash_key(), filtered_readers: BTreeSet::new() })
+        .unwrap();
+    }
+    writer.process_writer_command();
+    writer.handle_lifespan_expiry();
+
+    let (sn_1, sn_2) = (SequenceNumber::from(1), SequenceNumber::from(2));
+    let cached = |writer: &Writer, sn| 
+      writer.sequence_number_to_instant(sn).and_then( |ts| writer.find_cache_change(&ts) ).is_some();
+    assert!(!cached(&writer, sn_1));
+    assert!(cached(&writer, sn_2));
+    assert_eq!(writer.lifespan_expiries.keys().copied().collect::<Vec<_>>(), vec![sn_2]);
+
+    // Late joiners no longer get the expired sample.
+    let reader_guid = GUID::new(GuidPrefix::new(b"LateJoiner"), EntityId::ENTITYID_UNKNOWN);
+    writer.update_reader_proxy(RtpsReaderProxy::new(reader_guid, qos.clone()), qos);
+    assert_eq!(writer.readers.get(&reader_guid).unwrap().unsent_changes,
+      BTreeSet::from_iter(Some(sn_2)));
+  }
+
+  #[test]
+  fn filtered_changes_leave_with_history() {
+    let qos = QosPolicies::builder()
+      .reliability(Reliability::BestEffort)
+      .history(History::KeepLast { depth: 1 })
+      .build();
+    let (mut writer, command_sender) = test_writer("FilteredTopic", &qos);
+    let reader_guid = GUID::new(GuidPrefix::new(b"Filtered"), EntityId::ENTITYID_UNKNOWN);
+    writer.update_reader_proxy(RtpsReaderProxy::new(reader_guid, qos.clone()), qos);
+
+    // None of the samples pass the content filter of the best-effort Reader.
+    for _ in 0..5 {
+      let data = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![0; 4]));
+      command_sender
+        .send(WriterCommand::DDSData { data, source_timestamp: None, key_hash: 1i32.into_hash_key(),
+                                        filtered_readers: BTreeSet::from_iter(Some(reader_guid)) })
+        .unwrap();
+    }
+    writer.process_writer_command();
+    assert_eq!(writer.readers.get(&reader_guid).unwrap().irrelevant_changes.len(), 5);
+
+    writer.handle_cache_cleaning();
+    assert_eq!(writer.readers.get(&reader_guid).unwrap().irrelevant_changes,
+      BTreeSet::from_iter(Some(SequenceNumber::from(5))));
+  }
+
+  #[test]
+  fn nackfrag_resends_requested_fragments() {
+    use crate::{
+      messages::submessages::submessages::{EntitySubmessage, NackFrag},
+      network::udp_listener::UDPListener,
+      serialization::submessage::SubmessageBody,
+      structure::sequence_number::FragmentNumberSet,
+    };
+
+    let mut listener = UDPListener::new_unicast(Token(0), "127.0.0.1", 10702).unwrap();
+    let qos = QosPolicies::builder()
+      .reliability(Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(History::KeepLast { depth: 1 })
+      .build();
+    let (mut writer, command_sender) = test_writer("NackFragTopic", &qos);
+    writer.fragment_size = 100;
+    let reader_guid = GUID::new(GuidPrefix::new(b"NackFrag"), 
+      EntityId::createCustomEntityID([1; 3], EntityKind::READER_WITH_KEY_USER_DEFINED));
+    let mut reader_proxy = RtpsReaderProxy::new(reader_guid, qos.clone());
+    reader_proxy.unicast_locator_list = 
+      vec![Locator::from(std::net::SocketAddr::new("127.0.0.1".parse().unwrap(), 10702))];
+    writer.update_reader_proxy(reader_proxy, qos);
+
+    // 4 bytes of SerializedPayload header and 350 of data make 4 fragments.
+    let data = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![0; 350]));
+    command_sender
+      .send(WriterCommand::DDSData { data, source_timestamp: None, key_hash: 1i32.into_hash_key(),
+                                      filtered_readers: BTreeSet::new() })
+      .unwrap();
+    writer.process_writer_command();
+
+    let data_frags = |listener: &mut UDPListener| {
+      std::thread::sleep(std::time::Duration::from_millis(100));
+      listener.get_messages().into_iter()
+        .flat_map( |datagram| Message::read_from_buffer(datagram).unwrap().submessages )
+        .filter_map( |submessage| match submessage.body {
+          SubmessageBody::Entity(EntitySubmessage::DataFrag(datafrag, _)) => 
+            Some(u32::from(datafrag.fragment_starting_num)),
+          _ => None,
+        })
+        .collect::<Vec<u32>>()
+    };
+    assert_eq!(data_frags(&mut listener), vec![1, 2, 3, 4]);
+
+    let nackfrag = NackFrag {
+      reader_id: reader_guid.entityId,
+      writer_id: writer.get_entity_id(),
+      writer_sn: SequenceNumber::from(1),
+      fragment_number_state: FragmentNumberSet::from_base_and_set(FragmentNumber::from(2u32),
+        &BTreeSet::from_iter(vec![FragmentNumber::from(2u32), FragmentNumber::from(4u32)])),
+      count: 1,
+    };
+    writer.handle_ack_nack(reader_guid.guidPrefix, AckSubmessage::NackFrag_Variant(nackfrag));
+    writer.handle_repair_data_send(reader_guid);
+    assert_eq!(data_frags(&mut listener), vec![2, 4]);
+  }
+
+  #[test]
+  fn ack_waiters_complete_independently() {
+    use crate::messages::submessages::submessages::AckNack;
+    use crate::structure::sequence_number::SequenceNumberSet;
+
+    let qos = QosPolicies::builder()
+      .reliability(Reliability::Reliable { max_blocking_time: Duration::DURATION_ZERO })
+      .history(History::KeepAll)
+      .build();
+    let (mut writer, command_sender) = test_writer("AckWaiterTopic", &qos);
+    let reader_guid = GUID::new(GuidPrefix::new(b"AckWaiter"), 
+      EntityId::createCustomEntityID([1; 3], EntityKind::READER_WITH_KEY_USER_DEFINED));
+    writer.update_reader_proxy(RtpsReaderProxy::new(reader_guid, qos.clone()), qos);
+
+    let write_and_wait = |writer: &mut Writer, deadline| {
+      let data = DDSData::new(SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![0; 4]));
+      command_sender
+        .send(WriterCommand::DDSData { data, source_timestamp: None, key_hash: 1i32.into_hash_key(),
+                                        filtered_readers: BTreeSet::new() })
+        .unwrap();
+      let (all_acked, acked_receiver) = mio_channel::sync_channel::<()>(1);
+      command_sender
+        .send(WriterCommand::WaitForAcknowledgments { all_acked, deadline })
+        .unwrap();
+      writer.process_writer_command();
+      acked_receiver
+    };
+    let first = write_and_wait(&mut writer, None);
+    let second = write_and_wait(&mut writer, None);
+    // This one has timed out already, so the Writer forgets it.
+    let _timed_out = write_and_wait(&mut writer, Some(std::time::Instant::now()));
+    assert_eq!(writer.ack_waiters.len(), 2);
+
+    let ack = |writer: &mut Writer, base: i64| {
+      writer.handle_ack_nack(reader_guid.guidPrefix, AckSubmessage::AckNack_Variant(AckNack {
+        reader_id: reader_guid.entityId,
+        writer_id: writer.get_entity_id(),
+        reader_sn_state: SequenceNumberSet::new_empty(SequenceNumber::from(base)),
+        count: base as i32,
+      }));
+    };
+    // Acknowledging the first sample completes only the first wait.
+    ack(&mut writer, 2);
+    assert!(first.try_recv().is_ok());
+    assert!(second.try_recv().is_err());
+    assert_eq!(writer.ack_waiters.len(), 1);
+
+    ack(&mut writer, 3);
+    assert!(second.try_recv().is_ok());
+    assert!(writer.ack_waiters.is_empty());
+  }
+
+  #[test]
+  fn history_usage_resource_limits() {
+    let qos = QosPolicies::builder()
+      .history(History::KeepAll)
+      .resource_limits(policy::ResourceLimits {
+        max_samples: 3,
+        max_instances: 2,
+        max_samples_per_instance: 2,
+      })
+      .build();
+    let (k1, k2, k3) = (1i32.into_hash_key(), 2i32.into_hash_key(), 3i32.into_hash_key());
+    let mut usage = HistoryUsage::new();
+    assert_eq!(usage.admit(k1, false, &qos), Ok(()));
+    assert_eq!(usage.admit(k1, false, &qos), Ok(()));
+    assert_eq!(usage.admit(k1, false, &qos), Err(SampleRejectedStatusKind::BySamplesPerInstanceLimit));
+    assert_eq!(usage.admit(k2, false, &qos), Ok(()));
+    assert_eq!(usage.admit(k3, false, &qos), Err(SampleRejectedStatusKind::ByInstancesLimit));
+    assert_eq!(usage.admit(k2, false, &qos), Err(SampleRejectedStatusKind::BySamplesLimit));
+
+    // Acknowledged samples make room, and a disposed instance no longer counts.
+    usage.release(k1);
+    assert_eq!(usage.admit(k2, true, &qos), Ok(()));
+    usage.release(k2);
+    usage.release(k2);
+    assert_eq!(usage.admit(k3, false, &qos), Ok(()));
+
+    // Without ResourceLimits there is nothing to enforce.
+    let mut usage = HistoryUsage::new();
+    for _ in 0..100 {
+      assert_eq!(usage.admit(k1, false, &QosPolicies::qos_none()), Ok(()));
+    }
+  }
 }
@@ -127,18 +127,21 @@ impl SPDPDiscoveredParticipantData {
     lease_duration: Duration,
   ) -> SPDPDiscoveredParticipantData {
     let spdp_multicast_port = get_spdp_well_known_multicast_port(participant.domain_id());
-    let metatraffic_multicast_locators = get_local_multicast_locators(spdp_multicast_port);
+    let metatraffic_multicast_locators = get_local_multicast_locators(spdp_multicast_port, participant.ip_mode());
 
     let spdp_unicast_port =
       get_spdp_well_known_unicast_port(participant.domain_id(), participant.participant_id());
-    let metatraffic_unicast_locators = get_local_unicast_socket_address(spdp_unicast_port);
+    let mut metatraffic_unicast_locators = get_local_unicast_socket_address(spdp_unicast_port, participant.ip_mode());
+    // Participants on the same host can use shared memory.
+    metatraffic_unicast_locators.extend(participant.shm_locator());
 
     let multicast_port = get_user_traffic_multicast_port(participant.domain_id());
-    let default_multicast_locators = get_local_multicast_locators(multicast_port);
+    let default_multicast_locators = get_local_multicast_locators(multicast_port, participant.ip_mode());
 
     let unicast_port =
       get_user_traffic_unicast_port(participant.domain_id(), participant.participant_id());
-    let default_unicast_locators = get_local_unicast_socket_address(unicast_port);
+    let mut default_unicast_locators = get_local_unicast_socket_address(unicast_port, participant.ip_mode());
+    default_unicast_locators.extend(participant.shm_locator());
 
     let builtin_endpoints = BuiltinEndpointSet::DISC_BUILTIN_ENDPOINT_PARTICIPANT_ANNOUNCER
       | BuiltinEndpointSet::DISC_BUILTIN_ENDPOINT_PARTICIPANT_DETECTOR
@@ -8,7 +8,7 @@ use crate::{
   dds::{
     qos::policy::{
       Deadline, Durability, LatencyBudget, Reliability, Ownership, DestinationOrder, Liveliness,
-      TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits,
+      TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits, Partition,
     },
     traits::key::Keyed,
     traits::serde_adapters::with_key::SerializerAdapter, // these are WITH_KEY data
@@ -114,7 +114,7 @@ pub struct SubscriptionBuiltinTopicData {
   // pub user_data: Option<UserData>,
   time_based_filter: Option<TimeBasedFilter>,
   presentation: Option<Presentation>,
-  // pub partition: Option<Partition>,
+  partition: Option<Partition>,
   // pub topic_data: Option<TopicData>,
   // pub group_data: Option<GroupData>,
   // pub durability_service: Option<DurabilityService>,
@@ -142,6 +142,7 @@ impl SubscriptionBuiltinTopicData {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
     };
 
@@ -217,6 +218,10 @@ impl SubscriptionBuiltinTopicData {
     &self.presentation
   }
 
+  pub fn partition(&self) -> &Option<Partition> {
+    &self.partition
+  }
+
   pub fn lifespan(&self) -> &Option<Lifespan> {
     &self.lifespan
   }
@@ -231,6 +236,7 @@ impl SubscriptionBuiltinTopicData {
     self.destination_order = qos.destination_order;
     self.time_based_filter = qos.time_based_filter;
     self.presentation = qos.presentation;
+    self.partition = qos.partition.clone();
     self.lifespan = qos.lifespan;
   }
 
@@ -248,6 +254,7 @@ impl SubscriptionBuiltinTopicData {
       history: None,         // TODO: Check that this really does not exist in source
       resource_limits: None, // TODO: Check that this really does not exist in source
       lifespan: self.lifespan,
+      partition: self.partition.clone(),
     }
   }
 }
@@ -480,6 +487,7 @@ pub struct PublicationBuiltinTopicData {
   pub ownership: Option<Ownership>,
   pub destination_order: Option<DestinationOrder>,
   pub presentation: Option<Presentation>,
+  pub partition: Option<Partition>,
 }
 
 impl PublicationBuiltinTopicData {
@@ -504,6 +512,7 @@ impl PublicationBuiltinTopicData {
       ownership: None,
       destination_order: None,
       presentation: None,
+      partition: None,
     }
   }
 
@@ -518,6 +527,7 @@ impl PublicationBuiltinTopicData {
     self.ownership = qos.ownership;
     self.destination_order = qos.destination_order;
     self.presentation = qos.presentation;
+    self.partition = qos.partition.clone();
   }
 
   pub fn qos(&self) -> QosPolicies {
@@ -534,6 +544,7 @@ impl PublicationBuiltinTopicData {
       history: None,         // TODO: ???
       resource_limits: None, // TODO: ???
       lifespan: self.lifespan,
+      partition: self.partition.clone(),
     }
   }
 }
@@ -610,7 +621,8 @@ impl DiscoveredWriterData {
     dp: &DomainParticipant,
   ) -> DiscoveredWriterData {
     let unicast_port = get_user_traffic_unicast_port(dp.domain_id(), dp.participant_id());
-    let unicast_addresses = get_local_unicast_socket_address(unicast_port);
+    let mut unicast_addresses = get_local_unicast_socket_address(unicast_port, dp.ip_mode());
+    unicast_addresses.extend(dp.shm_locator());
 
     let writer_proxy = WriterProxy::new(writer.get_guid(), vec![], unicast_addresses);
     let mut publication_topic_data = PublicationBuiltinTopicData::new(
@@ -620,7 +632,7 @@ impl DiscoveredWriterData {
       &topic.get_type().name().to_string(),
     );
 
-    publication_topic_data.read_qos(&topic.get_qos());
+    publication_topic_data.read_qos(&writer.get_qos());
 
     DiscoveredWriterData {
       last_updated: Instant::now(),
@@ -720,6 +732,7 @@ impl HasQoSPolicy for TopicBuiltinTopicData {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      partition: None,
     }
   }
 }
@@ -18,7 +18,7 @@ use mio_extras::timer::Timer;
 use mio_extras::channel as mio_channel;
 
 use std::{
-  sync::{Arc, RwLock},
+  sync::{Arc, RwLock, Mutex},
   sync::RwLockReadGuard,
   sync::RwLockWriteGuard,
   time::Duration as StdDuration,
@@ -39,6 +39,8 @@ use crate::{
       },
     },
     readcondition::ReadCondition,
+    statusevents::{DiscoveryEvent, DiscoveryEventQueue},
+    helpers::TaskWakers,
   },
   dds::values::result::{Error,Result},
   structure::entity::RTPSEntity,
@@ -73,14 +75,12 @@ pub enum DiscoveryCommand {
 
 pub struct LivelinessState {
   last_auto_update: Timestamp,
-  last_manual_participant_update: Timestamp,
 }
 
 impl LivelinessState {
   pub fn new() -> LivelinessState {
     LivelinessState {
       last_auto_update: Timestamp::now(),
-      last_manual_participant_update: Timestamp::now(),
     }
   }
 }
@@ -94,6 +94,9 @@ pub(crate) struct Discovery {
   discovery_started_sender: std::sync::mpsc::Sender<Result<()>>,
   // notification sender goes to dp_event_loop thread
   discovery_updated_sender: mio_channel::SyncSender<DiscoveryNotificationType>,
+  // DiscoveryEvents go to the application, e.g. async DiscoveryEventStream
+  discovery_events: Arc<Mutex<DiscoveryEventQueue>>,
+  discovery_event_wakers: TaskWakers,
   // Discovery gets commands from dp_event_loop from this channel
   discovery_command_receiver: mio_channel::Receiver<DiscoveryCommand>,
 
@@ -161,6 +164,7 @@ impl Discovery {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    partition: None,
   };
 
   pub fn new(
@@ -169,6 +173,8 @@ impl Discovery {
     discovery_started_sender: std::sync::mpsc::Sender<Result<()>>,
     discovery_updated_sender: mio_channel::SyncSender<DiscoveryNotificationType>,
     discovery_command_receiver: mio_channel::Receiver<DiscoveryCommand>,
+    discovery_events: Arc<Mutex<DiscoveryEventQueue>>,
+    discovery_event_wakers: TaskWakers,
   ) -> Result<Discovery> {
 
     // helper macro to handle initialization failures.
@@ -441,6 +447,8 @@ impl Discovery {
       discovery_db,
       discovery_started_sender,
       discovery_updated_sender,
+      discovery_events,
+      discovery_event_wakers,
       discovery_command_receiver,
 
       liveliness_state: LivelinessState::new(),
@@ -554,7 +562,7 @@ impl Discovery {
                   }
                 }
                 DiscoveryCommand::MANUAL_ASSERT_LIVELINESS => {
-                  self.liveliness_state.last_manual_participant_update = Timestamp::now();
+                  self.write_manual_participant_message();
                 }
                 DiscoveryCommand::ASSERT_TOPIC_LIVELINESS { writer_guid  , manual_assertion } => {
                   self.send_discovery_notification(
@@ -632,7 +640,8 @@ impl Discovery {
           }
           DISCOVERY_PARTICIPANT_MESSAGE_TIMER_TOKEN => {
             self.write_participant_message();
-            self.dcps_participant_message_timer.set_timeout(Discovery::CHECK_PARTICIPANT_MESSAGES, ());
+            let period = self.participant_message_period();
+            self.dcps_participant_message_timer.set_timeout(period, ());
           }
           other_token => {
             error!("discovery event loop got token: {:?}", other_token);
@@ -679,7 +688,7 @@ impl Discovery {
       GuidPrefix::GUIDPREFIX_UNKNOWN, EntityId::ENTITYID_SPDP_BUILTIN_PARTICIPANT_READER);
 
     let mut reader_proxy = ReaderProxy::new(reader_guid);
-    reader_proxy.multicast_locator_list = get_local_multicast_locators(mc_port);
+    reader_proxy.multicast_locator_list = get_local_multicast_locators(mc_port, dp.ip_mode());
 
     let sub_topic_data = SubscriptionBuiltinTopicData::new(
       reader_guid,
@@ -699,8 +708,8 @@ impl Discovery {
         EntityId::ENTITYID_SPDP_BUILTIN_PARTICIPANT_WRITER );
 
     let writer_proxy = WriterProxy::new(writer_guid, 
-      get_local_unicast_socket_address(uc_port), 
-      get_local_multicast_locators(mc_port));
+      get_local_unicast_socket_address(uc_port, dp.ip_mode()), 
+      get_local_multicast_locators(mc_port, dp.ip_mode()));
 
     let pub_topic_data = PublicationBuiltinTopicData::new(
       writer_guid,
@@ -747,6 +756,8 @@ impl Discovery {
               self.send_discovery_notification(
                 DiscoveryNotificationType::ParticipantUpdated { guid_prefix } );
               if was_new {
+                self.send_discovery_event(DiscoveryEvent::ParticipantDiscovered {
+                  participant: participant_data.participant_guid });
                 // This may be a rediscovery of a previously seen participant that
                 // was temporarily lost due to network outage. Check if we already know
                 // what it has (readers, writers, topics).
@@ -762,6 +773,7 @@ impl Discovery {
               self.discovery_db_write().remove_participant(guid.0.guidPrefix);
               self.send_discovery_notification(
                 DiscoveryNotificationType::ParticipantLost { guid_prefix: guid.0.guidPrefix });
+              self.send_discovery_event(DiscoveryEvent::ParticipantLost { participant: guid.0 });
             }
           },
         Ok(None) => {
@@ -797,6 +809,8 @@ impl Discovery {
             if let Some( (drd,rtps_reader_proxy) )  = db.update_subscription(&d) {
               debug!("handle_subscription_reader - send_discovery_notification ReaderUpdated {:?} -- {:?}",
                 &drd, &rtps_reader_proxy);
+              self.send_discovery_event(DiscoveryEvent::ReaderDiscovered {
+                reader: drd.subscription_topic_data.clone() });
               self.send_discovery_notification(
                 DiscoveryNotificationType::ReaderUpdated {
                   discovered_reader_data: drd, 
@@ -823,6 +837,7 @@ impl Discovery {
             .remove_topic_reader(reader_key.0);
           self.send_discovery_notification(
               DiscoveryNotificationType::ReaderLost { reader_guid: reader_key.0 });
+          self.send_discovery_event(DiscoveryEvent::ReaderLost { reader: reader_key.0 });
         }
       }      
     } // loop
@@ -848,6 +863,8 @@ impl Discovery {
               if let Some(discovered_writer_data) = 
                   self.discovery_db_write().update_publication(&dwd) 
               {
+                self.send_discovery_event(DiscoveryEvent::WriterDiscovered {
+                  writer: discovered_writer_data.publication_topic_data.clone() });
                 self.send_discovery_notification(
                     DiscoveryNotificationType::WriterUpdated{ discovered_writer_data } );
               }
@@ -858,6 +875,7 @@ impl Discovery {
               self.discovery_db_write().remove_topic_writer(writer_key.0);
               self.send_discovery_notification(
                 DiscoveryNotificationType::WriterLost { writer_guid: writer_key.0 });
+              self.send_discovery_event(DiscoveryEvent::WriterLost { writer: writer_key.0 });
               debug!("Disposed Writer {:?}", writer_key);
             }
           
@@ -920,107 +938,98 @@ impl Discovery {
       None => return,
     };
 
-    let mut db = self.discovery_db_write();
-    for msg in msgs.into_iter() {
-      db.update_lease_duration(msg);
+    let mut asserted = Vec::with_capacity(msgs.len());
+    {
+      let mut db = self.discovery_db_write();
+      for msg in msgs.into_iter() {
+        let manual_assertion = match msg.kind {
+          ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_AUTOMATIC_LIVELINESS_UPDATE => Some(false),
+          ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_MANUAL_LIVELINESS_UPDATE => Some(true),
+          _ => None,
+        };
+        if let Some(manual_assertion) = manual_assertion {
+          asserted.push((msg.guid, manual_assertion));
+        }
+        db.update_lease_duration(msg);
+      }
+    }
+    // Readers need to know that the Writers of these participants are alive.
+    for (guid_prefix, manual_assertion) in asserted {
+      self.send_discovery_notification(
+        DiscoveryNotificationType::ParticipantLivelinessAsserted { guid_prefix, manual_assertion });
     }
   }
 
-  // TODO: Explain what happens here and by what logic
-  pub fn write_participant_message(&mut self) {
-    let writer_liveliness: Vec<Liveliness> = self.discovery_db_read()
+  // Shortest lease duration of local Writers with AUTOMATIC Liveliness.
+  fn min_automatic_lease_duration(&self) -> Option<Duration> {
+    self.discovery_db_read()
       .get_all_local_topic_writers()
-      .filter_map(|p| {
-        let liveliness = match p.publication_topic_data.liveliness {
-          Some(lv) => lv,
-          None => return None,
-        };
-
-        Some(liveliness)
+      .filter_map(|p| match p.publication_topic_data.liveliness {
+        Some(Liveliness::Automatic { lease_duration }) 
+          if lease_duration != Duration::DURATION_INFINITE => Some(lease_duration),
+        _ => None,
       })
-      .collect();
-
-    let (automatic, manual): (Vec<&Liveliness>, Vec<&Liveliness>) =
-      writer_liveliness.iter().partition(|p| match p {
-        Liveliness::Automatic { lease_duration: _ } => true,
-        Liveliness::ManualByParticipant { lease_duration: _ } => false,
-        Liveliness::ManualByTopic { lease_duration: _ } => false,
-      });
+      .min()
+  }
 
-    let (manual_by_participant, _manual_by_topic): (Vec<&Liveliness>, Vec<&Liveliness>) =
-      manual.iter().partition(|p| match p {
-        Liveliness::Automatic { lease_duration: _ } => false,
-        Liveliness::ManualByParticipant { lease_duration: _ } => true,
-        Liveliness::ManualByTopic { lease_duration: _ } => false,
-      });
+  // How often to check if automatic liveliness must be asserted.
+  // Assertions are sent at least four times per lease duration.
+  fn participant_message_period(&self) -> StdDuration {
+    match self.min_automatic_lease_duration() {
+      Some(lease_duration) => 
+        std::cmp::min(Discovery::CHECK_PARTICIPANT_MESSAGES, (lease_duration / 4).to_std()),
+      None => Discovery::CHECK_PARTICIPANT_MESSAGES,
+    }
+  }
 
+  // Automatic liveliness of all local Writers is asserted by sending ParticipantMessages
+  // often enough to renew the shortest lease of an AUTOMATIC Writer.
+  // Manual assertions are sent, when the application asserts liveliness of the Participant.
+  pub fn write_participant_message(&mut self) {
+    let min_automatic = match self.min_automatic_lease_duration() {
+      Some(lease_duration) => lease_duration,
+      None => return, // No-one needs automatic assertions
+    };
     let inow = Timestamp::now();
-
-    // Automatic
-    {
-      let current_duration =
-        inow.duration_since(self.liveliness_state.last_auto_update) / 3;
-      let min_automatic = automatic
-        .iter()
-        .map(|lv| match lv {
-          Liveliness::Automatic { lease_duration }
-          | Liveliness::ManualByParticipant { lease_duration }
-          | Liveliness::ManualByTopic { lease_duration } => lease_duration,
-        })
-        .min();
-      trace!(
-        "Current auto duration {:?}. Min auto duration {:?}",
-        current_duration, min_automatic
-      );
-      if let Some(&mm) = min_automatic {
-        if current_duration > mm {
-          let pp = ParticipantMessageData {
-            guid: self.domain_participant.get_guid_prefix(),
-            kind:
-              ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_AUTOMATIC_LIVELINESS_UPDATE,
-            data: Vec::new(),
-          };
-          match self.dcps_participant_message_writer.write(pp, None) {
-            Ok(_) => (),
-            Err(e) => {
-              error!("Failed to write ParticipantMessageData auto. {:?}", e);
-              return;
-            }
-          }
-          self.liveliness_state.last_auto_update = inow;
-        }
+    let since_last = inow.duration_since(self.liveliness_state.last_auto_update);
+    trace!("Since last auto update {:?}. Min auto lease duration {:?}", since_last, min_automatic);
+    if since_last >= min_automatic / 4 {
+      let pp = ParticipantMessageData {
+        guid: self.domain_participant.get_guid_prefix(),
+        kind:
+          ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_AUTOMATIC_LIVELINESS_UPDATE,
+        data: Vec::new(),
       };
+      match self.dcps_participant_message_writer.write(pp, None) {
+        Ok(_) => (),
+        Err(e) => {
+          error!("Failed to write ParticipantMessageData auto. {:?}", e);
+          return;
+        }
+      }
+      self.liveliness_state.last_auto_update = inow;
     }
+  }
 
-    // Manual By Participant
-    {
-      let current_duration =
-        inow.duration_since(self.liveliness_state.last_manual_participant_update) / 3;
-      let min_manual_participant = manual_by_participant
-        .iter()
-        .map(|lv| match lv {
-          Liveliness::Automatic { lease_duration }
-          | Liveliness::ManualByParticipant { lease_duration }
-          | Liveliness::ManualByTopic { lease_duration } => lease_duration,
-        })
-        .min();
-      if let Some(&dur) = min_manual_participant {
-        if current_duration > dur {
-          let pp = ParticipantMessageData {
-            guid: self.domain_participant.get_guid_prefix(),
-            kind:
-              ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_MANUAL_LIVELINESS_UPDATE,
-            data: Vec::new(),
-          };
-          match self.dcps_participant_message_writer.write(pp, None) {
-            Ok(_) => (),
-            Err(e) => {
-              error!("Failed to writer ParticipantMessageData manual. {:?}", e);
-            }
-          }
-        }
-      };
+  // Application has asserted liveliness of this Participant. This covers local Writers
+  // with MANUAL_BY_PARTICIPANT (or AUTOMATIC) Liveliness.
+  fn write_manual_participant_message(&mut self) {
+    let guid_prefix = self.domain_participant.get_guid_prefix();
+    let pp = ParticipantMessageData {
+      guid: guid_prefix,
+      kind:
+        ParticipantMessageDataKind::PARTICIPANT_MESSAGE_DATA_KIND_MANUAL_LIVELINESS_UPDATE,
+      data: Vec::new(),
+    };
+    match self.dcps_participant_message_writer.write(pp, None) {
+      Ok(_) => (),
+      Err(e) => {
+        error!("Failed to writer ParticipantMessageData manual. {:?}", e);
+      }
     }
+    // Local Writers and Readers learn of the assertion directly.
+    self.send_discovery_notification(
+      DiscoveryNotificationType::ParticipantLivelinessAsserted { guid_prefix, manual_assertion: true });
   }
 
   pub fn participant_cleanup(&self) {
@@ -1030,6 +1039,8 @@ impl Discovery {
       debug!("participant cleanup - timeout for {:?}", guid_prefix);
       self.send_discovery_notification(
                 DiscoveryNotificationType::ParticipantLost { guid_prefix });
+      self.send_discovery_event(DiscoveryEvent::ParticipantLost {
+        participant: GUID::new_with_prefix_and_id(guid_prefix, EntityId::ENTITYID_PARTICIPANT) });
     }
   }
 
@@ -1195,6 +1206,16 @@ impl Discovery {
       Err(e) => error!("Failed to send DiscoveryNotification {:?}", e),
     }
   }
+
+  fn send_discovery_event(&self, event: DiscoveryEvent) {
+    match self.discovery_events.lock() {
+      Ok(mut events) => 
+        if events.push(event) {
+          self.discovery_event_wakers.wake_all()
+        }
+      Err(e) => error!("send_discovery_event: DiscoveryEvent queue is poisoned: {:?}", e),
+    }
+  }
 }
 
 
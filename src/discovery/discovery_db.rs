@@ -23,6 +23,7 @@ use crate::{
 };
 
 use super::{
+  content_filter_property::ContentFilterProperty,
   data_types::{
     spdp_participant_data::SPDPDiscoveredParticipantData,
     topic_data::{
@@ -134,6 +135,10 @@ impl DiscoveryDB {
     self.external_topic_readers.get(&guid)
   }
 
+  pub fn find_remote_writer(&self, guid:GUID) -> Option<&DiscoveredWriterData>{
+    self.external_topic_writers.get(&guid)
+  }
+
   fn remove_topic_reader_with_prefix(&mut self, guid_prefix: GuidPrefix) {
     // TODO: Implement this using .drain_filter() in BTreeMap once it lands in stable.
     let to_remove :Vec<GUID> = 
@@ -405,26 +410,26 @@ impl DiscoveryDB {
     domain_participant: &DomainParticipant,
     topic: &Topic,
     reader: &ReaderIngredients,
+    content_filter: Option<ContentFilterProperty>,
   ) {
     let reader_guid = reader.guid;
 
-    let reader_proxy = RtpsReaderProxy::from_reader(
+    let mut reader_proxy = RtpsReaderProxy::from_reader(
       reader,
       domain_participant.domain_id(),
       domain_participant.participant_id(),
+      domain_participant.ip_mode(),
     );
+    reader_proxy.unicast_locator_list.extend(domain_participant.shm_locator());
 
     let mut subscription_data = SubscriptionBuiltinTopicData::new(
       reader_guid,
       &topic.get_name(),
       topic.get_type().name(),
-      &topic.get_qos(),
+      &reader.qos_policy,
     );
     subscription_data.set_participant_key(domain_participant.get_guid());
 
-    // TODO: possibly change content filter to dynamic value
-    let content_filter = None;
-
     let discovered_reader_data = DiscoveredReaderData {
       reader_proxy: ReaderProxy::from(reader_proxy),
       subscription_topic_data: subscription_data,
@@ -518,11 +523,12 @@ mod tests {
       test_data::{subscription_builtin_topic_data, spdp_participant_data, reader_proxy_data},
     },
   };
-  use std::sync::{RwLock, Arc};
+  use std::sync::{RwLock, Arc, Mutex};
   use std::rc::Rc;
 
   use crate::network::udp_sender::UDPSender;
-  use crate::dds::reader::Reader;
+  use crate::dds::reader::{Reader, OwnershipCandidates, LostWriters, ReaderHistoryUsage};
+  use crate::dds::helpers::TaskWakers;
   use crate::structure::guid::*;
   use crate::serialization::cdr_serializer::CDRSerializerAdapter;
   use byteorder::LittleEndian;
@@ -671,13 +677,17 @@ mod tests {
       topic_name: topic.get_name(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver1,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
-    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing);
+    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing, None);
     assert_eq!(discoverydb.local_topic_readers.len(), 1);
     assert_eq!(discoverydb.get_local_topic_readers(&topic).len(), 1);
 
-    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing);
+    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing, None);
     assert_eq!(discoverydb.local_topic_readers.len(), 1);
     assert_eq!(discoverydb.get_local_topic_readers(&topic).len(), 1);
 
@@ -701,9 +711,13 @@ mod tests {
       topic_name: topic.get_name(),
       qos_policy: QosPolicies::qos_none(),
       data_reader_command_receiver: reader_command_receiver2,
+      ownership_candidates: Arc::new(Mutex::new(OwnershipCandidates::new())),
+      lost_writers: Arc::new(Mutex::new(LostWriters::new())),
+      history_usage: Arc::new(Mutex::new(ReaderHistoryUsage::default())),
+      data_reader_wakers: TaskWakers::new(),
     };
 
-    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing);
+    discoverydb.update_local_topic_reader(&dp, &topic, &reader_ing, None);
     assert_eq!(discoverydb.get_local_topic_readers(&topic).len(), 2);
     assert_eq!(discoverydb.get_all_local_topic_readers().count(), 2);
 
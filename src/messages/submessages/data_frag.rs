@@ -1,12 +1,10 @@
 
 use crate::messages::submessages::submessage_elements::parameter_list::ParameterList;
-use crate::messages::submessages::submessage_elements::serialized_payload::SerializedPayload;
 use crate::structure::guid::EntityId;
 use crate::structure::sequence_number::{SequenceNumber,FragmentNumber};
 
 use crate::messages::submessages::submessages::*;
 
-use log::debug;
 use speedy::{Context, Writer, Readable, Writable, Error};
 use enumflags2::BitFlags;
 use bytes::Bytes;
@@ -58,7 +56,11 @@ pub struct DataFrag {
   /// Represents part of the new value of the data-object
   /// after the change. Present only if either the DataFlag or the KeyFlag are
   /// set in the header. Present only if DataFlag is set in the header.
-  pub serialized_payload: SerializedPayload,
+  ///
+  /// Note: These are raw bytes of the fragments. The SerializedPayload header
+  /// (representation identifier and options) is part of the first fragment only,
+  /// so it can be parsed only after the fragments have been reassembled.
+  pub serialized_payload: Bytes,
 }
 
 impl<'a> DataFrag {
@@ -91,13 +93,13 @@ impl<'a> DataFrag {
 
     // Skip any possible fields we do not know about.
     let rtps_v23_header_size: u16 = 7 * 4;
-    let extra_octets = octets_to_inline_qos - rtps_v23_header_size;
     if octets_to_inline_qos < rtps_v23_header_size {
       return Err(io::Error::new(
         io::ErrorKind::Other,
         "DataFrag has too low octetsToInlineQos",
       ));
     }
+    let extra_octets = octets_to_inline_qos - rtps_v23_header_size;
     cursor.set_position(cursor.position() + extra_octets as u64);
 
     let inline_qos = if expect_qos {
@@ -110,8 +112,7 @@ impl<'a> DataFrag {
     };
 
     // Payload should be always present, be it data or key fragments.
-    let serialized_payload = 
-      SerializedPayload::from_bytes(buffer.clone().split_off(cursor.position() as usize))?;
+    let serialized_payload = buffer.slice(cursor.position() as usize ..);
 
     Ok(DataFrag {
       reader_id,
@@ -130,12 +131,9 @@ impl<'a> DataFrag {
 impl<C: Context> Writable<C> for DataFrag {
   fn write_to<T: ?Sized + Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
     writer.write_u16(0)?;
-    if self.inline_qos.is_some() && !self.inline_qos.as_ref().unwrap().parameters.is_empty() {
-      debug!("self.inline_qos {:?}", self.inline_qos);
-      todo!()
-    } else if self.inline_qos.is_some() && self.inline_qos.as_ref().unwrap().parameters.is_empty() || self.inline_qos.is_none() {
-      writer.write_u16(24)?;
-    }
+    // octetsToInlineQos: readerId, writerId, writerSN, fragmentStartingNum,
+    // fragmentsInSubmessage, fragmentSize and sampleSize take 7 * 4 octets.
+    writer.write_u16(7 * 4)?;
     writer.write_value(&self.reader_id)?;
     writer.write_value(&self.writer_id)?;
     writer.write_value(&self.writer_sn)?;
@@ -143,10 +141,10 @@ impl<C: Context> Writable<C> for DataFrag {
     writer.write_value(&self.fragments_in_submessage)?;
     writer.write_value(&self.fragment_size)?;
     writer.write_value(&self.data_size)?;
-    if self.inline_qos.is_some() && !self.inline_qos.as_ref().unwrap().parameters.is_empty() {
-      writer.write_value(&self.inline_qos)?;
+    if let Some(inline_qos) = self.inline_qos.as_ref() {
+      writer.write_value(inline_qos)?;
     }
-    writer.write_value(&self.serialized_payload)?;
+    writer.write_bytes(&self.serialized_payload)?;
     Ok(())
   }
 }
@@ -1,7 +1,16 @@
-use crate::structure::guid::EntityId;
+use crate::{
+  serialization::SubMessage, serialization::SubmessageBody, structure::guid::EntityId,
+  messages::submessages::submessages::SubmessageHeader,
+};
 use crate::structure::sequence_number::*;
+use enumflags2::BitFlags;
+use log::error;
 use speedy::{Readable, Writable};
 
+use super::{
+  submessage::EntitySubmessage, submessage_flag::NACKFRAG_Flags, submessage_kind::SubmessageKind,
+};
+
 /// The NackFrag Submessage is used to communicate the state of a Reader to a
 /// Writer. When a data change is sent as a series of fragments, the NackFrag
 /// Submessage allows the Reader to inform the Writer about specific fragment
@@ -35,6 +44,29 @@ pub struct NackFrag {
   pub count: i32,
 }
 
+impl NackFrag {
+  pub fn create_submessage(self, flags: BitFlags<NACKFRAG_Flags>) -> Option<SubMessage> {
+    let submessage_len = match self.write_to_vec() {
+      Ok(bytes) => bytes.len() as u16,
+      Err(e) => {
+        error!("Reader couldn't write nackfrag to bytes. Error: {}", e);
+        return None;
+      }
+    };
+
+    let nackfrag_header = SubmessageHeader {
+      kind: SubmessageKind::NACK_FRAG,
+      flags: flags.bits(),
+      content_length: submessage_len,
+    };
+
+    Some(SubMessage {
+      header: nackfrag_header,
+      body: SubmessageBody::Entity(EntitySubmessage::NackFrag(self, flags)),
+    })
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
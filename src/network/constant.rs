@@ -43,6 +43,13 @@ pub const ACKNACK_MESSGAGE_TO_LOCAL_WRITER_TOKEN: Token = Token(20 + PTB);
 pub const DISCOVERY_UPDATE_NOTIFICATION_TOKEN: Token = Token(21 + PTB);
 pub const DISCOVERY_COMMAND_TOKEN: Token = Token(22 + PTB);
 
+pub const SHM_LISTENER_TOKEN: Token = Token(23 + PTB);
+
+pub const DISCOVERY_LISTENER_V6_TOKEN: Token = Token(24 + PTB);
+pub const DISCOVERY_MUL_LISTENER_V6_TOKEN: Token = Token(25 + PTB);
+pub const USER_TRAFFIC_LISTENER_V6_TOKEN: Token = Token(26 + PTB);
+pub const USER_TRAFFIC_MUL_LISTENER_V6_TOKEN: Token = Token(27 + PTB);
+
 pub const DISCOVERY_PARTICIPANT_DATA_TOKEN: Token = Token(30 + PTB);
 pub const DISCOVERY_PARTICIPANT_CLEANUP_TOKEN: Token = Token(31 + PTB);
 pub const DISCOVERY_SEND_PARTICIPANT_INFO_TOKEN: Token = Token(32 + PTB);
@@ -112,4 +119,5 @@ pub(crate) enum DiscoveryNotificationType {
   ParticipantLost { guid_prefix : GuidPrefix },
   TopicsInfoUpdated,
   AssertTopicLiveliness { writer_guid: GUID , manual_assertion: bool, },
+  ParticipantLivelinessAsserted { guid_prefix: GuidPrefix, manual_assertion: bool, },
 }
@@ -0,0 +1,773 @@
+//! Shared-memory transport between DomainParticipants on the same host.
+//! Participants use it by default, unless created with
+//! `DomainParticipant::new_without_shared_memory`.
+//!
+//! Each participant owns a shared memory segment, into which it writes the RTPS
+//! messages it sends, and a Unix datagram socket, where other participants wake
+//! it up after writing messages for it. The receiver then scans the segments of
+//! the senders, copies out the messages addressed to it, and releases the slots
+//! for reuse.
+//!
+//! The wakeup notifications carry only the id of the sender, so losing some of
+//! them, when the socket queue is full, is harmless: the receiver has not yet
+//! processed the queued ones.
+//!
+//! The transport is advertised in Discovery with a vendor-specific locator kind
+//! `LocatorKind::LOCATOR_KIND_SHM`. The locator address identifies the host and the
+//! port identifies the participant's segment and socket. Shared-memory locators of
+//! other hosts, or of participants whose segment we cannot map, are not reachable,
+//! and the sender falls back to UDP.
+//!
+//! The segment and the socket are accessible only to the user running the participant.
+//! They are removed when the participant is dropped. The owner holds an exclusive
+//! `flock` on its segment file for as long as the transport exists. The kernel releases
+//! the lock when the process exits, however it exits, so a segment whose lock can be
+//! taken has been left behind by a crashed process. Such files are removed when a new
+//! transport is created. Unlike process ids, the locks work also between processes in
+//! different PID namespaces that share the directory.
+//!
+//! This is available on Unix platforms only. Elsewhere `ShmTransport::new()` fails
+//! and only UDP is used.
+
+#[allow(unused_imports)]
+use log::{debug, error, warn, info, trace};
+
+use std::io;
+
+#[cfg(unix)]
+use std::{
+  cell::{Cell, RefCell},
+  collections::{hash_map::Entry, HashMap},
+  fs::{self, File, OpenOptions},
+  io::Read,
+  path::{Path, PathBuf},
+  os::unix::{
+    fs::{OpenOptionsExt, PermissionsExt},
+    io::AsRawFd,
+    net::UnixDatagram,
+  },
+  sync::atomic::{AtomicU32, AtomicU64, Ordering},
+  time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+#[cfg(unix)]
+use mio::unix::EventedFd;
+#[cfg(unix)]
+use memmap2::MmapMut;
+
+use crate::structure::locator::Locator;
+#[cfg(unix)]
+use crate::structure::locator::LocatorKind;
+
+#[cfg(unix)]
+const SLOT_COUNT: usize = 64;
+// Messages are limited by UDP datagram size anyway.
+#[cfg(unix)]
+const SLOT_DATA_SIZE: usize = 64 * 1024;
+
+// Segment header: magic, slot count, slot data size, closed flag.
+#[cfg(unix)]
+const SEGMENT_HEADER_SIZE: usize = 64;
+// The last byte is the format version. Version 3 segments are locked by their owner.
+#[cfg(unix)]
+const SEGMENT_MAGIC: u64 = 0x5275_7374_4444_5333; // "RustDDS3"
+
+// Only the user running the participant may access the segment and the socket.
+#[cfg(unix)]
+const FILE_MODE: u32 = 0o600;
+
+// Slot header: control (state and generation), length, receiver shm id.
+#[cfg(unix)]
+const SLOT_HEADER_SIZE: usize = 16;
+#[cfg(unix)]
+const SLOT_FREE: u32 = 0;
+#[cfg(unix)]
+const SLOT_WRITING: u32 = 1;
+#[cfg(unix)]
+const SLOT_READY: u32 = 2;
+
+// Slots not released by the receiver in this time are assumed to be lost,
+// e.g. because the receiving process has exited.
+#[cfg(unix)]
+const SLOT_RECLAIM_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Notification: sender shm id
+#[cfg(unix)]
+const NOTIFICATION_SIZE: usize = 4;
+
+#[cfg(unix)]
+fn pack_control(state: u32, generation: u32) -> u64 {
+  (u64::from(generation) << 32) | u64::from(state)
+}
+
+#[cfg(unix)]
+fn unpack_control(control: u64) -> (u32, u32) {
+  (control as u32, (control >> 32) as u32)
+}
+
+#[cfg(unix)]
+fn shm_directory() -> PathBuf {
+  let dev_shm = PathBuf::from("/dev/shm");
+  if dev_shm.is_dir() {
+    dev_shm
+  } else {
+    std::env::temp_dir()
+  }
+}
+
+#[cfg(unix)]
+fn segment_path(shm_id: u32) -> PathBuf {
+  shm_directory().join(format!("rustdds-shm-{:08x}.seg", shm_id))
+}
+
+#[cfg(unix)]
+fn socket_path(shm_id: u32) -> PathBuf {
+  shm_directory().join(format!("rustdds-shm-{:08x}.sock", shm_id))
+}
+
+// shm id of a segment or socket file name
+#[cfg(unix)]
+fn shm_id_of(path: &Path) -> Option<u32> {
+  let name = path.file_name()?.to_str()?.strip_prefix("rustdds-shm-")?;
+  let id = name.strip_suffix(".seg").or_else(|| name.strip_suffix(".sock"))?;
+  u32::from_str_radix(id, 16).ok()
+}
+
+// Takes an exclusive lock on the file. It is released when the file is closed.
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<()> {
+  let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+  if result == 0 {
+    Ok(())
+  } else {
+    Err(io::Error::last_os_error())
+  }
+}
+
+// Opens the segment, if it is stale, i.e. in our format but no longer locked by its owner.
+// The returned file holds the lock, so that the owner cannot be replaced before the
+// files are removed.
+#[cfg(unix)]
+fn open_stale_segment(shm_id: u32) -> Option<File> {
+  let mut file = OpenOptions::new().read(true).open(segment_path(shm_id)).ok()?;
+  // Held by the owner. Its files are removed when it is dropped.
+  try_lock(&file).ok()?;
+  // The owner locks the segment before writing the magic, so a segment without it
+  // may be still being created. Segments of other formats use other means, if any,
+  // to tell whether they are in use.
+  let mut magic = [0u8; 8];
+  file.read_exact(&mut magic).ok()?;
+  if magic == SEGMENT_MAGIC.to_ne_bytes() { Some(file) } else { None }
+}
+
+// Removes the segments and sockets of processes that have exited without removing them,
+// e.g. because they crashed. A segment is stale, if nobody holds its lock. A socket is
+// stale, if its segment is stale or missing altogether.
+#[cfg(unix)]
+fn remove_stale_files() {
+  let entries = match fs::read_dir(shm_directory()) {
+    Ok(entries) => entries,
+    Err(e) => {
+      debug!("ShmTransport: Cannot list {:?}: {:?}", shm_directory(), e);
+      return
+    }
+  };
+  for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+    let shm_id = match shm_id_of(&path) {
+      Some(shm_id) => shm_id,
+      None => continue,
+    };
+    let is_segment = path.extension().map_or(false, |ext| ext == "seg");
+    if is_segment {
+      if let Some(_lock) = open_stale_segment(shm_id) {
+        // The socket first, as the owner creates it after the segment.
+        for stale in &[socket_path(shm_id), path] {
+          match fs::remove_file(stale) {
+            Ok(()) => info!("ShmTransport: Removed stale {:?}", stale),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => debug!("ShmTransport: Cannot remove stale {:?}: {:?}", stale, e),
+          }
+        }
+      }
+    } else if !segment_path(shm_id).exists() {
+      // The segment is created before the socket and removed after it.
+      match fs::remove_file(&path) {
+        Ok(()) => info!("ShmTransport: Removed stale {:?}", path),
+        Err(e) => debug!("ShmTransport: Cannot remove stale {:?}: {:?}", path, e),
+      }
+    }
+  }
+}
+
+/// Identifier of this host, used as the address of shared-memory locators.
+#[cfg(unix)]
+fn host_id() -> [u8; 16] {
+  let id = fs::read("/etc/machine-id")
+    .or_else(|_| fs::read("/var/lib/dbus/machine-id"))
+    .or_else(|_| fs::read("/proc/sys/kernel/hostname"))
+    .unwrap_or_default();
+  md5::compute(id).0
+}
+
+/// Shared memory segment of a sender, mapped either by the sender itself or
+/// by a receiver.
+#[cfg(unix)]
+#[derive(Debug)]
+struct Segment {
+  mmap: MmapMut,
+  base: *mut u8,
+  // The sender keeps the segment file open and locked, to show that it is in use.
+  // The map refers to the same open file, so the lock is held until the map is gone, too.
+  lock: Option<File>,
+}
+
+// The raw pointer points to the memory map owned by the Segment, and all
+// concurrently accessed fields are accessed through atomics.
+#[cfg(unix)]
+unsafe impl Send for Segment {}
+
+#[cfg(unix)]
+impl Segment {
+  const fn size() -> usize {
+    SEGMENT_HEADER_SIZE + SLOT_COUNT * (SLOT_HEADER_SIZE + SLOT_DATA_SIZE)
+  }
+
+  fn create(shm_id: u32) -> io::Result<Segment> {
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create_new(true)
+      .mode(FILE_MODE)
+      .open(segment_path(shm_id))?;
+    // Before the magic is written, so that a segment in our format is always locked
+    // while its owner lives. Nobody else can have locked the file we just created,
+    // except a cleaner that will find it is not in our format yet.
+    if let Err(e) = try_lock(&file) {
+      let _ = fs::remove_file(segment_path(shm_id));
+      return Err(e)
+    }
+    file.set_len(Self::size() as u64)?;
+    let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    mmap[8..12].copy_from_slice(&(SLOT_COUNT as u32).to_ne_bytes());
+    mmap[12..16].copy_from_slice(&(SLOT_DATA_SIZE as u32).to_ne_bytes());
+    mmap[0..8].copy_from_slice(&SEGMENT_MAGIC.to_ne_bytes());
+    let base = mmap.as_mut_ptr();
+    Ok(Segment { mmap, base, lock: Some(file) })
+  }
+
+  fn open(shm_id: u32) -> io::Result<Segment> {
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .open(segment_path(shm_id))?;
+    let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    if mmap.len() < Self::size()
+      || mmap[0..8] != SEGMENT_MAGIC.to_ne_bytes()
+      || mmap[8..12] != (SLOT_COUNT as u32).to_ne_bytes()
+      || mmap[12..16] != (SLOT_DATA_SIZE as u32).to_ne_bytes()
+    {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown shared memory segment format"))
+    }
+    let base = mmap.as_mut_ptr();
+    Ok(Segment { mmap, base, lock: None })
+  }
+
+  fn slot_offset(slot: usize) -> usize {
+    SEGMENT_HEADER_SIZE + slot * (SLOT_HEADER_SIZE + SLOT_DATA_SIZE)
+  }
+
+  // Headers are 4-byte aligned, as the map itself is page-aligned.
+  fn atomic_at(&self, offset: usize) -> &AtomicU32 {
+    assert!(offset + 4 <= Self::size());
+    unsafe { &*(self.base.add(offset) as *const AtomicU32) }
+  }
+
+  // Set by the sender, when it exits.
+  fn closed(&self) -> &AtomicU32 {
+    self.atomic_at(16)
+  }
+
+  fn slot_field(&self, slot: usize, field: usize) -> &AtomicU32 {
+    assert!(slot < SLOT_COUNT);
+    self.atomic_at(Self::slot_offset(slot) + 4 * field)
+  }
+
+  // State and generation of the slot change together, so that the receiver releasing
+  // a slot and the sender reclaiming it cannot both succeed.
+  // Slot headers are 8-byte aligned, as the map is page-aligned and all sizes
+  // are multiples of 8.
+  fn slot_control(&self, slot: usize) -> &AtomicU64 {
+    assert!(slot < SLOT_COUNT);
+    unsafe { &*(self.base.add(Self::slot_offset(slot)) as *const AtomicU64) }
+  }
+
+  fn slot_length(&self, slot: usize) -> &AtomicU32 {
+    self.slot_field(slot, 2)
+  }
+
+  fn slot_receiver(&self, slot: usize) -> &AtomicU32 {
+    self.slot_field(slot, 3)
+  }
+
+  fn slot_data(&self, slot: usize) -> *mut u8 {
+    assert!(slot < SLOT_COUNT);
+    unsafe { self.base.add(Self::slot_offset(slot) + SLOT_HEADER_SIZE) }
+  }
+
+  // Takes all the messages addressed to the receiver, in the order they were written.
+  fn take_messages(&self, receiver_id: u32) -> Vec<Bytes> {
+    let mut ready: Vec<(u32, usize)> = (0..SLOT_COUNT)
+      .filter_map(|slot| match unpack_control(self.slot_control(slot).load(Ordering::Acquire)) {
+        (SLOT_READY, generation) 
+          if self.slot_receiver(slot).load(Ordering::Relaxed) == receiver_id => Some((generation, slot)),
+        _ => None,
+      })
+      .collect();
+    // Generations increase in wrapping arithmetic, and the slots in use are a
+    // small window of them.
+    if let Some(&(base, _)) = ready.first() {
+      ready.sort_by_key(|&(generation, _)| generation.wrapping_sub(base) as i32);
+    }
+    ready.into_iter()
+      .filter_map(|(generation, slot)| self.take_message(slot, generation))
+      .collect()
+  }
+
+  // Copies the message out of the slot and releases it, if the slot is still ready with
+  // the same generation, i.e. the sender has not reclaimed it in the meantime.
+  fn take_message(&self, slot: usize, generation: u32) -> Option<Bytes> {
+    let length = self.slot_length(slot).load(Ordering::Relaxed) as usize;
+    if length > SLOT_DATA_SIZE {
+      return None
+    }
+    let mut message = Vec::with_capacity(length);
+    unsafe {
+      std::ptr::copy_nonoverlapping(self.slot_data(slot), message.as_mut_ptr(), length);
+      message.set_len(length);
+    }
+    match self.slot_control(slot).compare_exchange(
+        pack_control(SLOT_READY, generation), pack_control(SLOT_FREE, generation),
+        Ordering::AcqRel, Ordering::Relaxed) {
+      Ok(_) => Some(Bytes::from(message)),
+      Err(_) => {
+        debug!("ShmListener: Message in slot {} was reclaimed by sender", slot);
+        None
+      }
+    }
+  }
+}
+
+/// Receives messages that other participants have written to shared memory.
+/// Registered to the event loop Poll like the UDPListeners.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct ShmListener {
+  shm_id: u32,
+  socket: UnixDatagram,
+  socket_path: PathBuf,
+  // Segments of the senders, opened on the first message.
+  segments: HashMap<u32, Segment>,
+}
+
+#[cfg(not(unix))]
+#[derive(Debug)]
+pub enum ShmListener {}
+
+#[cfg(unix)]
+impl ShmListener {
+  fn new(shm_id: u32) -> io::Result<ShmListener> {
+    let socket_path = socket_path(shm_id);
+    // Leftover from a crashed process. We own the shm_id, because we managed to
+    // create the segment.
+    let _ = fs::remove_file(&socket_path);
+    let socket = UnixDatagram::bind(&socket_path)?;
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(FILE_MODE))?;
+    socket.set_nonblocking(true)?;
+    Ok(ShmListener { shm_id, socket, socket_path, segments: HashMap::new() })
+  }
+
+  /// Get all messages waiting in shared memory.
+  pub fn get_messages(&mut self) -> Vec<Bytes> {
+    let mut notification = [0u8; NOTIFICATION_SIZE];
+    loop {
+      match self.socket.recv(&mut notification) {
+        Ok(NOTIFICATION_SIZE) => self.open_segment(u32::from_ne_bytes(notification)),
+        Ok(n) => warn!("ShmListener: Ignoring notification of {} bytes", n),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        Err(e) => {
+          warn!("ShmListener: recv failed: {:?}", e);
+          break
+        }
+      }
+    }
+    // The socket is now empty, so any later messages will wake us up again.
+    let shm_id = self.shm_id;
+    let messages = self.segments.values()
+      .flat_map(|segment| segment.take_messages(shm_id))
+      .collect();
+    self.segments.retain(|_, segment| segment.closed().load(Ordering::Acquire) == 0);
+    messages
+  }
+
+  fn open_segment(&mut self, sender_id: u32) {
+    if let Entry::Vacant(entry) = self.segments.entry(sender_id) {
+      match Segment::open(sender_id) {
+        Ok(segment) => { entry.insert(segment); }
+        Err(e) => debug!("ShmListener: Cannot open segment {:08x}: {:?}", sender_id, e),
+      }
+    }
+  }
+}
+
+#[cfg(not(unix))]
+impl ShmListener {
+  pub fn get_messages(&mut self) -> Vec<Bytes> {
+    match *self {}
+  }
+}
+
+#[cfg(unix)]
+impl Drop for ShmListener {
+  fn drop(&mut self) {
+    fs::remove_file(&self.socket_path)
+      .unwrap_or_else(|e| warn!("Cannot remove {:?}: {:?}", self.socket_path, e));
+  }
+}
+
+#[cfg(unix)]
+impl Evented for ShmListener {
+  fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+    EventedFd(&self.socket.as_raw_fd()).register(poll, token, interest, opts)
+  }
+
+  fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+    EventedFd(&self.socket.as_raw_fd()).reregister(poll, token, interest, opts)
+  }
+
+  fn deregister(&self, poll: &Poll) -> io::Result<()> {
+    EventedFd(&self.socket.as_raw_fd()).deregister(poll)
+  }
+}
+
+#[cfg(not(unix))]
+impl Evented for ShmListener {
+  fn register(&self, _poll: &Poll, _token: Token, _interest: Ready, _opts: PollOpt) -> io::Result<()> {
+    match *self {}
+  }
+
+  fn reregister(&self, _poll: &Poll, _token: Token, _interest: Ready, _opts: PollOpt) -> io::Result<()> {
+    match *self {}
+  }
+
+  fn deregister(&self, _poll: &Poll) -> io::Result<()> {
+    match *self {}
+  }
+}
+
+/// Writes messages to our shared memory segment and notifies the receiving
+/// participants.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct ShmSender {
+  shm_id: u32,
+  host_id: [u8; 16],
+  segment: Segment,
+  socket: UnixDatagram,
+  next_slot: Cell<usize>,
+  generation: Cell<u32>,
+  claimed_at: RefCell<Vec<Option<Instant>>>,
+  // Cache of receivers found (or not) on this host, by shm id.
+  reachable: RefCell<HashMap<u32, bool>>,
+}
+
+#[cfg(not(unix))]
+#[derive(Debug)]
+pub enum ShmSender {}
+
+#[cfg(unix)]
+impl ShmSender {
+  fn new(shm_id: u32, host_id: [u8; 16], segment: Segment) -> io::Result<ShmSender> {
+    let socket = UnixDatagram::unbound()?;
+    // We must not block the event loop, if a receiver is slow.
+    socket.set_nonblocking(true)?;
+    Ok(ShmSender {
+      shm_id,
+      host_id,
+      segment,
+      socket,
+      next_slot: Cell::new(0),
+      generation: Cell::new(0),
+      claimed_at: RefCell::new(vec![None; SLOT_COUNT]),
+      reachable: RefCell::new(HashMap::new()),
+    })
+  }
+
+  /// Is the locator a shared-memory locator of a participant on this host,
+  /// which we can access and whose segment format we understand?
+  pub fn is_reachable(&self, locator: &Locator) -> bool {
+    locator.kind == LocatorKind::LOCATOR_KIND_SHM
+      && locator.address == self.host_id
+      && *self.reachable.borrow_mut()
+          .entry(locator.port)
+          .or_insert_with(|| Self::probe(locator.port))
+  }
+
+  // The receiver maps our segment, so we check that we can map its segment, i.e.
+  // it is of the same format and we have the permissions. Then it can map ours.
+  fn probe(shm_id: u32) -> bool {
+    match Segment::open(shm_id) {
+      Ok(segment) if segment.closed().load(Ordering::Acquire) == 0 =>
+        socket_path(shm_id).exists(),
+      Ok(_) => false,
+      Err(e) => {
+        info!("ShmSender: Cannot use segment {:08x}: {:?}. Using UDP.", shm_id, e);
+        false
+      }
+    }
+  }
+
+  /// Returns false, if the message could not be sent. Then it is lost, as it
+  /// would be in UDP, and will be repaired by the reliability protocol.
+  pub fn send(&self, buffer: &[u8], locator: &Locator) -> bool {
+    if buffer.len() > SLOT_DATA_SIZE {
+      warn!("ShmSender: Message of {} bytes does not fit in a slot", buffer.len());
+      return false
+    }
+    let slot = match self.claim_slot() {
+      Some(slot) => slot,
+      None => {
+        warn!("ShmSender: No free slots. Receivers are not keeping up.");
+        return false
+      }
+    };
+    let generation = self.generation.get().wrapping_add(1);
+    self.generation.set(generation);
+    unsafe {
+      std::ptr::copy_nonoverlapping(buffer.as_ptr(), self.segment.slot_data(slot), buffer.len());
+    }
+    self.segment.slot_length(slot).store(buffer.len() as u32, Ordering::Relaxed);
+    self.segment.slot_receiver(slot).store(locator.port, Ordering::Relaxed);
+    self.segment.slot_control(slot).store(pack_control(SLOT_READY, generation), Ordering::Release);
+
+    match self.socket.send_to(&self.shm_id.to_ne_bytes(), socket_path(locator.port)) {
+      Ok(_) => true,
+      // The receiver has not yet processed the earlier notifications, so it will
+      // find this message, too.
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => true,
+      Err(e) => {
+        self.release_slot(slot, generation);
+        match e.kind() {
+          io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => {
+            info!("ShmSender: Receiver {:08x} is gone. Using UDP from now on.", locator.port);
+            self.reachable.borrow_mut().insert(locator.port, false);
+          }
+          _ => debug!("ShmSender: send to {:08x} failed: {:?}", locator.port, e),
+        }
+        false
+      }
+    }
+  }
+
+  fn claim_slot(&self) -> Option<usize> {
+    let now = Instant::now();
+    let mut claimed_at = self.claimed_at.borrow_mut();
+    for i in 0..SLOT_COUNT {
+      let slot = (self.next_slot.get() + i) % SLOT_COUNT;
+      let control = self.segment.slot_control(slot);
+      let current = control.load(Ordering::Acquire);
+      let (state, generation) = unpack_control(current);
+      let stale = state != SLOT_FREE && claimed_at[slot]
+        .map_or(false, |claimed| now.duration_since(claimed) > SLOT_RECLAIM_TIMEOUT);
+      // Fails, if the receiver has just released the slot. Then it is free, and
+      // we find it on the next round.
+      let claimed = (state == SLOT_FREE || stale)
+        && control.compare_exchange(current, pack_control(SLOT_WRITING, generation),
+                                    Ordering::Acquire, Ordering::Relaxed).is_ok();
+      if claimed {
+        claimed_at[slot] = Some(now);
+        self.next_slot.set(slot + 1);
+        return Some(slot)
+      }
+    }
+    None
+  }
+
+  fn release_slot(&self, slot: usize, generation: u32) {
+    self.claimed_at.borrow_mut()[slot] = None;
+    // The receiver may have taken the message already.
+    let _ = self.segment.slot_control(slot).compare_exchange(
+      pack_control(SLOT_READY, generation), pack_control(SLOT_FREE, generation),
+      Ordering::Release, Ordering::Relaxed);
+  }
+}
+
+#[cfg(not(unix))]
+impl ShmSender {
+  pub fn is_reachable(&self, _locator: &Locator) -> bool {
+    match *self {}
+  }
+
+  pub fn send(&self, _buffer: &[u8], _locator: &Locator) -> bool {
+    match *self {}
+  }
+}
+
+#[cfg(unix)]
+impl Drop for ShmSender {
+  fn drop(&mut self) {
+    // Receivers that have the segment mapped can still use it, until they see
+    // that it is closed.
+    self.segment.closed().store(1, Ordering::Release);
+    let path = segment_path(self.shm_id);
+    fs::remove_file(&path)
+      .unwrap_or_else(|e| warn!("Cannot remove {:?}: {:?}", path, e));
+  }
+}
+
+/// Shared-memory transport of a DomainParticipant.
+#[derive(Debug)]
+pub struct ShmTransport {
+  pub listener: ShmListener,
+  pub sender: ShmSender,
+  locator: Locator,
+}
+
+impl ShmTransport {
+  #[cfg(unix)]
+  pub fn new() -> io::Result<ShmTransport> {
+    remove_stale_files();
+    let host_id = host_id();
+    let mut attempts = 0;
+    let (shm_id, segment) = loop {
+      let shm_id = rand::random::<u32>();
+      match Segment::create(shm_id) {
+        Ok(segment) => break (shm_id, segment),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => attempts += 1,
+        Err(e) => return Err(e),
+      }
+    };
+    // The sender is created first, so that the segment is removed, if the
+    // listener cannot be created.
+    let sender = ShmSender::new(shm_id, host_id, segment)?;
+    let listener = ShmListener::new(shm_id)?;
+    let locator = Locator {
+      kind: LocatorKind::LOCATOR_KIND_SHM,
+      port: shm_id,
+      address: host_id,
+    };
+    info!("ShmTransport: New transport {:08x} in {:?}", shm_id, shm_directory());
+    Ok(ShmTransport { listener, sender, locator })
+  }
+
+  #[cfg(not(unix))]
+  pub fn new() -> io::Result<ShmTransport> {
+    Err(io::Error::new(io::ErrorKind::Other, "Shared memory transport is supported on Unix only"))
+  }
+
+  /// Locator to be advertised in Discovery.
+  pub fn locator(&self) -> Locator {
+    self.locator
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shm_send_and_receive() {
+    let mut receiver = ShmTransport::new().unwrap();
+    let sender = ShmTransport::new().unwrap();
+    let locator = receiver.locator();
+    assert!(sender.sender.is_reachable(&locator));
+
+    let messages: Vec<Vec<u8>> = (0..SLOT_COUNT + 10).map(|i| vec![i as u8; 100 + i]).collect();
+    for message in &messages {
+      assert!(sender.sender.send(message, &locator));
+      // Each message is taken out immediately, so the slots are reused.
+      assert_eq!(receiver.listener.get_messages(), vec![Bytes::from(message.clone())]);
+    }
+
+    // A burst of messages is received in order, even if it does not fit in the
+    // socket queue.
+    for message in &messages[0..SLOT_COUNT] {
+      assert!(sender.sender.send(message, &locator));
+    }
+    let received = receiver.listener.get_messages();
+    assert_eq!(received.len(), SLOT_COUNT);
+    assert!(received.iter().zip(&messages).all(|(r, m)| r == m));
+    assert!(receiver.listener.get_messages().is_empty());
+  }
+
+  #[test]
+  fn shm_unreachable_locators() {
+    let transport = ShmTransport::new().unwrap();
+    let other = ShmTransport::new().unwrap();
+
+    let mut other_host = other.locator();
+    other_host.address[0] ^= 0xff;
+    assert!(!transport.sender.is_reachable(&other_host));
+
+    let udp = Locator::from(std::net::SocketAddr::new("127.0.0.1".parse().unwrap(), 7412));
+    assert!(!transport.sender.is_reachable(&udp));
+
+    // Segment of a different format, e.g. of another RustDDS version.
+    let mut other_version = other.locator();
+    other_version.port = other_version.port.wrapping_add(1);
+    fs::write(segment_path(other_version.port), SEGMENT_MAGIC.wrapping_add(1).to_ne_bytes()).unwrap();
+    fs::write(socket_path(other_version.port), []).unwrap();
+    assert!(!transport.sender.is_reachable(&other_version));
+    fs::remove_file(segment_path(other_version.port)).unwrap();
+    fs::remove_file(socket_path(other_version.port)).unwrap();
+
+    // Receiver exits: sending fails, and it is no longer reachable.
+    let gone = other.locator();
+    drop(other);
+    assert!(!transport.sender.send(&[1, 2, 3], &gone));
+    assert!(!transport.sender.is_reachable(&gone));
+  }
+
+  #[test]
+  fn shm_files_are_private_and_stale_ones_removed() {
+    let transport = ShmTransport::new().unwrap();
+    let alive_id = transport.locator().port;
+    for path in &[segment_path(alive_id), socket_path(alive_id)] {
+      assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, FILE_MODE);
+    }
+
+    // The owner exits without removing its files. Exiting unmaps and closes
+    // the segment, which releases the lock.
+    let crashed_id = alive_id.wrapping_add(1);
+    drop(Segment::create(crashed_id).unwrap());
+    fs::write(socket_path(crashed_id), []).unwrap();
+    // Socket without a segment
+    let orphan_id = alive_id.wrapping_add(2);
+    fs::write(socket_path(orphan_id), []).unwrap();
+
+    let _other = ShmTransport::new().unwrap();
+    for shm_id in &[crashed_id, orphan_id] {
+      assert!(!segment_path(*shm_id).exists());
+      assert!(!socket_path(*shm_id).exists());
+    }
+    // Files of a live transport are not stale.
+    assert!(segment_path(alive_id).exists());
+    assert!(socket_path(alive_id).exists());
+  }
+
+  #[test]
+  fn shm_slots_run_out_and_are_reclaimed() {
+    let receiver = ShmTransport::new().unwrap();
+    let sender = ShmTransport::new().unwrap();
+    let locator = receiver.locator();
+
+    // Receiver does not read anything.
+    for _ in 0..SLOT_COUNT {
+      assert!(sender.sender.send(&[0; 10], &locator));
+    }
+    assert!(!sender.sender.send(&[0; 10], &locator));
+    std::thread::sleep(SLOT_RECLAIM_TIMEOUT + Duration::from_millis(100));
+    assert!(sender.sender.send(&[0; 10], &locator));
+  }
+}
@@ -10,7 +10,7 @@ use socket2::{Socket,Domain, Type, SockAddr, Protocol, };
 
 use bytes::{Bytes,BytesMut};
 
-use crate::network::util::get_local_multicast_ip_addrs;
+use crate::network::util::{get_local_multicast_ip_addrs, get_local_multicast_ipv6_interfaces};
 
 const MAX_MESSAGE_SIZE : usize = 64 * 1024; // This is max we can get from UDP.
 const MESSAGE_BUFFER_ALLOCATION_CHUNK : usize = 256 * 1024; // must be >= MAX_MESSAGE_SIZE
@@ -22,15 +22,15 @@ pub struct UDPListener {
   socket: UdpSocket,
   token: Token,
   receive_buffer: BytesMut,
-  multicast_group: Option<Ipv4Addr>,
+  multicast_groups: Vec<IpAddr>,
 }
 
 impl Drop for UDPListener {
   fn drop(&mut self) {
-    if let Some(mcg) = self.multicast_group { self
-    .socket
-    .leave_multicast_v4(&mcg, &Ipv4Addr::UNSPECIFIED)
-    .unwrap_or_else(|e| { error!("leave_multicast_group: {:?}",e); } ) }
+    for mcg in self.multicast_groups.clone() {
+      self.leave_multicast(&mcg)
+        .unwrap_or_else(|e| { error!("leave_multicast_group: {:?}",e); } )
+    }
   }
 }
 
@@ -40,7 +40,18 @@ impl UDPListener {
   // TODO: Why is is this function even necessary? Doesn't try_bind() do just the same?
 
   fn new_listening_socket(host: &str, port: u16, reuse_addr: bool) -> io::Result<UdpSocket> {
-    let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP) )?;
+    let address = SocketAddr::new(
+      host.parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?, 
+      port);
+
+    let raw_socket = Socket::new(Domain::for_address(address), Type::DGRAM, Some(Protocol::UDP) )?;
+
+    // IPv6 sockets listen to IPv6 only, so that there can be a separate IPv4
+    // socket on the same port.
+    if address.is_ipv6() {
+      raw_socket.set_only_v6(true)?;
+    }
 
     // We set ReuseAddr so that other DomainParticipants on this host can
     // bind to the same multicast address and port.
@@ -58,11 +69,6 @@ impl UDPListener {
       }
     }
 
-    let address = SocketAddr::new(
-      host.parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?, 
-      port);
-
     if let Err(e) = raw_socket.bind( &SockAddr::from(address) ) {
       info!("new_socket - cannot bind socket: {:?}",e);
       return Err(e)
@@ -89,11 +95,13 @@ impl UDPListener {
       socket: mio_socket, 
       token,
       receive_buffer: BytesMut::with_capacity(MESSAGE_BUFFER_ALLOCATION_CHUNK),
-      multicast_group: None,
+      multicast_groups: Vec::new(),
     })
   }
 
-  pub fn new_multicast(token: Token, host: &str, port: u16, multicast_group: Ipv4Addr) 
+  // The host must be an unspecified address ("0.0.0.0" or "::") of the same
+  // IP version as the multicast group.
+  pub fn new_multicast(token: Token, host: &str, port: u16, multicast_group: IpAddr) 
     -> io::Result<UDPListener> 
   {
     if ! multicast_group.is_multicast() {
@@ -102,21 +110,14 @@ impl UDPListener {
 
     let mio_socket = Self::new_listening_socket(host, port, true)?;
 
-    for multicast_if_ipaddr in get_local_multicast_ip_addrs()? {
-      match multicast_if_ipaddr {
-        IpAddr::V4(a) => {
-          mio_socket.join_multicast_v4(&multicast_group, &a)?;
-        }   
-        IpAddr::V6(_a) => error!("UDPListener::new_multicast() not implemented for IpV6") , // TODO
-      }
-    }
-
-    Ok(UDPListener { 
+    let mut listener = UDPListener { 
       socket: mio_socket, 
       token,
       receive_buffer: BytesMut::with_capacity(MESSAGE_BUFFER_ALLOCATION_CHUNK),
-      multicast_group: Some(multicast_group),
-    })
+      multicast_groups: Vec::new(),
+    };
+    listener.join_multicast(&multicast_group)?;
+    Ok(listener)
   }
 
 
@@ -191,28 +192,48 @@ impl UDPListener {
     messages
   }
 
-  pub fn join_multicast(&self, address: &Ipv4Addr) -> io::Result<()> {
-    if address.is_multicast() {
-      return self
-        .socket
-        .join_multicast_v4(address, &Ipv4Addr::UNSPECIFIED);
+  /// Join the multicast group on all interfaces.
+  pub fn join_multicast(&mut self, address: &IpAddr) -> io::Result<()> {
+    match address {
+      IpAddr::V4(group) if group.is_multicast() => {
+        for multicast_if_ipaddr in get_local_multicast_ip_addrs()? {
+          if let IpAddr::V4(a) = multicast_if_ipaddr {
+            self.socket.join_multicast_v4(group, &a)?;
+          }
+        }
+      }
+      IpAddr::V6(group) if group.is_multicast() => {
+        for interface in Self::ipv6_multicast_interfaces()? {
+          self.socket.join_multicast_v6(group, interface)?;
+        }
+      }
+      _ => return io::Result::Err(io::Error::new(io::ErrorKind::Other, "Not a multicast address")),
     }
-    io::Result::Err(io::Error::new(
-      io::ErrorKind::Other,
-      "Not a multicast address",
-    ))
+    self.multicast_groups.push(*address);
+    Ok(())
   }
 
-  pub fn leave_multicast(&self, address: &Ipv4Addr) -> io::Result<()> {
-    if address.is_multicast() {
-      return self
-        .socket
-        .leave_multicast_v4(address, &Ipv4Addr::UNSPECIFIED);
+  pub fn leave_multicast(&self, address: &IpAddr) -> io::Result<()> {
+    match address {
+      IpAddr::V4(group) if group.is_multicast() => 
+        self.socket.leave_multicast_v4(group, &Ipv4Addr::UNSPECIFIED),
+      IpAddr::V6(group) if group.is_multicast() => {
+        for interface in Self::ipv6_multicast_interfaces()? {
+          self.socket.leave_multicast_v6(group, interface)?;
+        }
+        Ok(())
+      }
+      _ => io::Result::Err(io::Error::new(io::ErrorKind::Other, "Not a multicast address")),
+    }
+  }
+
+  fn ipv6_multicast_interfaces() -> io::Result<Vec<u32>> {
+    let interfaces = get_local_multicast_ipv6_interfaces()?;
+    if interfaces.is_empty() {
+      Ok(vec![0]) // let the OS choose
+    } else {
+      Ok(interfaces)
     }
-    io::Result::Err(io::Error::new(
-      io::ErrorKind::Other,
-      "Not a multicast address",
-    ))
   }
 }
 
@@ -220,6 +241,8 @@ impl UDPListener {
 mod tests {
   use super::*;
   use crate::network::udp_sender::*;
+  use crate::network::util::MULTICAST_GROUP_V6;
+  use crate::structure::locator::Locator;
 
   //use std::os::unix::io::AsRawFd;
   //use nix::sys::socket::setsockopt;
@@ -245,7 +268,7 @@ mod tests {
 
   #[test]
   fn udpl_multicast_address() {
-    let listener = UDPListener::new_multicast(Token(0), "0.0.0.0", 10002,Ipv4Addr::new(239, 255, 0, 1)).unwrap();
+    let listener = UDPListener::new_multicast(Token(0), "0.0.0.0", 10002,IpAddr::V4(Ipv4Addr::new(239, 255, 0, 1))).unwrap();
     let sender = UDPSender::new_with_random_port().unwrap();
 
     //setsockopt(sender.socket.as_raw_fd(), IpMulticastLoop, &true)
@@ -263,10 +286,27 @@ mod tests {
     let rec_data = listener.get_message();
 
     listener
-      .leave_multicast(&Ipv4Addr::new(239, 255, 0, 1))
+      .leave_multicast(&IpAddr::V4(Ipv4Addr::new(239, 255, 0, 1)))
       .unwrap();
 
     assert_eq!(rec_data.len(), 3);
     assert_eq!(rec_data, data);
   }
+
+  #[test]
+  fn udpl_multicast_address_v6() {
+    let group = IpAddr::V6(MULTICAST_GROUP_V6);
+    let listener = UDPListener::new_multicast(Token(0), "::", 10004, group).unwrap();
+    let sender = UDPSender::new_with_random_port().unwrap();
+
+    let data: Vec<u8> = vec![1, 3, 5];
+    sender.send_to_locator(&data, &Locator::from(SocketAddr::new(group, 10004)));
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let rec_data = listener.get_message();
+    listener.leave_multicast(&group).unwrap();
+
+    assert_eq!(rec_data, data);
+  }
 }
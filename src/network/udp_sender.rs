@@ -2,7 +2,7 @@
 use log::{debug,warn,error,trace, info};
 
 use mio::net::UdpSocket;
-use std::net::{SocketAddr, IpAddr};
+use std::net::{SocketAddr, IpAddr, Ipv6Addr};
 use socket2::{Socket,Domain, Type, SockAddr, Protocol, };
 
 #[cfg(windows)] use local_ip_address::list_afinet_netifas;
@@ -10,7 +10,8 @@ use socket2::{Socket,Domain, Type, SockAddr, Protocol, };
 #[cfg(test)] use std::net::Ipv4Addr;
 use std::io;
 use crate::structure::locator::{LocatorKind, Locator};
-use crate::network::util::get_local_multicast_ip_addrs;
+use crate::network::util::{get_local_multicast_ip_addrs, get_local_multicast_ipv6_interfaces};
+use crate::network::shm_transport::ShmSender;
 
 // We need one multicast sender socket per interface
 
@@ -18,6 +19,11 @@ use crate::network::util::get_local_multicast_ip_addrs;
 pub struct UDPSender {
   unicast_socket: UdpSocket,
   multicast_sockets: Vec<UdpSocket>,
+  // IPv6 sockets are missing, if IPv6 is not available on the host.
+  unicast_socket_v6: Option<UdpSocket>,
+  multicast_sockets_v6: Vec<UdpSocket>,
+  // Receivers on the same host are reached through shared memory, if available.
+  shm_sender: Option<ShmSender>,
 }
 
 impl UDPSender {
@@ -50,17 +56,16 @@ impl UDPSender {
 
     let mut multicast_sockets = Vec::with_capacity(1);
     for multicast_if_ipaddr in get_local_multicast_ip_addrs()? {
+      let a = match multicast_if_ipaddr {
+        IpAddr::V4(a) => a,
+        IpAddr::V6(_) => continue, // IPv6 multicast sockets are per interface index, below.
+      };
       let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP) )?;
       // beef: specify otput interface
       info!("UDPSender: Multicast sender on interface {:?}",multicast_if_ipaddr);
-      match multicast_if_ipaddr {
-        IpAddr::V4(a) => {
-          raw_socket.set_multicast_if_v4(&a)?;
-          if cfg!(windows) { raw_socket.set_reuse_address(true)?; } // Necessary? TODO: Check if necessary.
-          raw_socket.bind( &SockAddr::from(SocketAddr::new(multicast_if_ipaddr, 0)) )?;
-        }   
-        IpAddr::V6(_a) => error!("UDPSender::new() not implemented for IpV6") , // TODO
-      }
+      raw_socket.set_multicast_if_v4(&a)?;
+      if cfg!(windows) { raw_socket.set_reuse_address(true)?; } // Necessary? TODO: Check if necessary.
+      raw_socket.bind( &SockAddr::from(SocketAddr::new(multicast_if_ipaddr, 0)) )?;
       
       let mc_socket = std::net::UdpSocket::from( raw_socket );
       mc_socket.set_multicast_loop_v4(true)
@@ -68,18 +73,72 @@ impl UDPSender {
       multicast_sockets.push( UdpSocket::from_socket(mc_socket)? );
     } // end for
 
-    let sender = UDPSender { unicast_socket, multicast_sockets, };
+    let unicast_socket_v6 = Self::new_unicast_socket_v6(sender_port)
+      .map_err(|e| info!("UDPSender: No IPv6 unicast socket: {:?}", e))
+      .ok();
+    let multicast_sockets_v6 = if unicast_socket_v6.is_some() {
+      Self::new_multicast_sockets_v6()
+        .unwrap_or_else(|e| { info!("UDPSender: No IPv6 multicast sockets: {:?}", e); vec![] })
+    } else {
+      vec![]
+    };
+
+    let sender = UDPSender { 
+      unicast_socket, multicast_sockets, unicast_socket_v6, multicast_sockets_v6, shm_sender: None, 
+    };
     info!("UDPSender::new() --> {:?}", sender);
     Ok(sender)
   }
 
+  fn new_unicast_socket_v6(sender_port: u16) -> io::Result<UdpSocket> {
+    let raw_socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    raw_socket.set_only_v6(true)?;
+    raw_socket.bind(&SockAddr::from(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), sender_port)))?;
+    UdpSocket::from_socket(std::net::UdpSocket::from(raw_socket))
+  }
+
+  fn new_multicast_sockets_v6() -> io::Result<Vec<UdpSocket>> {
+    let mut interfaces = get_local_multicast_ipv6_interfaces()?;
+    if interfaces.is_empty() {
+      interfaces.push(0); // let the OS choose
+    }
+    let mut sockets = Vec::with_capacity(interfaces.len());
+    for interface in interfaces {
+      info!("UDPSender: IPv6 multicast sender on interface index {}", interface);
+      let raw_socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+      raw_socket.set_only_v6(true)?;
+      raw_socket.set_multicast_if_v6(interface)?;
+      // Loop on, as for IPv4
+      raw_socket.set_multicast_loop_v6(true)?;
+      raw_socket.bind(&SockAddr::from(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)))?;
+      sockets.push(UdpSocket::from_socket(std::net::UdpSocket::from(raw_socket))?);
+    }
+    Ok(sockets)
+  }
+
   pub fn new_with_random_port() -> io::Result<UDPSender> {
     Self::new(0)
   }
 
 
+  pub fn set_shm_sender(&mut self, shm_sender: ShmSender) {
+    self.shm_sender = Some(shm_sender);
+  }
+
+  /// Is the locator a shared-memory locator, which we can reach from this host?
+  pub fn can_reach_via_shm(&self, l: &Locator) -> bool {
+    self.shm_sender.as_ref().map_or(false, |shm| shm.is_reachable(l))
+  }
+
+  // If the list contains a reachable shared-memory locator, it is used instead of
+  // the others, as they all lead to the same receiver.
   pub fn send_to_locator_list(&self, buffer: &[u8], ll: &[Locator]) {
-    for loc in ll {
+    if let Some(shm_locator) = ll.iter().find(|l| self.can_reach_via_shm(l)) {
+      if self.shm_sender.as_ref().map_or(false, |shm| shm.send(buffer, shm_locator)) {
+        return
+      }
+    }
+    for loc in ll.iter().filter(|l| l.kind != LocatorKind::LOCATOR_KIND_SHM) {
       self.send_to_locator(buffer,loc)
     }
   }
@@ -100,8 +159,7 @@ impl UDPSender {
 
   pub fn send_to_locator(&self, buffer: &[u8], l: &Locator) {
       match l.kind {
-        LocatorKind::LOCATOR_KIND_UDPv4 |
-        LocatorKind::LOCATOR_KIND_UDPv6 => {
+        LocatorKind::LOCATOR_KIND_UDPv4 => {
           let a = l.to_socket_address();
           if a.ip().is_multicast() {
             for socket in self.multicast_sockets.iter() {
@@ -111,6 +169,24 @@ impl UDPSender {
             self.send_to_udp_socket(buffer, &self.unicast_socket, &a);
           }
         }
+        LocatorKind::LOCATOR_KIND_UDPv6 => {
+          let a = l.to_socket_address();
+          if a.ip().is_multicast() {
+            for socket in self.multicast_sockets_v6.iter() {
+              self.send_to_udp_socket(buffer, socket, &a);
+            }
+          } else {
+            match self.unicast_socket_v6 {
+              Some(ref socket) => self.send_to_udp_socket(buffer, socket, &a),
+              None => trace!("send_to_locator: No IPv6 socket for {:?}", a),
+            }
+          }
+        }
+        LocatorKind::LOCATOR_KIND_SHM =>
+          match self.shm_sender {
+            Some(ref shm) if shm.is_reachable(l) => { shm.send(buffer, l); }
+            _ => trace!("send_to_locator: Shared memory locator {:?} is not reachable", l),
+          }
         LocatorKind::LOCATOR_KIND_INVALID |
         LocatorKind::LOCATOR_KIND_RESERVED =>
           error!("send_to_locator: Cannot send to {:?}",l.kind),
@@ -155,6 +231,7 @@ impl UDPSender {
 mod tests {
   use super::*;
   use crate::network::udp_listener::*;
+  use bytes::Bytes;
   use mio::Token;
 
   #[test]
@@ -195,4 +272,44 @@ mod tests {
     assert_eq!(rec_data_2.len(), 6);
     assert_eq!(rec_data_2, data);
   }
+
+  #[test]
+  fn udps_send_to_ipv6_locator() {
+    let listener = UDPListener::new_unicast(Token(0), "::1", 10401).unwrap();
+    let sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
+
+    let data: Vec<u8> = vec![6, 0, 6];
+    let locator = Locator::from(SocketAddr::new("::1".parse().unwrap(), 10401));
+    assert_eq!(locator.kind, LocatorKind::LOCATOR_KIND_UDPv6);
+    sender.send_to_locator(&data, &locator);
+
+    assert_eq!(listener.get_message(), data);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn udps_falls_back_from_shm_to_udp() {
+    use crate::network::shm_transport::ShmTransport;
+
+    let mut listener = UDPListener::new_unicast(Token(0), "127.0.0.1", 10601).unwrap();
+    let mut sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
+    let ShmTransport { sender: shm_sender, .. } = ShmTransport::new().unwrap();
+    sender.set_shm_sender(shm_sender);
+    let mut receiver = ShmTransport::new().unwrap();
+    let locators = vec![receiver.locator(),
+                        Locator::from(SocketAddr::new("127.0.0.1".parse().unwrap(), 10601))];
+    let data: Vec<u8> = vec![6, 0, 1];
+
+    // Shared memory is used, when it works.
+    sender.send_to_locator_list(&data, &locators);
+    assert_eq!(receiver.listener.get_messages(), vec![Bytes::from(data.clone())]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(listener.get_messages().is_empty());
+
+    // Receiver is gone, so the message goes over UDP.
+    drop(receiver);
+    sender.send_to_locator_list(&data, &locators);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(listener.get_messages(), vec![Bytes::from(data)]);
+  }
 }
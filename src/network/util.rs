@@ -1,5 +1,5 @@
 use std::{
-  net::{SocketAddr, IpAddr, },
+  net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr, },
 };
 use std::io;
 
@@ -7,9 +7,50 @@ use std::io;
 
 use crate::structure::locator::{LocatorList, Locator};
 
-pub fn get_local_multicast_locators(port: u16) -> LocatorList {
-  let saddr = SocketAddr::new("239.255.0.1".parse().unwrap(), port);
-  vec![Locator::from(saddr)]
+/// IP versions used by a DomainParticipant for RTPS over UDP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpMode {
+  /// IPv4 only
+  V4Only,
+  /// IPv6 only, for hosts without IPv4 connectivity
+  V6Only,
+  /// Both IPv4 and IPv6. IPv6 is used only if it is available on the host.
+  Dual,
+}
+
+impl IpMode {
+  pub fn uses_ipv4(self) -> bool {
+    self != IpMode::V6Only
+  }
+
+  pub fn uses_ipv6(self) -> bool {
+    self != IpMode::V4Only
+  }
+}
+
+impl Default for IpMode {
+  fn default() -> Self {
+    IpMode::Dual
+  }
+}
+
+pub const MULTICAST_GROUP_V4: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 1);
+
+// IPv6 multicast groups are 239.255.0.1 mapped into link-local (ff02::) and
+// site-local (ff05::) scope. We advertise the link-local one, but listen to both.
+pub const MULTICAST_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0xffff, 0xefff, 0x0001);
+pub const MULTICAST_GROUP_V6_SITE: Ipv6Addr =
+  Ipv6Addr::new(0xff05, 0, 0, 0, 0, 0xffff, 0xefff, 0x0001);
+
+pub fn get_local_multicast_locators(port: u16, ip_mode: IpMode) -> LocatorList {
+  let mut locators = Vec::with_capacity(2);
+  if ip_mode.uses_ipv4() {
+    locators.push(Locator::from(SocketAddr::new(IpAddr::V4(MULTICAST_GROUP_V4), port)));
+  }
+  if ip_mode.uses_ipv6() {
+    locators.push(Locator::from(SocketAddr::new(IpAddr::V6(MULTICAST_GROUP_V6), port)));
+  }
+  locators
 }
 
 pub fn get_local_multicast_ip_addrs() -> io::Result<Vec<IpAddr>> {
@@ -21,12 +62,50 @@ pub fn get_local_multicast_ip_addrs() -> io::Result<Vec<IpAddr>> {
   )
 }
 
-pub fn get_local_unicast_socket_address(port: u16) -> LocatorList {
+// Interface indices for IPv6 multicast. If we cannot find out the indices,
+// index 0 lets the OS choose the interface.
+pub fn get_local_multicast_ipv6_interfaces() -> io::Result<Vec<u32>> {
+  let ifs = if_addrs::get_if_addrs()?;
+  let mut indices: Vec<u32> = ifs.iter()
+    .filter( |ifaddr| ! ifaddr.is_loopback() && ifaddr.ip().is_ipv6() )
+    .map( |ifaddr| interface_index(&ifaddr.name) )
+    .collect();
+  indices.sort_unstable();
+  indices.dedup();
+  Ok(indices)
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> u32 {
+  match std::ffi::CString::new(name) {
+    Ok(c_name) => unsafe { libc::if_nametoindex(c_name.as_ptr()) },
+    Err(_) => 0,
+  }
+}
+
+#[cfg(not(unix))]
+fn interface_index(_name: &str) -> u32 {
+  0
+}
+
+// Link-local IPv6 addresses are not usable in locators, as they do not
+// carry the interface (scope id).
+fn is_ipv6_unicast_link_local(ip: &IpAddr) -> bool {
+  match ip {
+    IpAddr::V6(a) => (a.segments()[0] & 0xffc0) == 0xfe80,
+    IpAddr::V4(_) => false,
+  }
+}
+
+pub fn get_local_unicast_socket_address(port: u16, ip_mode: IpMode) -> LocatorList {
   match if_addrs::get_if_addrs() {
     Ok(ifaces) => {
       ifaces.iter()
         .filter(|ip| ! ip.is_loopback())
-        .map(|ip| Locator::from(SocketAddr::new(ip.ip(), port)))
+        .map(|ip| ip.ip())
+        .filter(|ip| if ip.is_ipv4() { ip_mode.uses_ipv4() } else { ip_mode.uses_ipv6() })
+        .filter(|ip| ! is_ipv6_unicast_link_local(ip))
+        .map(|ip| Locator::from(SocketAddr::new(ip, port)))
         .collect()
     }
     Err(e) => {
@@ -35,3 +114,29 @@ pub fn get_local_unicast_socket_address(port: u16) -> LocatorList {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::structure::locator::LocatorKind;
+
+  #[test]
+  fn util_locators_by_ip_mode() {
+    let kinds = |locators: LocatorList| -> Vec<LocatorKind> {
+      locators.iter().map(|l| l.kind).collect()
+    };
+    assert_eq!(kinds(get_local_multicast_locators(7400, IpMode::V4Only)),
+      vec![LocatorKind::LOCATOR_KIND_UDPv4]);
+    assert_eq!(kinds(get_local_multicast_locators(7400, IpMode::V6Only)),
+      vec![LocatorKind::LOCATOR_KIND_UDPv6]);
+    assert_eq!(get_local_multicast_locators(7400, IpMode::Dual)[1].to_socket_address(),
+      SocketAddr::new(IpAddr::V6(MULTICAST_GROUP_V6), 7400));
+
+    assert!(get_local_unicast_socket_address(7410, IpMode::V4Only).iter()
+      .all(|l| l.kind == LocatorKind::LOCATOR_KIND_UDPv4));
+    for locator in get_local_unicast_socket_address(7410, IpMode::V6Only) {
+      assert_eq!(locator.kind, LocatorKind::LOCATOR_KIND_UDPv6);
+      assert!(!is_ipv6_unicast_link_local(&locator.to_socket_address().ip()));
+    }
+  }
+}
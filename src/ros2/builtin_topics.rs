@@ -31,6 +31,7 @@ impl ROSDiscoveryTopic {
     lifespan: Some(Lifespan {
       duration: Duration::DURATION_INFINITE,
     }),
+    partition: None,
   };
 
   const TOPIC_NAME: &'static str = "ros_discovery_info";
@@ -67,6 +68,7 @@ impl ParameterEventsTopic {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    partition: None,
   };
 
   const TOPIC_NAME: &'static str = "rt/parameter_events";
@@ -109,6 +111,7 @@ impl RosOutTopic {
     lifespan: Some(Lifespan {
       duration: Duration::from_secs(10),
     }),
+    partition: None,
   };
 
   const TOPIC_NAME: &'static str = "rt/rosout";
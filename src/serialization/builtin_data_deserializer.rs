@@ -32,7 +32,7 @@ use crate::{
   dds::{
     qos::policy::{
       Deadline, Durability, LatencyBudget, Liveliness, Reliability, Ownership, DestinationOrder,
-      TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits,
+      TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits, Partition,
     },
     traits::serde_adapters::no_key::*,
   },
@@ -90,6 +90,7 @@ pub struct BuiltinDataDeserializer {
   pub destination_order: Option<DestinationOrder>,
   pub time_based_filter: Option<TimeBasedFilter>,
   pub presentation: Option<Presentation>,
+  pub partition: Option<Partition>,
   pub lifespan: Option<Lifespan>,
   pub history: Option<History>,
   pub resource_limits: Option<ResourceLimits>,
@@ -132,6 +133,7 @@ impl BuiltinDataDeserializer {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       history: None,
       resource_limits: None,
@@ -257,6 +259,11 @@ impl BuiltinDataDeserializer {
       None => qos,
     };
 
+    let qos = match self.partition.as_ref() {
+      Some(p) => qos.partition(p.clone()),
+      None => qos,
+    };
+
     let qos = qos.build();
 
     let key = match self.endpoint_guid {
@@ -302,6 +309,7 @@ impl BuiltinDataDeserializer {
       ownership: self.ownership,
       destination_order: self.destination_order,
       presentation: self.presentation,
+      partition: self.partition.clone(),
     })
   }
 
@@ -708,6 +716,15 @@ impl BuiltinDataDeserializer {
           return self;
         }
       }
+      ParameterId::PID_PARTITION => {
+        let partition: Result<Partition, Error> =
+          CDRDeserializerAdapter::from_bytes(&buffer[4..4 + parameter_length], rep);
+        if let Ok(p) = partition {
+          self.partition = Some(p);
+          buffer.drain(..4 + parameter_length);
+          return self;
+        }
+      }
       ParameterId::PID_LIFESPAN => {
         let lifespan: Result<Lifespan, Error> =
           CDRDeserializerAdapter::from_bytes(&buffer[4..4 + parameter_length], rep);
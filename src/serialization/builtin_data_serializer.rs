@@ -30,7 +30,7 @@ use crate::{
   },
   dds::qos::policy::{
     Deadline, Durability, LatencyBudget, Liveliness, Reliability, Ownership, DestinationOrder,
-    TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits, QosData,
+    TimeBasedFilter, Presentation, Lifespan, History, ResourceLimits, Partition, QosData,
   },
 };
 use serde::{Serialize, Serializer, ser::SerializeStruct, Deserialize};
@@ -55,6 +55,27 @@ impl StringData {
   }
 }
 
+#[derive(Serialize, Deserialize)]
+struct PartitionData {
+  parameter_id: ParameterId,
+  parameter_length: u16,
+  partition: Partition,
+}
+
+impl PartitionData {
+  pub fn new(partition: &Partition) -> PartitionData {
+    // sequence length + each string with its length and padding
+    let parameter_length: usize = 4 + partition.name.iter()
+      .map( |name| name.len() + (4 - name.len() % 4) + 4 )
+      .sum::<usize>();
+    PartitionData {
+      parameter_id: ParameterId::PID_PARTITION,
+      parameter_length: parameter_length as u16,
+      partition: partition.clone(),
+    }
+  }
+}
+
 #[derive(Serialize, Deserialize)]
 struct U32Data {
   parameter_id: ParameterId,
@@ -182,6 +203,7 @@ pub struct BuiltinDataSerializer<'a> {
   pub destination_order: Option<DestinationOrder>,
   pub time_based_filter: Option<TimeBasedFilter>,
   pub presentation: Option<Presentation>,
+  pub partition: Option<&'a Partition>,
   pub lifespan: Option<Lifespan>,
   pub history: Option<History>,
   pub resource_limits: Option<ResourceLimits>,
@@ -303,6 +325,10 @@ impl<'a> BuiltinDataSerializer<'a> {
       Some(v) => Some(v),
       None => self.presentation,
     };
+    self.partition = match other.partition {
+      Some(v) => Some(v),
+      None => self.partition,
+    };
     self.lifespan = match other.lifespan {
       Some(v) => Some(v),
       None => self.lifespan,
@@ -355,6 +381,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       history: None,
       resource_limits: None,
@@ -392,6 +419,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       history: None,
       resource_limits: None,
@@ -429,6 +457,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       history: None,
       resource_limits: None,
@@ -468,6 +497,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: *subscription_topic_data.destination_order(),
       time_based_filter: *subscription_topic_data.time_based_filter(),
       presentation: *subscription_topic_data.presentation(),
+      partition: subscription_topic_data.partition().as_ref(),
       lifespan: *subscription_topic_data.lifespan(),
       history: None,
       resource_limits: None,
@@ -507,6 +537,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: publication_topic_data.destination_order,
       time_based_filter: publication_topic_data.time_based_filter,
       presentation: publication_topic_data.presentation,
+      partition: publication_topic_data.partition.as_ref(),
       lifespan: publication_topic_data.lifespan,
       history: None,
       resource_limits: None,
@@ -544,6 +575,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       history: None,
       resource_limits: None,
@@ -581,6 +613,7 @@ impl<'a> BuiltinDataSerializer<'a> {
       destination_order: topic_data.destination_order,
       time_based_filter: None,
       presentation: topic_data.presentation,
+      partition: None,
       lifespan: topic_data.lifespan,
       history: topic_data.history,
       resource_limits: topic_data.resource_limits,
@@ -665,6 +698,7 @@ impl<'a> BuiltinDataSerializer<'a> {
     self.add_destination_order::<S>(&mut s);
     self.add_time_based_filter::<S>(&mut s);
     self.add_presentation::<S>(&mut s);
+    self.add_partition::<S>(&mut s);
     self.add_lifespan::<S>(&mut s);
     self.add_history::<S>(&mut s);
     self.add_resource_limits::<S>(&mut s);
@@ -736,6 +770,7 @@ impl<'a> BuiltinDataSerializer<'a> {
     count += self.destination_order.is_some() as usize;
     count += self.time_based_filter.is_some() as usize;
     count += self.presentation.is_some() as usize;
+    count += self.partition.is_some() as usize;
     count += self.lifespan.is_some() as usize;
     count += self.history.is_some() as usize;
     count += self.resource_limits.is_some() as usize;
@@ -1142,6 +1177,13 @@ impl<'a> BuiltinDataSerializer<'a> {
     }
   }
 
+  fn add_partition<S: Serializer>(&self, s: &mut S::SerializeStruct) {
+    if let Some(p) = self.partition {
+      s.serialize_field("partition", &PartitionData::new(p))
+        .unwrap();
+    }
+  }
+
   fn add_lifespan<S: Serializer>(&self, s: &mut S::SerializeStruct) {
     if let Some(ls) = self.lifespan {
       s.serialize_field("lifespan", &QosData::new(ParameterId::PID_LIFESPAN, ls))
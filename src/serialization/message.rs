@@ -1,5 +1,6 @@
 use std::collections::{BTreeSet,HashSet,};
 use std::io;
+use std::cmp::min;
 
 use crate::{
   structure::entity::RTPSEntity,
@@ -19,6 +20,7 @@ use crate::{
   messages::{ protocol_version::ProtocolVersion, vendor_id::VendorId, protocol_id::ProtocolId},
   serialization::submessage::{SubMessage, SubmessageBody, },
   structure::{ sequence_number::SequenceNumber, sequence_number::SequenceNumberSet, 
+    sequence_number::FragmentNumber,
     guid::{GuidPrefix,EntityKind,},
     cache_change::{CacheChange},
     parameter_id::ParameterId, 
@@ -185,6 +187,14 @@ impl<'a> Message {
           ))
         }
 
+        SubmessageKind::HEARTBEAT_FRAG => {
+          let f = BitFlags::<HEARTBEATFRAG_Flags>::from_bits_truncate(sub_header.flags);
+          mk_e_subm(EntitySubmessage::HeartbeatFrag(
+            HeartbeatFrag::read_from_buffer_with_ctx(e, &sub_content_buffer)?,
+            f,
+          ))
+        }
+
         // interpreter submessages
         SubmessageKind::INFO_DST => {
           let f = BitFlags::<INFODESTINATION_Flags>::from_bits_truncate(sub_header.flags);
@@ -342,22 +352,22 @@ impl MessageBuilder {
     writer_entity_id: EntityId,
     endianness: Endianness,
   ) -> MessageBuilder {
-    let inline_qos = match cache_change.data_value {
-      DDSData::Data {..} /*| DDSData::DataFrags{..}*/  => None,
-      DDSData::DisposeByKey {..} => None,
-      DDSData::DisposeByKeyHash{ key_hash, .. } => {
-        let mut param_list = ParameterList::new();
-        let key_hash_param = Parameter {
-          parameter_id: ParameterId::PID_KEY_HASH,
-          value: key_hash.to_vec(),
-        };
-        param_list.parameters.push(key_hash_param);
-        let status_info = Parameter::create_pid_status_info_parameter(true, true, false);
-        param_list.parameters.push(status_info);
-        Some(param_list)
-      }
-    };
-
+    let mut param_list = ParameterList::new();
+    if let DDSData::DisposeByKeyHash{ key_hash, .. } = cache_change.data_value {
+      let key_hash_param = Parameter {
+        parameter_id: ParameterId::PID_KEY_HASH,
+        value: key_hash.to_vec(),
+      };
+      param_list.parameters.push(key_hash_param);
+      let status_info = Parameter::create_pid_status_info_parameter(true, true, false);
+      param_list.parameters.push(status_info);
+    }
+    if let Some(coherent_set) = cache_change.coherent_set {
+      param_list.parameters.push( Self::coherent_set_parameter(coherent_set, endianness) );
+    }
+    let inline_qos = 
+      if param_list.parameters.is_empty() { None } 
+      else { Some(param_list) };
 
     let mut data_message = Data {
       reader_id: reader_entity_id,
@@ -377,16 +387,19 @@ impl MessageBuilder {
       if let Some(sp) = data_message.serialized_payload.as_mut() { sp.representation_identifier = RepresentationIdentifier::PL_CDR_LE }
     }
 
-    let flags: BitFlags<DATA_Flags> = 
+    let mut flags: BitFlags<DATA_Flags> = 
       BitFlags::<DATA_Flags>::from_endianness(endianness)
       | ( match cache_change.data_value {
            DDSData::Data {..} /*| DDSData::DataFrags {..} */ 
               => BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data),
            DDSData::DisposeByKey{..} => BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Key),
-           DDSData::DisposeByKeyHash{..} => BitFlags::<DATA_Flags>::from_flag(DATA_Flags::InlineQos),
-           //_ => BitFlags::<DATA_Flags>::from_flag(DATA_Flags::InlineQos),
+           DDSData::DisposeByKeyHash{..} | DDSData::CoherentSetEnd 
+              => BitFlags::<DATA_Flags>::empty(),
           }
         ); 
+    if data_message.inline_qos.is_some() {
+      flags |= DATA_Flags::InlineQos;
+    }
     // TODO: This is stupid. There should be an easier way to get the submessage length
     // than serializing it!
     let size = data_message
@@ -406,6 +419,77 @@ impl MessageBuilder {
     self
   }
 
+  /// Adds a DATA_FRAG submessage carrying fragment number `fragment_number` (counted from 1)
+  /// of `serialized_data`. The latter must be the complete serialized payload of
+  /// `cache_change`, including SerializedPayload header.
+  #[allow(clippy::too_many_arguments)]
+  pub fn data_frag_msg(
+    mut self,
+    cache_change: &CacheChange,
+    serialized_data: &Bytes,
+    reader_entity_id: EntityId,
+    writer_entity_id: EntityId,
+    fragment_number: FragmentNumber,
+    fragment_size: u16,
+    endianness: Endianness,
+  ) -> MessageBuilder {
+    let frag_size = usize::from(fragment_size);
+    let from_byte = (u32::from(fragment_number) as usize - 1) * frag_size;
+    let to_before_byte = min(from_byte + frag_size, serialized_data.len());
+
+    let data_frag = DataFrag {
+      reader_id: reader_entity_id,
+      writer_id: writer_entity_id,
+      writer_sn: cache_change.sequence_number,
+      fragment_starting_num: fragment_number,
+      fragments_in_submessage: 1,
+      data_size: serialized_data.len() as u32,
+      fragment_size,
+      inline_qos: cache_change.coherent_set
+        .map( |cs| {
+          let mut param_list = ParameterList::new();
+          param_list.parameters.push( Self::coherent_set_parameter(cs, endianness) );
+          param_list
+        }),
+      serialized_payload: serialized_data.slice(from_byte..to_before_byte),
+    };
+
+    let mut flags: BitFlags<DATAFRAG_Flags> = 
+      BitFlags::<DATAFRAG_Flags>::from_endianness(endianness)
+      | ( match cache_change.data_value {
+           DDSData::DisposeByKey{..} => BitFlags::<DATAFRAG_Flags>::from_flag(DATAFRAG_Flags::Key),
+           _ => BitFlags::<DATAFRAG_Flags>::empty(),
+          }
+        ); 
+    if data_frag.inline_qos.is_some() {
+      flags |= DATAFRAG_Flags::InlineQos;
+    }
+
+    let size = data_frag
+      .write_to_vec_with_ctx(endianness)
+      .unwrap()
+      .len() as u16;
+
+    self.submessages
+      .push( SubMessage {
+                header: SubmessageHeader {
+                  kind: SubmessageKind::DATA_FRAG,
+                  flags: flags.bits(),
+                  content_length: size,
+                },
+                body: SubmessageBody::Entity(EntitySubmessage::DataFrag(data_frag, flags)),
+              } );   
+    self
+  }
+
+  // PID_COHERENT_SET inline QoS parameter. Its value is the first SequenceNumber of the set.
+  fn coherent_set_parameter(coherent_set: SequenceNumber, endianness: Endianness) -> Parameter {
+    Parameter {
+      parameter_id: ParameterId::PID_COHERENT_SET,
+      value: coherent_set.write_to_vec_with_ctx(endianness).unwrap(),
+    }
+  }
+
   // TODO: We should optimize this entire thing to allow long contiguous irrelevant set to be
   // represented as start_sn + 
   pub fn gap_msg(mut self, irrelevant_sns: BTreeSet<SequenceNumber>, writer: &RtpsWriter, reader_guid: GUID) 
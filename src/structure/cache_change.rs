@@ -17,6 +17,8 @@ pub struct CacheChange {
   pub sequence_number: SequenceNumber,
   pub source_timestamp: Option<Timestamp>,
   pub data_value: DDSData, 
+  // First SequenceNumber of the coherent set this change belongs to, if any.
+  pub coherent_set: Option<SequenceNumber>,
 }
 
 #[cfg(test)]
@@ -26,6 +28,7 @@ impl PartialEq for CacheChange {
       && self.sequence_number == other.sequence_number
       && self.source_timestamp == other.source_timestamp
       && self.data_value == other.data_value
+      && self.coherent_set == other.coherent_set
   }
 }
 
@@ -36,7 +39,7 @@ impl CacheChange {
     source_timestamp: Option<Timestamp>,
     data_value: DDSData,  
   ) -> CacheChange {
-    CacheChange { writer_guid, sequence_number, source_timestamp, data_value, }
+    CacheChange { writer_guid, sequence_number, source_timestamp, data_value, coherent_set: None, }
   }
 
   pub fn change_kind(&self) -> ChangeKind {
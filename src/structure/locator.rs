@@ -15,6 +15,9 @@ impl LocatorKind {
   pub const LOCATOR_KIND_RESERVED: LocatorKind = LocatorKind { value: 0 };
   pub const LOCATOR_KIND_UDPv4: LocatorKind = LocatorKind { value: 1 };
   pub const LOCATOR_KIND_UDPv6: LocatorKind = LocatorKind { value: 2 };
+  // Vendor-specific kinds have the most significant bit set. The rest contains
+  // our VendorId (0x0112) and a transport number.
+  pub const LOCATOR_KIND_SHM: LocatorKind = LocatorKind { value: 0x8112_0001_u32 as i32 };
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize, PartialOrd, Ord)]
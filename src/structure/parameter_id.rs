@@ -61,6 +61,7 @@ impl ParameterId {
   pub const PID_ENTITY_NAME: ParameterId = ParameterId { value: 0x0062 };
   pub const PID_KEY_HASH: ParameterId = ParameterId { value: 0x0070 };
   pub const PID_STATUS_INFO: ParameterId = ParameterId { value: 0x0071 };
+  pub const PID_COHERENT_SET: ParameterId = ParameterId { value: 0x0056 };
 }
 
 #[cfg(test)]
@@ -379,5 +380,11 @@ mod tests {
       ParameterId::PID_STATUS_INFO,
       le = [0x71, 0x00],
       be = [0x00, 0x71]
+  },
+  {
+      pid_coherent_set,
+      ParameterId::PID_COHERENT_SET,
+      le = [0x56, 0x00],
+      be = [0x00, 0x56]
   });
 }
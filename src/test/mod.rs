@@ -3,3 +3,4 @@ pub(crate) mod random_data;
 pub(crate) mod shape_type;
 pub(crate) mod test_data;
 pub(crate) mod test_properties;
+pub(crate) mod wait_util;
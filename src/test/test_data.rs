@@ -76,7 +76,7 @@ use crate::{
   dds::{
     qos::policy::{
       Deadline, Durability, LatencyBudget, Liveliness, Reliability, Ownership, DestinationOrder,
-      TimeBasedFilter, Presentation, PresentationAccessScope, Lifespan, History, ResourceLimits,
+      TimeBasedFilter, Presentation, PresentationAccessScope, Lifespan, History, ResourceLimits, Partition,
     },
     traits::serde_adapters::no_key::DeserializerAdapter,
     qos::QosPolicyBuilder,
@@ -257,6 +257,7 @@ pub(crate) fn subscription_builtin_topic_data() -> Option<SubscriptionBuiltinTop
       coherent_access: false,
       ordered_access: true,
     })
+    .partition(Partition::new(&["sensor*", "fleet_a1b", "x"]))
     .lifespan(Lifespan {
       duration: Duration::from(StdDuration::from_secs(6 * 60)),
     })
@@ -296,6 +297,7 @@ pub(crate) fn publication_builtin_topic_data() -> Option<PublicationBuiltinTopic
       coherent_access: true,
       ordered_access: false,
     }),
+    partition: Some(Partition::new(&["sensors"])),
   };
 
   Some(pub_topic_data)
@@ -0,0 +1,76 @@
+// Helpers for tests that need to wait for something to happen on the network.
+// These wake up as soon as the awaited event arrives, instead of sleeping a fixed time.
+
+use std::time::{Duration, Instant};
+
+use mio::{Evented, Events, Poll, PollOpt, Ready, Token};
+use serde::de::DeserializeOwned;
+
+use crate::dds::{
+  statusevents::StatusEvented,
+  traits::key::{Key, Keyed},
+  traits::serde_adapters::with_key::DeserializerAdapter,
+  with_key::{datareader::DataReader, datasample::DataSample},
+};
+
+thread_local! {
+  // Channel receivers can be registered to one Poll only, so a test waiting on
+  // the same entity many times must use the same Poll.
+  static POLL: Poll = Poll::new().unwrap();
+}
+
+fn register(evented: &dyn Evented) {
+  POLL.with( |poll| {
+    poll.register(evented, Token(0), Ready::readable(), PollOpt::edge())
+      .or_else( |_| poll.reregister(evented, Token(0), Ready::readable(), PollOpt::edge()) )
+      .unwrap()
+  })
+}
+
+// Waits until `ready` returns something, or the timeout passes. `ready` is tried again
+// whenever something registered becomes readable.
+fn wait_until<T>(timeout: Duration, mut ready: impl FnMut() -> Option<T>) -> Option<T> {
+  POLL.with( |poll| {
+    let deadline = Instant::now() + timeout;
+    let mut events = Events::with_capacity(4);
+    loop {
+      if let Some(result) = ready() {
+        return Some(result)
+      }
+      let now = Instant::now();
+      if now >= deadline {
+        return None
+      }
+      poll.poll(&mut events, Some(deadline - now)).unwrap();
+    }
+  })
+}
+
+// Waits until the Entity reports a status accepted by `wanted`, or the timeout passes.
+pub(crate) fn wait_for_status<E, S>(entity: &mut E, timeout: Duration, wanted: impl Fn(&S) -> bool)
+  -> Option<S>
+where
+  E: StatusEvented<S>,
+{
+  register(entity.as_status_evented());
+  wait_until(timeout, || {
+    while let Some(status) = entity.try_recv_status() {
+      if wanted(&status) {
+        return Some(status)
+      }
+    }
+    None
+  })
+}
+
+// Waits until the DataReader has a sample to take, or the timeout passes.
+pub(crate) fn wait_for_sample<D, DA>(reader: &mut DataReader<D, DA>, timeout: Duration)
+  -> Option<DataSample<D>>
+where
+  D: Keyed + DeserializeOwned + 'static,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  register(reader);
+  wait_until(timeout, || reader.take_next_sample().unwrap())
+}